@@ -82,6 +82,12 @@ export class BackupService {
       return err(uploadUrlResult.error);
     }
 
+    if (uploadUrlResult.value.status !== "upload") {
+      // Only returned as unchanged when a checksum was sent, which this client doesn't do yet
+      const error = new Error("Server did not return an upload URL");
+      reportBackupFailure("Failed to prepare backup upload.", error);
+      return err(error);
+    }
     const { upload_url, s3_key } = uploadUrlResult.value;
 
     // Upload the encrypted backup to S3
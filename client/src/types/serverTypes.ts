@@ -19,7 +19,17 @@ message: string,
 /**
  * Safe error reason for compatibility.
  */
-reason: string, };
+reason: string, 
+/**
+ * Server unix time in seconds, sent with `K1_EXPIRED` and `CLOCK_SKEW` so clients can
+ * detect clock skew.
+ */
+server_time?: number, 
+/**
+ * Validation messages keyed by field, sent with `INVALID_ARGUMENT` when request payload
+ * validation fails.
+ */
+field_errors?: { [key in string]?: Array<string> }, };
 
 export type AppVersionCheckPayload = { client_version: string, };
 
@@ -51,13 +61,39 @@ expiry: number,
  */
 encoded: string, };
 
+/**
+ * Tells the client whether a backup exists and which version to restore.
+ */
+export type BackupExistsResponse = { exists: boolean, 
+/**
+ * The version last completed by the client, which `download_url` serves by default.
+ */
+latest_version: number | null, };
+
 export type BackupInfo = { backup_version: number, created_at: string, backup_size: number, };
 
 export type BackupSettingsPayload = { backup_enabled: boolean, };
 
 export type BackupTriggerNotification = { notification_k1: string, };
 
-export type CompleteUploadPayload = { s3_key: string, backup_version: number, backup_size: number, };
+/**
+ * Storage used by the user's backups, for the settings screen.
+ */
+export type BackupUsageResponse = { total_bytes: number, backup_count: number, oldest_backup_at: string | null, newest_backup_at: string | null, 
+/**
+ * Bytes left under `MAX_TOTAL_BACKUP_BYTES_PER_USER`, null when no byte quota is set.
+ */
+remaining_bytes: number | null, 
+/**
+ * Backups left under `MAX_BACKUPS_PER_USER`, null when no count quota is set.
+ */
+remaining_backups: number | null, };
+
+export type CompleteUploadPayload = { s3_key: string, backup_version: number, backup_size: number, 
+/**
+ * Hex SHA-256 of the uploaded backup, compared by later `upload_url` calls.
+ */
+checksum?: string, };
 
 export type DefaultSuccessPayload = { success: boolean, };
 
@@ -70,19 +106,77 @@ export type DeviceInfo = { device_manufacturer: string | null, device_model: str
 
 export type DownloadUrlResponse = { download_url: string, backup_size: number, };
 
+/**
+ * Defines the payload for checking whether an email is already in use.
+ */
+export type EmailAvailabilityPayload = { email: string, };
+
+/**
+ * Represents the response for an email availability check.
+ *
+ * Only says whether another account has verified the address, never which one.
+ */
+export type EmailAvailabilityResponse = { 
+/**
+ * `false` when another account has verified the address. An account verifying its first
+ * email may still share it, but a verified account cannot change to it.
+ */
+available: boolean, };
+
 /**
  * Represents the response for email verification requests.
  */
 export type EmailVerificationResponse = { success: boolean, message: string | null, };
 
+/**
+ * Represents the feature flags resolved for the authenticated user.
+ */
+export type FeatureFlagsResponse = { 
+/**
+ * Global flags with the user's rollout overrides applied.
+ */
+feature_flags: { [key in string]?: boolean }, };
+
 export type GetDownloadUrlPayload = { backup_version: number | null, };
 
-export type GetUploadUrlPayload = { backup_version: number, };
+export type GetUploadUrlPayload = { backup_version: number, 
+/**
+ * Hex SHA-256 of the backup about to be uploaded. When it matches the checksum stored for
+ * this version, no upload URL is issued.
+ */
+checksum?: string, };
 
 export type HeartbeatNotification = { notification_id: string, };
 
 export type HeartbeatResponsePayload = { notification_id: string, };
 
+/**
+ * Represents a status update for an invoice request streamed over WebSocket.
+ */
+export type InvoiceStatusFrame = { "status": "pending", transaction_id: string, } | { "status": "push_sent", transaction_id: string, } | { "status": "invoiced", pr: string, } | { "status": "timeout" } | { "status": "error", reason: string, };
+
+/**
+ * Defines the query for checking whether a lightning address username is available.
+ */
+export type LightningAddressAvailabilityQuery = { 
+/**
+ * The username part of the lightning address, without the domain.
+ */
+username: string, };
+
+/**
+ * Represents whether a lightning address username can be registered.
+ */
+export type LightningAddressAvailabilityResponse = { 
+/**
+ * Whether the username is free to register.
+ */
+available: boolean, 
+/**
+ * Available alternative lightning addresses, only set when the username is taken or reserved.
+ */
+suggestions: Array<string>, };
+
 /**
  * Defines the payload for querying lightning address suggestions.
  */
@@ -101,12 +195,140 @@ export type LightningAddressSuggestionsResponse = {
  */
 suggestions: Array<string>, };
 
-export type LightningInvoiceRequestNotification = { transaction_id: string, amount: number, };
+export type LightningInvoiceRequestNotification = { transaction_id: string, 
+/**
+ * Requested amount in millisatoshis, as received from the LNURL-pay payer.
+ */
+amount: number, 
+/**
+ * Hex SHA256 of the LNURL metadata the payer saw, to use as the invoice's description hash.
+ */
+description_hash?: string, };
+
+/**
+ * The LUD-06 error response LNURL wallets understand.
+ *
+ * Every `ApiErrorResponse` carries these two fields, so errors from the LNURL endpoints can be
+ * read by any wallet as well as by the client.
+ */
+export type LnurlErrorResponse = { 
+/**
+ * Always "ERROR".
+ */
+status: string, 
+/**
+ * Why the request failed, safe to show to the payer.
+ */
+reason: string, };
+
+/**
+ * Everything the client needs to render the user's receive QR codes.
+ */
+export type LnurlMetadataResponse = { 
+/**
+ * The bech32-encoded LNURL of the user's LNURL-pay endpoint.
+ */
+lnurl: string, 
+/**
+ * The user's lightning address.
+ */
+lightning_address: string, };
+
+/**
+ * Represents the first response in the LNURL-pay protocol.
+ *
+ * This response provides the necessary details for a wallet to make a payment,
+ * such as the callback URL, sendable amounts, and metadata.
+ */
+export type LnurlpDefaultResponse = { 
+/**
+ * The URL where the wallet should send the second request.
+ */
+callback: string, 
+/**
+ * The maximum amount that can be sent in a single payment, in millisatoshis.
+ */
+maxSendable: number, 
+/**
+ * The minimum amount that can be sent in a single payment, in millisatoshis.
+ */
+minSendable: number, 
+/**
+ * A JSON string containing metadata about the payment.
+ */
+metadata: string, 
+/**
+ * The LNURL-pay tag, which is always "payRequest".
+ */
+tag: string, 
+/**
+ * The maximum length of a comment that can be included with the payment.
+ */
+commentAllowed: number, };
+
+/**
+ * Represents the second response in the LNURL-pay protocol.
+ *
+ * This response contains the BOLT11 invoice that the wallet will use to pay.
+ */
+export type LnurlpInvoiceResponse = { 
+/**
+ * The BOLT11 payment request (invoice).
+ */
+pr: string, 
+/**
+ * A list of routes for the payment, typically empty.
+ */
+routes: Array<string>, 
+/**
+ * The recipient's Ark address, only sent to Noah wallets.
+ */
+ark?: string, 
+/**
+ * Shown by the payer's wallet after paying, configured by the recipient.
+ */
+successAction?: LnurlpSuccessAction, };
+
+/**
+ * Defines the payload for pre-registering an invoice request to poll for later.
+ */
+export type LnurlpPreRegisterPayload = { 
+/**
+ * The amount of the payment in millisatoshis.
+ */
+amount: number, };
+
+/**
+ * Identifies a pre-registered invoice request, to be polled at `/lnurlp/poll/{transaction_id}`.
+ */
+export type LnurlpPreRegisterResponse = { transaction_id: string, };
+
+/**
+ * LUD-09 success action the payer's wallet shows once the payment went through.
+ */
+export type LnurlpSuccessAction = { "tag": "message", message: string, } | { "tag": "url", description: string, url: string, };
 
 export type MaintenanceNotification = { notification_k1: string, };
 
 export type NotificationData = { "notification_type": "maintenance" } & MaintenanceNotification | { "notification_type": "lightning_invoice_request" } & LightningInvoiceRequestNotification | { "notification_type": "backup_trigger" } & BackupTriggerNotification | { "notification_type": "heartbeat" } & HeartbeatNotification;
 
+/**
+ * A push token registered for the authenticated user, with the token itself masked.
+ */
+export type PushTokenInfo = { id: number, 
+/**
+ * Only the last characters of the token are shown.
+ */
+masked_token: string, created_at: string, updated_at: string, 
+/**
+ * Device the token was registered from, `null` when no device info was reported.
+ */
+device_model: string | null, os_name: string | null, os_version: string | null, 
+/**
+ * Last time the device reported its info to the server.
+ */
+last_seen_at: string | null, };
+
 /**
  * Defines the payload for a user registration request.
  */
@@ -150,25 +372,85 @@ event: AuthEvent | null,
  */
 reason: string | null, 
 /**
- * The user's lightning address.
+ * The user's effective lightning address, including one assigned by the server.
  */
 lightning_address: string | null, 
+/**
+ * The user's registered Ark address.
+ */
+ark_address: string | null, 
+/**
+ * Whether automatic backups are enabled for the user.
+ */
+backup_enabled: boolean, 
 /**
  * Whether the user's email is verified.
  */
 is_email_verified: boolean, };
 
+/**
+ * Defines the payload for reporting unwanted LNURL-pay requests.
+ */
+export type ReportAbusePayload = { 
+/**
+ * Free-form description for the operator reviewing the report.
+ */
+reason?: string, };
+
 export type ReportJobStatusPayload = { notification_k1: string, report_type: ReportType, status: ReportStatus, error_message: string | null, };
 
 export type ReportStatus = "pending" | "success" | "failure" | "timeout";
 
 export type ReportType = "maintenance" | "backup";
 
+/**
+ * Defines the payload for revoking one of the user's push tokens.
+ */
+export type RevokePushTokenPayload = { id: number, };
+
 /**
  * Defines the payload for requesting an email verification code.
  */
 export type SendEmailVerificationPayload = { email: string, };
 
+/**
+ * Describes the server to clients.
+ */
+export type ServerInfoResponse = { 
+/**
+ * Bitcoin network the server operates on.
+ */
+network: string, 
+/**
+ * Hex public key that signs critical responses, `null` when signing is disabled.
+ *
+ * Signed responses carry an `x-server-sig` header holding the hex DER ECDSA signature
+ * over the SHA-256 of the response body.
+ */
+signing_pubkey: string | null, 
+/**
+ * Feature flags enabled or disabled globally on this deployment.
+ */
+feature_flags: { [key in string]?: boolean }, };
+
+/**
+ * Server clock reference, so clients can spot clock skew before a login fails with
+ * `K1_EXPIRED`.
+ */
+export type ServerTimeResponse = { 
+/**
+ * Unix time in seconds, from the same clock that timestamps k1s.
+ */
+server_time: number, 
+/**
+ * How long an issued k1 stays valid, in seconds.
+ */
+k1_ttl_secs: number, 
+/**
+ * Clock drift tolerated on either side of the k1 lifetime, in seconds.
+ */
+k1_clock_skew_tolerance_secs: number, };
+
 /**
  * Defines the payload for submitting a BOLT11 invoice.
  */
@@ -182,6 +464,15 @@ invoice: string,
  */
 transaction_id: string, };
 
+/**
+ * Defines the payload for setting the amount suggested to LNURL-pay payers.
+ */
+export type UpdateDefaultSendablePayload = { 
+/**
+ * The suggested amount in millisatoshis, or `null` to stop suggesting one.
+ */
+default_sendable_msat: number | null, };
+
 /**
  * Defines the payload for updating a user's lightning address.
  */
@@ -191,9 +482,52 @@ export type UpdateLnAddressPayload = {
  */
 ln_address: string, };
 
-export type UploadUrlResponse = { upload_url: string, s3_key: string, };
+/**
+ * Defines the payload for setting the success action shown to LNURL-pay payers.
+ */
+export type UpdateSuccessActionPayload = { 
+/**
+ * The action to show, or `null` to stop showing one.
+ */
+success_action: LnurlpSuccessAction | null, };
+
+/**
+ * Defines the payload for setting the user's timezone.
+ */
+export type UpdateTimezonePayload = { 
+/**
+ * IANA timezone name such as `Europe/Berlin`, or `null` to fall back to UTC.
+ */
+timezone: string | null, };
+
+/**
+ * Represents where to upload a backup, or that the upload can be skipped.
+ */
+export type UploadUrlResponse = { "status": "upload", upload_url: string, s3_key: string, } | { "status": "unchanged" };
 
 /**
  * Defines the payload for verifying an email with a code.
  */
 export type VerifyEmailPayload = { code: string, };
+
+/**
+ * Defines the payload for checking an offboarding address signature.
+ */
+export type VerifyOffboardingSignaturePayload = { 
+/**
+ * The on-chain address the user wants to offboard to.
+ */
+address: string, 
+/**
+ * Signature over the address made with the user's key.
+ */
+address_signature: string, };
+
+/**
+ * Represents the result of an offboarding signature check.
+ */
+export type VerifyOffboardingSignatureResponse = { 
+/**
+ * Whether the signature is valid for the address and the user's key.
+ */
+valid: boolean, };
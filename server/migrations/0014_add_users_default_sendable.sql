@@ -0,0 +1,2 @@
+-- Optional amount hint shown to payers in the LNURL-pay metadata
+ALTER TABLE users ADD COLUMN default_sendable_msat BIGINT;
@@ -0,0 +1,6 @@
+-- Spacing checks look up the latest heartbeat per user, which the single-column
+-- pubkey index can't answer without visiting every heartbeat row for that user.
+CREATE INDEX idx_heartbeat_notifications_pubkey_sent_at ON heartbeat_notifications(pubkey, sent_at DESC);
+
+-- Superseded by the composite index above.
+DROP INDEX IF EXISTS idx_heartbeat_notifications_pubkey;
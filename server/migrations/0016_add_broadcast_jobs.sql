@@ -0,0 +1,22 @@
+CREATE TABLE broadcast_jobs (
+    id BIGSERIAL PRIMARY KEY,
+    notification_type TEXT NOT NULL,
+    priority TEXT NOT NULL,
+    status TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    completed_at TIMESTAMPTZ
+);
+
+-- At most one running broadcast per notification type, so instances resume it instead of
+-- starting a second one
+CREATE UNIQUE INDEX idx_broadcast_jobs_running_type
+    ON broadcast_jobs(notification_type)
+    WHERE status = 'running';
+
+CREATE TABLE broadcast_job_recipients (
+    job_id BIGINT NOT NULL REFERENCES broadcast_jobs(id) ON DELETE CASCADE,
+    pubkey TEXT NOT NULL,
+    processed_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (job_id, pubkey)
+);
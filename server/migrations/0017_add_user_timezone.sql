@@ -0,0 +1,2 @@
+-- IANA timezone name, NULL means UTC
+ALTER TABLE users ADD COLUMN timezone TEXT;
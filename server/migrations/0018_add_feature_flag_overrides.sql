@@ -0,0 +1,7 @@
+CREATE TABLE feature_flag_overrides (
+    pubkey TEXT NOT NULL REFERENCES users(pubkey) ON DELETE CASCADE,
+    flag TEXT NOT NULL,
+    enabled BOOLEAN NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (pubkey, flag)
+);
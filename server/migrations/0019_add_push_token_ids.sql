@@ -0,0 +1,2 @@
+-- Stable identifier so users can list and revoke individual push tokens
+ALTER TABLE push_tokens ADD COLUMN id BIGSERIAL UNIQUE;
@@ -0,0 +1,10 @@
+-- Identifier push tokens can reference
+ALTER TABLE devices ADD COLUMN id BIGSERIAL UNIQUE;
+
+-- Device a push token was registered from
+ALTER TABLE push_tokens ADD COLUMN device_id BIGINT REFERENCES devices(id) ON DELETE SET NULL;
+
+UPDATE push_tokens
+SET device_id = devices.id
+FROM devices
+WHERE devices.pubkey = push_tokens.pubkey;
@@ -0,0 +1,3 @@
+-- Consecutive DeviceNotRegistered tickets, so a transient Expo error doesn't drop a valid token
+ALTER TABLE push_tokens ADD COLUMN not_registered_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE push_tokens ADD COLUMN first_not_registered_at TIMESTAMPTZ;
@@ -0,0 +1,2 @@
+-- LUD-09 success action shown to payers after paying, stored as JSON
+ALTER TABLE users ADD COLUMN lnurlp_success_action TEXT;
@@ -0,0 +1,9 @@
+-- Lightning addresses a user moved away from, so payments to them can still be routed for a while
+CREATE TABLE lightning_address_history (
+    lightning_address TEXT PRIMARY KEY,
+    pubkey TEXT NOT NULL REFERENCES users(pubkey) ON DELETE CASCADE,
+    retired_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX idx_lightning_address_history_pubkey
+    ON lightning_address_history(pubkey);
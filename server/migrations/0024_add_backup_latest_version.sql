@@ -0,0 +1,9 @@
+-- Version the client last completed, so "latest" doesn't depend on created_at ordering
+ALTER TABLE backup_settings ADD COLUMN latest_backup_version INTEGER;
+
+INSERT INTO backup_settings (pubkey, latest_backup_version)
+SELECT DISTINCT ON (pubkey) pubkey, backup_version
+FROM backup_metadata
+ORDER BY pubkey, created_at DESC
+ON CONFLICT (pubkey)
+DO UPDATE SET latest_backup_version = excluded.latest_backup_version;
@@ -0,0 +1,2 @@
+-- SHA-256 of the uploaded backup as reported by the client, so unchanged backups can be skipped
+ALTER TABLE backup_metadata ADD COLUMN checksum TEXT;
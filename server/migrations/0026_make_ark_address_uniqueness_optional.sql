@@ -0,0 +1,4 @@
+-- Uniqueness is now enforced by the server when ARK_ADDRESS_UNIQUE is set, so deployments can
+-- share an ark address across accounts
+ALTER TABLE users DROP CONSTRAINT users_ark_address_key;
+CREATE INDEX idx_users_ark_address ON users(ark_address);
@@ -0,0 +1,10 @@
+-- Complaints from users spammed with LNURL-pay requests, kept for operator review
+CREATE TABLE abuse_reports (
+    id BIGSERIAL PRIMARY KEY,
+    pubkey TEXT NOT NULL REFERENCES users(pubkey) ON DELETE CASCADE,
+    reason TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX idx_abuse_reports_created_at
+    ON abuse_reports(created_at DESC);
@@ -0,0 +1,5 @@
+-- The run holding a broadcast job renews heartbeat_at while it sends. Other runs only take the
+-- job over once the lease has expired, so a live broadcast is never joined by a second one
+ALTER TABLE broadcast_jobs
+    ADD COLUMN owner TEXT,
+    ADD COLUMN heartbeat_at TIMESTAMPTZ NOT NULL DEFAULT now();
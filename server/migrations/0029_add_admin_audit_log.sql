@@ -0,0 +1,12 @@
+-- Durable record of destructive admin actions. Rows outlive the users they are about, so the
+-- subject is a hash of the pubkey rather than the pubkey itself
+CREATE TABLE admin_audit_log (
+    id BIGSERIAL PRIMARY KEY,
+    action TEXT NOT NULL,
+    subject_hash TEXT NOT NULL,
+    details JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX idx_admin_audit_log_created_at
+    ON admin_audit_log(created_at DESC);
@@ -0,0 +1,141 @@
+use std::{
+    convert::Infallible,
+    fmt,
+    net::{IpAddr, SocketAddr},
+};
+
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::request::Parts,
+};
+
+use crate::{AppState, errors::ApiError};
+
+/// The caller's IP, taken from the proxy headers before falling back to the peer address.
+///
+/// Mirrors the lookup order of the rate limiter's `SmartIpKeyExtractor`.
+#[derive(Debug, Clone, Copy)]
+pub struct ClientIp(pub Option<IpAddr>);
+
+impl<S: Send + Sync> FromRequestParts<S> for ClientIp {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let header_ip = |name: &str| {
+            parts
+                .headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.split(',').next())
+                .and_then(|ip| ip.trim().parse::<IpAddr>().ok())
+        };
+
+        let ip = header_ip("x-forwarded-for")
+            .or_else(|| header_ip("x-real-ip"))
+            .or_else(|| {
+                parts
+                    .extensions
+                    .get::<ConnectInfo<SocketAddr>>()
+                    .map(|ConnectInfo(addr)| addr.ip())
+            });
+
+        Ok(Self(ip))
+    }
+}
+
+/// Suspicious events that count towards a subject's abuse score.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AbuseEvent {
+    FailedSignature,
+    K1Request,
+    InvoiceRequest,
+}
+
+impl AbuseEvent {
+    fn points(self) -> u64 {
+        match self {
+            // A failed signature is a strong signal, issuing k1s and invoices is normal in moderation
+            AbuseEvent::FailedSignature => 10,
+            AbuseEvent::K1Request => 1,
+            AbuseEvent::InvoiceRequest => 1,
+        }
+    }
+}
+
+/// Who an abuse score or block applies to.
+#[derive(Debug, Clone, Copy)]
+pub enum AbuseSubject<'a> {
+    Pubkey(&'a str),
+    Ip(IpAddr),
+}
+
+impl fmt::Display for AbuseSubject<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AbuseSubject::Pubkey(pubkey) => write!(f, "pubkey:{}", pubkey),
+            AbuseSubject::Ip(ip) => write!(f, "ip:{}", ip),
+        }
+    }
+}
+
+/// Rejects the request if any of the subjects is currently blocked.
+///
+/// Redis errors are logged and let the request through so an outage doesn't lock everyone out.
+pub async fn ensure_not_blocked(
+    state: &AppState,
+    subjects: &[AbuseSubject<'_>],
+) -> Result<(), ApiError> {
+    if state.config.abuse_settings().is_none() {
+        return Ok(());
+    }
+
+    for subject in subjects {
+        match state.abuse_store.blocked_for(&subject.to_string()).await {
+            Ok(Some(retry_after)) => return Err(ApiError::TemporarilyBlocked(retry_after)),
+            Ok(None) => {}
+            Err(e) => tracing::error!(error = %e, "Failed to check abuse block"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Records a suspicious event against each subject.
+///
+/// Returns `TemporarilyBlocked` when the event pushes a subject over the threshold.
+pub async fn record_abuse(
+    state: &AppState,
+    event: AbuseEvent,
+    subjects: &[AbuseSubject<'_>],
+) -> Result<(), ApiError> {
+    let Some(settings) = state.config.abuse_settings() else {
+        return Ok(());
+    };
+
+    let mut blocked_for = None;
+    for subject in subjects {
+        let subject = subject.to_string();
+        match state
+            .abuse_store
+            .add_score(&subject, event.points(), &settings)
+            .await
+        {
+            Ok(Some(ban_secs)) => {
+                tracing::warn!(
+                    subject = %subject,
+                    event = ?event,
+                    ban_secs = ban_secs,
+                    "Abuse threshold reached, temporarily blocking"
+                );
+                blocked_for = Some(ban_secs);
+            }
+            Ok(None) => {}
+            Err(e) => tracing::error!(error = %e, "Failed to record abuse event"),
+        }
+    }
+
+    match blocked_for {
+        Some(ban_secs) => Err(ApiError::TemporarilyBlocked(ban_secs)),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,41 @@
+use std::sync::{Arc, atomic::AtomicBool};
+use std::time::Duration;
+
+use futures_util::StreamExt;
+
+use crate::{AppState, cache::admin_command_bus::AdminCommand, s3_client};
+
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(5);
+
+/// Runs admin commands published on the command bus by any instance, including this one.
+///
+/// Redis doesn't replay messages sent while the subscription was down, so a command published
+/// during a reconnect is missed and has to be sent again.
+pub async fn run_admin_command_listener(app_state: AppState, s3_healthy: Arc<AtomicBool>) {
+    loop {
+        match app_state.admin_command_bus.subscribe().await {
+            Ok(commands) => {
+                let mut commands = std::pin::pin!(commands);
+                while let Some(command) = commands.next().await {
+                    execute(&app_state, &s3_healthy, command).await;
+                }
+                tracing::warn!("Admin command subscription closed, resubscribing");
+            }
+            Err(e) => {
+                tracing::error!("Failed to subscribe to admin commands: {}", e);
+            }
+        }
+        tokio::time::sleep(RESUBSCRIBE_DELAY).await;
+    }
+}
+
+async fn execute(app_state: &AppState, s3_healthy: &AtomicBool, command: AdminCommand) {
+    tracing::info!(?command, "Running admin command");
+    match command {
+        AdminCommand::RecheckS3 => {
+            if let Err(e) = s3_client::refresh_s3_health(&app_state.config, s3_healthy).await {
+                tracing::error!("Failed to recheck S3 bucket access: {}", e);
+            }
+        }
+    }
+}
@@ -1,19 +1,65 @@
 use crate::{
     AppState,
-    notification_coordinator::{NotificationCoordinator, NotificationRequest},
+    notification_coordinator::{
+        DispatchSummary, NotificationCoordinator, NotificationRequest, SendOutcome,
+    },
     types::NotificationRequestData,
 };
 
-use bitcoin::hex::DisplayHex;
+use bitcoin::{Network, hex::DisplayHex};
 use expo_push_notification_client::Priority;
-use server_rpc::{ServerConnection, protos::Empty};
+use futures_util::{Stream, StreamExt, stream};
+use serde::Serialize;
+use server_rpc::{
+    ServerConnection,
+    protos::{ArkInfo, Empty},
+};
+use std::sync::{Arc, RwLock};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 const POLL_INTERVAL: Duration = Duration::from_secs(30);
 
+/// Snapshot of the Ark connection, reported by the readiness endpoint.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ArkConnectionState {
+    pub connected: bool,
+    pub last_handshake_error: Option<String>,
+}
+
+/// Shared handle the Ark client task updates as it connects and reconnects.
+#[derive(Debug, Clone, Default)]
+pub struct ArkConnectionStatus(Arc<RwLock<ArkConnectionState>>);
+
+impl ArkConnectionStatus {
+    pub fn snapshot(&self) -> ArkConnectionState {
+        self.0.read().map(|state| state.clone()).unwrap_or_default()
+    }
+
+    fn set_connected(&self) {
+        if let Ok(mut state) = self.0.write() {
+            state.connected = true;
+            state.last_handshake_error = None;
+        }
+    }
+
+    fn set_disconnected(&self) {
+        if let Ok(mut state) = self.0.write() {
+            state.connected = false;
+        }
+    }
+
+    fn set_handshake_error(&self, error: &anyhow::Error) {
+        if let Ok(mut state) = self.0.write() {
+            state.connected = false;
+            state.last_handshake_error = Some(format!("{error:#}"));
+        }
+    }
+}
+
 pub async fn connect_to_ark_server(
     app_state: AppState,
     ark_server_url: String,
+    status: ArkConnectionStatus,
 ) -> anyhow::Result<()> {
     const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(2);
     const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
@@ -21,7 +67,7 @@ pub async fn connect_to_ark_server(
     let mut retry_delay = INITIAL_RETRY_DELAY;
 
     loop {
-        match establish_connection_and_process(&app_state, &ark_server_url).await {
+        match establish_connection_and_process(&app_state, &ark_server_url, &status).await {
             Ok(_) => {
                 tracing::warn!(
                     service = "ark_client",
@@ -39,6 +85,7 @@ pub async fn connect_to_ark_server(
                 );
             }
         }
+        status.set_disconnected();
 
         tracing::info!(
             service = "ark_client",
@@ -53,15 +100,59 @@ pub async fn connect_to_ark_server(
     }
 }
 
+/// Connects to the Ark server and fetches its info.
+///
+/// `connect_timeout` bounds establishing the connection while `handshake_timeout`
+/// bounds the whole exchange, so a server that accepts but never answers can't stall us.
+pub(crate) async fn ark_handshake(
+    ark_server_url: &str,
+    network: Network,
+    connect_timeout: Duration,
+    handshake_timeout: Duration,
+) -> anyhow::Result<(ServerConnection, ArkInfo)> {
+    let handshake = async {
+        let mut connection = tokio::time::timeout(
+            connect_timeout,
+            ServerConnection::connect(ark_server_url, network),
+        )
+        .await
+        .map_err(|_| {
+            anyhow::anyhow!(
+                "Timed out connecting after {}s",
+                connect_timeout.as_secs_f64()
+            )
+        })?
+        .map_err(|e| anyhow::anyhow!("Failed to connect: {e:#}"))?;
+        let info = connection.client.get_ark_info(Empty {}).await?.into_inner();
+        anyhow::Ok((connection, info))
+    };
+
+    tokio::time::timeout(handshake_timeout, handshake)
+        .await
+        .map_err(|_| {
+            anyhow::anyhow!(
+                "Handshake timed out after {}s",
+                handshake_timeout.as_secs_f64()
+            )
+        })?
+}
+
 async fn establish_connection_and_process(
     app_state: &AppState,
     ark_server_url: &str,
+    status: &ArkConnectionStatus,
 ) -> anyhow::Result<()> {
     let network = app_state.config.network()?;
-    let connection = ServerConnection::connect(ark_server_url, network)
-        .await
-        .map_err(|e| anyhow::anyhow!("Failed to connect: {e:#}"))?;
-    let mut client = connection.client;
+    let (connection, info) = ark_handshake(
+        ark_server_url,
+        network,
+        Duration::from_secs(app_state.config.ark_connect_timeout_secs),
+        Duration::from_secs(app_state.config.ark_handshake_timeout_secs),
+    )
+    .await
+    .inspect_err(|e| status.set_handshake_error(e))?;
+    status.set_connected();
+    let client = connection.client;
 
     tracing::info!(
         service = "ark_client",
@@ -69,109 +160,167 @@ async fn establish_connection_and_process(
         "connected to ark server"
     );
 
-    let info = client.get_ark_info(Empty {}).await?.into_inner();
-
-    tracing::info!(
-        service = "ark_client",
-        event = "ark_info",
-        server_pubkey = %info.server_pubkey.to_lower_hex_string(),
-        "received ark server info"
-    );
-
-    let maintenance_interval_rounds = app_state.config.maintenance_interval_rounds;
+    // The round counter lives in Redis so the maintenance cadence survives restarts
+    let round_counter = app_state.maintenance_store.get_round_counter().await?;
 
     tracing::info!(
         service = "ark_client",
         event = "polling_started",
         poll_interval_secs = POLL_INTERVAL.as_secs(),
-        maintenance_interval_rounds = maintenance_interval_rounds,
+        maintenance_interval_rounds = app_state.config.maintenance_interval_rounds,
+        round_counter = round_counter,
         "polling for next round time"
     );
 
-    loop {
+    let connected = stream::once(async move {
+        Ok(ArkEvent::Connected {
+            server_pubkey: info.server_pubkey.to_lower_hex_string(),
+        })
+    });
+    let rounds = stream::unfold(client, |mut client| async move {
         tokio::time::sleep(POLL_INTERVAL).await;
+        let event = client
+            .next_round_time(Empty {})
+            .await
+            .map(|response| ArkEvent::NextRoundScheduled {
+                timestamp: response.into_inner().timestamp,
+            })
+            .map_err(anyhow::Error::from);
+        Some((event, client))
+    });
+
+    process_ark_events(app_state, connected.chain(rounds)).await
+}
 
-        let response = client.next_round_time(Empty {}).await?;
-        let next_round_ts = response.into_inner().timestamp;
-
-        let last_ts = app_state
-            .maintenance_store
-            .get_last_round_timestamp()
-            .await?;
-        let counter = app_state.maintenance_store.get_round_counter().await?;
-        let advance_secs = app_state.config.maintenance_notification_advance_secs;
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .map(|d| d.as_secs())
-            .unwrap_or(0);
+/// Events observed from the Ark server that other subsystems react to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArkEvent {
+    /// A connection was established and the server info fetched.
+    Connected { server_pubkey: String },
+    /// The server announced the start time of its next round.
+    NextRoundScheduled { timestamp: u64 },
+}
 
-        let action = evaluate_maintenance(
-            next_round_ts,
-            last_ts,
-            counter,
-            maintenance_interval_rounds,
-            advance_secs,
-            now,
-        );
+/// Dispatches Ark events until the stream ends or yields an error.
+///
+/// The stream ending means the connection is gone and the caller should reconnect.
+pub async fn process_ark_events<S>(app_state: &AppState, events: S) -> anyhow::Result<()>
+where
+    S: Stream<Item = anyhow::Result<ArkEvent>>,
+{
+    let mut events = std::pin::pin!(events);
+    while let Some(event) = events.next().await {
+        dispatch_ark_event(app_state, event?).await?;
+    }
+    Ok(())
+}
 
-        match action {
-            MaintenanceAction::NoChange => continue,
-            MaintenanceAction::RoundDetected => {
-                app_state
-                    .maintenance_store
-                    .set_last_round_timestamp(next_round_ts)
-                    .await?;
-                let counter = app_state
-                    .maintenance_store
-                    .increment_round_counter()
-                    .await?;
-                tracing::info!(
-                    service = "ark_client",
-                    event = "round_detected",
-                    next_round_ts = next_round_ts,
-                    counter = counter,
-                    "new round detected"
-                );
-            }
-            MaintenanceAction::TooClose => {
-                app_state
-                    .maintenance_store
-                    .set_last_round_timestamp(next_round_ts)
-                    .await?;
-                app_state
-                    .maintenance_store
-                    .increment_round_counter()
-                    .await?;
-                tracing::info!(
-                    service = "ark_client",
-                    event = "maintenance_skipped",
-                    next_round_ts = next_round_ts,
-                    advance_secs = advance_secs,
-                    "next round too close, skipping to next one"
-                );
-            }
-            MaintenanceAction::Send => {
-                app_state
-                    .maintenance_store
-                    .set_last_round_timestamp(next_round_ts)
-                    .await?;
-                tracing::info!(
-                    service = "ark_client",
-                    event = "maintenance_triggered",
-                    next_round_ts = next_round_ts,
-                    secs_until_round = next_round_ts.saturating_sub(now),
-                    "sending maintenance notification"
-                );
+/// Routes a single Ark event to the subsystem that handles it.
+///
+/// Returns the maintenance decision taken for round events.
+async fn dispatch_ark_event(
+    app_state: &AppState,
+    event: ArkEvent,
+) -> anyhow::Result<Option<MaintenanceAction>> {
+    match event {
+        ArkEvent::Connected { server_pubkey } => {
+            tracing::info!(
+                service = "ark_client",
+                event = "ark_info",
+                server_pubkey = %server_pubkey,
+                "received ark server info"
+            );
+            Ok(None)
+        }
+        ArkEvent::NextRoundScheduled { timestamp } => {
+            handle_next_round(app_state, timestamp).await.map(Some)
+        }
+    }
+}
 
-                let app_state_clone = app_state.clone();
-                tokio::spawn(async move {
-                    let _ = maintenance(app_state_clone).await;
-                });
+async fn handle_next_round(
+    app_state: &AppState,
+    next_round_ts: u64,
+) -> anyhow::Result<MaintenanceAction> {
+    let last_ts = app_state
+        .maintenance_store
+        .get_last_round_timestamp()
+        .await?;
+    let counter = app_state.maintenance_store.get_round_counter().await?;
+    let advance_secs = app_state.config.maintenance_notification_advance_secs;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let action = evaluate_maintenance(
+        next_round_ts,
+        last_ts,
+        counter,
+        app_state.config.maintenance_interval_rounds,
+        advance_secs,
+        now,
+    );
 
-                app_state.maintenance_store.reset_round_counter().await?;
-            }
+    match action {
+        MaintenanceAction::NoChange => {}
+        MaintenanceAction::RoundDetected => {
+            app_state
+                .maintenance_store
+                .set_last_round_timestamp(next_round_ts)
+                .await?;
+            let counter = app_state
+                .maintenance_store
+                .increment_round_counter()
+                .await?;
+            tracing::info!(
+                service = "ark_client",
+                event = "round_detected",
+                next_round_ts = next_round_ts,
+                counter = counter,
+                "new round detected"
+            );
+        }
+        MaintenanceAction::TooClose => {
+            app_state
+                .maintenance_store
+                .set_last_round_timestamp(next_round_ts)
+                .await?;
+            app_state
+                .maintenance_store
+                .increment_round_counter()
+                .await?;
+            tracing::info!(
+                service = "ark_client",
+                event = "maintenance_skipped",
+                next_round_ts = next_round_ts,
+                advance_secs = advance_secs,
+                "next round too close, skipping to next one"
+            );
+        }
+        MaintenanceAction::Send => {
+            app_state
+                .maintenance_store
+                .set_last_round_timestamp(next_round_ts)
+                .await?;
+            tracing::info!(
+                service = "ark_client",
+                event = "maintenance_triggered",
+                next_round_ts = next_round_ts,
+                secs_until_round = next_round_ts.saturating_sub(now),
+                "sending maintenance notification"
+            );
+
+            let app_state_clone = app_state.clone();
+            tokio::spawn(async move {
+                let _ = maintenance(app_state_clone).await;
+            });
+
+            app_state.maintenance_store.reset_round_counter().await?;
         }
     }
+
+    Ok(action)
 }
 
 #[derive(Debug, PartialEq)]
@@ -213,6 +362,15 @@ fn evaluate_maintenance(
 }
 
 pub async fn maintenance(app_state: AppState) -> anyhow::Result<()> {
+    if let Err(e) = broadcast_maintenance(app_state).await {
+        tracing::error!(service = "ark_client", job = "maintenance", error = %e, "notification failed");
+    }
+
+    Ok(())
+}
+
+/// Broadcasts the maintenance notification to all users, independent of the round counter.
+pub async fn broadcast_maintenance(app_state: AppState) -> anyhow::Result<DispatchSummary> {
     let coordinator = NotificationCoordinator::new(app_state);
 
     let request = NotificationRequest {
@@ -221,16 +379,21 @@ pub async fn maintenance(app_state: AppState) -> anyhow::Result<()> {
         target_pubkey: None, // Broadcast to all users
     };
 
-    if let Err(e) = coordinator.send_notification(request).await {
-        tracing::error!(service = "ark_client", job = "maintenance", error = %e, "notification failed");
+    match coordinator.send_notification(request).await? {
+        SendOutcome::Broadcast(summary) => Ok(summary),
+        outcome => anyhow::bail!(
+            "Unexpected outcome for maintenance broadcast: {:?}",
+            outcome
+        ),
     }
-
-    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::tests::common::{
+        TestUser, setup_public_test_app, setup_public_test_app_with_config,
+    };
 
     const INTERVAL: u16 = 10;
     const ADVANCE: u64 = 30;
@@ -328,4 +491,186 @@ mod tests {
         let action = evaluate_maintenance(2000, Some(1000), 15, INTERVAL, ADVANCE, 1500);
         assert_eq!(action, MaintenanceAction::Send);
     }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn process_ark_events_dispatches_mock_stream() {
+        let (_app, app_state, _guard) = setup_public_test_app().await;
+        app_state
+            .maintenance_store
+            .reset_round_counter()
+            .await
+            .unwrap();
+
+        let first_round = now() + 600;
+        let events = stream::iter(vec![
+            Ok(ArkEvent::Connected {
+                server_pubkey: "02ab".to_string(),
+            }),
+            Ok(ArkEvent::NextRoundScheduled {
+                timestamp: first_round,
+            }),
+            // Polling again before the round starts reports the same timestamp
+            Ok(ArkEvent::NextRoundScheduled {
+                timestamp: first_round,
+            }),
+            Ok(ArkEvent::NextRoundScheduled {
+                timestamp: first_round + 60,
+            }),
+        ]);
+
+        process_ark_events(&app_state, events).await.unwrap();
+
+        assert_eq!(
+            app_state
+                .maintenance_store
+                .get_last_round_timestamp()
+                .await
+                .unwrap(),
+            Some(first_round + 60)
+        );
+        assert_eq!(
+            app_state
+                .maintenance_store
+                .get_round_counter()
+                .await
+                .unwrap(),
+            2
+        );
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn process_ark_events_stops_on_stream_error() {
+        let (_app, app_state, _guard) = setup_public_test_app().await;
+
+        let events = stream::iter(vec![
+            Err(anyhow::anyhow!("connection reset")),
+            Ok(ArkEvent::NextRoundScheduled { timestamp: now() }),
+        ]);
+
+        assert!(process_ark_events(&app_state, events).await.is_err());
+        assert_eq!(
+            app_state
+                .maintenance_store
+                .get_last_round_timestamp()
+                .await
+                .unwrap(),
+            None
+        );
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn dispatch_connected_event_has_no_maintenance_action() {
+        let (_app, app_state, _guard) = setup_public_test_app().await;
+
+        let action = dispatch_ark_event(
+            &app_state,
+            ArkEvent::Connected {
+                server_pubkey: "02ab".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(action, None);
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn maintenance_fires_every_interval_rounds_across_reconnects() {
+        let mut config = TestUser::get_config();
+        config.maintenance_interval_rounds = 3;
+        let (_app, app_state, _guard) = setup_public_test_app_with_config(config).await;
+
+        let first_round = now() + 600;
+        let mut actions = Vec::new();
+        for round in 0..4 {
+            let event = ArkEvent::NextRoundScheduled {
+                timestamp: first_round + round * 60,
+            };
+            actions.push(dispatch_ark_event(&app_state, event).await.unwrap());
+        }
+
+        // A reconnect starts a fresh event stream but keeps the persisted round count
+        let reconnected = stream::iter((4..6).map(|round| {
+            Ok(ArkEvent::NextRoundScheduled {
+                timestamp: first_round + round * 60,
+            })
+        }));
+        process_ark_events(&app_state, reconnected).await.unwrap();
+
+        assert_eq!(
+            actions,
+            vec![
+                Some(MaintenanceAction::RoundDetected),
+                Some(MaintenanceAction::RoundDetected),
+                Some(MaintenanceAction::Send),
+                Some(MaintenanceAction::RoundDetected),
+            ]
+        );
+        // Rounds 5 and 6 completed the second interval, which reset the counter again
+        assert_eq!(
+            app_state
+                .maintenance_store
+                .get_round_counter()
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            app_state
+                .maintenance_store
+                .get_last_round_timestamp()
+                .await
+                .unwrap(),
+            Some(first_round + 5 * 60)
+        );
+    }
+
+    #[tokio::test]
+    async fn handshake_times_out_against_unresponsive_server() {
+        // Accepts TCP connections but never speaks gRPC
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                held.push(socket);
+            }
+        });
+
+        let started = std::time::Instant::now();
+        let result = ark_handshake(
+            &format!("http://{addr}"),
+            Network::Regtest,
+            Duration::from_millis(200),
+            Duration::from_millis(500),
+        )
+        .await;
+        server.abort();
+
+        let error = match result {
+            Ok(_) => panic!("handshake should not complete"),
+            Err(e) => format!("{e:#}"),
+        };
+        assert!(error.to_lowercase().contains("timed out"), "{error}");
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let status = ArkConnectionStatus::default();
+        status.set_handshake_error(&anyhow::anyhow!(error.clone()));
+        let state = status.snapshot();
+        assert!(!state.connected);
+        assert_eq!(state.last_handshake_error, Some(error));
+
+        status.set_connected();
+        assert!(status.snapshot().last_handshake_error.is_none());
+    }
 }
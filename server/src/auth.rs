@@ -11,6 +11,9 @@ pub struct AccessTokenClaims {
     pub sub: String,
     pub iat: i64,
     pub exp: i64,
+    /// Set when the token was minted from a k1 bound to an action, see `K1Binding`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -20,7 +23,12 @@ pub struct MintedAccessToken {
     pub expires_in_seconds: u64,
 }
 
-pub fn mint_access_token(config: &Config, pubkey: &str) -> anyhow::Result<MintedAccessToken> {
+/// Mints an access token that is only accepted on `action`'s route when an action is given.
+pub fn mint_access_token(
+    config: &Config,
+    pubkey: &str,
+    action: Option<&str>,
+) -> anyhow::Result<MintedAccessToken> {
     let issued_at = Utc::now();
     let expires_at = issued_at + Duration::hours(config.auth_jwt_ttl_hours as i64);
     let expires_in_seconds = (expires_at - issued_at).num_seconds() as u64;
@@ -29,6 +37,7 @@ pub fn mint_access_token(config: &Config, pubkey: &str) -> anyhow::Result<Minted
         sub: pubkey.to_string(),
         iat: issued_at.timestamp(),
         exp: expires_at.timestamp(),
+        action: action.map(str::to_string),
     };
 
     let token = encode(
@@ -61,5 +70,6 @@ pub fn verify_access_token(config: &Config, token: &str) -> Result<Authenticated
 
     Ok(AuthenticatedUser {
         key: token_data.claims.sub,
+        action: token_data.claims.action,
     })
 }
@@ -0,0 +1,275 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AppState,
+    db::backup_repo::{BackupMetadata, BackupRepository},
+    s3_client::S3BackupClient,
+};
+
+/// A `backup_metadata` row whose S3 object no longer exists.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct OrphanedBackupRow {
+    pub pubkey: String,
+    pub backup_version: i32,
+    pub s3_key: String,
+}
+
+/// Outcome of reconciling `backup_metadata` against the objects in the backup bucket.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub struct BackupIntegrityReport {
+    pub checked_rows: usize,
+    pub checked_objects: usize,
+    /// Rows pointing at objects that are missing from the bucket.
+    pub orphaned_rows: Vec<OrphanedBackupRow>,
+    /// Objects no row points at, such as uploads that were never completed.
+    pub orphaned_objects: Vec<String>,
+    /// Orphaned rows deleted because repair was requested.
+    pub repaired_rows: usize,
+}
+
+/// Compares backup metadata with the keys listed from S3.
+fn reconcile(metadata: &[BackupMetadata], object_keys: &[String]) -> BackupIntegrityReport {
+    let objects: HashSet<&str> = object_keys.iter().map(String::as_str).collect();
+    let referenced: HashSet<&str> = metadata.iter().map(|row| row.s3_key.as_str()).collect();
+
+    let orphaned_rows = metadata
+        .iter()
+        .filter(|row| !objects.contains(row.s3_key.as_str()))
+        .map(|row| OrphanedBackupRow {
+            pubkey: row.pubkey.clone(),
+            backup_version: row.backup_version,
+            s3_key: row.s3_key.clone(),
+        })
+        .collect();
+
+    let mut orphaned_objects: Vec<String> = object_keys
+        .iter()
+        .filter(|key| !referenced.contains(key.as_str()))
+        .cloned()
+        .collect();
+    orphaned_objects.sort();
+
+    BackupIntegrityReport {
+        checked_rows: metadata.len(),
+        checked_objects: object_keys.len(),
+        orphaned_rows,
+        orphaned_objects,
+        repaired_rows: 0,
+    }
+}
+
+/// Reconciles backup metadata with the bucket and reports orphans on both sides.
+///
+/// Only objects under `S3_KEY_PREFIX` are listed, so environments sharing the bucket don't see
+/// each other's objects as orphans.
+///
+/// With `repair`, rows whose object is gone are deleted. Orphaned objects are only reported,
+/// since an upload may still be waiting for its `complete_upload` call.
+pub async fn verify_backup_integrity(
+    app_state: &AppState,
+    repair: bool,
+) -> anyhow::Result<BackupIntegrityReport> {
+    let backup_repo = BackupRepository::new(&app_state.db_pool);
+    let s3_client = S3BackupClient::from_config(&app_state.config).await?;
+
+    // Read metadata before listing the bucket. Uploads store the object before its row, so a
+    // backup completed in between shows up as an orphaned object, which is only reported,
+    // rather than as an orphaned row that repair would delete
+    let (metadata, legacy_rows): (Vec<_>, Vec<_>) = backup_repo
+        .list_all_metadata()
+        .await?
+        .into_iter()
+        .partition(|row| row.s3_key.starts_with(s3_client.key_prefix()));
+    let object_keys = s3_client.list_keys().await?;
+
+    let mut report = reconcile(&metadata, &object_keys);
+
+    // Rows written before S3_KEY_PREFIX changed point outside the listed prefix
+    for row in legacy_rows {
+        report.checked_rows += 1;
+        if !s3_client.object_exists(&row.s3_key).await? {
+            report.orphaned_rows.push(OrphanedBackupRow {
+                pubkey: row.pubkey,
+                backup_version: row.backup_version,
+                s3_key: row.s3_key,
+            });
+        }
+    }
+
+    if repair {
+        for row in &report.orphaned_rows {
+            backup_repo
+                .delete_by_version(&row.pubkey, row.backup_version)
+                .await?;
+        }
+        report.repaired_rows = report.orphaned_rows.len();
+    }
+
+    tracing::info!(
+        checked_rows = report.checked_rows,
+        checked_objects = report.checked_objects,
+        orphaned_rows = report.orphaned_rows.len(),
+        orphaned_objects = report.orphaned_objects.len(),
+        repaired_rows = report.repaired_rows,
+        "Backup integrity check finished"
+    );
+
+    Ok(report)
+}
+
+/// Outcome of moving backups to the configured key layout.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub struct BackupRekeyReport {
+    pub checked_rows: usize,
+    pub rekeyed_rows: usize,
+    /// Rows left under their old key because the new key was taken or copying or deleting failed.
+    pub failed_rows: usize,
+}
+
+/// Moves every backup whose key doesn't match `S3_KEY_PREFIX` and `S3_HASH_PUBKEYS` to the
+/// key the current settings derive.
+///
+/// Meant to run once after changing either setting. Each object is copied, its row updated,
+/// and only then the old object deleted, so an interrupted run can be repeated. An object
+/// already at the new key is never overwritten, since it may be a newer upload, so a copy left
+/// behind by an interrupted run shows up as a failed row to clean up by hand.
+pub async fn rekey_backups(app_state: &AppState) -> anyhow::Result<BackupRekeyReport> {
+    let backup_repo = BackupRepository::new(&app_state.db_pool);
+    let s3_client = S3BackupClient::from_config(&app_state.config).await?;
+
+    let metadata = backup_repo.list_all_metadata().await?;
+    let mut report = BackupRekeyReport {
+        checked_rows: metadata.len(),
+        ..Default::default()
+    };
+
+    for row in metadata {
+        let new_key = s3_client.backup_key(&row.pubkey, row.backup_version);
+        if row.s3_key == new_key {
+            continue;
+        }
+
+        // A newer upload may have replaced the row since it was listed
+        let current_key = backup_repo
+            .find_s3_key_by_version(&row.pubkey, row.backup_version)
+            .await?;
+        if current_key.as_deref() != Some(row.s3_key.as_str()) {
+            continue;
+        }
+
+        if s3_client.object_exists(&new_key).await? {
+            tracing::warn!(
+                pubkey = %row.pubkey,
+                backup_version = row.backup_version,
+                "Not rekeying backup, an object already exists under its new key"
+            );
+            report.failed_rows += 1;
+            continue;
+        }
+
+        if let Err(e) = s3_client.copy_object(&row.s3_key, &new_key).await {
+            tracing::warn!(
+                pubkey = %row.pubkey,
+                backup_version = row.backup_version,
+                "Failed to copy backup to its new key: {}",
+                e
+            );
+            report.failed_rows += 1;
+            continue;
+        }
+
+        if !backup_repo
+            .update_s3_key(&row.pubkey, row.backup_version, &row.s3_key, &new_key)
+            .await?
+        {
+            // A newer upload replaced the row while copying. Drop the copy, unless that upload
+            // was stored under the new key itself.
+            let current_key = backup_repo
+                .find_s3_key_by_version(&row.pubkey, row.backup_version)
+                .await?;
+            if current_key.as_deref() != Some(new_key.as_str())
+                && let Err(e) = s3_client.delete_object(&new_key).await
+            {
+                tracing::warn!(
+                    pubkey = %row.pubkey,
+                    backup_version = row.backup_version,
+                    "Failed to delete unused backup copy: {}",
+                    e
+                );
+            }
+            continue;
+        }
+
+        if let Err(e) = s3_client.delete_object(&row.s3_key).await {
+            tracing::warn!(
+                pubkey = %row.pubkey,
+                backup_version = row.backup_version,
+                "Failed to delete backup under its old key: {}",
+                e
+            );
+            report.failed_rows += 1;
+            continue;
+        }
+        report.rekeyed_rows += 1;
+    }
+
+    tracing::info!(
+        checked_rows = report.checked_rows,
+        rekeyed_rows = report.rekeyed_rows,
+        failed_rows = report.failed_rows,
+        "Backup rekey finished"
+    );
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(pubkey: &str, backup_version: i32) -> BackupMetadata {
+        BackupMetadata {
+            pubkey: pubkey.to_string(),
+            s3_key: format!("{}/backup_v{}.db", pubkey, backup_version),
+            backup_size: 1024,
+            backup_version,
+        }
+    }
+
+    #[test]
+    fn reconcile_reports_orphans_on_both_sides() {
+        let metadata = vec![row("alice", 1), row("alice", 2), row("bob", 1)];
+        let object_keys = vec![
+            "alice/backup_v1.db".to_string(),
+            "bob/backup_v1.db".to_string(),
+            "carol/backup_v1.db".to_string(),
+        ];
+
+        let report = reconcile(&metadata, &object_keys);
+
+        assert_eq!(report.checked_rows, 3);
+        assert_eq!(report.checked_objects, 3);
+        assert_eq!(
+            report.orphaned_rows,
+            vec![OrphanedBackupRow {
+                pubkey: "alice".to_string(),
+                backup_version: 2,
+                s3_key: "alice/backup_v2.db".to_string(),
+            }]
+        );
+        assert_eq!(report.orphaned_objects, vec!["carol/backup_v1.db"]);
+    }
+
+    #[test]
+    fn reconcile_is_clean_when_everything_matches() {
+        let metadata = vec![row("alice", 1)];
+        let object_keys = vec!["alice/backup_v1.db".to_string()];
+
+        let report = reconcile(&metadata, &object_keys);
+
+        assert!(report.orphaned_rows.is_empty());
+        assert!(report.orphaned_objects.is_empty());
+    }
+}
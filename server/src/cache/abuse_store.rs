@@ -0,0 +1,82 @@
+use deadpool_redis::redis::{AsyncCommands, cmd};
+
+use super::redis_client::RedisClient;
+use crate::config::AbuseSettings;
+
+const ABUSE_SCORE_PREFIX: &str = "abuse_score:";
+const ABUSE_BAN_PREFIX: &str = "abuse_ban:";
+const ABUSE_REPORT_PREFIX: &str = "abuse_report:";
+
+/// Tracks suspicious activity per subject (a pubkey or an IP) and temporary blocks in Redis.
+///
+/// Scores expire `window_secs` after the first event, so only bursts of suspicious
+/// activity lead to a block. Reaching the threshold resets the score and starts a block.
+#[derive(Clone)]
+pub struct AbuseStore {
+    client: RedisClient,
+}
+
+impl AbuseStore {
+    pub fn new(client: RedisClient) -> Self {
+        Self { client }
+    }
+
+    /// Adds `points` to the subject's score.
+    ///
+    /// Returns the block duration in seconds when this pushes the score to the threshold.
+    pub async fn add_score(
+        &self,
+        subject: &str,
+        points: u64,
+        settings: &AbuseSettings,
+    ) -> anyhow::Result<Option<u64>> {
+        let score_key = format!("{}{}", ABUSE_SCORE_PREFIX, subject);
+        let mut conn = self.client.get_connection().await?;
+
+        let score: u64 = conn.incr(&score_key, points).await?;
+        if score == points {
+            let _: () = conn.expire(&score_key, settings.window_secs as i64).await?;
+        }
+        if score < settings.threshold {
+            return Ok(None);
+        }
+
+        let ban_key = format!("{}{}", ABUSE_BAN_PREFIX, subject);
+        let _: () = conn.set_ex(&ban_key, score, settings.ban_secs).await?;
+        let _: () = conn.del(&score_key).await?;
+        Ok(Some(settings.ban_secs))
+    }
+
+    /// Starts a reporting window for the reporter, unless one is still open.
+    ///
+    /// Returns the seconds left in the open window when the reporter has to wait.
+    pub async fn claim_report_window(
+        &self,
+        reporter: &str,
+        window_secs: u64,
+    ) -> anyhow::Result<Option<u64>> {
+        let key = format!("{}{}", ABUSE_REPORT_PREFIX, reporter);
+        let mut conn = self.client.get_connection().await?;
+        let claimed: Option<String> = cmd("SET")
+            .arg(&key)
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(window_secs)
+            .query_async(&mut conn)
+            .await?;
+        if claimed.is_some() {
+            return Ok(None);
+        }
+        let ttl: i64 = conn.ttl(&key).await?;
+        Ok(Some(ttl.max(1) as u64))
+    }
+
+    /// Returns the remaining block time in seconds if the subject is currently blocked.
+    pub async fn blocked_for(&self, subject: &str) -> anyhow::Result<Option<u64>> {
+        let ban_key = format!("{}{}", ABUSE_BAN_PREFIX, subject);
+        let mut conn = self.client.get_connection().await?;
+        let ttl: i64 = conn.ttl(&ban_key).await?;
+        Ok((ttl > 0).then_some(ttl as u64))
+    }
+}
@@ -0,0 +1,66 @@
+use deadpool_redis::redis::cmd;
+use futures_util::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use super::redis_client::RedisClient;
+
+const ADMIN_COMMANDS_CHANNEL: &str = "admin_commands";
+
+/// An operator command that every server instance executes, not just the one that received it.
+///
+/// Only state that lives inside one process belongs here. The other commands asked for with
+/// the bus are deliberately not on it:
+/// - broadcasts already reach every instance's users, they run as a leased broadcast job that
+///   `POST /admin/trigger_maintenance` starts on one instance
+/// - config is read once at startup, reloading it means rolling the deployment
+/// - all caches are in the shared Redis, so there is nothing per instance to flush
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum AdminCommand {
+    /// Re-run the S3 bucket check and update the health reported by `/health`.
+    RecheckS3,
+}
+
+/// Fans admin commands out to all instances over Redis pub/sub.
+#[derive(Clone)]
+pub struct AdminCommandBus {
+    client: RedisClient,
+}
+
+impl AdminCommandBus {
+    pub fn new(client: RedisClient) -> Self {
+        Self { client }
+    }
+
+    /// Publishes a command, returning how many instances were subscribed to receive it.
+    pub async fn publish(&self, command: &AdminCommand) -> anyhow::Result<usize> {
+        let payload = serde_json::to_string(command)?;
+        let mut conn = self.client.get_connection().await?;
+        let receivers: usize = cmd("PUBLISH")
+            .arg(ADMIN_COMMANDS_CHANNEL)
+            .arg(payload)
+            .query_async(&mut conn)
+            .await?;
+        Ok(receivers)
+    }
+
+    /// Subscribes to published commands. The stream ends when the connection drops.
+    ///
+    /// Commands this instance doesn't know, e.g. from a newer instance during a rolling deploy,
+    /// are skipped.
+    pub async fn subscribe(&self) -> anyhow::Result<impl Stream<Item = AdminCommand> + use<>> {
+        let mut pubsub = self.client.get_pubsub().await?;
+        pubsub.subscribe(ADMIN_COMMANDS_CHANNEL).await?;
+
+        Ok(pubsub.into_on_message().filter_map(|msg| async move {
+            let payload: String = msg.get_payload().ok()?;
+            match serde_json::from_str(&payload) {
+                Ok(command) => Some(command),
+                Err(e) => {
+                    tracing::warn!(payload = %payload, "Skipping unknown admin command: {}", e);
+                    None
+                }
+            }
+        }))
+    }
+}
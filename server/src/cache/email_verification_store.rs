@@ -1,31 +1,51 @@
-use deadpool_redis::redis::AsyncCommands;
+use deadpool_redis::redis::{AsyncCommands, cmd};
 use rand::Rng;
 
 use super::redis_client::RedisClient;
 
 const EMAIL_VERIFICATION_PREFIX: &str = "email_verification:";
-const EMAIL_VERIFICATION_TTL_SECONDS: u64 = 600; // 10 minutes
+// Pubkeys with a pending verification, scored by when their code expires
+const EMAIL_VERIFICATION_PENDING_KEY: &str = "email_verification_pending";
 const TEST_VERIFICATION_CODE: &str = "000000";
+const EMAIL_SEND_PREFIX: &str = "email_verification_send:";
+const EMAIL_RESEND_COOLDOWN_SECONDS: u64 = 60;
+const EMAIL_SEND_WINDOW_SECONDS: u64 = 3600; // 1 hour
+const MAX_SENDS_PER_PUBKEY: i64 = 5;
+const MAX_SENDS_PER_EMAIL: i64 = 3;
+const EMAIL_AVAILABILITY_PREFIX: &str = "email_availability:";
+const EMAIL_AVAILABILITY_WINDOW_SECONDS: u64 = 3600; // 1 hour
+const MAX_AVAILABILITY_CHECKS_PER_PUBKEY: i64 = 10;
 
+/// Pending email verifications in Redis.
+///
+/// Each pending code and the email it verifies expire together after `code_ttl_secs`, so an
+/// abandoned verification never lingers.
 #[derive(Clone)]
 pub struct EmailVerificationStore {
     client: RedisClient,
+    code_ttl_secs: u64,
 }
 
 impl EmailVerificationStore {
-    pub fn new(client: RedisClient) -> Self {
-        Self { client }
+    pub fn new(client: RedisClient, code_ttl_secs: u64) -> Self {
+        Self {
+            client,
+            code_ttl_secs,
+        }
     }
 
+    /// Starts a verification of `email`, replacing any pending one for `pubkey`.
     pub async fn store(&self, pubkey: &str, email: &str, code: &str) -> anyhow::Result<()> {
         let key = format!("{}{}:code", EMAIL_VERIFICATION_PREFIX, pubkey);
         let email_key = format!("{}{}:email", EMAIL_VERIFICATION_PREFIX, pubkey);
+        let expires_at = chrono::Utc::now().timestamp() + self.code_ttl_secs as i64;
         let mut conn = self.client.get_connection().await?;
-        let _: () = conn
-            .set_ex(&key, code, EMAIL_VERIFICATION_TTL_SECONDS)
-            .await?;
-        let _: () = conn
-            .set_ex(&email_key, email, EMAIL_VERIFICATION_TTL_SECONDS)
+        let _: () = deadpool_redis::redis::pipe()
+            .atomic()
+            .set_ex(&key, code, self.code_ttl_secs)
+            .set_ex(&email_key, email, self.code_ttl_secs)
+            .zadd(EMAIL_VERIFICATION_PENDING_KEY, pubkey, expires_at)
+            .query_async(&mut conn)
             .await?;
         Ok(())
     }
@@ -54,7 +74,7 @@ impl EmailVerificationStore {
         if dev_mode && code == TEST_VERIFICATION_CODE {
             let email = self.get_email(pubkey).await?;
             if email.is_some() {
-                self.remove(pubkey).await?;
+                self.clear(pubkey).await?;
                 return Ok(email);
             }
         }
@@ -63,22 +83,109 @@ impl EmailVerificationStore {
         match stored_code {
             Some(stored) if stored == code => {
                 let email = self.get_email(pubkey).await?;
-                self.remove(pubkey).await?;
+                self.clear(pubkey).await?;
                 Ok(email)
             }
             _ => Ok(None),
         }
     }
 
-    pub async fn remove(&self, pubkey: &str) -> anyhow::Result<()> {
+    /// Drops the pending verification for `pubkey`, if any.
+    ///
+    /// Called once a code is used, when the user goes back to their current email, and when
+    /// the account is removed.
+    pub async fn clear(&self, pubkey: &str) -> anyhow::Result<()> {
         let code_key = format!("{}{}:code", EMAIL_VERIFICATION_PREFIX, pubkey);
         let email_key = format!("{}{}:email", EMAIL_VERIFICATION_PREFIX, pubkey);
         let mut conn = self.client.get_connection().await?;
-        let _: () = conn.del(&code_key).await?;
-        let _: () = conn.del(&email_key).await?;
+        let _: () = deadpool_redis::redis::pipe()
+            .atomic()
+            .del(&code_key)
+            .del(&email_key)
+            .zrem(EMAIL_VERIFICATION_PENDING_KEY, pubkey)
+            .query_async(&mut conn)
+            .await?;
         Ok(())
     }
 
+    /// Counts verifications whose code hasn't been used or expired yet.
+    pub async fn pending_count(&self) -> anyhow::Result<u64> {
+        let now = chrono::Utc::now().timestamp();
+        let mut conn = self.client.get_connection().await?;
+        let _: () = conn
+            .zrembyscore(EMAIL_VERIFICATION_PENDING_KEY, "-inf", now)
+            .await?;
+        let pending: u64 = conn.zcard(EMAIL_VERIFICATION_PENDING_KEY).await?;
+        Ok(pending)
+    }
+
+    /// Records a verification email send for `pubkey` to `email`.
+    ///
+    /// Returns the number of seconds to wait when the pubkey is still in its resend cooldown
+    /// or either the pubkey or the target address has used up its hourly budget. The address
+    /// is tracked separately so rotating pubkeys can't be used to flood a single inbox.
+    pub async fn throttle_send(&self, pubkey: &str, email: &str) -> anyhow::Result<Option<u64>> {
+        let mut conn = self.client.get_connection().await?;
+
+        let cooldown_key = format!("{}cooldown:{}", EMAIL_SEND_PREFIX, pubkey);
+        let cooldown_started: Option<String> = cmd("SET")
+            .arg(&cooldown_key)
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(EMAIL_RESEND_COOLDOWN_SECONDS)
+            .query_async(&mut conn)
+            .await?;
+        if cooldown_started.is_none() {
+            let ttl: i64 = conn.ttl(&cooldown_key).await?;
+            return Ok(Some(ttl.max(1) as u64));
+        }
+
+        let counters = [
+            (
+                format!("{}pubkey:{}", EMAIL_SEND_PREFIX, pubkey),
+                MAX_SENDS_PER_PUBKEY,
+            ),
+            (
+                format!("{}email:{}", EMAIL_SEND_PREFIX, email.trim().to_lowercase()),
+                MAX_SENDS_PER_EMAIL,
+            ),
+        ];
+        for (key, max_sends) in counters {
+            let sends: i64 = conn.incr(&key, 1).await?;
+            if sends == 1 {
+                let _: () = conn.expire(&key, EMAIL_SEND_WINDOW_SECONDS as i64).await?;
+            }
+            if sends > max_sends {
+                let ttl: i64 = conn.ttl(&key).await?;
+                return Ok(Some(ttl.max(1) as u64));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Records an email availability check for `pubkey`.
+    ///
+    /// Returns the number of seconds to wait once the pubkey has used up its hourly budget.
+    /// The budget is per account rather than per IP, so rotating addresses doesn't help
+    /// enumerate which emails are registered.
+    pub async fn throttle_availability(&self, pubkey: &str) -> anyhow::Result<Option<u64>> {
+        let mut conn = self.client.get_connection().await?;
+        let key = format!("{}{}", EMAIL_AVAILABILITY_PREFIX, pubkey);
+        let checks: i64 = conn.incr(&key, 1).await?;
+        if checks == 1 {
+            let _: () = conn
+                .expire(&key, EMAIL_AVAILABILITY_WINDOW_SECONDS as i64)
+                .await?;
+        }
+        if checks > MAX_AVAILABILITY_CHECKS_PER_PUBKEY {
+            let ttl: i64 = conn.ttl(&key).await?;
+            return Ok(Some(ttl.max(1) as u64));
+        }
+        Ok(None)
+    }
+
     pub fn generate_code() -> String {
         let code: u32 = rand::rng().random_range(100000..1000000);
         code.to_string()
@@ -1,9 +1,26 @@
-use deadpool_redis::redis::AsyncCommands;
+use chrono::NaiveDate;
+use deadpool_redis::redis::{AsyncCommands, cmd};
 
 use super::redis_client::RedisClient;
 
 const INVOICE_PREFIX: &str = "invoice:";
 const INVOICE_TTL_SECONDS: u64 = 60;
+const PENDING_PREFIX: &str = "invoice_pending:";
+const DESCRIPTION_HASH_PREFIX: &str = "invoice_description_hash:";
+const DAILY_REQUESTS_PREFIX: &str = "invoice_requests:";
+// Outlives the UTC day the counter belongs to
+const DAILY_REQUESTS_TTL_SECONDS: i64 = 2 * 24 * 60 * 60;
+const REPORTED_CAP_PREFIX: &str = "invoice_requests_reported:";
+const PUSH_SENT: &str = "push_sent";
+
+/// How far a pre-registered invoice request has progressed before the invoice arrives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PendingInvoice {
+    /// The request is registered but the recipient hasn't been notified yet.
+    Requested,
+    /// The push notification asking for an invoice went out to the recipient's device.
+    PushSent,
+}
 
 #[derive(Clone)]
 pub struct InvoiceStore {
@@ -29,10 +46,100 @@ impl InvoiceStore {
         Ok(invoice)
     }
 
+    /// Remembers that `transaction_id` awaits an invoice, so pollers can tell it from an
+    /// unknown or expired one.
+    pub async fn mark_pending(&self, transaction_id: &str, ttl_seconds: u64) -> anyhow::Result<()> {
+        let key = format!("{}{}", PENDING_PREFIX, transaction_id);
+        let mut conn = self.client.get_connection().await?;
+        let _: () = conn.set_ex(&key, "requested", ttl_seconds).await?;
+        Ok(())
+    }
+
+    /// Records that the recipient was notified. Does nothing unless `transaction_id` is pending,
+    /// and keeps the pending request's expiry.
+    pub async fn mark_push_sent(&self, transaction_id: &str) -> anyhow::Result<()> {
+        let key = format!("{}{}", PENDING_PREFIX, transaction_id);
+        let mut conn = self.client.get_connection().await?;
+        let _: Option<String> = cmd("SET")
+            .arg(&key)
+            .arg(PUSH_SENT)
+            .arg("XX")
+            .arg("KEEPTTL")
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Returns the progress of a pending request, or `None` if it's unknown or expired.
+    pub async fn pending(&self, transaction_id: &str) -> anyhow::Result<Option<PendingInvoice>> {
+        let key = format!("{}{}", PENDING_PREFIX, transaction_id);
+        let mut conn = self.client.get_connection().await?;
+        let state: Option<String> = conn.get(&key).await?;
+        Ok(state.map(|state| {
+            if state == PUSH_SENT {
+                PendingInvoice::PushSent
+            } else {
+                PendingInvoice::Requested
+            }
+        }))
+    }
+
+    /// Remembers the description hash the invoice for `transaction_id` has to commit to.
+    pub async fn expect_description_hash(
+        &self,
+        transaction_id: &str,
+        description_hash: &str,
+        ttl_seconds: u64,
+    ) -> anyhow::Result<()> {
+        let key = format!("{}{}", DESCRIPTION_HASH_PREFIX, transaction_id);
+        let mut conn = self.client.get_connection().await?;
+        let _: () = conn.set_ex(&key, description_hash, ttl_seconds).await?;
+        Ok(())
+    }
+
+    /// Returns the description hash recorded for `transaction_id`, if any.
+    pub async fn expected_description_hash(
+        &self,
+        transaction_id: &str,
+    ) -> anyhow::Result<Option<String>> {
+        let key = format!("{}{}", DESCRIPTION_HASH_PREFIX, transaction_id);
+        let mut conn = self.client.get_connection().await?;
+        let description_hash: Option<String> = conn.get(&key).await?;
+        Ok(description_hash)
+    }
+
+    /// Counts an invoice request for `pubkey` and returns how many it received on `day`.
+    pub async fn record_daily_request(&self, pubkey: &str, day: NaiveDate) -> anyhow::Result<u64> {
+        let key = format!("{}{}:{}", DAILY_REQUESTS_PREFIX, pubkey, day);
+        let mut conn = self.client.get_connection().await?;
+        let count: u64 = conn.incr(&key, 1).await?;
+        if count == 1 {
+            let _: () = conn.expire(&key, DAILY_REQUESTS_TTL_SECONDS).await?;
+        }
+        Ok(count)
+    }
+
+    /// Applies the reduced daily cap to `pubkey` for the next `ttl_seconds` after an abuse report.
+    pub async fn tighten_daily_cap(&self, pubkey: &str, ttl_seconds: u64) -> anyhow::Result<()> {
+        let key = format!("{}{}", REPORTED_CAP_PREFIX, pubkey);
+        let mut conn = self.client.get_connection().await?;
+        let _: () = conn.set_ex(&key, 1, ttl_seconds).await?;
+        Ok(())
+    }
+
+    pub async fn is_daily_cap_tightened(&self, pubkey: &str) -> anyhow::Result<bool> {
+        let key = format!("{}{}", REPORTED_CAP_PREFIX, pubkey);
+        let mut conn = self.client.get_connection().await?;
+        let tightened: bool = conn.exists(&key).await?;
+        Ok(tightened)
+    }
+
     pub async fn remove(&self, transaction_id: &str) -> anyhow::Result<()> {
         let key = format!("{}{}", INVOICE_PREFIX, transaction_id);
+        let pending_key = format!("{}{}", PENDING_PREFIX, transaction_id);
+        let description_hash_key = format!("{}{}", DESCRIPTION_HASH_PREFIX, transaction_id);
         let mut conn = self.client.get_connection().await?;
-        let _: () = conn.del(&key).await?;
+        let _: () = conn.del(&[key, pending_key, description_hash_key]).await?;
         Ok(())
     }
 }
@@ -1,3 +1,5 @@
+use std::fmt;
+use std::str::FromStr;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use deadpool_redis::redis::{AsyncCommands, cmd};
@@ -5,6 +7,137 @@ use rand::RngCore;
 
 use super::redis_client::RedisClient;
 
+const POW_CHALLENGE_PREFIX: &str = "k1_pow_challenge:";
+const POW_CHALLENGE_TTL_SECONDS: u64 = 120;
+const K1_NONCE_BYTES: usize = 32;
+const K1_ACTION_PREFIX: &str = "action:";
+const MAX_K1_ACTION_LENGTH: usize = 64;
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum K1ParseError {
+    #[error("k1 is missing the timestamp suffix")]
+    MissingTimestamp,
+    #[error("k1 nonce must be {} lowercase hex characters", K1_NONCE_BYTES * 2)]
+    InvalidNonce,
+    #[error("k1 timestamp must be a positive unix timestamp")]
+    InvalidTimestamp,
+}
+
+/// A login challenge in the `"{hex}_{timestamp}"` format handed out by `get_k1`.
+///
+/// The nonce is 32 random bytes and the timestamp is the issuance time in unix seconds.
+/// `Display` produces the exact string stored in Redis and signed by the client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct K1 {
+    nonce: [u8; K1_NONCE_BYTES],
+    timestamp: u64,
+}
+
+impl K1 {
+    pub fn new(nonce: [u8; K1_NONCE_BYTES], timestamp: u64) -> Self {
+        Self { nonce, timestamp }
+    }
+
+    /// Creates a k1 with a random nonce, stamped with the current time.
+    pub fn generate() -> Self {
+        let mut nonce = [0u8; K1_NONCE_BYTES];
+        rand::rng().fill_bytes(&mut nonce);
+        Self::new(nonce, current_timestamp())
+    }
+
+    pub fn parse(s: &str) -> Result<Self, K1ParseError> {
+        let (nonce_hex, timestamp) = s.split_once('_').ok_or(K1ParseError::MissingTimestamp)?;
+
+        // Only accept the canonical lowercase form so the parsed k1 maps back to the stored key
+        if nonce_hex.len() != K1_NONCE_BYTES * 2
+            || !nonce_hex
+                .bytes()
+                .all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+        {
+            return Err(K1ParseError::InvalidNonce);
+        }
+        let mut nonce = [0u8; K1_NONCE_BYTES];
+        hex::decode_to_slice(nonce_hex, &mut nonce).map_err(|_| K1ParseError::InvalidNonce)?;
+
+        if timestamp.is_empty() || !timestamp.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(K1ParseError::InvalidTimestamp);
+        }
+        let timestamp = timestamp
+            .parse::<u64>()
+            .ok()
+            .filter(|timestamp| *timestamp > 0)
+            .ok_or(K1ParseError::InvalidTimestamp)?;
+
+        Ok(Self::new(nonce, timestamp))
+    }
+
+    /// Unix timestamp, in seconds, at which the k1 was issued.
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// Whether the k1 is older than `ttl_secs` or dated in the future, as seen at `now`.
+    ///
+    /// `skew_secs` of clock drift is tolerated in both directions so that instances with
+    /// slightly different clocks agree on which k1s are still valid.
+    pub fn is_expired(&self, now: u64, ttl_secs: u64, skew_secs: u64) -> bool {
+        let too_old = now.saturating_sub(self.timestamp) > ttl_secs.saturating_add(skew_secs);
+        let from_future = self.timestamp > now.saturating_add(skew_secs);
+        too_old || from_future
+    }
+}
+
+impl fmt::Display for K1 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}_{}", hex::encode(self.nonce), self.timestamp)
+    }
+}
+
+impl FromStr for K1 {
+    type Err = K1ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+/// What a k1 may be used for, recorded when it was issued.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum K1Binding {
+    /// Usable for any action, the default.
+    Unbound,
+    /// Only usable for the named action, the gated route path without its leading slash.
+    Action(String),
+}
+
+impl K1Binding {
+    /// Reads the binding from the value stored under the k1. Unbound k1s store their timestamp.
+    fn from_stored(value: &str) -> Self {
+        match value.strip_prefix(K1_ACTION_PREFIX) {
+            Some(action) => Self::Action(action.to_string()),
+            None => Self::Unbound,
+        }
+    }
+
+    pub fn action(&self) -> Option<&str> {
+        match self {
+            Self::Unbound => None,
+            Self::Action(action) => Some(action),
+        }
+    }
+}
+
+/// Whether `action` looks like a route path a k1 can be bound to, such as `register` or
+/// `backup/upload_url`.
+pub fn is_valid_k1_action(action: &str) -> bool {
+    !action.is_empty()
+        && action.len() <= MAX_K1_ACTION_LENGTH
+        && !action.starts_with('/')
+        && action
+            .bytes()
+            .all(|b| matches!(b, b'a'..=b'z' | b'0'..=b'9' | b'_' | b'/'))
+}
+
 /// Handles issuing and validating k1 challenges in Redis.
 #[derive(Clone)]
 pub struct K1Store {
@@ -20,62 +153,191 @@ impl K1Store {
         }
     }
 
-    /// Generates, stores, and returns a fresh k1 token.
-    pub async fn issue_k1(&self) -> anyhow::Result<String> {
-        let mut k1_bytes = [0u8; 32];
-        rand::rng().fill_bytes(&mut k1_bytes);
+    /// How long an issued k1 stays valid, in seconds.
+    pub fn ttl_seconds(&self) -> u64 {
+        u64::try_from(self.ttl_seconds).unwrap_or(u64::MAX)
+    }
 
-        let timestamp = current_timestamp();
-        let k1_with_timestamp = format!("{}_{}", hex::encode(k1_bytes), timestamp);
+    /// Generates, stores, and returns a fresh k1 token.
+    pub async fn issue_k1(&self) -> anyhow::Result<K1> {
+        self.issue_k1_for_action(None).await
+    }
 
-        self.persist(&k1_with_timestamp, timestamp).await?;
-        Ok(k1_with_timestamp)
+    /// Generates, stores, and returns a fresh k1 token, bound to `action` when one is given.
+    pub async fn issue_k1_for_action(&self, action: Option<&str>) -> anyhow::Result<K1> {
+        let k1 = K1::generate();
+        self.store(&k1, action).await?;
+        Ok(k1)
     }
 
-    /// Checks whether the provided k1 exists in the cache.
-    pub async fn contains(&self, k1: &str) -> anyhow::Result<bool> {
+    /// Checks whether the provided key exists in the cache.
+    pub async fn contains(&self, key: &str) -> anyhow::Result<bool> {
         let mut conn = self.client.get_connection().await?;
-        let exists: bool = conn.exists(k1).await?;
+        let exists: bool = conn.exists(key).await?;
         Ok(exists)
     }
 
     /// Removes a k1 token from the cache.
-    pub async fn remove(&self, k1: &str) -> anyhow::Result<()> {
+    pub async fn remove(&self, k1: &K1) -> anyhow::Result<()> {
         let mut conn = self.client.get_connection().await?;
-        let _: () = conn.del(k1).await?;
+        let _: () = conn.del(k1.to_string()).await?;
         Ok(())
     }
 
     /// Atomically consumes a k1 token so it cannot be reused.
-    pub async fn take(&self, k1: &str) -> anyhow::Result<bool> {
+    ///
+    /// Returns the k1's binding, or `None` when it was never issued or is already used.
+    pub async fn take(&self, k1: &K1) -> anyhow::Result<Option<K1Binding>> {
         let mut conn = self.client.get_connection().await?;
-        let value: Option<i64> = cmd("GETDEL").arg(k1).query_async(&mut conn).await?;
+        let value: Option<String> = cmd("GETDEL")
+            .arg(k1.to_string())
+            .query_async(&mut conn)
+            .await?;
+        Ok(value.as_deref().map(K1Binding::from_stored))
+    }
+
+    /// Generates, stores, and returns a fresh proof-of-work challenge for `get_k1`.
+    pub async fn issue_pow_challenge(&self) -> anyhow::Result<String> {
+        let mut challenge_bytes = [0u8; 32];
+        rand::rng().fill_bytes(&mut challenge_bytes);
+        let challenge = hex::encode(challenge_bytes);
+
+        let key = format!("{}{}", POW_CHALLENGE_PREFIX, challenge);
+        let mut conn = self.client.get_connection().await?;
+        let _: () = conn.set_ex(&key, 1, POW_CHALLENGE_TTL_SECONDS).await?;
+        Ok(challenge)
+    }
+
+    /// Atomically consumes a proof-of-work challenge so a solution can only be used once.
+    pub async fn take_pow_challenge(&self, challenge: &str) -> anyhow::Result<bool> {
+        let key = format!("{}{}", POW_CHALLENGE_PREFIX, challenge);
+        let mut conn = self.client.get_connection().await?;
+        let value: Option<i64> = cmd("GETDEL").arg(&key).query_async(&mut conn).await?;
         Ok(value.is_some())
     }
 
-    /// Inserts an externally created k1 string. Useful for tests.
-    pub async fn insert_with_timestamp(&self, k1: &str, timestamp: u64) -> anyhow::Result<()> {
-        self.persist(k1, timestamp).await
+    /// Stores an externally created k1, keyed by its string form. Useful for tests.
+    pub async fn insert(&self, k1: &K1) -> anyhow::Result<()> {
+        self.store(k1, None).await
     }
 
-    /// Clears all cached values. Only intended for tests.
-    pub async fn clear_all(&self) -> anyhow::Result<()> {
+    async fn store(&self, k1: &K1, action: Option<&str>) -> anyhow::Result<()> {
+        let value = match action {
+            Some(action) => format!("{}{}", K1_ACTION_PREFIX, action),
+            None => k1.timestamp().to_string(),
+        };
         let mut conn = self.client.get_connection().await?;
-        let _: () = cmd("FLUSHDB").query_async(&mut conn).await?;
+        let _: () = conn
+            .set_ex(k1.to_string(), value, self.ttl_seconds())
+            .await?;
         Ok(())
     }
 
-    async fn persist(&self, k1: &str, timestamp: u64) -> anyhow::Result<()> {
+    /// Clears all cached values. Only intended for tests.
+    pub async fn clear_all(&self) -> anyhow::Result<()> {
         let mut conn = self.client.get_connection().await?;
-        let ttl_seconds = u64::try_from(self.ttl_seconds).unwrap_or(u64::MAX);
-        let _: () = conn.set_ex(k1, timestamp as i64, ttl_seconds).await?;
+        let _: () = cmd("FLUSHDB").query_async(&mut conn).await?;
         Ok(())
     }
 }
 
-fn current_timestamp() -> u64 {
+/// Current unix time in seconds, the clock k1 timestamps are issued and checked against.
+pub fn current_timestamp() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap_or_else(|_| std::time::Duration::from_secs(0))
         .as_secs()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NONCE_HEX: &str = "5a9b8f7c6d5e4d3c2b1a0f9e8d7c6b5a4d3c2b1a0f9e8d7c6b5a4d3c2b1a0f9e";
+
+    #[test]
+    fn parses_well_formed_k1() {
+        let k1 = K1::parse(&format!("{}_1700000000", NONCE_HEX)).unwrap();
+        assert_eq!(k1.timestamp(), 1_700_000_000);
+        assert_eq!(k1.to_string(), format!("{}_1700000000", NONCE_HEX));
+    }
+
+    #[test]
+    fn generated_k1_roundtrips() {
+        let k1 = K1::generate();
+        assert_eq!(k1.to_string().parse::<K1>(), Ok(k1));
+    }
+
+    #[test]
+    fn expiry_respects_ttl_and_skew() {
+        let k1 = K1::new([0x11; 32], 1_000);
+
+        assert!(!k1.is_expired(1_600, 600, 0));
+        assert!(k1.is_expired(1_601, 600, 0));
+        assert!(!k1.is_expired(1_630, 600, 30));
+        assert!(k1.is_expired(1_631, 600, 30));
+    }
+
+    #[test]
+    fn future_dated_k1_is_expired_beyond_skew() {
+        let k1 = K1::new([0x11; 32], 1_000);
+
+        assert!(k1.is_expired(999, 600, 0));
+        assert!(!k1.is_expired(970, 600, 30));
+        assert!(k1.is_expired(969, 600, 30));
+    }
+
+    #[test]
+    fn binding_is_read_from_stored_value() {
+        assert_eq!(K1Binding::from_stored("1700000000"), K1Binding::Unbound);
+        assert_eq!(
+            K1Binding::from_stored("action:register"),
+            K1Binding::Action("register".to_string())
+        );
+        assert_eq!(
+            K1Binding::from_stored("action:backup/upload_url").action(),
+            Some("backup/upload_url")
+        );
+    }
+
+    #[test]
+    fn validates_k1_actions() {
+        assert!(is_valid_k1_action("register"));
+        assert!(is_valid_k1_action("backup/upload_url"));
+        assert!(!is_valid_k1_action(""));
+        assert!(!is_valid_k1_action("/register"));
+        assert!(!is_valid_k1_action("Register"));
+        assert!(!is_valid_k1_action("register?x=1"));
+        assert!(!is_valid_k1_action(&"a".repeat(MAX_K1_ACTION_LENGTH + 1)));
+    }
+
+    #[test]
+    fn rejects_malformed_k1() {
+        assert_eq!(K1::parse(NONCE_HEX), Err(K1ParseError::MissingTimestamp));
+        assert_eq!(K1::parse("invalid_k1"), Err(K1ParseError::InvalidNonce));
+        assert_eq!(
+            K1::parse(&format!("{}_1700000000", &NONCE_HEX[2..])),
+            Err(K1ParseError::InvalidNonce)
+        );
+        assert_eq!(
+            K1::parse(&format!("{}_1700000000", NONCE_HEX.to_uppercase())),
+            Err(K1ParseError::InvalidNonce)
+        );
+        assert_eq!(
+            K1::parse(&format!("{}_", NONCE_HEX)),
+            Err(K1ParseError::InvalidTimestamp)
+        );
+        assert_eq!(
+            K1::parse(&format!("{}_+1700000000", NONCE_HEX)),
+            Err(K1ParseError::InvalidTimestamp)
+        );
+        assert_eq!(
+            K1::parse(&format!("{}_1700000000_1", NONCE_HEX)),
+            Err(K1ParseError::InvalidTimestamp)
+        );
+        assert_eq!(
+            K1::parse(&format!("{}_0", NONCE_HEX)),
+            Err(K1ParseError::InvalidTimestamp)
+        );
+    }
+}
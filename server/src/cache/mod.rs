@@ -1,5 +1,8 @@
+pub mod abuse_store;
+pub mod admin_command_bus;
 pub mod email_verification_store;
 pub mod invoice_store;
 pub mod k1_store;
 pub mod maintenance_store;
+pub mod push_dedupe_store;
 pub mod redis_client;
@@ -0,0 +1,67 @@
+use deadpool_redis::redis::cmd;
+
+use super::redis_client::RedisClient;
+
+const PUSH_DEDUPE_PREFIX: &str = "push_dedupe:";
+
+/// Remembers which notifications went out recently so retries and resumed broadcasts
+/// don't push the same notification to a user twice.
+///
+/// A key covers one pubkey and one notification content, identified by its type and content
+/// hash, and expires `window_secs` after the send that claimed it.
+#[derive(Clone)]
+pub struct PushDedupeStore {
+    client: RedisClient,
+}
+
+impl PushDedupeStore {
+    pub fn new(client: RedisClient) -> Self {
+        Self { client }
+    }
+
+    fn key(pubkey: &str, notification_type: &str, content_hash: &str) -> String {
+        format!(
+            "{}{}:{}:{}",
+            PUSH_DEDUPE_PREFIX, notification_type, content_hash, pubkey
+        )
+    }
+
+    /// Claims the send for the next `window_secs`.
+    ///
+    /// Returns false when the same notification was sent to the pubkey within the window.
+    pub async fn claim(
+        &self,
+        pubkey: &str,
+        notification_type: &str,
+        content_hash: &str,
+        window_secs: u64,
+    ) -> anyhow::Result<bool> {
+        let key = Self::key(pubkey, notification_type, content_hash);
+
+        let mut conn = self.client.get_connection().await?;
+        let claimed: Option<String> = cmd("SET")
+            .arg(&key)
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(window_secs)
+            .query_async(&mut conn)
+            .await?;
+
+        Ok(claimed.is_some())
+    }
+
+    /// Gives up a claim, so a send that didn't go out can be retried within the window.
+    pub async fn release(
+        &self,
+        pubkey: &str,
+        notification_type: &str,
+        content_hash: &str,
+    ) -> anyhow::Result<()> {
+        let key = Self::key(pubkey, notification_type, content_hash);
+
+        let mut conn = self.client.get_connection().await?;
+        let _: () = cmd("DEL").arg(&key).query_async(&mut conn).await?;
+        Ok(())
+    }
+}
@@ -1,10 +1,14 @@
 use anyhow::Context;
-use deadpool_redis::{Connection, Pool, PoolConfig, Runtime, redis::cmd};
+use deadpool_redis::{
+    Connection, Pool, PoolConfig, Runtime,
+    redis::{Client, aio::PubSub, cmd},
+};
 
 /// Simple wrapper around a Redis connection pool.
 #[derive(Clone)]
 pub struct RedisClient {
     pool: Pool,
+    connection_url: String,
 }
 
 impl RedisClient {
@@ -23,7 +27,10 @@ impl RedisClient {
             .create_pool(Some(Runtime::Tokio1))
             .context("Failed to create Redis pool")?;
 
-        Ok(Self { pool })
+        Ok(Self {
+            pool,
+            connection_url: connection_url.to_string(),
+        })
     }
 
     /// Grab a pooled connection.
@@ -34,6 +41,15 @@ impl RedisClient {
             .context("Failed to acquire Redis connection")
     }
 
+    /// Open a dedicated pub/sub connection, since a subscribed connection can't be pooled.
+    pub async fn get_pubsub(&self) -> anyhow::Result<PubSub> {
+        let client = Client::open(self.connection_url.as_str()).context("Invalid Redis URL")?;
+        client
+            .get_async_pubsub()
+            .await
+            .context("Failed to open Redis pub/sub connection")
+    }
+
     /// Check connectivity by issuing a PING.
     pub async fn check_connection(&self) -> anyhow::Result<()> {
         let mut connection = self.get_connection().await?;
@@ -0,0 +1,132 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+
+use crate::config::Config;
+
+const HEALTHCHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Parser)]
+#[command(name = "noah-server", version)]
+#[command(about = "Noah server", long_about = None)]
+pub struct Cli {
+    /// Print the effective configuration as JSON with secrets redacted, then exit
+    #[arg(long)]
+    pub print_config: bool,
+
+    /// With --print-config, show whether each value came from the environment, the .env file
+    /// or the default
+    #[arg(long, requires = "print_config")]
+    pub config_sources: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Load and validate the configuration, then exit
+    ConfigCheck,
+
+    /// Probe a running instance and exit non-zero if it is unhealthy
+    Healthcheck {
+        /// URL to probe
+        #[arg(default_value = "http://127.0.0.1:3000/health")]
+        url: String,
+    },
+}
+
+/// Loads the config exactly as the server would and checks derived values.
+pub fn config_check() -> Result<()> {
+    let config = Config::load()?;
+    config.host()?;
+    config.network()?;
+
+    println!("Configuration is valid");
+    Ok(())
+}
+
+/// Loads the config exactly as the server would and prints it with secrets redacted.
+pub fn print_config(with_sources: bool) -> Result<()> {
+    let config = Config::load()?;
+    println!("{}", render_config(&config, with_sources)?);
+    Ok(())
+}
+
+fn render_config(config: &Config, with_sources: bool) -> Result<String> {
+    let entries: serde_json::Map<String, serde_json::Value> = config
+        .redacted_entries()
+        .into_iter()
+        .map(|(key, value)| {
+            let value = if with_sources {
+                serde_json::json!({ "value": value, "source": config.source_of(key) })
+            } else {
+                value
+            };
+            (key.to_string(), value)
+        })
+        .collect();
+    Ok(serde_json::to_string_pretty(&entries)?)
+}
+
+/// Sends a GET request to `url` and fails unless it returns a success status.
+pub fn healthcheck(url: &str) -> Result<()> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+
+    runtime.block_on(async {
+        let client = reqwest::Client::builder()
+            .timeout(HEALTHCHECK_TIMEOUT)
+            .build()?;
+        let response = client
+            .get(url)
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach {}", url))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            anyhow::bail!("{} returned {}", url, status);
+        }
+
+        println!("{} is healthy ({})", url, status);
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigSource;
+    use crate::tests::common::TestUser;
+
+    #[test]
+    fn render_config_redacts_secrets() {
+        let config = TestUser::get_config();
+        let rendered = render_config(&config, false).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&rendered).unwrap();
+
+        assert_eq!(parsed["PORT"], 3000);
+        assert_eq!(parsed["POSTGRES_URL"], "[REDACTED]");
+        assert!(!rendered.contains(&config.postgres_url));
+        assert!(!rendered.contains(&config.auth_jwt_secret));
+        assert!(!rendered.contains(&config.redis_url));
+    }
+
+    #[test]
+    fn render_config_includes_sources() {
+        let mut config = TestUser::get_config();
+        config.sources.insert("PORT", ConfigSource::Env);
+        config.sources.insert("POSTGRES_URL", ConfigSource::File);
+        let rendered = render_config(&config, true).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&rendered).unwrap();
+
+        assert_eq!(parsed["PORT"]["value"], 3000);
+        assert_eq!(parsed["PORT"]["source"], "env");
+        assert_eq!(parsed["POSTGRES_URL"]["value"], "[REDACTED]");
+        assert_eq!(parsed["POSTGRES_URL"]["source"], "file");
+        assert_eq!(parsed["HOST"]["source"], "default");
+    }
+}
@@ -1,7 +1,252 @@
 use anyhow::{Context, Result};
 use bitcoin::Network;
+use bitcoin::secp256k1::SecretKey;
+use chrono::{DateTime, Timelike, Utc};
+use chrono_tz::Tz;
+use std::collections::{BTreeMap, HashSet};
 use std::net::Ipv4Addr;
 use std::str::FromStr;
+use std::time::Duration;
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Where the value of a config field came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigSource {
+    /// Not set anywhere, so the built-in default applies.
+    Default,
+    /// Set in the `.env` file.
+    File,
+    /// Set in the process environment, which takes precedence over the `.env` file.
+    Env,
+}
+
+impl std::fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigSource::Default => write!(f, "default"),
+            ConfigSource::File => write!(f, "file"),
+            ConfigSource::Env => write!(f, "env"),
+        }
+    }
+}
+
+/// `dotenvy` never overrides variables that are already set, so a variable present before
+/// loading `.env` came from the process environment and one that only appears afterwards came
+/// from the file.
+fn config_source(set_before_dotenv: bool, set_after_dotenv: bool) -> ConfigSource {
+    match (set_before_dotenv, set_after_dotenv) {
+        (true, _) => ConfigSource::Env,
+        (false, true) => ConfigSource::File,
+        (false, false) => ConfigSource::Default,
+    }
+}
+
+/// Output format for the tracing subscriber.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human readable, multi-field lines.
+    Pretty,
+    /// One JSON object per line, for log aggregation pipelines.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "pretty" => Ok(LogFormat::Pretty),
+            "json" => Ok(LogFormat::Json),
+            _ => anyhow::bail!("Invalid log format: {} (expected 'pretty' or 'json')", s),
+        }
+    }
+}
+
+/// Token bucket settings for a single rate limiter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitSettings {
+    /// Seconds after which one request is replenished.
+    pub seconds_per_request: u64,
+    /// Number of requests that can be made in a burst.
+    pub burst_size: u32,
+}
+
+impl RateLimitSettings {
+    pub const fn new(seconds_per_request: u64, burst_size: u32) -> Self {
+        Self {
+            seconds_per_request,
+            burst_size,
+        }
+    }
+}
+
+impl FromStr for RateLimitSettings {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (seconds_per_request, burst_size) = s.split_once(':').context(format!(
+            "Invalid rate limit: {} (expected 'seconds:burst')",
+            s
+        ))?;
+        let settings = Self::new(
+            seconds_per_request.trim().parse().context(format!(
+                "Invalid rate limit period: {}",
+                seconds_per_request
+            ))?,
+            burst_size
+                .trim()
+                .parse()
+                .context(format!("Invalid rate limit burst size: {}", burst_size))?,
+        );
+        if settings.seconds_per_request == 0 || settings.burst_size == 0 {
+            anyhow::bail!("Rate limit period and burst size must be greater than 0");
+        }
+        Ok(settings)
+    }
+}
+
+/// Per-route rate limits that operators can tune through `RATE_LIMITS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimits {
+    pub lnurlp: RateLimitSettings,
+    pub register: RateLimitSettings,
+    pub email_send_verification: RateLimitSettings,
+    pub email_availability: RateLimitSettings,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            lnurlp: RateLimitSettings::new(5, 30),
+            register: RateLimitSettings::new(10, 20),
+            email_send_verification: RateLimitSettings::new(30, 5),
+            email_availability: RateLimitSettings::new(60, 3),
+        }
+    }
+}
+
+impl FromStr for RateLimits {
+    type Err = anyhow::Error;
+
+    /// Parses overrides in the form `lnurlp=5:30,register=10:20`, keeping defaults for the rest.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut limits = Self::default();
+        for entry in s
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let (route, settings) = entry.split_once('=').context(format!(
+                "Invalid rate limit entry: {} (expected 'route=seconds:burst')",
+                entry
+            ))?;
+            let settings = settings.parse()?;
+            match route.trim() {
+                "lnurlp" => limits.lnurlp = settings,
+                "register" => limits.register = settings,
+                "email_send_verification" => limits.email_send_verification = settings,
+                "email_availability" => limits.email_availability = settings,
+                other => anyhow::bail!("Unknown rate limited route: {}", other),
+            }
+        }
+        Ok(limits)
+    }
+}
+
+/// Global feature flags, each user can have per-flag overrides on top.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FeatureFlags(BTreeMap<String, bool>);
+
+impl FeatureFlags {
+    /// Resolves the flags for a user, where the user's overrides win over the global values.
+    pub fn resolve(&self, overrides: BTreeMap<String, bool>) -> BTreeMap<String, bool> {
+        let mut flags = self.0.clone();
+        flags.extend(overrides);
+        flags
+    }
+
+    pub fn as_map(&self) -> &BTreeMap<String, bool> {
+        &self.0
+    }
+}
+
+impl FromStr for FeatureFlags {
+    type Err = anyhow::Error;
+
+    /// Parses flags in the form `new_backups=true,beta_swaps=false`.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut flags = BTreeMap::new();
+        for entry in s
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let (flag, enabled) = entry.split_once('=').context(format!(
+                "Invalid feature flag entry: {} (expected 'flag=true|false')",
+                entry
+            ))?;
+            let enabled = enabled
+                .trim()
+                .parse()
+                .context(format!("Invalid value for feature flag {}", flag.trim()))?;
+            flags.insert(flag.trim().to_string(), enabled);
+        }
+        Ok(Self(flags))
+    }
+}
+
+/// Abuse scoring thresholds, see `AbuseStore`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AbuseSettings {
+    /// Score at which a pubkey or IP is temporarily blocked.
+    pub threshold: u64,
+    /// How long suspicious events keep counting towards the score.
+    pub window_secs: u64,
+    /// How long a block lasts once the threshold is reached.
+    pub ban_secs: u64,
+}
+
+/// When a push token is deleted after Expo reports `DeviceNotRegistered`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenPruneSettings {
+    /// Consecutive `DeviceNotRegistered` tickets needed before the token is deleted.
+    pub threshold: u32,
+    /// Window the consecutive tickets must fall in, counted from the first one.
+    pub window_secs: u64,
+}
+
+/// Daily window, in the user's local time, during which normal-priority notifications are held back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuietHours {
+    /// Hour of day the window starts, inclusive.
+    pub start_hour: u32,
+    /// Hour of day the window ends, exclusive. May be before `start_hour` to span midnight.
+    pub end_hour: u32,
+}
+
+impl QuietHours {
+    /// Whether `now` falls inside the window in the given timezone.
+    pub fn contains(&self, now: DateTime<Utc>, timezone: Tz) -> bool {
+        let hour = now.with_timezone(&timezone).hour();
+        if self.start_hour < self.end_hour {
+            (self.start_hour..self.end_hour).contains(&hour)
+        } else {
+            hour >= self.start_hour || hour < self.end_hour
+        }
+    }
+}
+
+/// Lower bound for `INACTIVE_ACCOUNT_PURGE_DAYS` so a typo can't wipe active accounts.
+pub const MIN_INACTIVE_ACCOUNT_PURGE_DAYS: u32 = 90;
+
+/// Upper bound for `K1_POW_DIFFICULTY` so clients on slow phones can still log in.
+pub const MAX_K1_POW_DIFFICULTY: u8 = 32;
+
+/// Slack the LNURL-pay route timeout keeps on top of `LNURLP_INVOICE_TIMEOUT_SECS`, so the
+/// handler's own invoice timeout always answers before the router gives up on the request.
+pub const LNURLP_ROUTE_TIMEOUT_BUFFER_SECS: u64 = 5;
 
 /// Configuration for the Noah server
 ///
@@ -16,35 +261,93 @@ pub struct Config {
     pub port: u16,
     pub private_port: u16,
     pub lnurl_domain: String,
+    pub accepted_lnurl_domains: Vec<String>,
     pub postgres_url: String,
     pub postgres_max_connections: u32,
     pub postgres_min_connections: Option<u32>,
     pub expo_access_token: String,
     pub ark_server_url: String,
+    pub ark_enabled: bool,
+    pub ark_address_unique: bool,
+    pub ark_connect_timeout_secs: u64,
+    pub ark_handshake_timeout_secs: u64,
     pub server_network: String,
     pub sentry_url: Option<String>,
+    pub sentry_enabled: Option<bool>,
+    pub sentry_traces_sample_rate: f32,
+    pub sentry_log_level: String,
+    pub log_format: String,
     pub backup_cron: String,
     pub maintenance_interval_rounds: u16,
     pub maintenance_notification_advance_secs: u64,
     pub heartbeat_cron: String,
     pub deregister_cron: String,
+    pub inactive_account_purge_days: Option<u32>,
+    pub inactive_account_purge_dry_run: bool,
+    pub inactive_account_purge_cron: String,
     pub notification_spacing_minutes: i64,
     pub s3_bucket_name: String,
+    pub s3_key_prefix: String,
+    pub s3_hash_pubkeys: bool,
+    pub aws_credentials_lazy: bool,
+    pub s3_startup_check: bool,
     pub minimum_app_version: String,
     pub redis_url: String,
     pub redis_pool_size: usize,
     pub ntfy_auth_token: String,
     pub ses_from_address: String,
     pub email_dev_mode: bool,
+    pub email_code_ttl_secs: u64,
     pub auth_jwt_secret: String,
     pub auth_jwt_ttl_hours: u64,
+    pub k1_pow_difficulty: Option<u8>,
+    pub k1_clock_skew_tolerance_secs: u64,
+    pub client_timestamp_max_skew_secs: Option<u64>,
+    pub rate_limits: String,
+    pub rate_limit_exempt_pubkeys: Vec<String>,
+    pub lnurlp_daily_request_cap: u64,
+    pub lnurlp_reported_daily_request_cap: u64,
+    pub lnurlp_reported_cap_secs: u64,
+    pub abuse_score_threshold: u64,
+    pub abuse_score_window_secs: u64,
+    pub abuse_ban_secs: u64,
+    pub quiet_hours_start: Option<u32>,
+    pub quiet_hours_end: Option<u32>,
+    pub push_dedupe_window_secs: u64,
+    pub backup_trigger_skip_disabled: bool,
+    pub max_concurrent_broadcasts: usize,
+    pub response_signing_key: Option<String>,
+    pub feature_flags: String,
+    pub request_timeout_secs: u64,
+    pub lnurlp_invoice_timeout_secs: u64,
+    pub lnurlp_poll_ttl_secs: u64,
+    pub device_not_registered_threshold: u32,
+    pub device_not_registered_window_secs: u64,
+    pub push_max_payload_bytes: usize,
+    pub job_status_retention_days: u32,
+    pub job_status_retention_cron: String,
+    pub job_status_soft_cap: u64,
+    pub lnurlp_require_whole_sats: bool,
+    pub lnurlp_amount_description: bool,
+    pub lnurlp_verify_description_hash: bool,
+    pub ln_address_history_enabled: bool,
+    pub ln_username_max_length: usize,
+    pub ln_address_retired_grace_days: u32,
+    pub max_backup_version: i32,
+    pub max_backups_per_user: Option<u32>,
+    pub max_total_backup_bytes_per_user: Option<u64>,
+    /// Source of each field, keyed by environment variable. Missing keys count as defaults.
+    pub sources: BTreeMap<&'static str, ConfigSource>,
 }
 
 impl Config {
     pub fn load() -> Result<Self> {
+        let process_env: HashSet<String> = std::env::vars_os()
+            .filter_map(|(key, _)| key.into_string().ok())
+            .collect();
         dotenvy::dotenv().ok();
 
-        let config = Self {
+        let mut config = Self {
             host: std::env::var("HOST").unwrap_or_else(|_| "0.0.0.0".to_string()),
             port: std::env::var("PORT")
                 .ok()
@@ -55,6 +358,16 @@ impl Config {
                 .and_then(|v| v.parse().ok())
                 .unwrap_or(3099),
             lnurl_domain: std::env::var("LNURL_DOMAIN").unwrap_or_else(|_| "localhost".to_string()),
+            // Former domains, comma-separated, whose addresses still resolve and are rewritten
+            // to LNURL_DOMAIN when set
+            accepted_lnurl_domains: std::env::var("ACCEPTED_LNURL_DOMAINS")
+                .map(|v| {
+                    v.split(',')
+                        .map(|domain| domain.trim().to_lowercase())
+                        .filter(|domain| !domain.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
             postgres_url: std::env::var("POSTGRES_URL").unwrap_or_default(),
             postgres_max_connections: std::env::var("POSTGRES_MAX_CONNECTIONS")
                 .ok()
@@ -65,9 +378,37 @@ impl Config {
                 .and_then(|v| v.parse().ok()),
             expo_access_token: std::env::var("EXPO_ACCESS_TOKEN").unwrap_or_default(),
             ark_server_url: std::env::var("ARK_SERVER_URL").unwrap_or_default(),
+            // Accept ark addresses at registration and offer them to Noah payers unless false
+            ark_enabled: std::env::var("ARK_ENABLED")
+                .map(|v| v != "false" && v != "0")
+                .unwrap_or(true),
+            // Reject an ark address already held by another account unless false
+            ark_address_unique: std::env::var("ARK_ADDRESS_UNIQUE")
+                .map(|v| v != "false" && v != "0")
+                .unwrap_or(true),
+            ark_connect_timeout_secs: std::env::var("ARK_CONNECT_TIMEOUT_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(10),
+            // Covers connecting and fetching the server info, so it bounds the whole handshake
+            ark_handshake_timeout_secs: std::env::var("ARK_HANDSHAKE_TIMEOUT_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(30),
             server_network: std::env::var("SERVER_NETWORK")
                 .unwrap_or_else(|_| "regtest".to_string()),
             sentry_url: std::env::var("SENTRY_URL").ok(),
+            // Unset keeps the network default, see `Config::sentry_enabled`
+            sentry_enabled: std::env::var("SENTRY_ENABLED")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            sentry_traces_sample_rate: std::env::var("SENTRY_TRACES_SAMPLE_RATE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(0.1),
+            sentry_log_level: std::env::var("SENTRY_LOG_LEVEL")
+                .unwrap_or_else(|_| "debug".to_string()),
+            log_format: std::env::var("LOG_FORMAT").unwrap_or_else(|_| "pretty".to_string()),
             backup_cron: std::env::var("BACKUP_CRON")
                 .unwrap_or_else(|_| "every 2 hours".to_string()),
             maintenance_interval_rounds: std::env::var("MAINTENANCE_INTERVAL_ROUNDS")
@@ -84,11 +425,32 @@ impl Config {
                 .unwrap_or_else(|_| "every 48 hours".to_string()),
             deregister_cron: std::env::var("DEREGISTER_CRON")
                 .unwrap_or_else(|_| "every 12 hours".to_string()),
+            inactive_account_purge_days: std::env::var("INACTIVE_ACCOUNT_PURGE_DAYS")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            // Purging is destructive, so anything other than an explicit opt-out stays a dry run
+            inactive_account_purge_dry_run: std::env::var("INACTIVE_ACCOUNT_PURGE_DRY_RUN")
+                .map(|v| v != "false" && v != "0")
+                .unwrap_or(true),
+            inactive_account_purge_cron: std::env::var("INACTIVE_ACCOUNT_PURGE_CRON")
+                .unwrap_or_else(|_| "every 24 hours".to_string()),
             notification_spacing_minutes: std::env::var("NOTIFICATION_SPACING_MINUTES")
                 .ok()
                 .and_then(|v| v.parse().ok())
                 .unwrap_or(45),
             s3_bucket_name: std::env::var("S3_BUCKET_NAME").unwrap_or_default(),
+            // Namespace for backup keys, so environments can share a bucket
+            s3_key_prefix: std::env::var("S3_KEY_PREFIX").unwrap_or_default(),
+            // Name backup objects after a hash of the pubkey instead of the pubkey itself
+            s3_hash_pubkeys: std::env::var("S3_HASH_PUBKEYS")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+            aws_credentials_lazy: std::env::var("AWS_CREDENTIALS_LAZY")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+            s3_startup_check: std::env::var("S3_STARTUP_CHECK")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
             minimum_app_version: std::env::var("MINIMUM_APP_VERSION")
                 .unwrap_or_else(|_| "0.0.1".to_string()),
             redis_url: std::env::var("REDIS_URL")
@@ -103,13 +465,189 @@ impl Config {
             email_dev_mode: std::env::var("EMAIL_DEV_MODE")
                 .map(|v| v == "true" || v == "1")
                 .unwrap_or(false),
+            // How long an emailed verification code stays valid
+            email_code_ttl_secs: std::env::var("EMAIL_CODE_TTL_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(600),
             auth_jwt_secret: std::env::var("AUTH_JWT_SECRET").unwrap_or_default(),
             auth_jwt_ttl_hours: std::env::var("AUTH_JWT_TTL_HOURS")
                 .ok()
                 .and_then(|v| v.parse().ok())
                 .unwrap_or(72),
+            // Leading zero bits required for the get_k1 proof of work, unset or 0 disables it
+            k1_pow_difficulty: std::env::var("K1_POW_DIFFICULTY")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|difficulty| *difficulty > 0),
+            // Drift allowed between the clock that issued a k1 and the one validating it
+            k1_clock_skew_tolerance_secs: std::env::var("K1_CLOCK_SKEW_TOLERANCE_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(30),
+            // When set, gated requests must send an x-timestamp header within this many seconds
+            client_timestamp_max_skew_secs: std::env::var("CLIENT_TIMESTAMP_MAX_SKEW_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|secs| *secs > 0),
+            rate_limits: std::env::var("RATE_LIMITS").unwrap_or_default(),
+            // Comma-separated pubkeys of trusted partners that skip the authenticated rate limits
+            rate_limit_exempt_pubkeys: std::env::var("RATE_LIMIT_EXEMPT_PUBKEYS")
+                .map(|v| {
+                    v.split(',')
+                        .map(|pubkey| pubkey.trim().to_string())
+                        .filter(|pubkey| !pubkey.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            // Invoice requests a single recipient can receive per UTC day, 0 (default) disables it
+            lnurlp_daily_request_cap: std::env::var("LNURLP_DAILY_REQUEST_CAP")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(0),
+            // Cap applied instead after the recipient reports abuse, 0 disables the tightening
+            lnurlp_reported_daily_request_cap: std::env::var("LNURLP_REPORTED_DAILY_REQUEST_CAP")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(10),
+            // How long the reduced cap stays in place after a report
+            lnurlp_reported_cap_secs: std::env::var("LNURLP_REPORTED_CAP_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(86400),
+            // Score at which a pubkey or IP gets temporarily blocked, 0 (default) disables scoring
+            abuse_score_threshold: std::env::var("ABUSE_SCORE_THRESHOLD")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(0),
+            abuse_score_window_secs: std::env::var("ABUSE_SCORE_WINDOW_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(600),
+            abuse_ban_secs: std::env::var("ABUSE_BAN_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(900),
+            // Local hours during which normal-priority notifications are deferred, unset disables
+            quiet_hours_start: std::env::var("QUIET_HOURS_START")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            quiet_hours_end: std::env::var("QUIET_HOURS_END")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            // Window in which a repeated push of the same notification is dropped, 0 disables
+            push_dedupe_window_secs: std::env::var("PUSH_DEDUPE_WINDOW_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(0),
+            // Leave users who turned off backups out of backup_trigger broadcasts
+            backup_trigger_skip_disabled: std::env::var("BACKUP_TRIGGER_SKIP_DISABLED")
+                .map(|v| v != "false" && v != "0")
+                .unwrap_or(true),
+            // Broadcasts allowed to run at once on this instance, later ones wait for a slot
+            max_concurrent_broadcasts: std::env::var("MAX_CONCURRENT_BROADCASTS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(1),
+            // Hex secp256k1 secret key used to sign critical responses, unset disables signing
+            response_signing_key: std::env::var("RESPONSE_SIGNING_KEY")
+                .ok()
+                .filter(|v| !v.is_empty()),
+            feature_flags: std::env::var("FEATURE_FLAGS").unwrap_or_default(),
+            // Upper bound on handling a v0 API request before answering 504
+            request_timeout_secs: std::env::var("REQUEST_TIMEOUT_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(30),
+            // How long the LNURL-pay callback waits for the device to submit an invoice
+            lnurlp_invoice_timeout_secs: std::env::var("LNURLP_INVOICE_TIMEOUT_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(30),
+            // How long a pre-registered transaction can be polled before it's forgotten
+            lnurlp_poll_ttl_secs: std::env::var("LNURLP_POLL_TTL_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(60),
+            // Consecutive DeviceNotRegistered tickets before a push token is deleted
+            device_not_registered_threshold: std::env::var("DEVICE_NOT_REGISTERED_THRESHOLD")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(3),
+            device_not_registered_window_secs: std::env::var("DEVICE_NOT_REGISTERED_WINDOW_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(7 * 24 * 60 * 60),
+            // Expo rejects notifications larger than 4 KiB, so larger ones aren't sent at all
+            push_max_payload_bytes: std::env::var("PUSH_MAX_PAYLOAD_BYTES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(4096),
+            // Job status reports older than this are deleted regardless of per-user pruning,
+            // 0 (default) keeps them all
+            job_status_retention_days: std::env::var("JOB_STATUS_RETENTION_DAYS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(0),
+            job_status_retention_cron: std::env::var("JOB_STATUS_RETENTION_CRON")
+                .unwrap_or_else(|_| "every 24 hours".to_string()),
+            // Row count above which the retention job alerts, 0 disables the alert
+            job_status_soft_cap: std::env::var("JOB_STATUS_SOFT_CAP")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(1_000_000),
+            // Reject LNURL-pay amounts that aren't a multiple of 1000 mSats, off by default
+            lnurlp_require_whole_sats: std::env::var("LNURLP_REQUIRE_WHOLE_SATS")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+            // Show the suggested amount in the LNURL-pay description, e.g. "Pay 1,234 sats to ..."
+            lnurlp_amount_description: std::env::var("LNURLP_AMOUNT_DESCRIPTION")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+            // Reject submitted invoices whose description hash doesn't commit to the LNURL metadata
+            lnurlp_verify_description_hash: std::env::var("LNURLP_VERIFY_DESCRIPTION_HASH")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+            // Keep replaced lightning addresses so payments to them still reach their owner
+            ln_address_history_enabled: std::env::var("LN_ADDRESS_HISTORY_ENABLED")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+            ln_address_retired_grace_days: std::env::var("LN_ADDRESS_RETIRED_GRACE_DAYS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(30),
+            // Longest lightning address username accepted, in characters
+            ln_username_max_length: std::env::var("LN_USERNAME_MAX_LENGTH")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(64),
+            // Clients rotate between backup versions 1..=MAX_BACKUP_VERSION
+            max_backup_version: std::env::var("MAX_BACKUP_VERSION")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(2),
+            // Per-user backup quotas enforced on complete_upload, unset or 0 disables each
+            max_backups_per_user: std::env::var("MAX_BACKUPS_PER_USER")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|max| *max > 0),
+            max_total_backup_bytes_per_user: std::env::var("MAX_TOTAL_BACKUP_BYTES_PER_USER")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|max| *max > 0),
+            sources: BTreeMap::new(),
         };
 
+        config.sources = config
+            .redacted_entries()
+            .into_iter()
+            .map(|(key, _)| {
+                let source =
+                    config_source(process_env.contains(key), std::env::var_os(key).is_some());
+                (key, source)
+            })
+            .collect();
+
         config.validate()?;
 
         Ok(config)
@@ -128,12 +666,89 @@ impl Config {
         if self.ark_server_url.is_empty() {
             anyhow::bail!("ARK_SERVER_URL is required");
         }
+        if self.ark_connect_timeout_secs == 0 || self.ark_handshake_timeout_secs == 0 {
+            anyhow::bail!(
+                "ARK_CONNECT_TIMEOUT_SECS and ARK_HANDSHAKE_TIMEOUT_SECS must be positive"
+            );
+        }
+        if self.request_timeout_secs == 0 {
+            anyhow::bail!("REQUEST_TIMEOUT_SECS must be positive");
+        }
+        if self.lnurlp_invoice_timeout_secs == 0 {
+            anyhow::bail!("LNURLP_INVOICE_TIMEOUT_SECS must be positive");
+        }
+        if self.ln_username_max_length == 0 {
+            anyhow::bail!("LN_USERNAME_MAX_LENGTH must be positive");
+        }
+        if self.max_concurrent_broadcasts == 0 {
+            anyhow::bail!("MAX_CONCURRENT_BROADCASTS must be positive");
+        }
+        if self.lnurlp_poll_ttl_secs == 0 {
+            anyhow::bail!("LNURLP_POLL_TTL_SECS must be positive");
+        }
+        if self.device_not_registered_threshold == 0 || self.device_not_registered_window_secs == 0
+        {
+            anyhow::bail!(
+                "DEVICE_NOT_REGISTERED_THRESHOLD and DEVICE_NOT_REGISTERED_WINDOW_SECS must be positive"
+            );
+        }
+        if self.email_code_ttl_secs == 0 {
+            anyhow::bail!("EMAIL_CODE_TTL_SECS must be positive");
+        }
+        if self.push_max_payload_bytes == 0 {
+            anyhow::bail!("PUSH_MAX_PAYLOAD_BYTES must be positive");
+        }
         if self.s3_bucket_name.is_empty() {
             anyhow::bail!("S3_BUCKET_NAME is required");
         }
+        if self.max_backup_version < 1 {
+            anyhow::bail!("MAX_BACKUP_VERSION must be positive");
+        }
         if self.auth_jwt_secret.is_empty() {
             anyhow::bail!("AUTH_JWT_SECRET is required");
         }
+        if !(0.0..=1.0).contains(&self.sentry_traces_sample_rate) {
+            anyhow::bail!("SENTRY_TRACES_SAMPLE_RATE must be between 0.0 and 1.0");
+        }
+        self.sentry_log_level()?;
+        self.log_format()?;
+        self.rate_limits()?;
+        for pubkey in &self.rate_limit_exempt_pubkeys {
+            bitcoin::secp256k1::PublicKey::from_str(pubkey).context(format!(
+                "RATE_LIMIT_EXEMPT_PUBKEYS contains an invalid pubkey: {}",
+                pubkey
+            ))?;
+        }
+        if let Some(days) = self.inactive_account_purge_days
+            && days < MIN_INACTIVE_ACCOUNT_PURGE_DAYS
+        {
+            anyhow::bail!(
+                "INACTIVE_ACCOUNT_PURGE_DAYS must be at least {}",
+                MIN_INACTIVE_ACCOUNT_PURGE_DAYS
+            );
+        }
+        if self.abuse_score_threshold > 0
+            && (self.abuse_score_window_secs == 0 || self.abuse_ban_secs == 0)
+        {
+            anyhow::bail!("ABUSE_SCORE_WINDOW_SECS and ABUSE_BAN_SECS must be positive");
+        }
+        match (self.quiet_hours_start, self.quiet_hours_end) {
+            (None, None) => {}
+            (Some(start), Some(end)) if start < 24 && end < 24 && start != end => {}
+            _ => anyhow::bail!(
+                "QUIET_HOURS_START and QUIET_HOURS_END must both be set to different hours between 0 and 23"
+            ),
+        }
+        self.response_signing_key()?;
+        self.feature_flags()?;
+        if let Some(difficulty) = self.k1_pow_difficulty
+            && difficulty > MAX_K1_POW_DIFFICULTY
+        {
+            anyhow::bail!(
+                "K1_POW_DIFFICULTY must be at most {}",
+                MAX_K1_POW_DIFFICULTY
+            );
+        }
         Ok(())
     }
 
@@ -141,57 +756,384 @@ impl Config {
         Ipv4Addr::from_str(&self.host).context(format!("Invalid host address: {}", self.host))
     }
 
+    /// Rewrites an address under one of `ACCEPTED_LNURL_DOMAINS` to `LNURL_DOMAIN`, leaving
+    /// any other address as it is.
+    pub fn canonical_lightning_address(&self, address: &str) -> String {
+        match address.rsplit_once('@') {
+            Some((username, domain)) if self.is_accepted_lnurl_domain(domain) => {
+                format!("{}@{}", username, self.lnurl_domain)
+            }
+            _ => address.to_string(),
+        }
+    }
+
+    /// The address itself followed by the same username under each of `ACCEPTED_LNURL_DOMAINS`,
+    /// since addresses registered before a domain migration are stored under the old domain.
+    pub fn lightning_address_aliases(&self, address: &str) -> Vec<String> {
+        let mut aliases = vec![address.to_string()];
+        if let Some((username, domain)) = address.rsplit_once('@') {
+            aliases.extend(
+                self.accepted_lnurl_domains
+                    .iter()
+                    .filter(|accepted| !accepted.eq_ignore_ascii_case(domain))
+                    .map(|accepted| format!("{}@{}", username, accepted)),
+            );
+        }
+        aliases
+    }
+
+    fn is_accepted_lnurl_domain(&self, domain: &str) -> bool {
+        !domain.eq_ignore_ascii_case(&self.lnurl_domain)
+            && self
+                .accepted_lnurl_domains
+                .iter()
+                .any(|accepted| accepted.eq_ignore_ascii_case(domain))
+    }
+
     pub fn network(&self) -> Result<Network> {
         Network::from_str(&self.server_network)
             .context(format!("Invalid network: {}", self.server_network))
     }
 
+    /// Whether to report to Sentry.
+    ///
+    /// Requires `SENTRY_URL`. `SENTRY_ENABLED` opts in or out explicitly, otherwise Sentry runs
+    /// on mainnet and signet only.
+    pub fn sentry_enabled(&self) -> Result<bool> {
+        Ok(sentry_enabled_for(
+            self.sentry_url.is_some(),
+            self.sentry_enabled,
+            self.network()?,
+        ))
+    }
+
+    /// Most verbose tracing level that is forwarded to Sentry as a log.
+    pub fn sentry_log_level(&self) -> Result<tracing::Level> {
+        tracing::Level::from_str(&self.sentry_log_level).context(format!(
+            "Invalid Sentry log level: {}",
+            self.sentry_log_level
+        ))
+    }
+
+    pub fn log_format(&self) -> Result<LogFormat> {
+        LogFormat::from_str(&self.log_format)
+    }
+
+    pub fn rate_limits(&self) -> Result<RateLimits> {
+        RateLimits::from_str(&self.rate_limits)
+    }
+
+    pub fn feature_flags(&self) -> Result<FeatureFlags> {
+        FeatureFlags::from_str(&self.feature_flags)
+    }
+
+    /// Abuse scoring settings, or `None` when `ABUSE_SCORE_THRESHOLD` is 0.
+    pub fn abuse_settings(&self) -> Option<AbuseSettings> {
+        (self.abuse_score_threshold > 0).then_some(AbuseSettings {
+            threshold: self.abuse_score_threshold,
+            window_secs: self.abuse_score_window_secs,
+            ban_secs: self.abuse_ban_secs,
+        })
+    }
+
+    /// Policy for deleting push tokens Expo reports as no longer registered.
+    pub fn token_prune_settings(&self) -> TokenPruneSettings {
+        TokenPruneSettings {
+            threshold: self.device_not_registered_threshold,
+            window_secs: self.device_not_registered_window_secs,
+        }
+    }
+
+    /// Key used to sign critical responses, or `None` when `RESPONSE_SIGNING_KEY` is unset.
+    pub fn response_signing_key(&self) -> Result<Option<SecretKey>> {
+        self.response_signing_key
+            .as_deref()
+            .map(|key| SecretKey::from_str(key).context("Invalid RESPONSE_SIGNING_KEY"))
+            .transpose()
+    }
+
+    /// Timeout applied to API routes other than the LNURL-pay callback.
+    pub fn request_timeout(&self) -> Duration {
+        Duration::from_secs(self.request_timeout_secs)
+    }
+
+    /// How long the LNURL-pay callback waits for the device to submit an invoice.
+    pub fn lnurlp_invoice_timeout(&self) -> Duration {
+        Duration::from_secs(self.lnurlp_invoice_timeout_secs)
+    }
+
+    /// Router-level timeout for the LNURL-pay callback, padded past the invoice wait.
+    pub fn lnurlp_route_timeout(&self) -> Duration {
+        self.lnurlp_invoice_timeout() + Duration::from_secs(LNURLP_ROUTE_TIMEOUT_BUFFER_SECS)
+    }
+
+    /// Quiet hours window, or `None` when `QUIET_HOURS_START`/`QUIET_HOURS_END` are unset.
+    pub fn quiet_hours(&self) -> Option<QuietHours> {
+        Some(QuietHours {
+            start_hour: self.quiet_hours_start?,
+            end_hour: self.quiet_hours_end?,
+        })
+    }
+
+    /// Effective configuration keyed by environment variable, with secrets redacted.
+    pub fn redacted_entries(&self) -> Vec<(&'static str, serde_json::Value)> {
+        use serde_json::{Value, json};
+
+        let redacted = || Value::from(REDACTED);
+
+        vec![
+            ("HOST", json!(self.host)),
+            ("PORT", json!(self.port)),
+            ("PRIVATE_PORT", json!(self.private_port)),
+            ("LNURL_DOMAIN", json!(self.lnurl_domain)),
+            ("ACCEPTED_LNURL_DOMAINS", json!(self.accepted_lnurl_domains)),
+            ("POSTGRES_URL", redacted()),
+            (
+                "POSTGRES_MAX_CONNECTIONS",
+                json!(self.postgres_max_connections),
+            ),
+            (
+                "POSTGRES_MIN_CONNECTIONS",
+                json!(self.postgres_min_connections.unwrap_or(1)),
+            ),
+            ("EXPO_ACCESS_TOKEN", redacted()),
+            ("ARK_SERVER_URL", json!(self.ark_server_url)),
+            ("ARK_ENABLED", json!(self.ark_enabled)),
+            ("ARK_ADDRESS_UNIQUE", json!(self.ark_address_unique)),
+            (
+                "ARK_CONNECT_TIMEOUT_SECS",
+                json!(self.ark_connect_timeout_secs),
+            ),
+            (
+                "ARK_HANDSHAKE_TIMEOUT_SECS",
+                json!(self.ark_handshake_timeout_secs),
+            ),
+            ("SERVER_NETWORK", json!(self.server_network)),
+            (
+                "SENTRY_URL",
+                self.sentry_url.as_ref().map_or(Value::Null, |_| redacted()),
+            ),
+            ("SENTRY_ENABLED", json!(self.sentry_enabled)),
+            (
+                "SENTRY_TRACES_SAMPLE_RATE",
+                json!(self.sentry_traces_sample_rate),
+            ),
+            ("SENTRY_LOG_LEVEL", json!(self.sentry_log_level)),
+            ("LOG_FORMAT", json!(self.log_format)),
+            ("BACKUP_CRON", json!(self.backup_cron)),
+            ("HEARTBEAT_CRON", json!(self.heartbeat_cron)),
+            ("DEREGISTER_CRON", json!(self.deregister_cron)),
+            (
+                "INACTIVE_ACCOUNT_PURGE_DAYS",
+                json!(self.inactive_account_purge_days),
+            ),
+            (
+                "INACTIVE_ACCOUNT_PURGE_DRY_RUN",
+                json!(self.inactive_account_purge_dry_run),
+            ),
+            (
+                "INACTIVE_ACCOUNT_PURGE_CRON",
+                json!(self.inactive_account_purge_cron),
+            ),
+            (
+                "NOTIFICATION_SPACING_MINUTES",
+                json!(self.notification_spacing_minutes),
+            ),
+            (
+                "MAINTENANCE_INTERVAL_ROUNDS",
+                json!(self.maintenance_interval_rounds),
+            ),
+            (
+                "MAINTENANCE_NOTIFICATION_ADVANCE_SECS",
+                json!(self.maintenance_notification_advance_secs),
+            ),
+            ("S3_BUCKET_NAME", redacted()),
+            ("S3_KEY_PREFIX", json!(self.s3_key_prefix)),
+            ("S3_HASH_PUBKEYS", json!(self.s3_hash_pubkeys)),
+            ("AWS_CREDENTIALS_LAZY", json!(self.aws_credentials_lazy)),
+            ("S3_STARTUP_CHECK", json!(self.s3_startup_check)),
+            ("MINIMUM_APP_VERSION", json!(self.minimum_app_version)),
+            ("REDIS_URL", redacted()),
+            ("REDIS_POOL_SIZE", json!(self.redis_pool_size)),
+            ("NTFY_AUTH_TOKEN", redacted()),
+            ("SES_FROM_ADDRESS", json!(self.ses_from_address)),
+            ("EMAIL_DEV_MODE", json!(self.email_dev_mode)),
+            ("EMAIL_CODE_TTL_SECS", json!(self.email_code_ttl_secs)),
+            ("AUTH_JWT_SECRET", redacted()),
+            ("AUTH_JWT_TTL_HOURS", json!(self.auth_jwt_ttl_hours)),
+            ("K1_POW_DIFFICULTY", json!(self.k1_pow_difficulty)),
+            (
+                "K1_CLOCK_SKEW_TOLERANCE_SECS",
+                json!(self.k1_clock_skew_tolerance_secs),
+            ),
+            (
+                "CLIENT_TIMESTAMP_MAX_SKEW_SECS",
+                json!(self.client_timestamp_max_skew_secs),
+            ),
+            ("RATE_LIMITS", json!(self.rate_limits)),
+            (
+                "RATE_LIMIT_EXEMPT_PUBKEYS",
+                json!(self.rate_limit_exempt_pubkeys),
+            ),
+            (
+                "LNURLP_DAILY_REQUEST_CAP",
+                json!(self.lnurlp_daily_request_cap),
+            ),
+            (
+                "LNURLP_REPORTED_DAILY_REQUEST_CAP",
+                json!(self.lnurlp_reported_daily_request_cap),
+            ),
+            (
+                "LNURLP_REPORTED_CAP_SECS",
+                json!(self.lnurlp_reported_cap_secs),
+            ),
+            ("ABUSE_SCORE_THRESHOLD", json!(self.abuse_score_threshold)),
+            (
+                "ABUSE_SCORE_WINDOW_SECS",
+                json!(self.abuse_score_window_secs),
+            ),
+            ("ABUSE_BAN_SECS", json!(self.abuse_ban_secs)),
+            ("QUIET_HOURS_START", json!(self.quiet_hours_start)),
+            ("QUIET_HOURS_END", json!(self.quiet_hours_end)),
+            (
+                "PUSH_DEDUPE_WINDOW_SECS",
+                json!(self.push_dedupe_window_secs),
+            ),
+            (
+                "BACKUP_TRIGGER_SKIP_DISABLED",
+                json!(self.backup_trigger_skip_disabled),
+            ),
+            (
+                "MAX_CONCURRENT_BROADCASTS",
+                json!(self.max_concurrent_broadcasts),
+            ),
+            ("FEATURE_FLAGS", json!(self.feature_flags)),
+            ("REQUEST_TIMEOUT_SECS", json!(self.request_timeout_secs)),
+            (
+                "LNURLP_INVOICE_TIMEOUT_SECS",
+                json!(self.lnurlp_invoice_timeout_secs),
+            ),
+            ("LNURLP_POLL_TTL_SECS", json!(self.lnurlp_poll_ttl_secs)),
+            (
+                "DEVICE_NOT_REGISTERED_THRESHOLD",
+                json!(self.device_not_registered_threshold),
+            ),
+            (
+                "DEVICE_NOT_REGISTERED_WINDOW_SECS",
+                json!(self.device_not_registered_window_secs),
+            ),
+            ("PUSH_MAX_PAYLOAD_BYTES", json!(self.push_max_payload_bytes)),
+            (
+                "JOB_STATUS_RETENTION_DAYS",
+                json!(self.job_status_retention_days),
+            ),
+            (
+                "JOB_STATUS_RETENTION_CRON",
+                json!(self.job_status_retention_cron),
+            ),
+            ("JOB_STATUS_SOFT_CAP", json!(self.job_status_soft_cap)),
+            (
+                "LNURLP_REQUIRE_WHOLE_SATS",
+                json!(self.lnurlp_require_whole_sats),
+            ),
+            (
+                "LNURLP_AMOUNT_DESCRIPTION",
+                json!(self.lnurlp_amount_description),
+            ),
+            (
+                "LNURLP_VERIFY_DESCRIPTION_HASH",
+                json!(self.lnurlp_verify_description_hash),
+            ),
+            (
+                "LN_ADDRESS_HISTORY_ENABLED",
+                json!(self.ln_address_history_enabled),
+            ),
+            (
+                "LN_ADDRESS_RETIRED_GRACE_DAYS",
+                json!(self.ln_address_retired_grace_days),
+            ),
+            ("LN_USERNAME_MAX_LENGTH", json!(self.ln_username_max_length)),
+            ("MAX_BACKUP_VERSION", json!(self.max_backup_version)),
+            ("MAX_BACKUPS_PER_USER", json!(self.max_backups_per_user)),
+            (
+                "MAX_TOTAL_BACKUP_BYTES_PER_USER",
+                json!(self.max_total_backup_bytes_per_user),
+            ),
+            (
+                "RESPONSE_SIGNING_KEY",
+                if self.response_signing_key.is_some() {
+                    redacted()
+                } else {
+                    Value::Null
+                },
+            ),
+        ]
+    }
+
+    /// Where the value of the field read from `key` came from.
+    pub fn source_of(&self, key: &str) -> ConfigSource {
+        self.sources
+            .get(key)
+            .copied()
+            .unwrap_or(ConfigSource::Default)
+    }
+
     pub fn log_config(&self) {
-        tracing::debug!("=== Server Configuration ===");
-        tracing::debug!("Host: {}", self.host);
-        tracing::debug!("Port: {}", self.port);
-        tracing::debug!("Private Port: {}", self.private_port);
-        tracing::debug!("LNURL Domain: {}", self.lnurl_domain);
-        tracing::debug!("Postgres URL: [REDACTED]");
-        tracing::debug!(
-            "Postgres connection pool: max={}, min={}",
-            self.postgres_max_connections,
-            self.postgres_min_connections.unwrap_or(1)
-        );
-        tracing::debug!("Expo Access Token: [REDACTED]");
-        tracing::debug!("Ark Server URL: {}", self.ark_server_url);
-        tracing::debug!("Server Network: {}", self.server_network);
-        tracing::debug!(
-            "Sentry URL: {}",
-            if self.sentry_url.is_some() {
-                "[SET]"
-            } else {
-                "[NOT SET]"
-            }
-        );
-        tracing::debug!("Backup Cron: {}", self.backup_cron);
-        tracing::debug!("Heartbeat Cron: {}", self.heartbeat_cron);
-        tracing::debug!("Deregister Cron: {}", self.deregister_cron);
-        tracing::debug!(
-            "Notification Spacing Minutes: {}",
-            self.notification_spacing_minutes
-        );
-        tracing::debug!(
-            "Maintenance Interval Rounds: {}",
-            self.maintenance_interval_rounds
-        );
-        tracing::debug!(
-            "Maintenance Notification Advance Secs: {}",
-            self.maintenance_notification_advance_secs
+        let entries = self.redacted_entries();
+        let count = |source| {
+            entries
+                .iter()
+                .filter(|(key, _)| self.source_of(key) == source)
+                .count()
+        };
+        tracing::info!(
+            env = count(ConfigSource::Env),
+            file = count(ConfigSource::File),
+            default = count(ConfigSource::Default),
+            "Configuration loaded"
         );
-        tracing::debug!("S3 Bucket Name: [REDACTED]");
-        tracing::debug!("Minimum App Version: {}", self.minimum_app_version);
-        tracing::debug!("Redis URL: [REDACTED]");
-        tracing::debug!("Redis Pool Size: {}", self.redis_pool_size);
-        tracing::debug!("Ntfy Auth Token: [REDACTED]");
-        tracing::debug!("SES From Address: {}", self.ses_from_address);
-        tracing::debug!("JWT Auth Secret: [REDACTED]");
-        tracing::debug!("JWT TTL Hours: {}", self.auth_jwt_ttl_hours);
+
+        tracing::debug!("=== Server Configuration ===");
+        for (key, value) in &entries {
+            tracing::debug!("{}: {} ({})", key, value, self.source_of(key));
+        }
         tracing::debug!("============================");
     }
 }
+
+fn sentry_enabled_for(has_url: bool, enabled: Option<bool>, network: Network) -> bool {
+    has_url && enabled.unwrap_or(matches!(network, Network::Bitcoin | Network::Signet))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sentry_enabled_decision_matrix() {
+        let cases = [
+            (true, None, Network::Bitcoin, true),
+            (true, None, Network::Signet, true),
+            (true, None, Network::Regtest, false),
+            (true, Some(false), Network::Signet, false),
+            (true, Some(true), Network::Regtest, true),
+            (false, Some(true), Network::Bitcoin, false),
+            (false, None, Network::Bitcoin, false),
+        ];
+
+        for (has_url, enabled, network, expected) in cases {
+            assert_eq!(
+                sentry_enabled_for(has_url, enabled, network),
+                expected,
+                "has_url={has_url} enabled={enabled:?} network={network}"
+            );
+        }
+    }
+
+    #[test]
+    fn config_source_prefers_process_env() {
+        assert_eq!(config_source(true, true), ConfigSource::Env);
+        assert_eq!(config_source(false, true), ConfigSource::File);
+        assert_eq!(config_source(false, false), ConfigSource::Default);
+    }
+}
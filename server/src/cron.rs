@@ -4,7 +4,7 @@ use crate::{
         backup_repo::BackupRepository, heartbeat_repo::HeartbeatRepository,
         job_status_repo::JobStatusRepository,
         mailbox_authorization_repo::MailboxAuthorizationRepository,
-        push_token_repo::PushTokenRepository,
+        push_token_repo::PushTokenRepository, user_repo::UserRepository,
     },
     notification_coordinator::{NotificationCoordinator, NotificationRequest},
     types::{HeartbeatNotification, NotificationRequestData},
@@ -17,6 +17,8 @@ const STALE_PENDING_JOB_SWEEP_SCHEDULE: &str = "every 10 minutes";
 const STALE_PENDING_JOB_ERROR_MESSAGE: &str = "Timed out after 1 hour waiting for client response";
 const STALE_PENDING_HEARTBEAT_TIMEOUT_MINUTES: i64 = 60;
 const STALE_PENDING_HEARTBEAT_SWEEP_SCHEDULE: &str = "every 10 minutes";
+const INACTIVE_ACCOUNT_PURGE_BATCH_LIMIT: i64 = 500;
+const JOB_STATUS_RETENTION_BATCH_LIMIT: i64 = 10_000;
 
 pub async fn send_backup_notifications(app_state: AppState) -> anyhow::Result<()> {
     let backup_repo = BackupRepository::new(&app_state.db_pool);
@@ -131,6 +133,123 @@ pub async fn check_and_deregister_inactive_users(app_state: AppState) -> anyhow:
     Ok(())
 }
 
+/// Deletes accounts that have been inactive for `inactive_days` and never stored a backup.
+///
+/// In dry-run mode candidates are only logged. Each run is capped at
+/// `INACTIVE_ACCOUNT_PURGE_BATCH_LIMIT` accounts.
+pub async fn purge_inactive_accounts(
+    app_state: AppState,
+    inactive_days: u32,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let inactive_days = i32::try_from(inactive_days)?;
+    let user_repo = UserRepository::new(&app_state.db_pool);
+
+    let candidates = user_repo
+        .find_inactive_without_backups(inactive_days, INACTIVE_ACCOUNT_PURGE_BATCH_LIMIT)
+        .await?;
+
+    if candidates.is_empty() {
+        return Ok(());
+    }
+
+    tracing::info!(
+        job = "inactive_account_purge",
+        user_count = candidates.len(),
+        inactive_days,
+        dry_run,
+        "starting"
+    );
+
+    let mut purged = 0;
+    for pubkey in candidates {
+        if dry_run {
+            tracing::info!(job = "inactive_account_purge", pubkey = %pubkey, "would purge account (dry run)");
+            continue;
+        }
+
+        let mut tx = app_state.db_pool.begin().await?;
+        match UserRepository::delete_if_inactive_without_backups(&mut tx, &pubkey, inactive_days)
+            .await
+        {
+            Ok(true) => {
+                if let Err(e) = tx.commit().await {
+                    tracing::error!(job = "inactive_account_purge", pubkey = %pubkey, step = "commit", error = %e, "transaction failed");
+                } else {
+                    purged += 1;
+                    tracing::info!(job = "inactive_account_purge", pubkey = %pubkey, "account purged");
+                }
+            }
+            Ok(false) => {
+                tracing::debug!(job = "inactive_account_purge", pubkey = %pubkey, "account no longer eligible, skipping");
+            }
+            Err(e) => {
+                tracing::error!(job = "inactive_account_purge", pubkey = %pubkey, error = %e, "delete failed");
+            }
+        }
+    }
+
+    if !dry_run {
+        tracing::info!(
+            job = "inactive_account_purge",
+            purged_count = purged,
+            "finished"
+        );
+    }
+
+    Ok(())
+}
+
+/// Deletes job status reports older than `retention_days` in batches, then reports the
+/// remaining row count and alerts when it is above `soft_cap`. A `retention_days` of 0 keeps
+/// every report and only does the reporting.
+///
+/// Per-user pruning bounds each user, this bounds the table as users churn.
+/// Returns the number of deleted reports.
+pub async fn prune_job_status_reports(
+    app_state: AppState,
+    retention_days: u32,
+    soft_cap: u64,
+) -> anyhow::Result<u64> {
+    let retention_days = i32::try_from(retention_days)?;
+
+    let mut deleted = 0;
+    if retention_days > 0 {
+        loop {
+            let batch = JobStatusRepository::delete_older_than(
+                &app_state.db_pool,
+                retention_days,
+                JOB_STATUS_RETENTION_BATCH_LIMIT,
+            )
+            .await?;
+            deleted += batch;
+            if batch < JOB_STATUS_RETENTION_BATCH_LIMIT as u64 {
+                break;
+            }
+        }
+    }
+
+    let remaining = JobStatusRepository::count_all(&app_state.db_pool).await?;
+    tracing::info!(
+        job = "job_status_retention",
+        deleted_count = deleted,
+        remaining_count = remaining,
+        retention_days,
+        "finished"
+    );
+
+    if soft_cap > 0 && remaining as u64 > soft_cap {
+        tracing::warn!(
+            job = "job_status_retention",
+            remaining_count = remaining,
+            soft_cap,
+            "job_status_reports is above its soft cap"
+        );
+    }
+
+    Ok(deleted)
+}
+
 async fn redis_keepalive(app_state: AppState) -> anyhow::Result<()> {
     app_state.k1_cache.contains("keepalive").await?;
     Ok(())
@@ -192,6 +311,8 @@ pub async fn cron_scheduler(
         stale_pending_job_timeout_minutes = STALE_PENDING_JOB_TIMEOUT_MINUTES,
         stale_pending_heartbeat_cleanup_schedule = %STALE_PENDING_HEARTBEAT_SWEEP_SCHEDULE,
         stale_pending_heartbeat_timeout_minutes = STALE_PENDING_HEARTBEAT_TIMEOUT_MINUTES,
+        job_status_retention_schedule = %app_state.config.job_status_retention_cron,
+        job_status_retention_days = app_state.config.job_status_retention_days,
         "scheduler initialized"
     );
 
@@ -230,6 +351,33 @@ pub async fn cron_scheduler(
     })?;
     sched.add(inactive_check_job).await?;
 
+    // Purge long-inactive accounts, only when explicitly configured
+    if let Some(inactive_days) = app_state.config.inactive_account_purge_days {
+        let dry_run = app_state.config.inactive_account_purge_dry_run;
+        tracing::info!(
+            service = "cron",
+            inactive_account_purge_schedule = %app_state.config.inactive_account_purge_cron,
+            inactive_days,
+            dry_run,
+            "inactive account purge enabled"
+        );
+
+        let purge_app_state = app_state.clone();
+        let purge_job = Job::new_async(
+            app_state.config.inactive_account_purge_cron.as_str(),
+            move |_, _| {
+                let app_state = purge_app_state.clone();
+                Box::pin(async move {
+                    if let Err(e) = purge_inactive_accounts(app_state, inactive_days, dry_run).await
+                    {
+                        tracing::error!(job = "inactive_account_purge", error = %e, "job failed");
+                    }
+                })
+            },
+        )?;
+        sched.add(purge_job).await?;
+    }
+
     // Mark stale pending job reports as timeout
     let stale_pending_job_cleanup_state = app_state.clone();
     let stale_pending_job_cleanup =
@@ -256,6 +404,24 @@ pub async fn cron_scheduler(
         })?;
     sched.add(stale_pending_heartbeat_cleanup).await?;
 
+    // Bulk cleanup of old job status reports
+    let retention_days = app_state.config.job_status_retention_days;
+    let soft_cap = app_state.config.job_status_soft_cap;
+    let job_status_retention_state = app_state.clone();
+    let job_status_retention = Job::new_async(
+        app_state.config.job_status_retention_cron.as_str(),
+        move |_, _| {
+            let app_state = job_status_retention_state.clone();
+            Box::pin(async move {
+                if let Err(e) = prune_job_status_reports(app_state, retention_days, soft_cap).await
+                {
+                    tracing::error!(job = "job_status_retention", error = %e, "job failed");
+                }
+            })
+        },
+    )?;
+    sched.add(job_status_retention).await?;
+
     // Redis keepalive to prevent Upstash idle connection timeout
     let keepalive_app_state = app_state.clone();
     let keepalive_job = Job::new_async("every 2 minutes", move |_, _| {
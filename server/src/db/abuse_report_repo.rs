@@ -0,0 +1,47 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, PgPool};
+
+/// A user's complaint about being spammed with invoice requests.
+#[derive(Debug, Clone, PartialEq, Eq, FromRow)]
+pub struct AbuseReport {
+    pub id: i64,
+    pub pubkey: String,
+    pub reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+pub struct AbuseReportRepository<'a> {
+    pool: &'a PgPool,
+}
+
+impl<'a> AbuseReportRepository<'a> {
+    pub fn new(pool: &'a PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Stores a report and returns its id.
+    pub async fn create(&self, pubkey: &str, reason: Option<&str>) -> Result<i64> {
+        let id = sqlx::query_scalar(
+            "INSERT INTO abuse_reports (pubkey, reason) VALUES ($1, $2) RETURNING id",
+        )
+        .bind(pubkey)
+        .bind(reason)
+        .fetch_one(self.pool)
+        .await?;
+        Ok(id)
+    }
+
+    /// Returns the most recent reports first.
+    pub async fn list_recent(&self, limit: i64) -> Result<Vec<AbuseReport>> {
+        let reports = sqlx::query_as::<_, AbuseReport>(
+            "SELECT id, pubkey, reason, created_at FROM abuse_reports
+             ORDER BY created_at DESC, id DESC
+             LIMIT $1",
+        )
+        .bind(limit)
+        .fetch_all(self.pool)
+        .await?;
+        Ok(reports)
+    }
+}
@@ -0,0 +1,36 @@
+use anyhow::Result;
+use bitcoin::hashes::{Hash, sha256};
+use serde::Serialize;
+use sqlx::{Postgres, Transaction};
+
+pub struct AdminAuditRepository;
+
+impl AdminAuditRepository {
+    /// Records an admin action against a user in the same transaction as the action itself.
+    ///
+    /// Only a hash of the pubkey is kept, so the row can outlive a purged user without
+    /// identifying them. Returns the id of the new row.
+    pub async fn record_tx(
+        tx: &mut Transaction<'_, Postgres>,
+        action: &str,
+        pubkey: &str,
+        details: &impl Serialize,
+    ) -> Result<i64> {
+        let id = sqlx::query_scalar(
+            "INSERT INTO admin_audit_log (action, subject_hash, details)
+             VALUES ($1, $2, $3::jsonb)
+             RETURNING id",
+        )
+        .bind(action)
+        .bind(audit_subject_hash(pubkey))
+        .bind(serde_json::to_string(details)?)
+        .fetch_one(&mut **tx)
+        .await?;
+        Ok(id)
+    }
+}
+
+/// Hash an audit row stores in place of the pubkey it is about.
+pub fn audit_subject_hash(pubkey: &str) -> String {
+    sha256::Hash::hash(pubkey.as_bytes()).to_string()
+}
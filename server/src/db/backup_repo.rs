@@ -1,6 +1,6 @@
 use anyhow::Result;
 use chrono::{DateTime, Utc};
-use sqlx::{PgPool, Row, postgres::PgRow};
+use sqlx::{PgPool, Postgres, Row, Transaction, postgres::PgRow};
 use std::convert::TryFrom;
 
 use crate::types::BackupInfo;
@@ -25,6 +25,15 @@ impl<'r> sqlx::FromRow<'r, PgRow> for BackupMetadata {
     }
 }
 
+/// Aggregate storage used by a user's backups.
+#[derive(Debug, PartialEq, Eq)]
+pub struct BackupUsage {
+    pub backup_count: u64,
+    pub total_bytes: u64,
+    pub oldest_backup_at: Option<DateTime<Utc>>,
+    pub newest_backup_at: Option<DateTime<Utc>>,
+}
+
 /// A struct to encapsulate backup-related database operations.
 pub struct BackupRepository<'a> {
     pool: &'a PgPool,
@@ -43,26 +52,123 @@ impl<'a> BackupRepository<'a> {
         s3_key: &str,
         backup_size: u64,
         backup_version: i32,
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        Self::upsert_metadata_tx(&mut tx, pubkey, s3_key, backup_size, backup_version, None)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Inserts or updates backup metadata within a transaction.
+    ///
+    /// The checksum is replaced too, so a backup uploaded without one never matches the
+    /// checksum of the backup it overwrote.
+    pub async fn upsert_metadata_tx(
+        tx: &mut Transaction<'_, Postgres>,
+        pubkey: &str,
+        s3_key: &str,
+        backup_size: u64,
+        backup_version: i32,
+        checksum: Option<&str>,
     ) -> Result<()> {
         let size = i64::try_from(backup_size)?;
         sqlx::query(
-            "INSERT INTO backup_metadata (pubkey, s3_key, backup_size, backup_version)
-             VALUES ($1, $2, $3, $4)
+            "INSERT INTO backup_metadata (pubkey, s3_key, backup_size, backup_version, checksum)
+             VALUES ($1, $2, $3, $4, $5)
              ON CONFLICT(pubkey, backup_version)
              DO UPDATE SET
                 s3_key = excluded.s3_key,
                 backup_size = excluded.backup_size,
+                checksum = excluded.checksum,
                 created_at = now()",
         )
         .bind(pubkey)
         .bind(s3_key)
         .bind(size)
         .bind(backup_version)
-        .execute(self.pool)
+        .bind(checksum)
+        .execute(&mut **tx)
         .await?;
         Ok(())
     }
 
+    /// Records that a backup was just completed by setting `last_backup_at` to now.
+    pub async fn touch_last_backup_at_tx(
+        tx: &mut Transaction<'_, Postgres>,
+        pubkey: &str,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO backup_settings (pubkey, last_backup_at)
+             VALUES ($1, now())
+             ON CONFLICT(pubkey)
+             DO UPDATE SET last_backup_at = excluded.last_backup_at",
+        )
+        .bind(pubkey)
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    /// Points the user's latest backup at the version that was just completed.
+    pub async fn set_latest_version_tx(
+        tx: &mut Transaction<'_, Postgres>,
+        pubkey: &str,
+        backup_version: i32,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO backup_settings (pubkey, latest_backup_version)
+             VALUES ($1, $2)
+             ON CONFLICT(pubkey)
+             DO UPDATE SET latest_backup_version = excluded.latest_backup_version",
+        )
+        .bind(pubkey)
+        .bind(backup_version)
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    /// Counts and sums the sizes of the user's backups, leaving out `exclude_version`, which
+    /// an upload of that version replaces.
+    /// Returns a tuple of (backup_count, total_bytes).
+    ///
+    /// Takes a transaction-scoped lock on the user's backups first, so two concurrent uploads
+    /// can't both fit under the quota before either commits.
+    pub async fn usage_excluding_version_tx(
+        tx: &mut Transaction<'_, Postgres>,
+        pubkey: &str,
+        exclude_version: i32,
+    ) -> Result<(u64, u64)> {
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtext('backup_quota:' || $1))")
+            .bind(pubkey)
+            .execute(&mut **tx)
+            .await?;
+
+        let (count, total) = sqlx::query_as::<_, (i64, i64)>(
+            "SELECT COUNT(*), COALESCE(SUM(backup_size), 0)::bigint
+             FROM backup_metadata
+             WHERE pubkey = $1 AND backup_version <> $2",
+        )
+        .bind(pubkey)
+        .bind(exclude_version)
+        .fetch_one(&mut **tx)
+        .await?;
+        Ok((count as u64, total as u64))
+    }
+
+    /// Gets the time of the user's last completed backup.
+    pub async fn get_last_backup_at(&self, pubkey: &str) -> Result<Option<DateTime<Utc>>> {
+        let last_backup_at = sqlx::query_scalar::<_, Option<DateTime<Utc>>>(
+            "SELECT last_backup_at FROM backup_settings WHERE pubkey = $1",
+        )
+        .bind(pubkey)
+        .fetch_optional(self.pool)
+        .await?;
+
+        Ok(last_backup_at.flatten())
+    }
+
     /// [TEST ONLY] Inserts or updates backup metadata with a specific creation timestamp.
     #[cfg(test)]
     pub async fn upsert_metadata_with_timestamp(
@@ -118,6 +224,28 @@ impl<'a> BackupRepository<'a> {
         Ok(backups)
     }
 
+    /// Sums the storage used by all of the user's backups.
+    pub async fn usage(&self, pubkey: &str) -> Result<BackupUsage> {
+        let (count, total, oldest, newest) = sqlx::query_as::<
+            _,
+            (i64, i64, Option<DateTime<Utc>>, Option<DateTime<Utc>>),
+        >(
+            "SELECT COUNT(*), COALESCE(SUM(backup_size), 0)::bigint, MIN(created_at), MAX(created_at)
+             FROM backup_metadata
+             WHERE pubkey = $1",
+        )
+        .bind(pubkey)
+        .fetch_one(self.pool)
+        .await?;
+
+        Ok(BackupUsage {
+            backup_count: count as u64,
+            total_bytes: total as u64,
+            oldest_backup_at: oldest,
+            newest_backup_at: newest,
+        })
+    }
+
     /// Finds a specific backup by version.
     /// Returns a tuple of (s3_key, backup_size).
     pub async fn find_by_version(
@@ -140,11 +268,18 @@ impl<'a> BackupRepository<'a> {
 
     /// Finds the latest backup for a user.
     /// Returns a tuple of (s3_key, backup_size).
+    ///
+    /// The latest backup is the one `latest_backup_version` points at, falling back to the
+    /// newest remaining backup when that version was deleted or the pointer was never set.
     pub async fn find_latest(&self, pubkey: &str) -> Result<Option<(String, u64)>> {
         let record = sqlx::query_as::<_, (String, i64)>(
-            "SELECT s3_key, backup_size
-             FROM backup_metadata WHERE pubkey = $1
-             ORDER BY created_at DESC LIMIT 1",
+            "SELECT m.s3_key, m.backup_size
+             FROM backup_metadata m
+             LEFT JOIN backup_settings s ON s.pubkey = m.pubkey
+             WHERE m.pubkey = $1
+             ORDER BY (m.backup_version = s.latest_backup_version) IS TRUE DESC,
+                      m.created_at DESC
+             LIMIT 1",
         )
         .bind(pubkey)
         .fetch_optional(self.pool)
@@ -152,6 +287,40 @@ impl<'a> BackupRepository<'a> {
         Ok(record.map(|(key, size)| (key, size as u64)))
     }
 
+    /// Finds the version of the user's latest backup, chosen the same way as `find_latest`.
+    pub async fn find_latest_version(&self, pubkey: &str) -> Result<Option<i32>> {
+        let version = sqlx::query_scalar::<_, i32>(
+            "SELECT m.backup_version
+             FROM backup_metadata m
+             LEFT JOIN backup_settings s ON s.pubkey = m.pubkey
+             WHERE m.pubkey = $1
+             ORDER BY (m.backup_version = s.latest_backup_version) IS TRUE DESC,
+                      m.created_at DESC
+             LIMIT 1",
+        )
+        .bind(pubkey)
+        .fetch_optional(self.pool)
+        .await?;
+        Ok(version)
+    }
+
+    /// Finds the checksum stored for a specific backup version, if the client sent one.
+    pub async fn find_checksum_by_version(
+        &self,
+        pubkey: &str,
+        version: i32,
+    ) -> Result<Option<String>> {
+        let checksum = sqlx::query_scalar::<_, Option<String>>(
+            "SELECT checksum FROM backup_metadata WHERE pubkey = $1 AND backup_version = $2",
+        )
+        .bind(pubkey)
+        .bind(version)
+        .fetch_optional(self.pool)
+        .await?;
+
+        Ok(checksum.flatten())
+    }
+
     /// Finds the S3 key for a specific backup version.
     pub async fn find_s3_key_by_version(
         &self,
@@ -169,6 +338,31 @@ impl<'a> BackupRepository<'a> {
         Ok(key)
     }
 
+    /// Lists the S3 keys of all of a user's backups.
+    pub async fn find_s3_keys(&self, pubkey: &str) -> Result<Vec<String>> {
+        let keys = sqlx::query_scalar::<_, String>(
+            "SELECT s3_key FROM backup_metadata WHERE pubkey = $1 ORDER BY backup_version",
+        )
+        .bind(pubkey)
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(keys)
+    }
+
+    /// Lists the metadata of every backup, for reconciling against the objects in S3.
+    pub async fn list_all_metadata(&self) -> Result<Vec<BackupMetadata>> {
+        let metadata = sqlx::query_as::<_, BackupMetadata>(
+            "SELECT pubkey, s3_key, backup_size, backup_version
+             FROM backup_metadata
+             ORDER BY pubkey, backup_version",
+        )
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(metadata)
+    }
+
     /// Finds the full metadata for a specific backup version.
     #[cfg(test)]
     pub async fn find_by_pubkey_and_version(
@@ -189,6 +383,29 @@ impl<'a> BackupRepository<'a> {
         Ok(metadata)
     }
 
+    /// Points a backup at a new S3 key, unless its key changed since it was read.
+    ///
+    /// Returns false when a newer upload replaced the row in the meantime.
+    pub async fn update_s3_key(
+        &self,
+        pubkey: &str,
+        version: i32,
+        old_s3_key: &str,
+        new_s3_key: &str,
+    ) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE backup_metadata SET s3_key = $1
+             WHERE pubkey = $2 AND backup_version = $3 AND s3_key = $4",
+        )
+        .bind(new_s3_key)
+        .bind(pubkey)
+        .bind(version)
+        .bind(old_s3_key)
+        .execute(self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
     /// Deletes a backup record by its version.
     pub async fn delete_by_version(&self, pubkey: &str, version: i32) -> Result<()> {
         sqlx::query("DELETE FROM backup_metadata WHERE pubkey = $1 AND backup_version = $2")
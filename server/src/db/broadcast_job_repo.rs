@@ -0,0 +1,250 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use anyhow::Result;
+use sqlx::PgPool;
+
+use crate::types::{BroadcastJobPriority, BroadcastJobStatus};
+
+/// How long a broadcast run keeps its job without renewing the lease. Once it lapses, another
+/// run may take the job over.
+pub const BROADCAST_JOB_LEASE_SECS: i64 = 120;
+
+/// Jobs whose lease expired longer ago than this are failed instead of resumed, since their
+/// recipients were notified too long ago to count for a new broadcast.
+pub const BROADCAST_JOB_MAX_RESUME_AGE_SECS: i64 = 6 * 60 * 60;
+
+/// A broadcast that was started but not yet completed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunningBroadcastJob {
+    pub id: i64,
+    pub notification_type: String,
+    pub priority: BroadcastJobPriority,
+    pub owner: Option<String>,
+}
+
+/// Result of trying to take a broadcast job for a run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BroadcastJobClaim {
+    /// A new job was started.
+    Started(i64),
+    /// An interrupted job whose lease expired was taken over.
+    Resumed(i64),
+    /// Another run holds a live lease on the job for this notification type.
+    Busy,
+}
+
+pub struct BroadcastJobRepository<'a> {
+    pool: &'a PgPool,
+}
+
+impl<'a> BroadcastJobRepository<'a> {
+    pub fn new(pool: &'a PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Starts a broadcast job for `owner`, or takes over the running one for the same
+    /// notification type once its lease has expired.
+    ///
+    /// Running jobs too old to resume are failed first, so the new run starts fresh.
+    pub async fn start_or_resume(
+        &self,
+        notification_type: &str,
+        priority: BroadcastJobPriority,
+        owner: &str,
+    ) -> Result<BroadcastJobClaim> {
+        sqlx::query(
+            "WITH abandoned AS (
+                 UPDATE broadcast_jobs
+                 SET status = $2, updated_at = now(), completed_at = now()
+                 WHERE notification_type = $1
+                   AND status = $3
+                   AND heartbeat_at < now() - make_interval(secs => $4)
+                   AND created_at < now() - make_interval(secs => $5)
+                 RETURNING id
+             )
+             DELETE FROM broadcast_job_recipients
+             WHERE job_id IN (SELECT id FROM abandoned)",
+        )
+        .bind(notification_type)
+        .bind(BroadcastJobStatus::Failed.to_string())
+        .bind(BroadcastJobStatus::Running.to_string())
+        .bind(BROADCAST_JOB_LEASE_SECS as f64)
+        .bind(BROADCAST_JOB_MAX_RESUME_AGE_SECS as f64)
+        .execute(self.pool)
+        .await?;
+
+        let created = sqlx::query_scalar::<_, i64>(
+            "INSERT INTO broadcast_jobs (notification_type, priority, status, owner, heartbeat_at)
+             VALUES ($1, $2, $3, $4, now())
+             ON CONFLICT (notification_type) WHERE status = 'running' DO NOTHING
+             RETURNING id",
+        )
+        .bind(notification_type)
+        .bind(priority.to_string())
+        .bind(BroadcastJobStatus::Running.to_string())
+        .bind(owner)
+        .fetch_optional(self.pool)
+        .await?;
+
+        if let Some(id) = created {
+            return Ok(BroadcastJobClaim::Started(id));
+        }
+
+        let taken_over = sqlx::query_scalar::<_, i64>(
+            "UPDATE broadcast_jobs
+             SET owner = $3, heartbeat_at = now(), updated_at = now()
+             WHERE notification_type = $1
+               AND status = $2
+               AND heartbeat_at < now() - make_interval(secs => $4)
+             RETURNING id",
+        )
+        .bind(notification_type)
+        .bind(BroadcastJobStatus::Running.to_string())
+        .bind(owner)
+        .bind(BROADCAST_JOB_LEASE_SECS as f64)
+        .fetch_optional(self.pool)
+        .await?;
+
+        Ok(match taken_over {
+            Some(id) => BroadcastJobClaim::Resumed(id),
+            None => BroadcastJobClaim::Busy,
+        })
+    }
+
+    /// Extends the lease of `owner` on a running job.
+    ///
+    /// Returns false when the job was taken over or finished, the run should then stop.
+    pub async fn renew_lease(&self, job_id: i64, owner: &str) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE broadcast_jobs
+             SET heartbeat_at = now(), updated_at = now()
+             WHERE id = $1 AND owner = $2 AND status = $3",
+        )
+        .bind(job_id)
+        .bind(owner)
+        .bind(BroadcastJobStatus::Running.to_string())
+        .execute(self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Lists interrupted broadcasts, i.e. running jobs whose lease has expired.
+    pub async fn find_expired(&self) -> Result<Vec<RunningBroadcastJob>> {
+        let rows = sqlx::query_as::<_, (i64, String, String, Option<String>)>(
+            "SELECT id, notification_type, priority, owner
+             FROM broadcast_jobs
+             WHERE status = $1 AND heartbeat_at < now() - make_interval(secs => $2)
+             ORDER BY created_at ASC",
+        )
+        .bind(BroadcastJobStatus::Running.to_string())
+        .bind(BROADCAST_JOB_LEASE_SECS as f64)
+        .fetch_all(self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|(id, notification_type, priority, owner)| {
+                Ok(RunningBroadcastJob {
+                    id,
+                    notification_type,
+                    priority: BroadcastJobPriority::from_str(&priority)?,
+                    owner,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the pubkeys already processed by a job.
+    pub async fn processed_pubkeys(&self, job_id: i64) -> Result<HashSet<String>> {
+        let pubkeys = sqlx::query_scalar::<_, String>(
+            "SELECT pubkey FROM broadcast_job_recipients WHERE job_id = $1",
+        )
+        .bind(job_id)
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(pubkeys.into_iter().collect())
+    }
+
+    /// Claims a recipient before sending to it.
+    ///
+    /// Returns false when the pubkey was already processed, so a resumed job never sends twice.
+    pub async fn claim_recipient(&self, job_id: i64, pubkey: &str) -> Result<bool> {
+        let result = sqlx::query(
+            "INSERT INTO broadcast_job_recipients (job_id, pubkey)
+             VALUES ($1, $2)
+             ON CONFLICT (job_id, pubkey) DO NOTHING",
+        )
+        .bind(job_id)
+        .bind(pubkey)
+        .execute(self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Marks a job held by `owner` as finished and drops its recipient list, which is only
+    /// needed to resume.
+    ///
+    /// Does nothing when the job was taken over by another run in the meantime.
+    pub async fn finish(
+        &self,
+        job_id: i64,
+        owner: Option<&str>,
+        status: BroadcastJobStatus,
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query(
+            "UPDATE broadcast_jobs
+             SET status = $1, updated_at = now(), completed_at = now()
+             WHERE id = $2 AND owner IS NOT DISTINCT FROM $3 AND status = $4",
+        )
+        .bind(status.to_string())
+        .bind(job_id)
+        .bind(owner)
+        .bind(BroadcastJobStatus::Running.to_string())
+        .execute(&mut *tx)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Ok(());
+        }
+
+        sqlx::query("DELETE FROM broadcast_job_recipients WHERE job_id = $1")
+            .bind(job_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// [TEST ONLY] Returns the status of a job.
+    #[cfg(test)]
+    pub async fn get_status(&self, job_id: i64) -> Result<BroadcastJobStatus> {
+        let status =
+            sqlx::query_scalar::<_, String>("SELECT status FROM broadcast_jobs WHERE id = $1")
+                .bind(job_id)
+                .fetch_one(self.pool)
+                .await?;
+
+        BroadcastJobStatus::from_str(&status)
+    }
+
+    /// [TEST ONLY] Lets a job's lease lapse, as if its run had died `secs_ago` seconds ago.
+    #[cfg(test)]
+    pub async fn expire_lease(&self, job_id: i64, secs_ago: i64) -> Result<()> {
+        sqlx::query(
+            "UPDATE broadcast_jobs
+             SET heartbeat_at = now() - make_interval(secs => $2),
+                 created_at = now() - make_interval(secs => $2)
+             WHERE id = $1",
+        )
+        .bind(job_id)
+        .bind(secs_ago as f64)
+        .execute(self.pool)
+        .await?;
+        Ok(())
+    }
+}
@@ -11,12 +11,14 @@ pub struct DeviceRepository;
 impl DeviceRepository {
     /// Inserts a new device record, or updates an existing one if the pubkey already exists.
     /// This operation is performed within a given transaction to ensure atomicity.
+    ///
+    /// A push token registered before the device info arrived is linked to the device here.
     pub async fn upsert(
         tx: &mut Transaction<'_, Postgres>,
         pubkey: &str,
         device_info: &DeviceInfo,
     ) -> Result<()> {
-        sqlx::query(
+        let device_id = sqlx::query_scalar::<_, i64>(
             "INSERT INTO devices (pubkey, device_manufacturer, device_model, os_name, os_version, app_version)
              VALUES ($1, $2, $3, $4, $5, $6)
              ON CONFLICT(pubkey) DO UPDATE SET
@@ -25,7 +27,8 @@ impl DeviceRepository {
                  os_name = excluded.os_name,
                  os_version = excluded.os_version,
                  app_version = excluded.app_version,
-                 updated_at = now()",
+                 updated_at = now()
+             RETURNING id",
         )
         .bind(pubkey)
         .bind(device_info.device_manufacturer.clone())
@@ -33,8 +36,14 @@ impl DeviceRepository {
         .bind(device_info.os_name.clone())
         .bind(device_info.os_version.clone())
         .bind(device_info.app_version.clone())
-        .execute(&mut **tx)
+        .fetch_one(&mut **tx)
         .await?;
+
+        sqlx::query("UPDATE push_tokens SET device_id = $1 WHERE pubkey = $2")
+            .bind(device_id)
+            .bind(pubkey)
+            .execute(&mut **tx)
+            .await?;
         Ok(())
     }
 }
@@ -0,0 +1,98 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use sqlx::PgPool;
+
+use crate::config::FeatureFlags;
+
+/// Per-user feature flag overrides used for staged rollouts.
+pub struct FeatureFlagRepository<'a> {
+    pool: &'a PgPool,
+}
+
+impl<'a> FeatureFlagRepository<'a> {
+    pub fn new(pool: &'a PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Returns the user's overrides keyed by flag name.
+    pub async fn find_overrides(&self, pubkey: &str) -> Result<BTreeMap<String, bool>> {
+        let rows = sqlx::query_as::<_, (String, bool)>(
+            "SELECT flag, enabled FROM feature_flag_overrides WHERE pubkey = $1",
+        )
+        .bind(pubkey)
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(rows.into_iter().collect())
+    }
+
+    /// Sets an override for the user, `None` removes it so the global value applies again.
+    pub async fn set_override(
+        &self,
+        pubkey: &str,
+        flag: &str,
+        enabled: Option<bool>,
+    ) -> Result<()> {
+        match enabled {
+            Some(enabled) => {
+                sqlx::query(
+                    "INSERT INTO feature_flag_overrides (pubkey, flag, enabled)
+                     VALUES ($1, $2, $3)
+                     ON CONFLICT (pubkey, flag)
+                     DO UPDATE SET enabled = EXCLUDED.enabled, updated_at = now()",
+                )
+                .bind(pubkey)
+                .bind(flag)
+                .bind(enabled)
+                .execute(self.pool)
+                .await?;
+            }
+            None => {
+                sqlx::query("DELETE FROM feature_flag_overrides WHERE pubkey = $1 AND flag = $2")
+                    .bind(pubkey)
+                    .bind(flag)
+                    .execute(self.pool)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Resolves all flags for the user, overrides taking precedence over the global values.
+    pub async fn resolve(
+        &self,
+        global: &FeatureFlags,
+        pubkey: &str,
+    ) -> Result<BTreeMap<String, bool>> {
+        Ok(global.resolve(self.find_overrides(pubkey).await?))
+    }
+
+    /// Whether `flag` is enabled for the user, flags that are not configured anywhere are off.
+    pub async fn is_enabled(
+        &self,
+        global: &FeatureFlags,
+        pubkey: &str,
+        flag: &str,
+    ) -> Result<bool> {
+        self.is_enabled_or(global, pubkey, flag, false).await
+    }
+
+    /// Whether `flag` is enabled for the user, falling back to `default` when it is not
+    /// configured anywhere.
+    pub async fn is_enabled_or(
+        &self,
+        global: &FeatureFlags,
+        pubkey: &str,
+        flag: &str,
+        default: bool,
+    ) -> Result<bool> {
+        Ok(self
+            .resolve(global, pubkey)
+            .await?
+            .get(flag)
+            .copied()
+            .unwrap_or(default))
+    }
+}
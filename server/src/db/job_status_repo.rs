@@ -112,6 +112,36 @@ impl JobStatusRepository {
         Ok(result.rows_affected())
     }
 
+    /// Deletes up to `limit` reports older than `older_than_days`, whatever their owner.
+    pub async fn delete_older_than(
+        pool: &sqlx::PgPool,
+        older_than_days: i32,
+        limit: i64,
+    ) -> Result<u64> {
+        let result = sqlx::query(
+            "DELETE FROM job_status_reports
+             WHERE id IN (
+                 SELECT id FROM job_status_reports
+                 WHERE created_at < now() - make_interval(days => $1)
+                 LIMIT $2
+             )",
+        )
+        .bind(older_than_days)
+        .bind(limit)
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Counts all job status reports.
+    pub async fn count_all(pool: &sqlx::PgPool) -> Result<i64> {
+        let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM job_status_reports")
+            .fetch_one(pool)
+            .await?;
+        Ok(count)
+    }
+
     /// [TEST ONLY] Counts the number of job status reports for a given user.
     #[cfg(test)]
     pub async fn count_by_pubkey(pool: &sqlx::PgPool, pubkey: &str) -> Result<i64> {
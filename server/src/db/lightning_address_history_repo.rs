@@ -0,0 +1,74 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, PgPool};
+
+/// A lightning address its owner has since replaced.
+#[derive(Debug, Clone, PartialEq, Eq, FromRow)]
+pub struct RetiredLightningAddress {
+    pub pubkey: String,
+    pub retired_at: DateTime<Utc>,
+}
+
+pub struct LightningAddressHistoryRepository<'a> {
+    pool: &'a PgPool,
+}
+
+impl<'a> LightningAddressHistoryRepository<'a> {
+    pub fn new(pool: &'a PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Records that `pubkey` stopped using `lightning_address`.
+    ///
+    /// An address retired again by a later owner only resolves to that owner.
+    pub async fn retire(&self, pubkey: &str, lightning_address: &str) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO lightning_address_history (lightning_address, pubkey, retired_at)
+             VALUES ($1, $2, now())
+             ON CONFLICT (lightning_address) DO UPDATE SET
+                pubkey = excluded.pubkey,
+                retired_at = excluded.retired_at",
+        )
+        .bind(lightning_address)
+        .bind(pubkey)
+        .execute(self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn find(&self, lightning_address: &str) -> Result<Option<RetiredLightningAddress>> {
+        let retired = sqlx::query_as::<_, RetiredLightningAddress>(
+            "SELECT pubkey, retired_at FROM lightning_address_history WHERE lightning_address = $1",
+        )
+        .bind(lightning_address)
+        .fetch_optional(self.pool)
+        .await?;
+        Ok(retired)
+    }
+
+    /// Drops the history of an address that was claimed again.
+    pub async fn forget(&self, lightning_address: &str) -> Result<()> {
+        sqlx::query("DELETE FROM lightning_address_history WHERE lightning_address = $1")
+            .bind(lightning_address)
+            .execute(self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// [TEST ONLY] Backdates when an address was retired.
+    #[cfg(test)]
+    pub async fn set_retired_at(
+        &self,
+        lightning_address: &str,
+        retired_at: DateTime<Utc>,
+    ) -> Result<()> {
+        sqlx::query(
+            "UPDATE lightning_address_history SET retired_at = $1 WHERE lightning_address = $2",
+        )
+        .bind(retired_at)
+        .bind(lightning_address)
+        .execute(self.pool)
+        .await?;
+        Ok(())
+    }
+}
@@ -1,7 +1,204 @@
 use anyhow::Result;
-use sqlx::PgPool;
+use serde::{Deserialize, Serialize};
+use sqlx::migrate::{AppliedMigration, Migrate, MigrateError, Migration, Migrator};
+use sqlx::{PgConnection, PgPool};
 
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// Applies pending migrations.
+///
+/// sqlx refuses to run over a partially applied or edited migration; that refusal is replaced
+/// with the full `MigrationStatus` description so startup says what is wrong with the schema.
 pub async fn run_migrations(pool: &PgPool) -> Result<()> {
-    sqlx::migrate!("./migrations").run(pool).await?;
-    Ok(())
+    match MIGRATOR.run(pool).await {
+        Ok(()) => Ok(()),
+        Err(e @ (MigrateError::Dirty(_) | MigrateError::VersionMismatch(_))) => {
+            migration_status(pool).await?.ensure_up_to_date()?;
+            Err(e.into())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Schema state of the connected database compared to the migrations built into this binary.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct MigrationStatus {
+    /// Versions recorded as applied in `_sqlx_migrations`, in ascending order.
+    pub applied_versions: Vec<i64>,
+    pub latest_applied_version: Option<i64>,
+    /// Versions built into the binary that the database hasn't applied yet.
+    pub pending_versions: Vec<i64>,
+    /// Version whose migration started but never finished, if any.
+    pub dirty_version: Option<i64>,
+    /// Applied versions whose checksum differs from the migration file built into the binary.
+    pub mismatched_versions: Vec<i64>,
+    /// True when nothing is pending, dirty or mismatched.
+    pub up_to_date: bool,
+}
+
+impl MigrationStatus {
+    /// Fails with a description of every problem unless the schema is up to date.
+    pub fn ensure_up_to_date(&self) -> Result<()> {
+        let mut problems = Vec::new();
+        if let Some(version) = self.dirty_version {
+            problems.push(format!(
+                "migration {} is dirty (partially applied)",
+                version
+            ));
+        }
+        if !self.pending_versions.is_empty() {
+            problems.push(format!(
+                "migrations {:?} have not been applied",
+                self.pending_versions
+            ));
+        }
+        if !self.mismatched_versions.is_empty() {
+            problems.push(format!(
+                "migrations {:?} were applied from a different file than the one built into this binary",
+                self.mismatched_versions
+            ));
+        }
+
+        if !problems.is_empty() {
+            anyhow::bail!(
+                "Database schema does not match this binary: {}. Fix the schema and the `_sqlx_migrations` table before starting the server",
+                problems.join("; ")
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Reads the applied migrations from sqlx's metadata table.
+pub async fn migration_status(pool: &PgPool) -> Result<MigrationStatus> {
+    let mut conn = pool.acquire().await?;
+    read_migration_status(&mut conn).await
+}
+
+pub(crate) async fn read_migration_status(conn: &mut PgConnection) -> Result<MigrationStatus> {
+    let applied = conn.list_applied_migrations().await?;
+    let dirty_version = conn.dirty_version().await?;
+
+    let known: Vec<&Migration> = MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+        .collect();
+
+    Ok(compare_migrations(&known, &applied, dirty_version))
+}
+
+fn compare_migrations(
+    known: &[&Migration],
+    applied: &[AppliedMigration],
+    dirty_version: Option<i64>,
+) -> MigrationStatus {
+    let mut applied_versions: Vec<i64> = applied.iter().map(|m| m.version).collect();
+    applied_versions.sort_unstable();
+
+    let pending_versions = known
+        .iter()
+        .filter(|m| applied_versions.binary_search(&m.version).is_err())
+        .map(|m| m.version)
+        .collect::<Vec<_>>();
+
+    let mismatched_versions = applied
+        .iter()
+        .filter(|a| {
+            known
+                .iter()
+                .any(|m| m.version == a.version && m.checksum != a.checksum)
+        })
+        .map(|a| a.version)
+        .collect::<Vec<_>>();
+
+    let up_to_date =
+        pending_versions.is_empty() && dirty_version.is_none() && mismatched_versions.is_empty();
+
+    MigrationStatus {
+        latest_applied_version: applied_versions.last().copied(),
+        applied_versions,
+        pending_versions,
+        dirty_version,
+        mismatched_versions,
+        up_to_date,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use sqlx::migrate::MigrationType;
+
+    use super::*;
+
+    fn migration(version: i64, sql: &'static str) -> Migration {
+        Migration::new(
+            version,
+            Cow::Borrowed("test"),
+            MigrationType::Simple,
+            Cow::Borrowed(sql),
+            false,
+        )
+    }
+
+    fn applied(migration: &Migration) -> AppliedMigration {
+        AppliedMigration {
+            version: migration.version,
+            checksum: migration.checksum.clone(),
+        }
+    }
+
+    #[test]
+    fn compare_migrations_reports_pending_and_mismatched() {
+        let first = migration(1, "CREATE TABLE a ();");
+        let second = migration(2, "CREATE TABLE b ();");
+        let third = migration(3, "CREATE TABLE c ();");
+        let edited_second = migration(2, "CREATE TABLE b (id INT);");
+
+        let status = compare_migrations(
+            &[&first, &second, &third],
+            &[applied(&first), applied(&edited_second)],
+            None,
+        );
+
+        assert_eq!(status.applied_versions, vec![1, 2]);
+        assert_eq!(status.latest_applied_version, Some(2));
+        assert_eq!(status.pending_versions, vec![3]);
+        assert_eq!(status.mismatched_versions, vec![2]);
+        assert!(!status.up_to_date);
+    }
+
+    #[test]
+    fn compare_migrations_is_up_to_date_when_all_applied() {
+        let first = migration(1, "CREATE TABLE a ();");
+
+        let status = compare_migrations(&[&first], &[applied(&first)], None);
+        assert!(status.up_to_date);
+
+        let status = compare_migrations(&[&first], &[applied(&first)], Some(1));
+        assert_eq!(status.dirty_version, Some(1));
+        assert!(!status.up_to_date);
+    }
+
+    #[test]
+    fn ensure_up_to_date_describes_every_problem() {
+        let first = migration(1, "CREATE TABLE a ();");
+        let second = migration(2, "CREATE TABLE b ();");
+
+        let status = compare_migrations(&[&first, &second], &[applied(&first)], None);
+        let err = status.ensure_up_to_date().unwrap_err().to_string();
+        assert!(
+            err.contains("migrations [2] have not been applied"),
+            "{}",
+            err
+        );
+
+        let status = compare_migrations(&[&first], &[applied(&first)], Some(1));
+        let err = status.ensure_up_to_date().unwrap_err().to_string();
+        assert!(err.contains("migration 1 is dirty"), "{}", err);
+
+        let status = compare_migrations(&[&first], &[applied(&first)], None);
+        assert!(status.ensure_up_to_date().is_ok());
+    }
 }
@@ -1,7 +1,12 @@
+pub mod abuse_report_repo;
+pub mod admin_audit_repo;
 pub mod backup_repo;
+pub mod broadcast_job_repo;
 pub mod device_repo;
+pub mod feature_flag_repo;
 pub mod heartbeat_repo;
 pub mod job_status_repo;
+pub mod lightning_address_history_repo;
 pub mod mailbox_authorization_repo;
 pub mod migrations;
 pub mod notification_tracking_repo;
@@ -4,6 +4,29 @@ use sqlx::PgPool;
 
 use crate::types::NotificationData;
 
+/// Matches users that have not been notified since `$1`.
+const SPACING_ELIGIBLE_PREDICATE: &str = "NOT EXISTS (
+    SELECT 1 FROM (
+        SELECT created_at AS sent_at
+        FROM job_status_reports
+        WHERE pubkey = u.pubkey
+        UNION ALL
+        SELECT sent_at
+        FROM heartbeat_notifications
+        WHERE pubkey = u.pubkey
+    ) notifications
+    WHERE notifications.sent_at > $1
+)";
+
+/// Matches users with backups enabled, unless the boolean parameter `$param` is false.
+pub(crate) fn backup_enabled_predicate(param: usize) -> String {
+    format!(
+        "(NOT ${param} OR EXISTS (
+    SELECT 1 FROM backup_settings bs WHERE bs.pubkey = u.pubkey AND bs.backup_enabled
+))"
+    )
+}
+
 /// Repository for reading notification timing used by spacing rules.
 ///
 /// Notification send time is now derived from:
@@ -59,22 +82,11 @@ impl<'a> NotificationTrackingRepository<'a> {
     pub async fn get_eligible_users(&self, min_spacing_minutes: i64) -> Result<Vec<String>> {
         let min_time = Utc::now() - chrono::Duration::minutes(min_spacing_minutes);
 
-        let pubkeys = sqlx::query_scalar::<_, String>(
+        let pubkeys = sqlx::query_scalar::<_, String>(&format!(
             "SELECT u.pubkey
              FROM users u
-             WHERE NOT EXISTS (
-                 SELECT 1 FROM (
-                     SELECT created_at AS sent_at
-                     FROM job_status_reports
-                     WHERE pubkey = u.pubkey
-                     UNION ALL
-                     SELECT sent_at
-                     FROM heartbeat_notifications
-                     WHERE pubkey = u.pubkey
-                 ) notifications
-                 WHERE notifications.sent_at > $1
-             )",
-        )
+             WHERE {SPACING_ELIGIBLE_PREDICATE}"
+        ))
         .bind(min_time)
         .fetch_all(self.pool)
         .await?;
@@ -82,6 +94,56 @@ impl<'a> NotificationTrackingRepository<'a> {
         Ok(pubkeys)
     }
 
+    /// Like `get_eligible_users`, but only returns users with a registered push token.
+    ///
+    /// With `backup_enabled_only`, users who turned off backups are left out as well.
+    pub async fn get_reachable_eligible_users(
+        &self,
+        min_spacing_minutes: i64,
+        backup_enabled_only: bool,
+    ) -> Result<Vec<String>> {
+        let min_time = Utc::now() - chrono::Duration::minutes(min_spacing_minutes);
+        let backup_enabled = backup_enabled_predicate(2);
+
+        let pubkeys = sqlx::query_scalar::<_, String>(&format!(
+            "SELECT u.pubkey
+             FROM users u
+             JOIN push_tokens pt ON pt.pubkey = u.pubkey
+             WHERE {SPACING_ELIGIBLE_PREDICATE}
+             AND {backup_enabled}"
+        ))
+        .bind(min_time)
+        .bind(backup_enabled_only)
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(pubkeys)
+    }
+
+    /// Counts users eligible by spacing that have no push token and so can't be notified.
+    pub async fn count_unreachable_eligible_users(
+        &self,
+        min_spacing_minutes: i64,
+        backup_enabled_only: bool,
+    ) -> Result<i64> {
+        let min_time = Utc::now() - chrono::Duration::minutes(min_spacing_minutes);
+        let backup_enabled = backup_enabled_predicate(2);
+
+        let count = sqlx::query_scalar::<_, i64>(&format!(
+            "SELECT COUNT(*)
+             FROM users u
+             WHERE {SPACING_ELIGIBLE_PREDICATE}
+             AND {backup_enabled}
+             AND NOT EXISTS (SELECT 1 FROM push_tokens pt WHERE pt.pubkey = u.pubkey)"
+        ))
+        .bind(min_time)
+        .bind(backup_enabled_only)
+        .fetch_one(self.pool)
+        .await?;
+
+        Ok(count)
+    }
+
     /// Get the last time a specific notification type was sent to a user.
     ///
     /// # Type Safety
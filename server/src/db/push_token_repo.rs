@@ -1,6 +1,24 @@
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use sqlx::{PgPool, Postgres, Transaction};
 
+use crate::config::TokenPruneSettings;
+
+/// A push token registered by a user, with the device it was registered from.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct PushTokenRecord {
+    pub id: i64,
+    pub push_token: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub device_id: Option<i64>,
+    pub device_model: Option<String>,
+    pub os_name: Option<String>,
+    pub os_version: Option<String>,
+    /// Last time the device reported its info to the server.
+    pub device_updated_at: Option<DateTime<Utc>>,
+}
+
 /// A struct to encapsulate push token-related database operations.
 pub struct PushTokenRepository<'a> {
     pool: &'a PgPool,
@@ -13,12 +31,19 @@ impl<'a> PushTokenRepository<'a> {
     }
 
     /// Inserts a new push token record, or updates the token if the pubkey already exists.
+    ///
+    /// The token is linked to the user's device when device info has been registered.
     pub async fn upsert(&self, pubkey: &str, push_token: &str) -> Result<()> {
         sqlx::query(
-            "INSERT INTO push_tokens (pubkey, push_token)
-             VALUES ($1, $2)
+            "INSERT INTO push_tokens (pubkey, push_token, device_id)
+             VALUES ($1, $2, (SELECT id FROM devices WHERE pubkey = $1))
              ON CONFLICT(pubkey)
-             DO UPDATE SET push_token = excluded.push_token, updated_at = now()",
+             DO UPDATE SET
+                 push_token = excluded.push_token,
+                 device_id = excluded.device_id,
+                 not_registered_count = 0,
+                 first_not_registered_at = NULL,
+                 updated_at = now()",
         )
         .bind(pubkey)
         .bind(push_token)
@@ -37,6 +62,98 @@ impl<'a> PushTokenRepository<'a> {
 
         Ok(token)
     }
+
+    /// Lists the push tokens registered by a user, newest first.
+    pub async fn list_by_pubkey(&self, pubkey: &str) -> Result<Vec<PushTokenRecord>> {
+        let records = sqlx::query_as::<_, PushTokenRecord>(
+            "SELECT p.id, p.push_token, p.created_at, p.updated_at, p.device_id,
+                    d.device_model, d.os_name, d.os_version, d.updated_at AS device_updated_at
+             FROM push_tokens p
+             LEFT JOIN devices d ON d.id = p.device_id
+             WHERE p.pubkey = $1
+             ORDER BY p.created_at DESC",
+        )
+        .bind(pubkey)
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Deletes one of a user's push tokens. Returns false when the user has no token with that id.
+    pub async fn delete_by_id(&self, pubkey: &str, id: i64) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM push_tokens WHERE pubkey = $1 AND id = $2")
+            .bind(pubkey)
+            .bind(id)
+            .execute(self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Records a `DeviceNotRegistered` ticket for a token and deletes the token once the
+    /// consecutive count reaches the threshold within the window.
+    ///
+    /// Returns true when the token was deleted.
+    pub async fn record_not_registered(
+        &self,
+        push_token: &str,
+        settings: &TokenPruneSettings,
+    ) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        // A streak older than the window starts over
+        let count = sqlx::query_scalar::<_, i32>(
+            "UPDATE push_tokens
+             SET not_registered_count = CASE
+                     WHEN first_not_registered_at IS NULL
+                       OR first_not_registered_at < now() - make_interval(secs => $2)
+                     THEN 1
+                     ELSE not_registered_count + 1
+                 END,
+                 first_not_registered_at = CASE
+                     WHEN first_not_registered_at IS NULL
+                       OR first_not_registered_at < now() - make_interval(secs => $2)
+                     THEN now()
+                     ELSE first_not_registered_at
+                 END
+             WHERE push_token = $1
+             RETURNING not_registered_count",
+        )
+        .bind(push_token)
+        .bind(settings.window_secs as f64)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(count) = count else {
+            return Ok(false);
+        };
+
+        let deleted = count >= settings.threshold as i32;
+        if deleted {
+            sqlx::query("DELETE FROM push_tokens WHERE push_token = $1")
+                .bind(push_token)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(deleted)
+    }
+
+    /// Resets the `DeviceNotRegistered` streak of tokens that accepted a push.
+    pub async fn clear_not_registered(&self, push_tokens: &[String]) -> Result<()> {
+        sqlx::query(
+            "UPDATE push_tokens
+             SET not_registered_count = 0, first_not_registered_at = NULL
+             WHERE push_token = ANY($1) AND not_registered_count > 0",
+        )
+        .bind(push_tokens)
+        .execute(self.pool)
+        .await?;
+        Ok(())
+    }
+
     /// Deletes all push tokens for a given user within a transaction.
     pub async fn delete_by_pubkey(tx: &mut Transaction<'_, Postgres>, pubkey: &str) -> Result<()> {
         sqlx::query("DELETE FROM push_tokens WHERE pubkey = $1")
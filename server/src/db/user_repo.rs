@@ -1,6 +1,10 @@
 use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use sqlx::{PgPool, Postgres, Transaction};
 
+use crate::types::LnurlpSuccessAction;
+
 #[derive(Debug, Clone)]
 pub struct LightningAddressTakenError;
 
@@ -32,6 +36,42 @@ pub struct User {
     pub ark_address: Option<String>,
     pub email: Option<String>,
     pub is_email_verified: bool,
+    /// IANA timezone name, `None` means UTC.
+    pub timezone: Option<String>,
+}
+
+/// Matches users inactive for `$1` days that have neither backups enabled nor any stored backup.
+const INACTIVE_WITHOUT_BACKUPS_PREDICATE: &str =
+    "COALESCE(u.last_login_at, u.created_at) < now() - make_interval(days => $1)
+    AND NOT EXISTS (SELECT 1 FROM backup_metadata bm WHERE bm.pubkey = u.pubkey)
+    AND NOT EXISTS (
+        SELECT 1 FROM backup_settings bs WHERE bs.pubkey = u.pubkey AND bs.backup_enabled
+    )";
+
+/// A user row as exposed to operators on the private admin API.
+#[derive(Debug, sqlx::FromRow)]
+pub struct AdminUserRecord {
+    pub pubkey: String,
+    pub lightning_address: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_login_at: Option<DateTime<Utc>>,
+    pub backup_enabled: bool,
+}
+
+/// Rows removed together with a user by `purge_tx`, counted per table.
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, sqlx::FromRow)]
+pub struct PurgedUserRows {
+    pub devices: i64,
+    pub push_tokens: i64,
+    pub backup_metadata: i64,
+    pub backup_settings: i64,
+    pub job_status_reports: i64,
+    pub heartbeat_notifications: i64,
+    pub mailbox_authorizations: i64,
+    pub feature_flag_overrides: i64,
+    pub lightning_address_history: i64,
+    pub abuse_reports: i64,
+    pub broadcast_job_recipients: i64,
 }
 
 // A struct to encapsulate user-related database operations
@@ -49,7 +89,7 @@ impl<'a> UserRepository<'a> {
     /// Finds a user by their public key.
     pub async fn find_by_pubkey(&self, pubkey: &str) -> Result<Option<User>> {
         let user = sqlx::query_as::<_, User>(
-            "SELECT pubkey, lightning_address, ark_address, email, is_email_verified FROM users WHERE pubkey = $1",
+            "SELECT pubkey, lightning_address, ark_address, email, is_email_verified, timezone FROM users WHERE pubkey = $1",
         )
         .bind(pubkey)
         .fetch_optional(self.pool)
@@ -58,6 +98,20 @@ impl<'a> UserRepository<'a> {
         Ok(user)
     }
 
+    /// Finds the users for the given public keys in a single query.
+    ///
+    /// Pubkeys without a user are skipped, so the result can be shorter than the input.
+    pub async fn find_many_by_pubkeys(&self, pubkeys: &[String]) -> Result<Vec<User>> {
+        let users = sqlx::query_as::<_, User>(
+            "SELECT pubkey, lightning_address, ark_address, email, is_email_verified, timezone FROM users WHERE pubkey = ANY($1)",
+        )
+        .bind(pubkeys)
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(users)
+    }
+
     /// Finds a user's pubkey by their lightning address.
     pub async fn find_pubkey_by_lightning_address(
         &self,
@@ -76,7 +130,7 @@ impl<'a> UserRepository<'a> {
     /// Finds a user by their lightning address.
     pub async fn find_by_lightning_address(&self, ln_address: &str) -> Result<Option<User>> {
         let user = sqlx::query_as::<_, User>(
-            "SELECT pubkey, lightning_address, ark_address, email, is_email_verified FROM users WHERE lightning_address = $1",
+            "SELECT pubkey, lightning_address, ark_address, email, is_email_verified, timezone FROM users WHERE lightning_address = $1",
         )
         .bind(ln_address)
         .fetch_optional(self.pool)
@@ -177,14 +231,67 @@ impl<'a> UserRepository<'a> {
                 if is_lightning_address_conflict(&e) {
                     return Err(LightningAddressTakenError.into());
                 }
-                if is_ark_address_conflict(&e) {
-                    return Err(DuplicateArkAddressError.into());
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Creates a user unless one with `pubkey` already exists, returning whether it was created.
+    ///
+    /// The existence check is the insert itself, so a concurrent insert of the same pubkey is
+    /// reported as existing rather than failing.
+    pub async fn create_if_absent(
+        tx: &mut Transaction<'_, Postgres>,
+        pubkey: &str,
+        ln_address: &str,
+    ) -> Result<bool> {
+        match sqlx::query(
+            "INSERT INTO users (pubkey, lightning_address) VALUES ($1, $2)
+             ON CONFLICT (pubkey) DO NOTHING",
+        )
+        .bind(pubkey)
+        .bind(ln_address)
+        .execute(&mut **tx)
+        .await
+        {
+            Ok(result) => Ok(result.rows_affected() > 0),
+            Err(e) => {
+                if is_lightning_address_conflict(&e) {
+                    return Err(LightningAddressTakenError.into());
                 }
                 Err(e.into())
             }
         }
     }
 
+    /// Fails with `DuplicateArkAddressError` if another user already has `ark_address`.
+    ///
+    /// Takes a transaction-scoped lock on the address first, so two transactions claiming the
+    /// same address can't both pass the check before either commits.
+    pub async fn ensure_ark_address_available_tx(
+        tx: &mut Transaction<'_, Postgres>,
+        pubkey: &str,
+        ark_address: &str,
+    ) -> Result<()> {
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtext('ark_address:' || $1))")
+            .bind(ark_address)
+            .execute(&mut **tx)
+            .await?;
+
+        let taken = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS(SELECT 1 FROM users WHERE ark_address = $1 AND pubkey <> $2)",
+        )
+        .bind(ark_address)
+        .bind(pubkey)
+        .fetch_one(&mut **tx)
+        .await?;
+
+        if taken {
+            return Err(DuplicateArkAddressError.into());
+        }
+        Ok(())
+    }
+
     /// Updates a user's lightning address.
     pub async fn update_lightning_address(&self, pubkey: &str, ln_address: &str) -> Result<()> {
         match sqlx::query(
@@ -205,22 +312,24 @@ impl<'a> UserRepository<'a> {
         }
     }
 
-    /// Updates a user's ark address.
-    pub async fn update_ark_address(&self, pubkey: &str, ark_address: &str) -> Result<()> {
-        match sqlx::query("UPDATE users SET ark_address = $1, updated_at = now() WHERE pubkey = $2")
+    /// Updates a user's ark address, rejecting one held by another user when `unique` is set.
+    pub async fn update_ark_address(
+        &self,
+        pubkey: &str,
+        ark_address: &str,
+        unique: bool,
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        if unique {
+            Self::ensure_ark_address_available_tx(&mut tx, pubkey, ark_address).await?;
+        }
+        sqlx::query("UPDATE users SET ark_address = $1, updated_at = now() WHERE pubkey = $2")
             .bind(ark_address)
             .bind(pubkey)
-            .execute(self.pool)
-            .await
-        {
-            Ok(_) => Ok(()),
-            Err(e) => {
-                if is_ark_address_conflict(&e) {
-                    return Err(DuplicateArkAddressError.into());
-                }
-                Err(e.into())
-            }
-        }
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
     }
 
     /// Checks if a user exists by their public key.
@@ -268,6 +377,18 @@ impl<'a> UserRepository<'a> {
         Ok(verified.unwrap_or(false))
     }
 
+    /// Checks whether an account other than `pubkey` has verified `email`, ignoring case.
+    pub async fn is_verified_email_taken(&self, email: &str, pubkey: &str) -> Result<bool> {
+        let taken = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS(SELECT 1 FROM users WHERE lower(email) = lower($1) AND is_email_verified AND pubkey <> $2)",
+        )
+        .bind(email.trim())
+        .bind(pubkey)
+        .fetch_one(self.pool)
+        .await?;
+        Ok(taken)
+    }
+
     /// Updates the user's last login timestamp.
     pub async fn update_last_login(&self, pubkey: &str) -> Result<()> {
         sqlx::query("UPDATE users SET last_login_at = now(), updated_at = now() WHERE pubkey = $1")
@@ -277,6 +398,222 @@ impl<'a> UserRepository<'a> {
         Ok(())
     }
 
+    /// Returns the amount the user suggests payers send, in millisatoshis.
+    pub async fn get_default_sendable_msat(&self, pubkey: &str) -> Result<Option<i64>> {
+        let default_sendable = sqlx::query_scalar::<_, Option<i64>>(
+            "SELECT default_sendable_msat FROM users WHERE pubkey = $1",
+        )
+        .bind(pubkey)
+        .fetch_optional(self.pool)
+        .await?
+        .flatten();
+        Ok(default_sendable)
+    }
+
+    /// Sets or clears the amount the user suggests payers send, in millisatoshis.
+    pub async fn update_default_sendable_msat(
+        &self,
+        pubkey: &str,
+        default_sendable_msat: Option<i64>,
+    ) -> Result<()> {
+        sqlx::query(
+            "UPDATE users SET default_sendable_msat = $1, updated_at = now() WHERE pubkey = $2",
+        )
+        .bind(default_sendable_msat)
+        .bind(pubkey)
+        .execute(self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Gets the success action shown to the user's LNURL-pay payers.
+    pub async fn get_lnurlp_success_action(
+        &self,
+        pubkey: &str,
+    ) -> Result<Option<LnurlpSuccessAction>> {
+        let success_action = sqlx::query_scalar::<_, Option<String>>(
+            "SELECT lnurlp_success_action FROM users WHERE pubkey = $1",
+        )
+        .bind(pubkey)
+        .fetch_optional(self.pool)
+        .await?
+        .flatten();
+
+        success_action
+            .map(|json| serde_json::from_str(&json))
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    /// Sets or clears the success action shown to the user's LNURL-pay payers.
+    pub async fn update_lnurlp_success_action(
+        &self,
+        pubkey: &str,
+        success_action: Option<&LnurlpSuccessAction>,
+    ) -> Result<()> {
+        let success_action = success_action.map(serde_json::to_string).transpose()?;
+        sqlx::query(
+            "UPDATE users SET lnurlp_success_action = $1, updated_at = now() WHERE pubkey = $2",
+        )
+        .bind(success_action)
+        .bind(pubkey)
+        .execute(self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Sets the user's IANA timezone, `None` resets it to UTC.
+    pub async fn update_timezone(&self, pubkey: &str, timezone: Option<&str>) -> Result<()> {
+        sqlx::query("UPDATE users SET timezone = $1, updated_at = now() WHERE pubkey = $2")
+            .bind(timezone)
+            .bind(pubkey)
+            .execute(self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Lists users ordered by `(created_at, pubkey)` using keyset pagination.
+    ///
+    /// `after` is the `(created_at, pubkey)` of the last row of the previous page.
+    pub async fn list_paginated(
+        &self,
+        after: Option<(DateTime<Utc>, &str)>,
+        limit: i64,
+    ) -> Result<Vec<AdminUserRecord>> {
+        let (after_created_at, after_pubkey) = match after {
+            Some((created_at, pubkey)) => (Some(created_at), Some(pubkey)),
+            None => (None, None),
+        };
+
+        let users = sqlx::query_as::<_, AdminUserRecord>(
+            "SELECT
+                u.pubkey,
+                u.lightning_address,
+                u.created_at,
+                u.last_login_at,
+                COALESCE(bs.backup_enabled, FALSE) AS backup_enabled
+            FROM users u
+            LEFT JOIN backup_settings bs ON bs.pubkey = u.pubkey
+            WHERE $1::timestamptz IS NULL OR (u.created_at, u.pubkey) > ($1, $2)
+            ORDER BY u.created_at ASC, u.pubkey ASC
+            LIMIT $3",
+        )
+        .bind(after_created_at)
+        .bind(after_pubkey)
+        .bind(limit)
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(users)
+    }
+
+    /// Returns the total number of registered users.
+    pub async fn count_all(&self) -> Result<i64> {
+        let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM users")
+            .fetch_one(self.pool)
+            .await?;
+        Ok(count)
+    }
+
+    /// Returns the number of users that logged in at or after `since`.
+    pub async fn count_active_since(&self, since: DateTime<Utc>) -> Result<i64> {
+        let count =
+            sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM users WHERE last_login_at >= $1")
+                .bind(since)
+                .fetch_one(self.pool)
+                .await?;
+        Ok(count)
+    }
+
+    /// Finds users that have not logged in for `inactive_days` and never stored a backup.
+    ///
+    /// Users that never reported a login are judged by their registration time.
+    pub async fn find_inactive_without_backups(
+        &self,
+        inactive_days: i32,
+        limit: i64,
+    ) -> Result<Vec<String>> {
+        let pubkeys = sqlx::query_scalar::<_, String>(&format!(
+            "SELECT u.pubkey
+            FROM users u
+            WHERE {INACTIVE_WITHOUT_BACKUPS_PREDICATE}
+            ORDER BY COALESCE(u.last_login_at, u.created_at) ASC
+            LIMIT $2"
+        ))
+        .bind(inactive_days)
+        .bind(limit)
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(pubkeys)
+    }
+
+    /// Deletes a user if they still match the inactivity criteria used by
+    /// `find_inactive_without_backups`. Dependent rows are removed by `ON DELETE CASCADE`.
+    ///
+    /// Returns `false` if the user became active or stored a backup in the meantime.
+    pub async fn delete_if_inactive_without_backups(
+        tx: &mut Transaction<'_, Postgres>,
+        pubkey: &str,
+        inactive_days: i32,
+    ) -> Result<bool> {
+        let result = sqlx::query(&format!(
+            "DELETE FROM users u
+            WHERE u.pubkey = $2 AND {INACTIVE_WITHOUT_BACKUPS_PREDICATE}"
+        ))
+        .bind(inactive_days)
+        .bind(pubkey)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Deletes a user and, through `ON DELETE CASCADE`, every row referencing them.
+    ///
+    /// Broadcast recipient rows don't reference `users`, so they are deleted here.
+    ///
+    /// Returns what was removed, or `None` if the user doesn't exist.
+    pub async fn purge_tx(
+        tx: &mut Transaction<'_, Postgres>,
+        pubkey: &str,
+    ) -> Result<Option<PurgedUserRows>> {
+        let rows = sqlx::query_as::<_, PurgedUserRows>(
+            "SELECT
+                (SELECT COUNT(*) FROM devices WHERE pubkey = $1) AS devices,
+                (SELECT COUNT(*) FROM push_tokens WHERE pubkey = $1) AS push_tokens,
+                (SELECT COUNT(*) FROM backup_metadata WHERE pubkey = $1) AS backup_metadata,
+                (SELECT COUNT(*) FROM backup_settings WHERE pubkey = $1) AS backup_settings,
+                (SELECT COUNT(*) FROM job_status_reports WHERE pubkey = $1) AS job_status_reports,
+                (SELECT COUNT(*) FROM heartbeat_notifications WHERE pubkey = $1)
+                    AS heartbeat_notifications,
+                (SELECT COUNT(*) FROM mailbox_authorizations WHERE pubkey = $1)
+                    AS mailbox_authorizations,
+                (SELECT COUNT(*) FROM feature_flag_overrides WHERE pubkey = $1)
+                    AS feature_flag_overrides,
+                (SELECT COUNT(*) FROM lightning_address_history WHERE pubkey = $1)
+                    AS lightning_address_history,
+                (SELECT COUNT(*) FROM abuse_reports WHERE pubkey = $1) AS abuse_reports,
+                (SELECT COUNT(*) FROM broadcast_job_recipients WHERE pubkey = $1)
+                    AS broadcast_job_recipients",
+        )
+        .bind(pubkey)
+        .fetch_one(&mut **tx)
+        .await?;
+
+        sqlx::query("DELETE FROM broadcast_job_recipients WHERE pubkey = $1")
+            .bind(pubkey)
+            .execute(&mut **tx)
+            .await?;
+
+        let result = sqlx::query("DELETE FROM users WHERE pubkey = $1")
+            .bind(pubkey)
+            .execute(&mut **tx)
+            .await?;
+
+        Ok((result.rows_affected() > 0).then_some(rows))
+    }
+
     #[cfg(test)]
     pub async fn get_last_login_at(
         &self,
@@ -298,12 +635,3 @@ fn is_lightning_address_conflict(error: &sqlx::Error) -> bool {
 
     false
 }
-
-fn is_ark_address_conflict(error: &sqlx::Error) -> bool {
-    if let sqlx::Error::Database(db_err) = error {
-        return db_err.code().as_deref() == Some("23505")
-            && db_err.constraint() == Some("users_ark_address_key");
-    }
-
-    false
-}
@@ -1,15 +1,21 @@
+use std::collections::BTreeMap;
+
 use axum::{
     Json,
-    http::StatusCode,
+    http::{HeaderValue, StatusCode, header},
     response::{IntoResponse, Response},
 };
 
+use validator::{ValidationErrors, ValidationErrorsKind};
+
 use crate::types::ApiErrorResponse;
 
 #[derive(Debug, thiserror::Error)]
 pub enum ApiError {
     #[error("Invalid argument: {0}")]
     InvalidArgument(String),
+    #[error("Validation failed: {0}")]
+    Validation(#[from] ValidationErrors),
     #[error("Serialize error: {0}")]
     SerializeErr(String),
     #[error("Server error: {0}")]
@@ -30,12 +36,28 @@ pub enum ApiError {
     InvalidToken,
     #[error("Token expired")]
     TokenExpired,
+    #[error("Token bound to action {0}")]
+    ActionNotAllowed(String),
     #[error("Not found: {0}")]
     NotFound(String),
-    #[error("K1 expired")]
-    K1Expired,
+    #[error("Route not found")]
+    RouteNotFound,
+    #[error("Method not allowed")]
+    MethodNotAllowed,
+    #[error("K1 expired (server time {server_time})")]
+    K1Expired { server_time: u64 },
+    #[error("Request timestamp outside the allowed skew (server time {server_time})")]
+    ClockSkew { server_time: u64 },
+    #[error("Invalid proof of work")]
+    InvalidProofOfWork,
     #[error("User not found")]
     UserNotFound,
+    #[error("Too many requests, retry after {0} seconds")]
+    TooManyRequests(u64),
+    #[error("Temporarily blocked for {0} seconds")]
+    TemporarilyBlocked(u64),
+    #[error("Quota exceeded: {0}")]
+    QuotaExceeded(String),
 }
 
 const GENERIC_SERVER_MESSAGE: &str = "Something went wrong on our end. Please try again.";
@@ -44,6 +66,7 @@ impl ApiError {
     fn status_code(&self) -> StatusCode {
         match self {
             ApiError::InvalidArgument(_) => StatusCode::BAD_REQUEST,
+            ApiError::Validation(_) => StatusCode::BAD_REQUEST,
             ApiError::SerializeErr(_) => StatusCode::INTERNAL_SERVER_ERROR,
             ApiError::ServerErr(_) => StatusCode::INTERNAL_SERVER_ERROR,
             ApiError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
@@ -54,15 +77,23 @@ impl ApiError {
             ApiError::AuthRequired => StatusCode::UNAUTHORIZED,
             ApiError::InvalidToken => StatusCode::UNAUTHORIZED,
             ApiError::TokenExpired => StatusCode::UNAUTHORIZED,
+            ApiError::ActionNotAllowed(_) => StatusCode::FORBIDDEN,
             ApiError::NotFound(_) => StatusCode::NOT_FOUND,
-            ApiError::K1Expired => StatusCode::UNAUTHORIZED,
+            ApiError::RouteNotFound => StatusCode::NOT_FOUND,
+            ApiError::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            ApiError::K1Expired { .. } => StatusCode::UNAUTHORIZED,
+            ApiError::ClockSkew { .. } => StatusCode::UNAUTHORIZED,
+            ApiError::InvalidProofOfWork => StatusCode::UNAUTHORIZED,
             ApiError::UserNotFound => StatusCode::UNAUTHORIZED,
+            ApiError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::TemporarilyBlocked(_) => StatusCode::FORBIDDEN,
+            ApiError::QuotaExceeded(_) => StatusCode::FORBIDDEN,
         }
     }
 
     fn error_code(&self) -> &'static str {
         match self {
-            ApiError::InvalidArgument(_) => "INVALID_ARGUMENT",
+            ApiError::InvalidArgument(_) | ApiError::Validation(_) => "INVALID_ARGUMENT",
             ApiError::SerializeErr(_) => "SERVER_ERROR",
             ApiError::ServerErr(_) => "SERVER_ERROR",
             ApiError::Database(_) => "SERVER_ERROR",
@@ -73,23 +104,59 @@ impl ApiError {
             ApiError::AuthRequired => "AUTH_REQUIRED",
             ApiError::InvalidToken => "INVALID_TOKEN",
             ApiError::TokenExpired => "TOKEN_EXPIRED",
+            ApiError::ActionNotAllowed(_) => "ACTION_NOT_ALLOWED",
             ApiError::NotFound(_) => "NOT_FOUND",
-            ApiError::K1Expired => "K1_EXPIRED",
+            ApiError::RouteNotFound => "ROUTE_NOT_FOUND",
+            ApiError::MethodNotAllowed => "METHOD_NOT_ALLOWED",
+            ApiError::K1Expired { .. } => "K1_EXPIRED",
+            ApiError::ClockSkew { .. } => "CLOCK_SKEW",
+            ApiError::InvalidProofOfWork => "INVALID_PROOF_OF_WORK",
             ApiError::UserNotFound => "USER_NOT_FOUND",
+            ApiError::TooManyRequests(_) => "TOO_MANY_REQUESTS",
+            ApiError::TemporarilyBlocked(_) => "TEMPORARILY_BLOCKED",
+            ApiError::QuotaExceeded(_) => "QUOTA_EXCEEDED",
         }
     }
 
     fn user_message(&self) -> String {
         match self {
             ApiError::InvalidArgument(e) => e.to_string(),
+            ApiError::Validation(e) => e.to_string(),
             ApiError::NotFound(e) => e.to_string(),
+            ApiError::QuotaExceeded(e) => e.to_string(),
             ApiError::ServerErr(e) => e.to_string(),
             ApiError::InvalidSignature => "Invalid signature".to_string(),
             ApiError::AuthRequired => "Authentication required".to_string(),
             ApiError::InvalidToken => "Invalid token".to_string(),
             ApiError::TokenExpired => "Token expired".to_string(),
-            ApiError::K1Expired => "K1 expired".to_string(),
+            ApiError::ActionNotAllowed(_) => {
+                "This token was issued for a different action".to_string()
+            }
+            ApiError::K1Expired { .. } => {
+                "K1 expired. Please check that your device clock is correct.".to_string()
+            }
+            ApiError::ClockSkew { .. } => {
+                "Request timestamp is too far from server time. Please check that your device clock is correct."
+                    .to_string()
+            }
+            ApiError::RouteNotFound => "Route not found".to_string(),
+            ApiError::MethodNotAllowed => {
+                "Method not allowed, see the Allow header for supported methods".to_string()
+            }
+            ApiError::InvalidProofOfWork => "Invalid proof of work".to_string(),
             ApiError::UserNotFound => "User not found".to_string(),
+            ApiError::TooManyRequests(retry_after) => {
+                format!(
+                    "Too many requests. Please try again in {} seconds.",
+                    retry_after
+                )
+            }
+            ApiError::TemporarilyBlocked(retry_after) => {
+                format!(
+                    "Temporarily blocked due to suspicious activity. Please try again in {} seconds.",
+                    retry_after
+                )
+            }
             ApiError::SerializeErr(_)
             | ApiError::Database(_)
             | ApiError::Expo(_)
@@ -107,7 +174,12 @@ impl IntoResponse for ApiError {
 
         // Log the error with appropriate level based on status code
         match status {
-            StatusCode::BAD_REQUEST | StatusCode::UNAUTHORIZED | StatusCode::NOT_FOUND => {
+            StatusCode::BAD_REQUEST
+            | StatusCode::UNAUTHORIZED
+            | StatusCode::FORBIDDEN
+            | StatusCode::NOT_FOUND
+            | StatusCode::METHOD_NOT_ALLOWED
+            | StatusCode::TOO_MANY_REQUESTS => {
                 tracing::warn!(
                     error_type = ?self,
                     status = %status.as_u16(),
@@ -125,13 +197,101 @@ impl IntoResponse for ApiError {
             }
         }
 
+        let retry_after = match &self {
+            ApiError::TooManyRequests(retry_after) | ApiError::TemporarilyBlocked(retry_after) => {
+                Some(*retry_after)
+            }
+            _ => None,
+        };
+        let server_time = match &self {
+            ApiError::K1Expired { server_time } | ApiError::ClockSkew { server_time } => {
+                Some(*server_time)
+            }
+            _ => None,
+        };
+        let field_errors = match &self {
+            ApiError::Validation(errors) => Some(field_errors(errors)),
+            _ => None,
+        };
+
         let body = Json(ApiErrorResponse {
             status: "ERROR".to_string(),
             code: code.to_string(),
             message: message.clone(),
             reason: message,
+            server_time,
+            field_errors,
         });
 
-        (status, body).into_response()
+        let mut response = (status, body).into_response();
+        if let Some(retry_after) = retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+        }
+        response
+    }
+}
+
+/// Flattens `ValidationErrors` into messages keyed by field, using dotted paths for nested
+/// structs and list entries (e.g. `items.0.name`).
+fn field_errors(errors: &ValidationErrors) -> BTreeMap<String, Vec<String>> {
+    let mut map = BTreeMap::new();
+    collect_field_errors(errors, "", &mut map);
+    map
+}
+
+fn collect_field_errors(
+    errors: &ValidationErrors,
+    prefix: &str,
+    map: &mut BTreeMap<String, Vec<String>>,
+) {
+    for (field, kind) in errors.errors() {
+        let path = if prefix.is_empty() {
+            field.to_string()
+        } else {
+            format!("{}.{}", prefix, field)
+        };
+        match kind {
+            ValidationErrorsKind::Field(field_errors) => {
+                let messages = map.entry(path).or_default();
+                // Fall back to the error code when the validator has no message attached
+                messages.extend(field_errors.iter().map(|e| {
+                    e.message
+                        .as_ref()
+                        .map(|m| m.to_string())
+                        .unwrap_or_else(|| e.code.to_string())
+                }));
+            }
+            ValidationErrorsKind::Struct(nested) => collect_field_errors(nested, &path, map),
+            ValidationErrorsKind::List(entries) => {
+                for (index, nested) in entries {
+                    collect_field_errors(nested, &format!("{}.{}", path, index), map);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use validator::ValidationError;
+
+    use super::*;
+
+    #[test]
+    fn field_errors_lists_every_failing_field() {
+        let mut errors = ValidationErrors::new();
+        errors.add("email", ValidationError::new("email"));
+        errors.add(
+            "ln_address",
+            ValidationError::new("length").with_message("Too long".into()),
+        );
+        errors.add("ln_address", ValidationError::new("lightning_address"));
+
+        let map = field_errors(&errors);
+
+        assert_eq!(map["email"], vec!["email"]);
+        assert_eq!(map["ln_address"], vec!["Too long", "lightning_address"]);
     }
 }
@@ -2,6 +2,7 @@ use std::sync::Arc;
 
 use sqlx::PgPool;
 use sqlx::postgres::PgPoolOptions;
+use tokio::sync::Semaphore;
 
 pub mod cache;
 pub mod config;
@@ -15,8 +16,10 @@ pub mod utils;
 
 use crate::{
     cache::{
+        abuse_store::AbuseStore, admin_command_bus::AdminCommandBus,
         email_verification_store::EmailVerificationStore, invoice_store::InvoiceStore,
-        k1_store::K1Store, maintenance_store::MaintenanceStore, redis_client::RedisClient,
+        k1_store::K1Store, maintenance_store::MaintenanceStore, push_dedupe_store::PushDedupeStore,
+        redis_client::RedisClient,
     },
     config::Config,
     email_client::EmailClient,
@@ -35,6 +38,11 @@ pub struct AppStruct {
     pub email_verification_store: EmailVerificationStore,
     pub email_client: EmailClient,
     pub maintenance_store: MaintenanceStore,
+    pub abuse_store: AbuseStore,
+    pub push_dedupe_store: PushDedupeStore,
+    pub admin_command_bus: AdminCommandBus,
+    /// Limits how many broadcasts run at once, see `MAX_CONCURRENT_BROADCASTS`.
+    pub broadcast_slots: Arc<Semaphore>,
 }
 
 pub async fn build_app_state(config: Config) -> anyhow::Result<AppState> {
@@ -53,7 +61,11 @@ pub async fn build_app_state(config: Config) -> anyhow::Result<AppState> {
     let k1_cache = K1Store::new(redis_client.clone(), K1_TTL_SECONDS);
     let invoice_store = InvoiceStore::new(redis_client.clone());
     let maintenance_store = MaintenanceStore::new(redis_client.clone());
-    let email_verification_store = EmailVerificationStore::new(redis_client);
+    let abuse_store = AbuseStore::new(redis_client.clone());
+    let push_dedupe_store = PushDedupeStore::new(redis_client.clone());
+    let admin_command_bus = AdminCommandBus::new(redis_client.clone());
+    let email_verification_store =
+        EmailVerificationStore::new(redis_client, config.email_code_ttl_secs);
     let email_client =
         EmailClient::new(config.ses_from_address.clone(), config.email_dev_mode).await?;
 
@@ -66,5 +78,9 @@ pub async fn build_app_state(config: Config) -> anyhow::Result<AppState> {
         email_verification_store,
         email_client,
         maintenance_store,
+        abuse_store,
+        push_dedupe_store,
+        admin_command_bus,
+        broadcast_slots: Arc::new(Semaphore::new(config.max_concurrent_broadcasts)),
     }))
 }
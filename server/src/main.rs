@@ -1,5 +1,5 @@
 use axum::{
-    Router,
+    Json, Router,
     http::StatusCode,
     middleware,
     routing::{get, post},
@@ -9,41 +9,62 @@ mod cache;
 mod config;
 mod routes;
 mod types;
-use bitcoin::Network;
+use clap::Parser;
 use sentry::integrations::{
     tower::{NewSentryLayer, SentryHttpLayer},
     tracing::EventFilter,
 };
-use std::{net::SocketAddr, sync::Arc};
+use std::{
+    net::SocketAddr,
+    sync::{Arc, atomic::AtomicBool},
+    time::{Duration, Instant},
+};
+use tokio::sync::Semaphore;
 
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 use crate::{
+    ark_client::ArkConnectionStatus,
     cache::{
+        abuse_store::AbuseStore, admin_command_bus::AdminCommandBus,
         email_verification_store::EmailVerificationStore, invoice_store::InvoiceStore,
-        k1_store::K1Store, maintenance_store::MaintenanceStore, redis_client::RedisClient,
+        k1_store::K1Store, maintenance_store::MaintenanceStore, push_dedupe_store::PushDedupeStore,
+        redis_client::RedisClient,
     },
-    config::Config,
+    config::{Config, LogFormat},
     cron::cron_scheduler,
     email_client::EmailClient,
     mailbox_worker::{Beta8MailboxTransport, MailboxWorker, MailboxWorkerConfig},
     routes::{
+        admin_api::{
+            active_users, bulk_register, email_verification_stats, list_abuse_reports, list_users,
+            migrations, publish_admin_command, purge_user, rekey_backup_objects,
+            set_feature_flag_override, trigger_maintenance, verify_backups,
+        },
         app_middleware,
         gated_api_v0::{
-            authorize_mailbox, complete_upload, delete_backup, deregister, get_download_url,
-            get_upload_url, get_user_info, heartbeat_response, list_backups,
-            ln_address_suggestions, register_push_token, report_job_status, report_last_login,
-            revoke_mailbox_authorization, submit_invoice, update_backup_settings,
-            update_ln_address,
+            BACKUP_USAGE_FLAG, authorize_mailbox, backup_exists, backup_usage, complete_upload,
+            delete_backup, deregister, get_download_url, get_feature_flags, get_upload_url,
+            get_user_info, heartbeat_response, list_backups, list_push_tokens,
+            ln_address_suggestions, lnurl_metadata, register_push_token, report_abuse,
+            report_job_status, report_last_login, revoke_mailbox_authorization, revoke_push_token,
+            submit_invoice, update_backup_settings, update_default_sendable, update_ln_address,
+            update_success_action, update_timezone, verify_offboarding_signature,
         },
         public_api_v0::{
-            auth_login, check_app_version, get_k1, lnurlp_request, register,
-            send_verification_email, verify_email,
+            HealthState, auth_login, check_app_version, email_availability, get_k1,
+            get_k1_challenge, health_check, ln_address_available, lnurlp_invoice_ws, lnurlp_poll,
+            lnurlp_pre_register, lnurlp_request, register, resend_verification_email,
+            send_verification_email, server_info, server_time, verify_email,
         },
     },
 };
 
+mod abuse;
+mod admin_commands;
 mod ark_client;
+mod backup_integrity;
+mod commands;
 mod cron;
 pub mod db;
 mod email_client;
@@ -75,15 +96,28 @@ pub struct AppStruct {
     pub email_verification_store: EmailVerificationStore,
     pub email_client: EmailClient,
     pub maintenance_store: MaintenanceStore,
+    pub abuse_store: AbuseStore,
+    pub push_dedupe_store: PushDedupeStore,
+    pub admin_command_bus: AdminCommandBus,
+    /// Limits how many broadcasts run at once, see `MAX_CONCURRENT_BROADCASTS`.
+    pub broadcast_slots: Arc<Semaphore>,
 }
 
 fn main() -> anyhow::Result<()> {
-    let config = Config::load()?;
+    let cli = commands::Cli::parse();
+    if cli.print_config {
+        return commands::print_config(cli.config_sources);
+    }
+    match cli.command {
+        Some(commands::Command::ConfigCheck) => return commands::config_check(),
+        Some(commands::Command::Healthcheck { url }) => return commands::healthcheck(&url),
+        None => {}
+    }
 
-    let server_network = config.network()?;
+    let config = Config::load()?;
 
-    // Initialize Sentry first if we're on production networks
-    let _sentry_guard = if server_network == Network::Bitcoin || server_network == Network::Signet {
+    // Initialize Sentry first when enabled for this environment
+    let _sentry_guard = if config.sentry_enabled()? {
         config.sentry_url.clone().map(|sentry_url| {
             sentry::init((
                 sentry_url,
@@ -91,7 +125,7 @@ fn main() -> anyhow::Result<()> {
                     release: sentry::release_name!(),
                     enable_logs: true,
                     send_default_pii: false,
-                    traces_sample_rate: 1.0,
+                    traces_sample_rate: config.sentry_traces_sample_rate,
                     ..Default::default()
                 },
             ))
@@ -100,24 +134,39 @@ fn main() -> anyhow::Result<()> {
         None
     };
 
-    // Build subscriber with conditional Sentry layer
+    // Build subscriber with the configured output format and a conditional Sentry layer
+    let log_format = config.log_format()?;
+    let (pretty_layer, json_layer) = match log_format {
+        LogFormat::Pretty => (Some(tracing_subscriber::fmt::layer()), None),
+        LogFormat::Json => (
+            None,
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .json()
+                    .with_current_span(true)
+                    .with_span_list(true),
+            ),
+        ),
+    };
     let subscriber = tracing_subscriber::registry()
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env()
                 .unwrap_or_else(|_| "server=debug,tower_http=debug".into()),
         )
-        .with(tracing_subscriber::fmt::layer());
+        .with(pretty_layer)
+        .with(json_layer);
 
     // Initialize subscriber with or without Sentry layer
     if _sentry_guard.is_some() {
-        let sentry_layer =
-            sentry::integrations::tracing::layer().event_filter(|md| match *md.level() {
-                tracing::Level::ERROR => EventFilter::Log,
-                tracing::Level::WARN => EventFilter::Log,
-                tracing::Level::INFO => EventFilter::Log,
-                tracing::Level::DEBUG => EventFilter::Log,
-                _ => EventFilter::Ignore,
-            });
+        // Levels up to and including the configured one are forwarded as Sentry logs
+        let sentry_log_level = config.sentry_log_level()?;
+        let sentry_layer = sentry::integrations::tracing::layer().event_filter(move |md| {
+            if *md.level() <= sentry_log_level {
+                EventFilter::Log
+            } else {
+                EventFilter::Ignore
+            }
+        });
         subscriber.with(sentry_layer).init();
     } else {
         subscriber.init();
@@ -133,8 +182,24 @@ fn main() -> anyhow::Result<()> {
 }
 
 async fn start_server(config: Config) -> anyhow::Result<()> {
+    let started_at = Instant::now();
     let host = config.host()?;
 
+    if config.aws_credentials_lazy {
+        tracing::info!("Skipping AWS credentials check (AWS_CREDENTIALS_LAZY)");
+    } else {
+        tracing::info!("Checking AWS credentials...");
+        s3_client::check_aws_credentials().await?;
+        tracing::info!("AWS credentials resolved");
+    }
+
+    // Cleared when the S3 self-test fails, /health reports it without failing the probe
+    let s3_healthy = Arc::new(AtomicBool::new(true));
+    if config.s3_startup_check {
+        tracing::info!("Checking S3 bucket access...");
+        s3_client::refresh_s3_health(&config, &s3_healthy).await?;
+    }
+
     tracing::info!("Checking Postgres connection...");
     let db_pool = PgPoolOptions::new()
         .max_connections(config.postgres_max_connections)
@@ -160,7 +225,11 @@ async fn start_server(config: Config) -> anyhow::Result<()> {
     let k1_cache = K1Store::new(redis_client.clone(), K1_TTL_SECONDS);
     let invoice_store = InvoiceStore::new(redis_client.clone());
     let maintenance_store = MaintenanceStore::new(redis_client.clone());
-    let email_verification_store = EmailVerificationStore::new(redis_client);
+    let abuse_store = AbuseStore::new(redis_client.clone());
+    let push_dedupe_store = PushDedupeStore::new(redis_client.clone());
+    let admin_command_bus = AdminCommandBus::new(redis_client.clone());
+    let email_verification_store =
+        EmailVerificationStore::new(redis_client, config.email_code_ttl_secs);
 
     tracing::info!("Initializing email client...");
     let email_client =
@@ -176,6 +245,10 @@ async fn start_server(config: Config) -> anyhow::Result<()> {
         email_verification_store,
         email_client,
         maintenance_store,
+        abuse_store,
+        push_dedupe_store,
+        admin_command_bus,
+        broadcast_slots: Arc::new(Semaphore::new(config.max_concurrent_broadcasts)),
     });
 
     config.log_config();
@@ -193,12 +266,37 @@ async fn start_server(config: Config) -> anyhow::Result<()> {
 
     cron_handle.start().await?;
 
+    let resume_app_state = app_state.clone();
+    tokio::spawn(async move {
+        // Jobs left by the previous process only become resumable once their lease lapses
+        tokio::time::sleep(Duration::from_secs(
+            db::broadcast_job_repo::BROADCAST_JOB_LEASE_SECS as u64,
+        ))
+        .await;
+        if let Err(e) =
+            notification_coordinator::resume_interrupted_broadcasts(resume_app_state).await
+        {
+            tracing::error!("Failed to resume interrupted broadcasts: {}", e);
+        }
+    });
+
+    tokio::spawn(admin_commands::run_admin_command_listener(
+        app_state.clone(),
+        s3_healthy.clone(),
+    ));
+
     let ark_client_app_state = app_state.clone();
     let ark_server_url = config.ark_server_url.clone();
+    let ark_status = ArkConnectionStatus::default();
+    let ark_client_status = ark_status.clone();
 
     tokio::spawn(async move {
-        if let Err(e) =
-            ark_client::connect_to_ark_server(ark_client_app_state, ark_server_url).await
+        if let Err(e) = ark_client::connect_to_ark_server(
+            ark_client_app_state,
+            ark_server_url,
+            ark_client_status,
+        )
+        .await
         {
             tracing::error!("Failed to connect to ark server: {}", e);
         }
@@ -239,43 +337,106 @@ async fn start_server(config: Config) -> anyhow::Result<()> {
         app_middleware::email_verified_middleware,
     );
 
+    // Middleware that hides /backup/usage while its feature flag is turned off
+    let backup_usage_flag_layer = middleware::from_fn_with_state(
+        (app_state.clone(), BACKUP_USAGE_FLAG),
+        app_middleware::feature_flag_middleware,
+    );
+
+    // Middleware that signs critical responses when a signing key is configured
+    let response_signing_layer =
+        middleware::from_fn_with_state(app_state.clone(), app_middleware::sign_response_middleware);
+
     // Create rate limiters
-    let public_rate_limiter = rate_limit::create_public_rate_limiter();
+    // getk1 and its challenge each get their own budget, a clone would share one
+    let getk1_rate_limiter = rate_limit::create_public_rate_limiter();
+    let getk1_challenge_rate_limiter = rate_limit::create_public_rate_limiter();
     let auth_login_rate_limiter = rate_limit::create_public_rate_limiter();
-    let auth_rate_limiter = rate_limit::create_auth_rate_limiter();
+    let ln_address_available_rate_limiter = rate_limit::create_public_rate_limiter();
+    let route_rate_limits = config.rate_limits()?;
+    // Only applied to limiters behind auth_middleware, where the caller's pubkey is verified
+    let rate_limit_exemptions =
+        rate_limit::RateLimitExemptions::new(config.rate_limit_exempt_pubkeys.clone());
+    let auth_rate_limiter = rate_limit_exemptions.apply(rate_limit::create_auth_rate_limiter());
+    // Shared by the LNURL-pay callback and its WebSocket and polling variants, which all send a push
+    let lnurlp_rate_limiter = rate_limit::create_route_rate_limiter(route_rate_limits.lnurlp);
+    let lnurlp_poll_rate_limiter = rate_limit::create_public_rate_limiter();
+    // Shared by sending and resending a code, so alternating between them doesn't double the rate
+    let email_send_verification_rate_limiter = rate_limit_exemptions.apply(
+        rate_limit::create_route_rate_limiter(route_rate_limits.email_send_verification),
+    );
 
     // Email verification routes - need auth and user to exist, but NOT email verification
     let email_verification_router = Router::new()
-        .route("/email/send_verification", post(send_verification_email))
+        .route(
+            "/email/send_verification",
+            post(send_verification_email).layer(email_send_verification_rate_limiter.clone()),
+        )
+        .route(
+            "/email/resend_verification",
+            post(resend_verification_email).layer(email_send_verification_rate_limiter),
+        )
         .route("/email/verify", post(verify_email))
+        .route(
+            "/email/availability",
+            post(email_availability).layer(rate_limit_exemptions.apply(
+                rate_limit::create_route_rate_limiter(route_rate_limits.email_availability),
+            )),
+        )
         .layer(user_exists_layer.clone());
 
     // Fully gated routes - need auth, user to exist, AND email to be verified
     let gated_router = Router::new()
         .route("/register_push_token", post(register_push_token))
+        .route("/push/list", post(list_push_tokens))
+        .route("/push/revoke", post(revoke_push_token))
         .route("/mailbox/authorize", post(authorize_mailbox))
         .route("/mailbox/revoke", post(revoke_mailbox_authorization))
         .route("/lnurlp/submit_invoice", post(submit_invoice))
         .route("/ln_address_suggestions", post(ln_address_suggestions))
         .route("/user_info", post(get_user_info))
+        .route("/lnurl_metadata", post(lnurl_metadata))
         .route("/update_ln_address", post(update_ln_address))
+        .route("/update_timezone", post(update_timezone))
+        .route("/feature_flags", post(get_feature_flags))
+        .route("/lnurlp/default_sendable", post(update_default_sendable))
+        .route("/lnurlp/success_action", post(update_success_action))
         .route("/deregister", post(deregister))
         .route("/backup/upload_url", post(get_upload_url))
         .route("/backup/complete_upload", post(complete_upload))
         .route("/backup/list", post(list_backups))
+        .route("/backup/exists", post(backup_exists))
+        .route(
+            "/backup/usage",
+            post(backup_usage).layer(backup_usage_flag_layer),
+        )
         .route("/backup/download_url", post(get_download_url))
         .route("/backup/delete", post(delete_backup))
         .route("/backup/settings", post(update_backup_settings))
         .route("/report_job_status", post(report_job_status))
         .route("/heartbeat_response", post(heartbeat_response))
         .route("/report_last_login", post(report_last_login))
+        .route("/report_abuse", post(report_abuse))
+        .route(
+            "/offboarding/verify_signature",
+            post(verify_offboarding_signature),
+        )
         .layer(email_verified_layer)
         .layer(user_exists_layer);
 
     // Routes that need auth but user may not exist (like registration)
     // Apply auth rate limiter to these routes
     let bearer_router = Router::new()
-        .route("/register", post(register))
+        .route(
+            "/register",
+            post(register)
+                .layer(
+                    rate_limit_exemptions.apply(rate_limit::create_route_rate_limiter(
+                        route_rate_limits.register,
+                    )),
+                )
+                .layer(response_signing_layer.clone()),
+        )
         .merge(email_verification_router)
         .merge(gated_router)
         .layer(auth_rate_limiter)
@@ -283,37 +444,124 @@ async fn start_server(config: Config) -> anyhow::Result<()> {
 
     // Public routes with strict rate limiting on getk1
     let v0_router = Router::new()
-        .route("/getk1", get(get_k1).layer(public_rate_limiter))
+        .route("/getk1", get(get_k1).layer(getk1_rate_limiter))
+        .route(
+            "/getk1/challenge",
+            get(get_k1_challenge).layer(getk1_challenge_rate_limiter),
+        )
         .route(
             "/auth/login",
             post(auth_login).layer(auth_login_rate_limiter),
         )
         .route("/app_version", post(check_app_version))
-        .merge(bearer_router);
+        .route("/info", get(server_info))
+        .route("/time", get(server_time))
+        .route(
+            "/lnurlp/{username}/ws",
+            get(lnurlp_invoice_ws).layer(lnurlp_rate_limiter.clone()),
+        )
+        .route(
+            "/lnurlp/{username}/request",
+            post(lnurlp_pre_register).layer(lnurlp_rate_limiter.clone()),
+        )
+        .route(
+            "/lnurlp/poll/{transaction_id}",
+            get(lnurlp_poll).layer(lnurlp_poll_rate_limiter),
+        )
+        .route(
+            "/ln_address_available",
+            get(ln_address_available).layer(ln_address_available_rate_limiter),
+        )
+        .merge(bearer_router)
+        .layer(app_middleware::request_timeout_layer(&config));
 
-    // Public route
-    let lnurl_router = Router::new().route("/.well-known/lnurlp/{username}", get(lnurlp_request));
+    // Public route, with its own timeout that outlasts the wait for the device's invoice
+    let lnurl_router = Router::new()
+        .route(
+            "/.well-known/lnurlp/{username}",
+            get(lnurlp_request)
+                .layer(lnurlp_rate_limiter)
+                .layer(response_signing_layer),
+        )
+        .layer(app_middleware::lnurlp_timeout_layer(&config));
 
     let app = Router::new()
         .route("/", get(|| async { StatusCode::NO_CONTENT }))
-        .route("/health", get(|| async { StatusCode::OK }))
+        .route(
+            "/health",
+            get(health_check).with_state(HealthState {
+                started_at,
+                network: config.server_network.clone(),
+                s3_healthy,
+            }),
+        )
+        .route(
+            "/ready",
+            get(move || async move {
+                let state = ark_status.snapshot();
+                let status = if state.connected {
+                    StatusCode::OK
+                } else {
+                    StatusCode::SERVICE_UNAVAILABLE
+                };
+                (status, Json(state))
+            }),
+        )
         .nest("/v0", v0_router)
         .merge(lnurl_router)
+        .fallback(app_middleware::route_not_found)
+        .method_not_allowed_fallback(app_middleware::method_not_allowed)
         .with_state(app_state.clone())
         .layer(middleware::from_fn(trace_layer::trace_middleware))
         .layer(SentryHttpLayer::new().enable_transaction())
         .layer(NewSentryLayer::new_from_top());
 
+    // Operator-only routes, served on the private port which is never exposed publicly
+    let admin_router = Router::new()
+        .route("/admin/users", get(list_users))
+        .route("/admin/abuse_reports", get(list_abuse_reports))
+        .route("/admin/stats/active_users", get(active_users))
+        .route(
+            "/admin/stats/email_verifications",
+            get(email_verification_stats),
+        )
+        .route("/admin/trigger_maintenance", post(trigger_maintenance))
+        .route("/admin/backups/verify", post(verify_backups))
+        .route("/admin/backups/rekey", post(rekey_backup_objects))
+        .route("/admin/migrations", get(migrations))
+        .route("/admin/commands", post(publish_admin_command))
+        .route("/admin/purge_user", post(purge_user))
+        .route("/admin/bulk_register", post(bulk_register))
+        .route(
+            "/admin/feature_flags/override",
+            post(set_feature_flag_override),
+        )
+        .fallback(app_middleware::route_not_found)
+        .method_not_allowed_fallback(app_middleware::method_not_allowed)
+        .with_state(app_state.clone())
+        .layer(middleware::from_fn(trace_layer::trace_middleware));
+
     let addr = SocketAddr::from((host, config.port));
     tracing::debug!("server started listening on {}", addr);
     let listener = tokio::net::TcpListener::bind(addr).await?;
 
+    let private_addr = SocketAddr::from((host, config.private_port));
+    tracing::debug!("private server started listening on {}", private_addr);
+    let private_listener = tokio::net::TcpListener::bind(private_addr).await?;
+
     // Important: Use into_make_service_with_connect_info to provide IP information for rate limiting
-    axum::serve(
-        listener,
-        app.into_make_service_with_connect_info::<SocketAddr>(),
-    )
-    .await?;
+    tokio::try_join!(
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .into_future(),
+        axum::serve(
+            private_listener,
+            admin_router.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .into_future(),
+    )?;
 
     Ok(())
 }
@@ -1,16 +1,27 @@
 use crate::{
     AppState,
+    config::QuietHours,
     db::{
+        broadcast_job_repo::{BROADCAST_JOB_LEASE_SECS, BroadcastJobClaim, BroadcastJobRepository},
         job_status_repo::JobStatusRepository,
-        notification_tracking_repo::NotificationTrackingRepository,
+        notification_tracking_repo::{NotificationTrackingRepository, backup_enabled_predicate},
+        user_repo::UserRepository,
     },
-    push::{PushDispatchReceipt, send_push_notification_with_unique_k1},
-    types::{NotificationRequestData, ReportStatus},
+    push::{
+        PushDispatchReceipt, PushSendSummary, add_push_breadcrumb, pubkey_hash,
+        send_push_notification_with_unique_k1,
+    },
+    types::{BroadcastJobPriority, BroadcastJobStatus, NotificationRequestData, ReportStatus},
 };
 use anyhow::Result;
 use chrono::Utc;
+use chrono_tz::Tz;
 use expo_push_notification_client::Priority;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 use tracing::{debug, info, warn};
+use uuid::Uuid;
 
 #[derive(Debug, Clone)]
 pub struct NotificationRequest {
@@ -19,35 +30,64 @@ pub struct NotificationRequest {
     pub target_pubkey: Option<String>, // None means broadcast to all users
 }
 
+/// Outcome of a coordinated send, used to report eligibility back to callers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DispatchSummary {
+    /// Users considered for the notification after spacing filters.
+    pub eligible: usize,
+    /// Users the notification was dispatched to.
+    pub sent: usize,
+    /// Users skipped by coordination rules.
+    pub skipped: usize,
+    /// Eligible users without a registered push token.
+    pub no_push_token: usize,
+}
+
+/// What `send_notification` did with a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SendOutcome {
+    /// Dispatched to the user's device.
+    Sent { ticket_id: Option<String> },
+    /// The user was notified too recently.
+    SkippedSpacing,
+    /// The user is in their quiet hours.
+    SkippedQuietHours,
+    /// The same notification already went out in the dedupe window.
+    SkippedDuplicate,
+    /// The user has no registered push token.
+    NoToken,
+    /// Broadcast to all eligible users.
+    Broadcast(DispatchSummary),
+}
+
 pub struct NotificationCoordinator {
     app_state: AppState,
     min_spacing_minutes: i64,
+    quiet_hours: Option<QuietHours>,
 }
 
 impl NotificationCoordinator {
     pub fn new(app_state: AppState) -> Self {
         let min_spacing_minutes = app_state.config.notification_spacing_minutes;
+        let quiet_hours = app_state.config.quiet_hours();
         Self {
             app_state,
             min_spacing_minutes,
+            quiet_hours,
         }
     }
 
     /// Send a notification with coordination and spacing rules
-    pub async fn send_notification(&self, request: NotificationRequest) -> Result<()> {
+    pub async fn send_notification(&self, request: NotificationRequest) -> Result<SendOutcome> {
         let tracking_repo = NotificationTrackingRepository::new(&self.app_state.db_pool);
 
         match request.target_pubkey {
-            Some(ref pubkey) => {
-                self.send_to_user(pubkey, &request, &tracking_repo).await?;
-            }
-            None => {
+            Some(ref pubkey) => self.send_to_user(pubkey, &request, &tracking_repo).await,
+            None => Ok(SendOutcome::Broadcast(
                 self.broadcast_notification(&request, &tracking_repo)
-                    .await?;
-            }
+                    .await?,
+            )),
         }
-
-        Ok(())
     }
 
     /// Send a notification to a specific user with coordination checks
@@ -56,47 +96,76 @@ impl NotificationCoordinator {
         pubkey: &str,
         request: &NotificationRequest,
         tracking_repo: &NotificationTrackingRepository<'_>,
-    ) -> Result<()> {
+    ) -> Result<SendOutcome> {
+        let target = pubkey_hash(pubkey);
+
         // Check if user should receive this notification
-        if !self
-            .should_send_to_user(pubkey, request, tracking_repo)
-            .await?
-        {
+        if let Some(skipped) = self.skip_reason(pubkey, request, tracking_repo).await? {
             debug!(
-                "Skipping {} notification to {} due to coordination rules",
-                request.data.notification_type(),
-                pubkey
+                pubkey_hash = %target,
+                outcome = ?skipped,
+                "Skipping {} notification due to coordination rules",
+                request.data.notification_type()
             );
-            return Ok(());
+            return Ok(skipped);
+        }
+
+        if !self.claim_dedupe(pubkey, request).await {
+            return Ok(SendOutcome::SkippedDuplicate);
         }
 
+        add_push_breadcrumb(
+            "dispatching notification",
+            sentry::Level::Info,
+            [
+                (
+                    "notification_type",
+                    request.data.notification_type().to_string(),
+                ),
+                ("priority", format!("{:?}", request.priority)),
+                ("target", target.clone()),
+            ],
+        );
+
         // Send the notification
-        let dispatches = send_push_notification_with_unique_k1(
+        let sent = send_push_notification_with_unique_k1(
             self.app_state.clone(),
             request.data.clone(),
             Some(pubkey.to_string()),
         )
-        .await?;
+        .await;
+        // Only a delivered push keeps the dedupe claim, so a failed send can be retried
+        if !matches!(&sent, Ok(sent) if sent.summary.delivered > 0) {
+            self.release_dedupe(pubkey, request).await;
+        }
+        let sent = sent?;
+        let dispatches = sent.receipts;
 
         if dispatches.is_empty() {
             debug!(
-                "No push tokens found for {} notification to {}",
-                request.data.notification_type(),
-                pubkey
+                pubkey_hash = %target,
+                "No push tokens found for {} notification",
+                request.data.notification_type()
             );
-            return Ok(());
+            return Ok(SendOutcome::NoToken);
         }
 
         self.record_pending_job_reports(&request.data, &dispatches)
             .await?;
 
         info!(
-            "Sent {} notification to {}",
-            request.data.notification_type(),
-            pubkey
+            pubkey_hash = %target,
+            delivered = sent.summary.delivered,
+            failed = sent.summary.failed,
+            "Sent {} notification",
+            request.data.notification_type()
         );
 
-        Ok(())
+        Ok(SendOutcome::Sent {
+            ticket_id: dispatches
+                .into_iter()
+                .find_map(|dispatch| dispatch.ticket_id),
+        })
     }
 
     /// Broadcast a notification to all eligible users
@@ -104,98 +173,253 @@ impl NotificationCoordinator {
         &self,
         request: &NotificationRequest,
         tracking_repo: &NotificationTrackingRepository<'_>,
-    ) -> Result<()> {
-        let eligible_users = if request.priority == Priority::High {
+    ) -> Result<DispatchSummary> {
+        // Overlapping broadcasts would add up their Expo and database load, so later ones wait
+        let _slot = match self.app_state.broadcast_slots.try_acquire() {
+            Ok(slot) => slot,
+            Err(_) => {
+                info!(
+                    "Queueing {} broadcast behind a running broadcast",
+                    request.data.notification_type()
+                );
+                self.app_state.broadcast_slots.acquire().await?
+            }
+        };
+
+        let job_repo = BroadcastJobRepository::new(&self.app_state.db_pool);
+        let owner = Uuid::new_v4().to_string();
+        let (job_id, resumed) = match job_repo
+            .start_or_resume(
+                request.data.notification_type(),
+                BroadcastJobPriority::from(&request.priority),
+                &owner,
+            )
+            .await?
+        {
+            BroadcastJobClaim::Started(job_id) => (job_id, false),
+            BroadcastJobClaim::Resumed(job_id) => (job_id, true),
+            BroadcastJobClaim::Busy => {
+                info!(
+                    "Skipping {} broadcast, another run holds its job",
+                    request.data.notification_type()
+                );
+                return Ok(DispatchSummary::default());
+            }
+        };
+
+        let result = self
+            .run_broadcast(request, tracking_repo, &job_repo, job_id, &owner, resumed)
+            .await;
+
+        // Any error leaves the job failed rather than running, so it can't block later broadcasts
+        let status = match result {
+            Ok(_) => BroadcastJobStatus::Completed,
+            Err(_) => BroadcastJobStatus::Failed,
+        };
+        if let Err(e) = job_repo.finish(job_id, Some(&owner), status).await {
+            warn!(job_id = job_id, "Failed to finish broadcast job: {}", e);
+        }
+
+        result
+    }
+
+    /// Sends a broadcast under a job held by `owner`, renewing its lease as it goes.
+    async fn run_broadcast(
+        &self,
+        request: &NotificationRequest,
+        tracking_repo: &NotificationTrackingRepository<'_>,
+        job_repo: &BroadcastJobRepository<'_>,
+        job_id: i64,
+        owner: &str,
+        resumed: bool,
+    ) -> Result<DispatchSummary> {
+        // Users who turned off backups have no use for a backup_trigger
+        let backup_enabled_only = request.data.requires_backup_enabled()
+            && self.app_state.config.backup_trigger_skip_disabled;
+
+        let (mut eligible_users, unreachable) = if request.priority == Priority::High {
             // `Priority::High` is used for critical notifications that go to all users
-            self.get_all_users().await?
+            (self.get_all_users(backup_enabled_only).await?, 0)
         } else {
-            // Normal notifications respect spacing
-            tracking_repo
-                .get_eligible_users(self.min_spacing_minutes)
-                .await?
+            // Normal notifications respect spacing, and users without a push token are
+            // counted rather than visited since they can't be reached anyway
+            let reachable = tracking_repo
+                .get_reachable_eligible_users(self.min_spacing_minutes, backup_enabled_only)
+                .await?;
+            let unreachable = tracking_repo
+                .count_unreachable_eligible_users(self.min_spacing_minutes, backup_enabled_only)
+                .await?;
+            (reachable, unreachable as usize)
         };
 
+        // Users in their quiet hours are picked up again by the next broadcast
+        let mut deferred = 0;
+        if request.priority != Priority::High {
+            let (outside, inside) = self.partition_quiet_hours(eligible_users).await?;
+            eligible_users = outside;
+            deferred = inside;
+        }
+
+        if resumed {
+            // Users handled before the interruption are left out so nobody gets it twice
+            let processed = job_repo.processed_pubkeys(job_id).await?;
+            eligible_users.retain(|pubkey| !processed.contains(pubkey));
+            info!(
+                job_id = job_id,
+                already_processed = processed.len(),
+                "Resuming interrupted {} broadcast",
+                request.data.notification_type()
+            );
+        }
+
         if eligible_users.is_empty() {
             debug!(
-                "No eligible users for {} notification",
+                unreachable = unreachable,
+                "No reachable eligible users for {} notification",
                 request.data.notification_type()
             );
-            return Ok(());
+            return Ok(DispatchSummary {
+                eligible: unreachable + deferred,
+                skipped: deferred,
+                no_push_token: unreachable,
+                ..Default::default()
+            });
         }
 
         info!(
+            unreachable = unreachable,
             "Broadcasting {} notification to {} users",
             request.data.notification_type(),
             eligible_users.len()
         );
 
-        let mut sent_count = 0;
-        let mut skipped_count = 0;
+        add_push_breadcrumb(
+            "broadcasting notification",
+            sentry::Level::Info,
+            [
+                (
+                    "notification_type",
+                    request.data.notification_type().to_string(),
+                ),
+                ("priority", format!("{:?}", request.priority)),
+                ("recipients", eligible_users.len().to_string()),
+            ],
+        );
+
+        let mut summary = DispatchSummary {
+            eligible: eligible_users.len() + unreachable + deferred,
+            skipped: deferred,
+            no_push_token: unreachable,
+            ..Default::default()
+        };
+        let mut push_summary = PushSendSummary::default();
+        let lease_renewal_interval = Duration::from_secs(BROADCAST_JOB_LEASE_SECS as u64 / 3);
+        let mut lease_renewed_at = Instant::now();
 
         for pubkey in eligible_users {
-            // For Normal priority, users are already filtered by get_eligible_users()
+            if lease_renewed_at.elapsed() >= lease_renewal_interval {
+                if !job_repo.renew_lease(job_id, owner).await? {
+                    anyhow::bail!("Lost the lease on broadcast job {}", job_id);
+                }
+                lease_renewed_at = Instant::now();
+            }
+
+            // Another instance resuming the same job may have taken this user already
+            if !job_repo.claim_recipient(job_id, &pubkey).await? {
+                summary.eligible -= 1;
+                continue;
+            }
+
+            // For Normal priority, users are already filtered by get_reachable_eligible_users()
             // For High priority, we need to check individually (e.g., spacing rules)
             let should_send = if request.priority == Priority::High {
-                self.should_send_to_user(&pubkey, request, tracking_repo)
+                self.skip_reason(&pubkey, request, tracking_repo)
                     .await?
+                    .is_none()
             } else {
                 true
             };
 
+            if should_send && !self.claim_dedupe(&pubkey, request).await {
+                summary.skipped += 1;
+                continue;
+            }
+
             if should_send {
                 // Send the notification
-                let dispatches = match send_push_notification_with_unique_k1(
+                let sent = send_push_notification_with_unique_k1(
                     self.app_state.clone(),
                     request.data.clone(),
                     Some(pubkey.clone()),
                 )
-                .await
-                {
-                    Ok(dispatches) => dispatches,
+                .await;
+                if !matches!(&sent, Ok(sent) if sent.summary.delivered > 0) {
+                    self.release_dedupe(&pubkey, request).await;
+                }
+                let dispatches = match sent {
+                    Ok(sent) => {
+                        push_summary += sent.summary;
+                        sent.receipts
+                    }
                     Err(e) => {
-                        warn!("Failed to send notification to {}: {}", pubkey, e);
+                        warn!(
+                            pubkey_hash = %pubkey_hash(&pubkey),
+                            "Failed to send notification: {}",
+                            e
+                        );
                         continue;
                     }
                 };
 
                 if dispatches.is_empty() {
                     debug!(
-                        "No push tokens found for {} notification to {}",
-                        request.data.notification_type(),
-                        pubkey
+                        pubkey_hash = %pubkey_hash(&pubkey),
+                        "No push tokens found for {} notification",
+                        request.data.notification_type()
                     );
+                    summary.no_push_token += 1;
                     continue;
                 }
 
                 self.record_pending_job_reports(&request.data, &dispatches)
                     .await?;
 
-                sent_count += 1;
+                summary.sent += 1;
             } else {
-                skipped_count += 1;
+                summary.skipped += 1;
             }
         }
 
         info!(
-            "Broadcast complete for {}: sent={}, skipped={}",
+            delivered = push_summary.delivered,
+            failed = push_summary.failed,
+            not_registered = push_summary.not_registered,
+            pruned_tokens = push_summary.pruned_tokens,
+            "Broadcast complete for {}: sent={}, skipped={}, no_push_token={}",
             request.data.notification_type(),
-            sent_count,
-            skipped_count
+            summary.sent,
+            summary.skipped,
+            summary.no_push_token
         );
 
-        Ok(())
+        Ok(summary)
     }
 
-    /// Determine if a notification should be sent to a specific user
-    async fn should_send_to_user(
+    /// Determine why a notification should not be sent to a specific user, if at all
+    async fn skip_reason(
         &self,
         pubkey: &str,
         request: &NotificationRequest,
         tracking_repo: &NotificationTrackingRepository<'_>,
-    ) -> Result<bool> {
+    ) -> Result<Option<SendOutcome>> {
         // `Priority::High` notifications bypass spacing checks
         if request.priority == Priority::High {
-            return Ok(true);
+            return Ok(None);
+        }
+
+        let (_, in_quiet_hours) = self.partition_quiet_hours(vec![pubkey.to_string()]).await?;
+        if in_quiet_hours > 0 {
+            return Ok(Some(SendOutcome::SkippedQuietHours));
         }
 
         // For normal priority, check spacing
@@ -208,12 +432,95 @@ impl NotificationCoordinator {
         {
             let minutes_since = (Utc::now() - last_time).num_minutes();
             debug!(
-                "Spacing check failed for {}: last notification {} minutes ago (need {})",
-                pubkey, minutes_since, self.min_spacing_minutes
+                pubkey_hash = %pubkey_hash(pubkey),
+                "Spacing check failed: last notification {} minutes ago (need {})",
+                minutes_since,
+                self.min_spacing_minutes
             );
         }
 
-        Ok(can_send)
+        Ok((!can_send).then_some(SendOutcome::SkippedSpacing))
+    }
+
+    /// Claims the send in the push dedupe window, returning false for a repeated send.
+    ///
+    /// Redis errors let the send through, spacing rules still apply on their own.
+    async fn claim_dedupe(&self, pubkey: &str, request: &NotificationRequest) -> bool {
+        let window_secs = self.app_state.config.push_dedupe_window_secs;
+        if window_secs == 0 {
+            return true;
+        }
+
+        let notification_type = request.data.notification_type();
+        match self
+            .app_state
+            .push_dedupe_store
+            .claim(
+                pubkey,
+                notification_type,
+                &request.data.content_hash(),
+                window_secs,
+            )
+            .await
+        {
+            Ok(true) => true,
+            Ok(false) => {
+                debug!(
+                    pubkey_hash = %pubkey_hash(pubkey),
+                    "Dropping duplicate {} notification", notification_type
+                );
+                false
+            }
+            Err(e) => {
+                warn!(error = %e, "Failed to check push dedupe, sending anyway");
+                true
+            }
+        }
+    }
+
+    /// Releases a dedupe claim for a send that delivered nothing, so a retry isn't dropped.
+    async fn release_dedupe(&self, pubkey: &str, request: &NotificationRequest) {
+        if self.app_state.config.push_dedupe_window_secs == 0 {
+            return;
+        }
+
+        if let Err(e) = self
+            .app_state
+            .push_dedupe_store
+            .release(
+                pubkey,
+                request.data.notification_type(),
+                &request.data.content_hash(),
+            )
+            .await
+        {
+            warn!(error = %e, "Failed to release push dedupe claim");
+        }
+    }
+
+    /// Splits users into those outside their local quiet hours and a count of those inside.
+    ///
+    /// Users without a timezone, or with one that no longer parses, are treated as UTC.
+    async fn partition_quiet_hours(&self, pubkeys: Vec<String>) -> Result<(Vec<String>, usize)> {
+        let Some(quiet_hours) = self.quiet_hours else {
+            return Ok((pubkeys, 0));
+        };
+
+        let timezones: HashMap<String, Tz> = UserRepository::new(&self.app_state.db_pool)
+            .find_many_by_pubkeys(&pubkeys)
+            .await?
+            .into_iter()
+            .filter_map(|user| Some((user.pubkey, user.timezone?.parse().ok()?)))
+            .collect();
+        let now = Utc::now();
+
+        let (inside, outside): (Vec<String>, Vec<String>) =
+            pubkeys.into_iter().partition(|pubkey| {
+                let timezone = timezones.get(pubkey).copied().unwrap_or(Tz::UTC);
+                quiet_hours.contains(now, timezone)
+            });
+
+        Ok((outside, inside.len()))
     }
 
     async fn record_pending_job_reports(
@@ -228,9 +535,9 @@ impl NotificationCoordinator {
         for dispatch in dispatches {
             if dispatch.notification_k1.is_empty() {
                 warn!(
-                    "Missing notification k1 for dispatched {} notification to {}",
-                    notification_data.notification_type(),
-                    dispatch.pubkey
+                    pubkey_hash = %pubkey_hash(&dispatch.pubkey),
+                    "Missing notification k1 for dispatched {} notification",
+                    notification_data.notification_type()
                 );
                 continue;
             }
@@ -251,16 +558,66 @@ impl NotificationCoordinator {
         Ok(())
     }
 
-    /// Get all users from the database
-    async fn get_all_users(&self) -> Result<Vec<String>> {
-        let pubkeys = sqlx::query_scalar::<_, String>("SELECT pubkey FROM users")
-            .fetch_all(&self.app_state.db_pool)
-            .await?;
+    /// Get all users from the database, optionally only those with backups enabled
+    async fn get_all_users(&self, backup_enabled_only: bool) -> Result<Vec<String>> {
+        let pubkeys = sqlx::query_scalar::<_, String>(&format!(
+            "SELECT u.pubkey
+             FROM users u
+             WHERE {}",
+            backup_enabled_predicate(1)
+        ))
+        .bind(backup_enabled_only)
+        .fetch_all(&self.app_state.db_pool)
+        .await?;
 
         Ok(pubkeys)
     }
 }
 
+/// Resumes broadcasts that were interrupted by a restart, once their lease has expired.
+///
+/// Recipients processed before the interruption are skipped, so each user is notified at most
+/// once per broadcast.
+pub async fn resume_interrupted_broadcasts(app_state: AppState) -> Result<()> {
+    let job_repo = BroadcastJobRepository::new(&app_state.db_pool);
+    let coordinator = NotificationCoordinator::new(app_state.clone());
+
+    for job in job_repo.find_expired().await? {
+        let Some(data) = NotificationRequestData::from_broadcast_type(&job.notification_type)
+        else {
+            warn!(
+                job_id = job.id,
+                "Cannot resume {} broadcast, marking it failed", job.notification_type
+            );
+            job_repo
+                .finish(job.id, job.owner.as_deref(), BroadcastJobStatus::Failed)
+                .await?;
+            continue;
+        };
+
+        let request = NotificationRequest {
+            priority: job.priority.into(),
+            data,
+            target_pubkey: None,
+        };
+
+        match coordinator.send_notification(request).await {
+            Ok(outcome) => info!(
+                job_id = job.id,
+                outcome = ?outcome,
+                "Resumed {} broadcast",
+                job.notification_type
+            ),
+            Err(e) => warn!(
+                job_id = job.id,
+                "Failed to resume {} broadcast: {}", job.notification_type, e
+            ),
+        }
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -270,4 +627,34 @@ mod tests {
         assert_eq!(Priority::High, Priority::High);
         assert_ne!(Priority::High, Priority::Normal);
     }
+
+    #[test]
+    fn test_quiet_hours_window() {
+        let at = |hour| {
+            chrono::NaiveDate::from_ymd_opt(2025, 1, 15)
+                .unwrap()
+                .and_hms_opt(hour, 30, 0)
+                .unwrap()
+                .and_utc()
+        };
+
+        let overnight = QuietHours {
+            start_hour: 22,
+            end_hour: 7,
+        };
+        assert!(overnight.contains(at(23), Tz::UTC));
+        assert!(overnight.contains(at(3), Tz::UTC));
+        assert!(!overnight.contains(at(7), Tz::UTC));
+        assert!(!overnight.contains(at(12), Tz::UTC));
+
+        let daytime = QuietHours {
+            start_hour: 9,
+            end_hour: 17,
+        };
+        assert!(daytime.contains(at(9), Tz::UTC));
+        assert!(!daytime.contains(at(17), Tz::UTC));
+
+        // 23:30 UTC is 08:30 in Tokyo, outside the overnight window there
+        assert!(!overnight.contains(at(23), chrono_tz::Asia::Tokyo));
+    }
 }
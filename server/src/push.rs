@@ -1,4 +1,7 @@
-use expo_push_notification_client::{Expo, ExpoClientOptions, ExpoPushMessage, Priority};
+use bitcoin::hashes::{Hash, sha256};
+use expo_push_notification_client::{
+    DetailsErrorType, Expo, ExpoClientOptions, ExpoPushMessage, ExpoPushTicket, Priority,
+};
 use futures_util::{StreamExt, stream};
 use reqwest::Client;
 use serde::Serialize;
@@ -18,6 +21,33 @@ fn is_expo_token(token: &str) -> bool {
             .is_match(token)
 }
 
+/// Returns a short, non-reversible identifier for a pubkey.
+///
+/// Sentry runs with `send_default_pii: false`, so anything attached to Sentry events,
+/// breadcrumbs or logs on the push path uses this instead of the raw pubkey.
+pub(crate) fn pubkey_hash(pubkey: &str) -> String {
+    let hash = sha256::Hash::hash(pubkey.as_bytes()).to_string();
+    hash[..16].to_string()
+}
+
+/// Records a breadcrumb for the notification pipeline so Sentry errors carry send context.
+pub(crate) fn add_push_breadcrumb(
+    message: &str,
+    level: sentry::Level,
+    data: impl IntoIterator<Item = (&'static str, String)>,
+) {
+    sentry::add_breadcrumb(sentry::Breadcrumb {
+        category: Some("push".to_string()),
+        message: Some(message.to_string()),
+        level,
+        data: data
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value.into()))
+            .collect(),
+        ..Default::default()
+    });
+}
+
 #[derive(Serialize, Clone, Debug)]
 pub struct PushNotificationData {
     pub title: Option<String>,
@@ -28,10 +58,65 @@ pub struct PushNotificationData {
     pub content_available: bool,
 }
 
+impl PushNotificationData {
+    /// Bytes Expo counts against its payload limit: the title, body and data.
+    fn payload_size(&self) -> usize {
+        self.title.as_ref().map_or(0, String::len)
+            + self.body.as_ref().map_or(0, String::len)
+            + self.data.len()
+    }
+
+    /// Logs and returns false when the payload is over the configured limit.
+    fn fits_payload_limit(&self, app_state: &AppState) -> bool {
+        let max_bytes = app_state.config.push_max_payload_bytes;
+        let size = self.payload_size();
+        if size <= max_bytes {
+            return true;
+        }
+
+        tracing::error!(
+            size,
+            max_bytes,
+            "Push notification payload is too large, not sending it"
+        );
+        false
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct PushDispatchReceipt {
     pub pubkey: String,
     pub notification_k1: String,
+    /// Expo ticket id, absent for UnifiedPush deliveries.
+    pub ticket_id: Option<String>,
+}
+
+/// Per-message outcome of a push send, summed over every batch and transport.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PushSendSummary {
+    pub delivered: usize,
+    pub failed: usize,
+    /// Failed messages whose token Expo reported as `DeviceNotRegistered`.
+    pub not_registered: usize,
+    /// Tokens deleted after repeated `DeviceNotRegistered` tickets.
+    pub pruned_tokens: usize,
+}
+
+impl std::ops::AddAssign for PushSendSummary {
+    fn add_assign(&mut self, other: Self) {
+        self.delivered += other.delivered;
+        self.failed += other.failed;
+        self.not_registered += other.not_registered;
+        self.pruned_tokens += other.pruned_tokens;
+    }
+}
+
+/// What `send_push_notification_with_unique_k1` sent.
+#[derive(Debug, Clone, Default)]
+pub struct UniqueK1SendResult {
+    /// One receipt per message the transport accepted.
+    pub receipts: Vec<PushDispatchReceipt>,
+    pub summary: PushSendSummary,
 }
 
 #[derive(Debug, Clone)]
@@ -44,7 +129,7 @@ pub async fn send_push_notification(
     app_state: AppState,
     data: PushNotificationData,
     pubkey: Option<String>,
-) -> anyhow::Result<(), ApiError> {
+) -> anyhow::Result<PushSendSummary, ApiError> {
     send_push_notification_internal(app_state, data, pubkey).await
 }
 
@@ -52,7 +137,7 @@ pub async fn send_push_notification_with_unique_k1(
     app_state: AppState,
     base_notification_data: NotificationRequestData,
     pubkey: Option<String>,
-) -> anyhow::Result<Vec<PushDispatchReceipt>, ApiError> {
+) -> anyhow::Result<UniqueK1SendResult, ApiError> {
     // For notifications that need unique k1 per device, we don't use the batching approach
     // Instead, we send individual notifications with unique k1 values
     let expo = Expo::new(ExpoClientOptions {
@@ -77,49 +162,55 @@ pub async fn send_push_notification_with_unique_k1(
     };
 
     if push_targets.is_empty() {
-        return Ok(vec![]);
+        return Ok(UniqueK1SendResult::default());
     }
 
+    let notification_type = base_notification_data.notification_type();
+
     // Send individual notifications with unique k1 for each device
-    let receipts = stream::iter(push_targets)
-        .filter_map(|target| {
+    let outcomes = stream::iter(push_targets)
+        .then(|target| {
             let expo_clone = expo.clone();
             let app_state_clone = app_state.clone();
             let base_data_clone = base_notification_data.clone();
             let http_client_clone = http_client.clone();
             let ntfy_auth = app_state.config.ntfy_auth_token.clone();
             async move {
+                let failed = PushSendSummary {
+                    failed: 1,
+                    ..Default::default()
+                };
+
                 // Create notification data with unique k1 if needed
                 let notification_k1 = if base_data_clone.needs_unique_k1() {
                     match make_k1(&app_state_clone.k1_cache).await {
-                        Ok(unique_k1) => Some(unique_k1),
+                        Ok(unique_k1) => Some(unique_k1.to_string()),
                         Err(e) => {
                             tracing::error!(
                                 "Failed to create unique k1 for push notification: {}",
                                 e
                             );
-                            return None;
+                            return (None, failed);
                         }
                     }
                 } else {
                     None
                 };
 
-                let notification_data = match base_data_clone
-                    .into_notification_data(notification_k1.clone())
-                {
-                    Ok(notification_data) => notification_data,
-                    Err(e) => {
-                        tracing::error!("Failed to build notification payload: {}", e);
-                        return None;
-                    }
-                };
+                let notification_data =
+                    match base_data_clone.into_notification_data(notification_k1.clone()) {
+                        Ok(notification_data) => notification_data,
+                        Err(e) => {
+                            tracing::error!("Failed to build notification payload: {}", e);
+                            return (None, failed);
+                        }
+                    };
 
                 let data_string = match serde_json::to_string(&notification_data) {
                     Ok(s) => s,
                     Err(e) => {
                         tracing::error!("Failed to serialize notification data: {}", e);
-                        return None;
+                        return (None, failed);
                     }
                 };
 
@@ -131,6 +222,9 @@ pub async fn send_push_notification_with_unique_k1(
                         priority: Priority::High,
                         content_available: true,
                     };
+                    if !push_data.fits_payload_limit(&app_state_clone) {
+                        return (None, failed);
+                    }
 
                     let message = match ExpoPushMessage::builder(vec![target.push_token.clone()])
                         .data(&push_data.data)
@@ -143,15 +237,26 @@ pub async fn send_push_notification_with_unique_k1(
                         Ok(msg) => msg,
                         Err(e) => {
                             tracing::error!("Failed to build push notification message: {}", e);
-                            return None;
+                            return (None, failed);
                         }
                     };
 
-                    expo_clone
-                        .send_push_notifications(message)
-                        .await
-                        .map(|_| ())
-                        .map_err(|e| e.to_string())
+                    match expo_clone.send_push_notifications(message).await {
+                        Ok(tickets) => {
+                            let summary = handle_expo_tickets(
+                                &app_state_clone,
+                                std::slice::from_ref(&target.push_token),
+                                &tickets,
+                            )
+                            .await;
+                            let ticket_id = tickets.into_iter().find_map(|ticket| match ticket {
+                                ExpoPushTicket::Ok(ticket) => Some(ticket.id),
+                                ExpoPushTicket::Error(_) => None,
+                            });
+                            Ok((ticket_id, summary))
+                        }
+                        Err(e) => Err(e.to_string()),
+                    }
                 } else {
                     send_unified_notification(
                         &http_client_clone,
@@ -160,36 +265,89 @@ pub async fn send_push_notification_with_unique_k1(
                         &ntfy_auth,
                     )
                     .await
+                    .map(|_| {
+                        let delivered = PushSendSummary {
+                            delivered: 1,
+                            ..Default::default()
+                        };
+                        (None, delivered)
+                    })
                     .map_err(|e| e.to_string())
                 };
 
-                if let Err(e) = send_result {
-                    tracing::error!(pubkey = %target.pubkey, "Failed to send push notification: {}", e);
-                    return None;
-                }
+                let target_hash = pubkey_hash(&target.pubkey);
+                let transport = if is_expo_token(&target.push_token) {
+                    "expo"
+                } else {
+                    "unified_push"
+                };
 
-                Some(PushDispatchReceipt {
+                let (ticket_id, summary) = match send_result {
+                    Ok((ticket_id, summary)) => {
+                        add_push_breadcrumb(
+                            "push notification sent",
+                            sentry::Level::Info,
+                            [
+                                ("notification_type", notification_type.to_string()),
+                                ("target", target_hash),
+                                ("transport", transport.to_string()),
+                                ("ticket_id", ticket_id.clone().unwrap_or_default()),
+                            ],
+                        );
+                        (ticket_id, summary)
+                    }
+                    Err(e) => {
+                        add_push_breadcrumb(
+                            "push notification failed",
+                            sentry::Level::Error,
+                            [
+                                ("notification_type", notification_type.to_string()),
+                                ("target", target_hash.clone()),
+                                ("transport", transport.to_string()),
+                            ],
+                        );
+                        tracing::error!(
+                            notification_type,
+                            pubkey_hash = %target_hash,
+                            transport,
+                            "Failed to send push notification: {}",
+                            e
+                        );
+                        return (None, failed);
+                    }
+                };
+
+                let receipt = PushDispatchReceipt {
                     pubkey: target.pubkey,
                     notification_k1: notification_k1.unwrap_or_default(),
-                })
+                    ticket_id,
+                };
+                (Some(receipt), summary)
             }
         })
         .collect::<Vec<_>>()
         .await;
 
+    let mut result = UniqueK1SendResult::default();
+    for (receipt, summary) in outcomes {
+        result.receipts.extend(receipt);
+        result.summary += summary;
+    }
+
     tracing::debug!(
+        summary = ?result.summary,
         "send_push_notification_with_unique_k1: Sent {} notifications with unique k1s {:?}",
-        receipts.len(),
+        result.receipts.len(),
         base_notification_data
     );
-    Ok(receipts)
+    Ok(result)
 }
 
 async fn send_push_notification_internal(
     app_state: AppState,
     data: PushNotificationData,
     pubkey: Option<String>,
-) -> anyhow::Result<(), ApiError> {
+) -> anyhow::Result<PushSendSummary, ApiError> {
     let expo = Expo::new(ExpoClientOptions {
         access_token: Some(app_state.config.expo_access_token.clone()),
     });
@@ -208,7 +366,7 @@ async fn send_push_notification_internal(
     };
 
     if push_tokens.is_empty() {
-        return Ok(());
+        return Ok(PushSendSummary::default());
     }
 
     tracing::debug!(
@@ -219,18 +377,24 @@ async fn send_push_notification_internal(
     let (expo_tokens, unified_tokens): (Vec<_>, Vec<_>) =
         push_tokens.into_iter().partition(|t| is_expo_token(t));
 
-    if !expo_tokens.is_empty() {
+    let mut summary = PushSendSummary::default();
+
+    if !expo_tokens.is_empty() && !data.fits_payload_limit(&app_state) {
+        summary.failed += expo_tokens.len();
+    } else if !expo_tokens.is_empty() {
         let chunks = expo_tokens
             .chunks(100)
             .map(|c| c.to_vec())
             .collect::<Vec<_>>();
+        let chunk_count = chunks.len();
 
-        stream::iter(chunks)
-            .for_each_concurrent(None, |chunk| {
+        let mut chunk_summaries = stream::iter(chunks)
+            .map(|chunk| {
                 let expo_clone = expo.clone();
                 let data_clone = data.clone();
+                let app_state_clone = app_state.clone();
                 async move {
-                    let mut builder = ExpoPushMessage::builder(chunk);
+                    let mut builder = ExpoPushMessage::builder(chunk.clone());
                     if let Some(title) = &data_clone.title {
                         builder = builder.title(title.clone());
                     }
@@ -246,48 +410,143 @@ async fn send_push_notification_internal(
                         Ok(msg) => msg,
                         Err(e) => {
                             tracing::error!("Failed to build push notification message: {}", e);
-                            return;
+                            return PushSendSummary {
+                                failed: chunk.len(),
+                                ..Default::default()
+                            };
                         }
                     };
 
-                    if let Err(e) = expo_clone.send_push_notifications(message).await {
-                        tracing::error!("Failed to send push notification chunk: {}", e);
+                    match expo_clone.send_push_notifications(message).await {
+                        Ok(tickets) => {
+                            handle_expo_tickets(&app_state_clone, &chunk, &tickets).await
+                        }
+                        Err(e) => {
+                            tracing::error!("Failed to send push notification chunk: {}", e);
+                            PushSendSummary {
+                                failed: chunk.len(),
+                                ..Default::default()
+                            }
+                        }
                     }
                 }
             })
-            .await;
+            .buffer_unordered(chunk_count);
+        while let Some(chunk_summary) = chunk_summaries.next().await {
+            summary += chunk_summary;
+        }
     }
 
     if !unified_tokens.is_empty() {
         let ntfy_auth = app_state.config.ntfy_auth_token.clone();
         let data_clone = data.clone();
-        stream::iter(unified_tokens)
-            .for_each_concurrent(None, |endpoint| {
+        let endpoint_count = unified_tokens.len();
+        let mut results = stream::iter(unified_tokens)
+            .map(|endpoint| {
                 let http_client_clone = http_client.clone();
                 let ntfy_auth = ntfy_auth.clone();
                 let payload = data_clone.clone();
                 async move {
-                    if let Err(e) = send_unified_notification(
+                    send_unified_notification(
                         &http_client_clone,
                         &endpoint,
                         &payload.data,
                         &ntfy_auth,
                     )
                     .await
-                    {
-                        tracing::error!("Failed to send unified push notification: {}", e);
-                    }
                 }
             })
-            .await;
+            .buffer_unordered(endpoint_count);
+        while let Some(result) = results.next().await {
+            match result {
+                Ok(()) => summary.delivered += 1,
+                Err(e) => {
+                    tracing::error!("Failed to send unified push notification: {}", e);
+                    summary.failed += 1;
+                }
+            }
+        }
     }
 
     tracing::debug!(
+        delivered = summary.delivered,
+        failed = summary.failed,
+        not_registered = summary.not_registered,
+        pruned_tokens = summary.pruned_tokens,
         "send_push_notification: Sent push notification with data: {:?}",
         data.data
     );
 
-    Ok(())
+    Ok(summary)
+}
+
+/// Handles the tickets of one Expo request per message, since part of a batch can fail.
+///
+/// Counts `DeviceNotRegistered` tickets against their tokens, deleting a token only after
+/// repeated failures since Expo occasionally reports it for devices that are still valid.
+/// Delivered tokens have their streak reset.
+///
+/// Expo returns tickets in the same order as the tokens of the message. Tokens without a
+/// ticket count as failed.
+pub(crate) async fn handle_expo_tickets(
+    app_state: &AppState,
+    push_tokens: &[String],
+    tickets: &[ExpoPushTicket],
+) -> PushSendSummary {
+    let push_token_repo = PushTokenRepository::new(&app_state.db_pool);
+    let settings = app_state.config.token_prune_settings();
+    let mut summary = PushSendSummary::default();
+
+    if tickets.len() != push_tokens.len() {
+        tracing::warn!(
+            tokens = push_tokens.len(),
+            tickets = tickets.len(),
+            "Expo returned a different number of tickets than messages"
+        );
+        summary.failed += push_tokens.len().saturating_sub(tickets.len());
+    }
+
+    let mut delivered = Vec::new();
+    for (push_token, ticket) in push_tokens.iter().zip(tickets) {
+        match ticket {
+            ExpoPushTicket::Ok(_) => delivered.push(push_token.clone()),
+            ExpoPushTicket::Error(error) => {
+                summary.failed += 1;
+                let not_registered = error.details.as_ref().is_some_and(|details| {
+                    matches!(details.error, Some(DetailsErrorType::DeviceNotRegistered))
+                });
+                if !not_registered {
+                    tracing::warn!("Expo rejected push notification: {}", error.message);
+                    continue;
+                }
+
+                summary.not_registered += 1;
+                match push_token_repo
+                    .record_not_registered(push_token, &settings)
+                    .await
+                {
+                    Ok(true) => {
+                        summary.pruned_tokens += 1;
+                        tracing::info!(
+                            "Deleted push token after {} DeviceNotRegistered tickets",
+                            settings.threshold
+                        )
+                    }
+                    Ok(false) => {}
+                    Err(e) => tracing::error!("Failed to record DeviceNotRegistered: {}", e),
+                }
+            }
+        }
+    }
+
+    summary.delivered = delivered.len();
+    if !delivered.is_empty()
+        && let Err(e) = push_token_repo.clear_not_registered(&delivered).await
+    {
+        tracing::error!("Failed to reset DeviceNotRegistered counts: {}", e);
+    }
+
+    summary
 }
 
 async fn send_unified_notification(
@@ -1,31 +1,220 @@
-use axum::body::Body;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use axum::{
+    body::Body,
+    http::{HeaderMap, HeaderValue, Request, Response},
+};
+use futures_util::future::BoxFuture;
+use governor::middleware::{NoOpMiddleware, StateInformationMiddleware};
+use tower::{Layer, Service, ServiceExt};
 use tower_governor::{
-    GovernorLayer, governor::GovernorConfigBuilder, key_extractor::SmartIpKeyExtractor,
+    GovernorLayer,
+    governor::{Governor, GovernorConfigBuilder},
+    key_extractor::SmartIpKeyExtractor,
 };
 
+use crate::{config::RateLimitSettings, types::AuthenticatedUser};
+
+const RATE_LIMIT_LIMIT: &str = "x-ratelimit-limit";
+const RATE_LIMIT_REMAINING: &str = "x-ratelimit-remaining";
+const RATE_LIMIT_AFTER: &str = "x-ratelimit-after";
+const RATE_LIMIT_RESET: &str = "x-ratelimit-reset";
+
 // Type alias to simplify the return type
-type RateLimiter = GovernorLayer<
-    SmartIpKeyExtractor,
-    governor::middleware::NoOpMiddleware<governor::clock::QuantaInstant>,
-    Body,
->;
+type AuthRateLimiter =
+    GovernorLayer<SmartIpKeyExtractor, NoOpMiddleware<governor::clock::QuantaInstant>, Body>;
+
+/// Rate limiting layer that reports the caller's quota in `X-RateLimit-*` response headers.
+///
+/// `X-RateLimit-Limit` is the burst size, `X-RateLimit-Remaining` the requests left in it, and
+/// `X-RateLimit-Reset` the seconds until the burst is fully replenished, or until the next
+/// request is allowed once it's used up.
+#[derive(Clone)]
+pub struct RateLimiter {
+    governor: GovernorLayer<SmartIpKeyExtractor, StateInformationMiddleware, Body>,
+    seconds_per_request: u64,
+}
+
+impl RateLimiter {
+    fn new(seconds_per_request: u64, burst_size: u32) -> Self {
+        let config = GovernorConfigBuilder::default()
+            .per_second(seconds_per_request)
+            .burst_size(burst_size)
+            .key_extractor(SmartIpKeyExtractor)
+            .use_headers()
+            .finish()
+            .expect("Failed to create rate limiter config");
+
+        Self {
+            governor: GovernorLayer::new(config),
+            seconds_per_request,
+        }
+    }
+}
+
+impl<S> Layer<S> for RateLimiter {
+    type Service =
+        RateLimitHeaders<Governor<SmartIpKeyExtractor, StateInformationMiddleware, S, Body>>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimitHeaders {
+            inner: self.governor.layer(inner),
+            seconds_per_request: self.seconds_per_request,
+        }
+    }
+}
+
+/// Adds `X-RateLimit-Reset` next to the limit headers set by the governor.
+#[derive(Clone)]
+pub struct RateLimitHeaders<S> {
+    inner: S,
+    seconds_per_request: u64,
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for RateLimitHeaders<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<Body>>,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Response<Body>, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        let seconds_per_request = self.seconds_per_request;
+        let response = self.inner.call(request);
+        Box::pin(async move {
+            let mut response = response.await?;
+            add_reset_header(response.headers_mut(), seconds_per_request);
+            Ok(response)
+        })
+    }
+}
+
+fn add_reset_header(headers: &mut HeaderMap, seconds_per_request: u64) {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok())
+    };
+
+    let reset = match (
+        header(RATE_LIMIT_AFTER),
+        header(RATE_LIMIT_LIMIT),
+        header(RATE_LIMIT_REMAINING),
+    ) {
+        (Some(after), _, _) => after,
+        (None, Some(limit), Some(remaining)) => {
+            limit.saturating_sub(remaining) * seconds_per_request
+        }
+        _ => return,
+    };
+    headers.insert(RATE_LIMIT_RESET, HeaderValue::from(reset));
+}
+
+/// Pubkeys of trusted partners, from `RATE_LIMIT_EXEMPT_PUBKEYS`, that skip rate limiters
+/// wrapped with [`RateLimitExemptions::apply`].
+///
+/// A request is only exempt once `auth_middleware` has verified its bearer token, so the
+/// exemption has no effect on public routes or on limiters that run before authentication.
+#[derive(Clone, Default)]
+pub struct RateLimitExemptions(Arc<HashSet<String>>);
+
+impl RateLimitExemptions {
+    pub fn new(pubkeys: impl IntoIterator<Item = String>) -> Self {
+        Self(Arc::new(pubkeys.into_iter().collect()))
+    }
+
+    /// Wraps `limiter` so that requests from exempt pubkeys bypass it.
+    pub fn apply<L>(&self, limiter: L) -> ExemptLayer<L> {
+        ExemptLayer {
+            limiter,
+            exemptions: self.clone(),
+        }
+    }
+
+    fn is_exempt<B>(&self, request: &Request<B>) -> bool {
+        !self.0.is_empty()
+            && request
+                .extensions()
+                .get::<AuthenticatedUser>()
+                .is_some_and(|user| self.0.contains(&user.key))
+    }
+}
+
+/// Layer applying a rate limiter to everyone except exempt pubkeys.
+#[derive(Clone)]
+pub struct ExemptLayer<L> {
+    limiter: L,
+    exemptions: RateLimitExemptions,
+}
+
+impl<S, L> Layer<S> for ExemptLayer<L>
+where
+    S: Clone,
+    L: Layer<S>,
+{
+    type Service = ExemptService<S, L::Service>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ExemptService {
+            limited: self.limiter.layer(inner.clone()),
+            unlimited: inner,
+            exemptions: self.exemptions.clone(),
+        }
+    }
+}
+
+/// Sends exempt requests straight to the inner service and the rest through the limiter.
+#[derive(Clone)]
+pub struct ExemptService<S, L> {
+    limited: L,
+    unlimited: S,
+    exemptions: RateLimitExemptions,
+}
+
+impl<S, L, ReqBody> Service<Request<ReqBody>> for ExemptService<S, L>
+where
+    S: Service<Request<ReqBody>, Response = Response<Body>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    L: Service<Request<ReqBody>, Response = Response<Body>, Error = S::Error>,
+    L::Future: Send + 'static,
+    ReqBody: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Response<Body>, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.limited.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        if self.exemptions.is_exempt(&request) {
+            Box::pin(self.unlimited.clone().oneshot(request))
+        } else {
+            Box::pin(self.limited.call(request))
+        }
+    }
+}
 
 /// Creates a rate limiting layer for public endpoints like getk1
 /// This is more restrictive to prevent abuse
 pub fn create_public_rate_limiter() -> RateLimiter {
-    let config = GovernorConfigBuilder::default()
-        .per_second(5)
-        .burst_size(60)
-        .key_extractor(SmartIpKeyExtractor)
-        .finish()
-        .expect("Failed to create rate limiter config");
-
-    GovernorLayer::new(config)
+    RateLimiter::new(5, 60)
 }
 
 /// Creates a rate limiting layer for authenticated endpoints
-/// This is less restrictive as users are already authenticated
-pub fn create_auth_rate_limiter() -> RateLimiter {
+/// This is less restrictive as users are already authenticated. It wraps routes that have
+/// their own limiter, so it doesn't set quota headers that would overwrite theirs.
+pub fn create_auth_rate_limiter() -> AuthRateLimiter {
     let config = GovernorConfigBuilder::default()
         .per_second(10)
         .burst_size(120)
@@ -35,3 +224,166 @@ pub fn create_auth_rate_limiter() -> RateLimiter {
 
     GovernorLayer::new(config)
 }
+
+/// Creates a rate limiting layer for a single route from its configured settings
+pub fn create_route_rate_limiter(settings: RateLimitSettings) -> RateLimiter {
+    RateLimiter::new(settings.seconds_per_request, settings.burst_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Router,
+        http::{Request, StatusCode},
+        routing::get,
+    };
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::config::RateLimits;
+
+    #[test]
+    fn rate_limits_parse_overrides() {
+        let limits: RateLimits = "lnurlp=60:2, email_send_verification=120:1"
+            .parse()
+            .unwrap();
+        assert_eq!(limits.lnurlp, RateLimitSettings::new(60, 2));
+        assert_eq!(
+            limits.email_send_verification,
+            RateLimitSettings::new(120, 1)
+        );
+        assert_eq!(limits.register, RateLimits::default().register);
+        assert_eq!(
+            "email_availability=300:2"
+                .parse::<RateLimits>()
+                .unwrap()
+                .email_availability,
+            RateLimitSettings::new(300, 2)
+        );
+
+        assert!("".parse::<RateLimits>().is_ok());
+        assert!("unknown=1:1".parse::<RateLimits>().is_err());
+        assert!("lnurlp=0:1".parse::<RateLimits>().is_err());
+        assert!("lnurlp=5".parse::<RateLimits>().is_err());
+    }
+
+    #[tokio::test]
+    async fn route_rate_limiter_enforces_configured_burst() {
+        let limits: RateLimits = "lnurlp=60:2".parse().unwrap();
+        let app = Router::new().route(
+            "/.well-known/lnurlp/{username}",
+            get(|| async { StatusCode::OK }).layer(create_route_rate_limiter(limits.lnurlp)),
+        );
+
+        let request = |ip: &str| {
+            Request::builder()
+                .uri("/.well-known/lnurlp/test")
+                .header("x-forwarded-for", ip)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        for _ in 0..2 {
+            let response = app.clone().oneshot(request("10.0.0.1")).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        let response = app.clone().oneshot(request("10.0.0.1")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        // Limits are tracked per client IP
+        let response = app.clone().oneshot(request("10.0.0.2")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn exempt_pubkeys_bypass_wrapped_limiter() {
+        let limits: RateLimits = "register=60:1".parse().unwrap();
+        let exemptions = RateLimitExemptions::new(["partner".to_string()]);
+        // Stands in for auth_middleware, which runs before the limiters on gated routes
+        let authenticate = |mut request: Request<Body>, next: axum::middleware::Next| async move {
+            let key = request.headers()["x-test-pubkey"]
+                .to_str()
+                .unwrap()
+                .to_string();
+            request
+                .extensions_mut()
+                .insert(AuthenticatedUser { key, action: None });
+            next.run(request).await
+        };
+        let app = Router::new()
+            .route(
+                "/register",
+                get(|| async { StatusCode::OK })
+                    .layer(exemptions.apply(create_route_rate_limiter(limits.register))),
+            )
+            .layer(axum::middleware::from_fn(authenticate));
+
+        let request = |pubkey: &str| {
+            Request::builder()
+                .uri("/register")
+                .header("x-forwarded-for", "10.0.0.1")
+                .header("x-test-pubkey", pubkey)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        for _ in 0..3 {
+            let response = app.clone().oneshot(request("partner")).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        let response = app.clone().oneshot(request("someone")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = app.clone().oneshot(request("someone")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        // Partner traffic never counted against the shared IP's quota
+        let response = app.clone().oneshot(request("partner")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn route_rate_limiter_reports_remaining_quota() {
+        let limits: RateLimits = "lnurlp=60:2".parse().unwrap();
+        let app = Router::new().route(
+            "/.well-known/lnurlp/{username}",
+            get(|| async { StatusCode::OK }).layer(create_route_rate_limiter(limits.lnurlp)),
+        );
+
+        let quota = |response: &axum::response::Response| {
+            let header = |name: &str| {
+                response.headers()[name]
+                    .to_str()
+                    .unwrap()
+                    .parse::<u64>()
+                    .unwrap()
+            };
+            (
+                header("x-ratelimit-limit"),
+                header("x-ratelimit-remaining"),
+                header("x-ratelimit-reset"),
+            )
+        };
+        let request = || {
+            Request::builder()
+                .uri("/.well-known/lnurlp/test")
+                .header("x-forwarded-for", "10.0.0.1")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(quota(&response), (2, 1, 60));
+
+        let response = app.clone().oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(quota(&response), (2, 0, 120));
+
+        let response = app.clone().oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let (limit, remaining, reset) = quota(&response);
+        assert_eq!((limit, remaining), (2, 0));
+        assert!(reset > 0 && reset <= 60);
+    }
+}
@@ -0,0 +1,620 @@
+use std::collections::BTreeSet;
+use std::str::FromStr;
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::{HeaderMap, HeaderValue},
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::Acquire;
+
+use crate::{
+    AppState,
+    ark_client::broadcast_maintenance,
+    backup_integrity::{
+        BackupIntegrityReport, BackupRekeyReport, rekey_backups, verify_backup_integrity,
+    },
+    cache::admin_command_bus::AdminCommand,
+    db::{
+        abuse_report_repo::{AbuseReport, AbuseReportRepository},
+        admin_audit_repo::AdminAuditRepository,
+        backup_repo::BackupRepository,
+        feature_flag_repo::FeatureFlagRepository,
+        migrations::{MigrationStatus, migration_status},
+        user_repo::{AdminUserRecord, LightningAddressTakenError, PurgedUserRows, UserRepository},
+    },
+    errors::ApiError,
+    notification_coordinator::DispatchSummary,
+    routes::public_api_v0::{ensure_valid_lightning_address, ln_address_taken_under_alias},
+    s3_client::S3BackupClient,
+    types::{DefaultSuccessPayload, is_reserved_lightning_address},
+};
+
+const DEFAULT_USERS_PAGE_SIZE: i64 = 50;
+const MAX_USERS_PAGE_SIZE: i64 = 500;
+const MAX_BULK_REGISTER_USERS: usize = 1000;
+pub const TOTAL_COUNT_HEADER: &str = "x-total-count";
+
+/// Defines the query parameters for listing users.
+#[derive(Deserialize)]
+pub struct ListUsersQuery {
+    /// Maximum number of users to return.
+    limit: Option<i64>,
+    /// Opaque cursor returned as `next_cursor` by the previous page.
+    cursor: Option<String>,
+}
+
+/// A single user entry in the admin listing.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AdminUser {
+    pub pubkey: String,
+    pub lightning_address: Option<String>,
+    pub created_at: String,
+    pub last_login_at: Option<String>,
+    pub backup_enabled: bool,
+}
+
+impl From<AdminUserRecord> for AdminUser {
+    fn from(record: AdminUserRecord) -> Self {
+        Self {
+            pubkey: record.pubkey,
+            lightning_address: record.lightning_address,
+            created_at: record.created_at.to_rfc3339(),
+            last_login_at: record.last_login_at.map(|ts| ts.to_rfc3339()),
+            backup_enabled: record.backup_enabled,
+        }
+    }
+}
+
+/// Represents a page of users.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ListUsersResponse {
+    pub users: Vec<AdminUser>,
+    /// Cursor for the next page, absent when this is the last page.
+    pub next_cursor: Option<String>,
+}
+
+fn encode_users_cursor(created_at: DateTime<Utc>, pubkey: &str) -> String {
+    format!("{}_{}", created_at.timestamp_micros(), pubkey)
+}
+
+fn decode_users_cursor(cursor: &str) -> Option<(DateTime<Utc>, String)> {
+    let (micros, pubkey) = cursor.split_once('_')?;
+    if pubkey.is_empty() {
+        return None;
+    }
+    let created_at = DateTime::from_timestamp_micros(micros.parse().ok()?)?;
+    Some((created_at, pubkey.to_string()))
+}
+
+/// Lists all users, ordered by registration time.
+///
+/// Uses keyset pagination on `(created_at, pubkey)` so that deep pages stay cheap.
+/// The total number of users is returned in the `x-total-count` header.
+pub async fn list_users(
+    State(app_state): State<AppState>,
+    Query(query): Query<ListUsersQuery>,
+) -> anyhow::Result<(HeaderMap, Json<ListUsersResponse>), ApiError> {
+    let limit = query.limit.unwrap_or(DEFAULT_USERS_PAGE_SIZE);
+    if !(1..=MAX_USERS_PAGE_SIZE).contains(&limit) {
+        return Err(ApiError::InvalidArgument(format!(
+            "limit must be between 1 and {}",
+            MAX_USERS_PAGE_SIZE
+        )));
+    }
+
+    let after = match query.cursor.as_deref() {
+        Some(cursor) => Some(
+            decode_users_cursor(cursor)
+                .ok_or_else(|| ApiError::InvalidArgument("Invalid cursor".to_string()))?,
+        ),
+        None => None,
+    };
+
+    let user_repo = UserRepository::new(&app_state.db_pool);
+    let records = user_repo
+        .list_paginated(
+            after
+                .as_ref()
+                .map(|(created_at, pubkey)| (*created_at, pubkey.as_str())),
+            limit,
+        )
+        .await?;
+    let total = user_repo.count_all().await?;
+
+    let next_cursor = if records.len() as i64 == limit {
+        records
+            .last()
+            .map(|last| encode_users_cursor(last.created_at, &last.pubkey))
+    } else {
+        None
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert(TOTAL_COUNT_HEADER, HeaderValue::from(total));
+
+    Ok((
+        headers,
+        Json(ListUsersResponse {
+            users: records.into_iter().map(AdminUser::from).collect(),
+            next_cursor,
+        }),
+    ))
+}
+
+/// Aggregate user activity counts.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct ActiveUsersResponse {
+    pub total: i64,
+    pub active_24h: i64,
+    pub active_7d: i64,
+    pub active_30d: i64,
+}
+
+/// Returns how many users logged in over the last day, week and month.
+///
+/// Only aggregate counts are exposed, never individual users.
+pub async fn active_users(
+    State(app_state): State<AppState>,
+) -> anyhow::Result<Json<ActiveUsersResponse>, ApiError> {
+    let user_repo = UserRepository::new(&app_state.db_pool);
+    let now = Utc::now();
+
+    Ok(Json(ActiveUsersResponse {
+        total: user_repo.count_all().await?,
+        active_24h: user_repo
+            .count_active_since(now - Duration::hours(24))
+            .await?,
+        active_7d: user_repo
+            .count_active_since(now - Duration::days(7))
+            .await?,
+        active_30d: user_repo
+            .count_active_since(now - Duration::days(30))
+            .await?,
+    }))
+}
+
+/// Email verification counts.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct EmailVerificationStatsResponse {
+    /// Verification codes sent that haven't been used or expired yet.
+    pub pending: u64,
+}
+
+/// Returns how many email verifications are waiting for their code.
+pub async fn email_verification_stats(
+    State(app_state): State<AppState>,
+) -> anyhow::Result<Json<EmailVerificationStatsResponse>, ApiError> {
+    Ok(Json(EmailVerificationStatsResponse {
+        pending: app_state.email_verification_store.pending_count().await?,
+    }))
+}
+
+/// Defines the payload for overriding a feature flag for one user.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SetFeatureFlagOverridePayload {
+    pub pubkey: String,
+    pub flag: String,
+    /// The user's value for the flag, `null` removes the override.
+    pub enabled: Option<bool>,
+}
+
+/// Overrides a feature flag for a single user, used for staged rollouts.
+pub async fn set_feature_flag_override(
+    State(app_state): State<AppState>,
+    Json(payload): Json<SetFeatureFlagOverridePayload>,
+) -> anyhow::Result<Json<DefaultSuccessPayload>, ApiError> {
+    if payload.flag.trim().is_empty() {
+        return Err(ApiError::InvalidArgument("flag is required".to_string()));
+    }
+
+    UserRepository::new(&app_state.db_pool)
+        .find_by_pubkey(&payload.pubkey)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
+
+    FeatureFlagRepository::new(&app_state.db_pool)
+        .set_override(&payload.pubkey, payload.flag.trim(), payload.enabled)
+        .await?;
+
+    tracing::info!(
+        flag = %payload.flag,
+        enabled = ?payload.enabled,
+        "Feature flag override updated"
+    );
+
+    Ok(Json(DefaultSuccessPayload { success: true }))
+}
+
+/// Triggers the maintenance broadcast immediately, without waiting for the round interval.
+///
+/// Runs the same coordinated broadcast as the round-based trigger and returns how many
+/// users were eligible, notified and skipped.
+pub async fn trigger_maintenance(
+    State(app_state): State<AppState>,
+) -> anyhow::Result<Json<DispatchSummary>, ApiError> {
+    tracing::info!("Manual maintenance broadcast triggered");
+    let summary = broadcast_maintenance(app_state).await?;
+    Ok(Json(summary))
+}
+
+/// Defines the query parameters for verifying backup integrity.
+#[derive(Deserialize)]
+pub struct VerifyBackupsQuery {
+    /// Delete metadata rows whose S3 object is missing.
+    #[serde(default)]
+    repair: bool,
+}
+
+/// Reconciles backup metadata against the objects in the backup bucket.
+///
+/// Reports rows whose object is missing and objects no row points at. With `repair=true`,
+/// the orphaned rows are deleted; orphaned objects are never touched.
+pub async fn verify_backups(
+    State(app_state): State<AppState>,
+    Query(query): Query<VerifyBackupsQuery>,
+) -> anyhow::Result<Json<BackupIntegrityReport>, ApiError> {
+    tracing::info!(repair = query.repair, "Backup integrity check triggered");
+    let report = verify_backup_integrity(&app_state, query.repair).await?;
+    Ok(Json(report))
+}
+
+/// Moves backups to the key layout set by `S3_KEY_PREFIX` and `S3_HASH_PUBKEYS`.
+///
+/// Run once after changing either setting; rows already on the current layout are skipped.
+pub async fn rekey_backup_objects(
+    State(app_state): State<AppState>,
+) -> anyhow::Result<Json<BackupRekeyReport>, ApiError> {
+    tracing::info!("Backup rekey triggered");
+    let report = rekey_backups(&app_state).await?;
+    Ok(Json(report))
+}
+
+/// Defines the query parameters for listing abuse reports.
+#[derive(Deserialize)]
+pub struct ListAbuseReportsQuery {
+    /// Maximum number of reports to return.
+    limit: Option<i64>,
+}
+
+/// A user's complaint about unwanted invoice requests.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AdminAbuseReport {
+    pub id: i64,
+    pub pubkey: String,
+    pub reason: Option<String>,
+    pub created_at: String,
+}
+
+impl From<AbuseReport> for AdminAbuseReport {
+    fn from(report: AbuseReport) -> Self {
+        Self {
+            id: report.id,
+            pubkey: report.pubkey,
+            reason: report.reason,
+            created_at: report.created_at.to_rfc3339(),
+        }
+    }
+}
+
+/// Lists the most recent abuse reports for review, newest first.
+pub async fn list_abuse_reports(
+    State(app_state): State<AppState>,
+    Query(query): Query<ListAbuseReportsQuery>,
+) -> anyhow::Result<Json<Vec<AdminAbuseReport>>, ApiError> {
+    let limit = query.limit.unwrap_or(DEFAULT_USERS_PAGE_SIZE);
+    if !(1..=MAX_USERS_PAGE_SIZE).contains(&limit) {
+        return Err(ApiError::InvalidArgument(format!(
+            "limit must be between 1 and {}",
+            MAX_USERS_PAGE_SIZE
+        )));
+    }
+
+    let reports = AbuseReportRepository::new(&app_state.db_pool)
+        .list_recent(limit)
+        .await?;
+    Ok(Json(
+        reports.into_iter().map(AdminAbuseReport::from).collect(),
+    ))
+}
+
+/// Reports which schema migrations the database has applied.
+///
+/// Compares sqlx's `_sqlx_migrations` table with the migrations built into this binary, so a
+/// deploy can be checked for pending, dirty or edited migrations.
+pub async fn migrations(
+    State(app_state): State<AppState>,
+) -> anyhow::Result<Json<MigrationStatus>, ApiError> {
+    let status = migration_status(&app_state.db_pool).await?;
+    Ok(Json(status))
+}
+
+/// Result of publishing an admin command.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AdminCommandResponse {
+    /// Number of instances subscribed when the command was published, this one included.
+    pub receivers: usize,
+}
+
+/// Publishes a command that every instance runs, not just the one serving this request.
+pub async fn publish_admin_command(
+    State(app_state): State<AppState>,
+    Json(command): Json<AdminCommand>,
+) -> anyhow::Result<Json<AdminCommandResponse>, ApiError> {
+    let receivers = app_state.admin_command_bus.publish(&command).await?;
+    tracing::info!(?command, receivers, "Admin command published");
+    Ok(Json(AdminCommandResponse { receivers }))
+}
+
+/// Defines the payload for purging a user's data.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PurgeUserPayload {
+    /// The user's pubkey or lightning address.
+    pub identifier: String,
+}
+
+/// Summary of the data removed for a user.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PurgeUserResponse {
+    pub pubkey: String,
+    /// Backup objects deleted from S3.
+    pub s3_objects_deleted: usize,
+    /// Database rows deleted along with the user.
+    pub rows: PurgedUserRows,
+}
+
+/// Deletes everything stored for one user, for deletion requests sent to support.
+///
+/// Backup objects are deleted from S3 before any row is touched, so a failed S3 call leaves the
+/// user intact and the purge can simply be retried. Besides the keys in `backup_metadata`, every
+/// object under the user's prefixes is deleted, which catches copies an earlier rekey left.
+/// The purge is recorded in `admin_audit_log` in the same transaction as the row deletes.
+pub async fn purge_user(
+    State(app_state): State<AppState>,
+    Json(payload): Json<PurgeUserPayload>,
+) -> anyhow::Result<Json<PurgeUserResponse>, ApiError> {
+    let identifier = payload.identifier.trim();
+    if identifier.is_empty() {
+        return Err(ApiError::InvalidArgument(
+            "identifier is required".to_string(),
+        ));
+    }
+
+    let user_repo = UserRepository::new(&app_state.db_pool);
+    let mut user = None;
+    if identifier.contains('@') {
+        for address in app_state.config.lightning_address_aliases(identifier) {
+            user = user_repo.find_by_lightning_address(&address).await?;
+            if user.is_some() {
+                break;
+            }
+        }
+    } else {
+        user = user_repo.find_by_pubkey(identifier).await?;
+    }
+    let pubkey = user
+        .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?
+        .pubkey;
+
+    let s3_client = S3BackupClient::from_config(&app_state.config).await?;
+    let mut s3_keys: BTreeSet<String> = BackupRepository::new(&app_state.db_pool)
+        .find_s3_keys(&pubkey)
+        .await?
+        .into_iter()
+        .collect();
+    for prefix in s3_client.user_key_prefixes(&pubkey) {
+        s3_keys.extend(s3_client.list_keys_under(&prefix).await?);
+    }
+    for key in &s3_keys {
+        s3_client.delete_object(key).await?;
+    }
+
+    let mut tx = app_state.db_pool.begin().await?;
+    let rows = UserRepository::purge_tx(&mut tx, &pubkey)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
+    let audit_id = AdminAuditRepository::record_tx(
+        &mut tx,
+        "purge_user",
+        &pubkey,
+        &serde_json::json!({
+            "s3_objects_deleted": s3_keys.len(),
+            "rows": &rows,
+        }),
+    )
+    .await?;
+    tx.commit().await?;
+
+    tracing::info!(
+        audit_id,
+        s3_objects_deleted = s3_keys.len(),
+        "User data purged"
+    );
+
+    // The user is gone either way, and a leftover code expires with its TTL
+    if let Err(e) = app_state.email_verification_store.clear(&pubkey).await {
+        tracing::warn!(
+            audit_id,
+            "Failed to clear pending email verification for purged user: {}",
+            e
+        );
+    }
+
+    Ok(Json(PurgeUserResponse {
+        pubkey,
+        s3_objects_deleted: s3_keys.len(),
+        rows,
+    }))
+}
+
+/// A user to pre-provision.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BulkRegisterUser {
+    pub pubkey: String,
+    pub ln_address: String,
+}
+
+/// Defines the payload for pre-provisioning users in bulk.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BulkRegisterPayload {
+    pub users: Vec<BulkRegisterUser>,
+}
+
+/// What happened to one user of a bulk registration.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkRegisterStatus {
+    Created,
+    /// The pubkey was already registered, possibly earlier in the same batch.
+    Skipped,
+    Failed,
+}
+
+/// Outcome for one user of a bulk registration, in request order.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BulkRegisterResult {
+    pub pubkey: String,
+    pub status: BulkRegisterStatus,
+    /// Why the user was skipped or not created.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Summary of a bulk registration.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BulkRegisterResponse {
+    pub created: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub results: Vec<BulkRegisterResult>,
+}
+
+/// Pre-provisions users, for migrations and partner onboarding.
+///
+/// Every user is inserted in one transaction, each under its own savepoint, so a rejected
+/// user doesn't undo the others. Addresses are validated like at registration.
+pub async fn bulk_register(
+    State(app_state): State<AppState>,
+    Json(payload): Json<BulkRegisterPayload>,
+) -> anyhow::Result<Json<BulkRegisterResponse>, ApiError> {
+    if payload.users.len() > MAX_BULK_REGISTER_USERS {
+        return Err(ApiError::InvalidArgument(format!(
+            "At most {} users can be registered at once",
+            MAX_BULK_REGISTER_USERS
+        )));
+    }
+
+    let mut results = Vec::with_capacity(payload.users.len());
+    let mut tx = app_state.db_pool.begin().await?;
+
+    for user in payload.users {
+        // Pubkeys are stored in their canonical form, so differently cased hex is the same user
+        let outcome = match bitcoin::secp256k1::PublicKey::from_str(&user.pubkey) {
+            Err(_) => Err((BulkRegisterStatus::Failed, "Invalid pubkey".to_string())),
+            Ok(pubkey) => {
+                provision_user(&app_state, &mut tx, &pubkey.to_string(), &user.ln_address).await?
+            }
+        };
+
+        results.push(match outcome {
+            Ok(()) => BulkRegisterResult {
+                pubkey: user.pubkey,
+                status: BulkRegisterStatus::Created,
+                reason: None,
+            },
+            Err((status, reason)) => BulkRegisterResult {
+                pubkey: user.pubkey,
+                status,
+                reason: Some(reason),
+            },
+        });
+    }
+    tx.commit().await?;
+
+    let count = |status| results.iter().filter(|r| r.status == status).count();
+    let response = BulkRegisterResponse {
+        created: count(BulkRegisterStatus::Created),
+        skipped: count(BulkRegisterStatus::Skipped),
+        failed: count(BulkRegisterStatus::Failed),
+        results,
+    };
+    tracing::info!(
+        created = response.created,
+        skipped = response.skipped,
+        failed = response.failed,
+        "Bulk registration finished"
+    );
+
+    Ok(Json(response))
+}
+
+/// Validates and inserts one bulk-registered user under a savepoint of `tx`.
+///
+/// `pubkey` must already be in canonical form. A pubkey that is already registered, including
+/// earlier in the same batch, is skipped by the insert itself. The inner result carries the
+/// reason a user was skipped or rejected, the outer one database failures.
+async fn provision_user(
+    app_state: &AppState,
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    pubkey: &str,
+    ln_address: &str,
+) -> anyhow::Result<Result<(), (BulkRegisterStatus, String)>, ApiError> {
+    let failed = |reason: &str| Ok(Err((BulkRegisterStatus::Failed, reason.to_string())));
+
+    let ln_address = app_state.config.canonical_lightning_address(ln_address);
+    match ensure_valid_lightning_address(&app_state.config, &ln_address) {
+        Ok(()) => {}
+        Err(ApiError::InvalidArgument(reason)) => return failed(&reason),
+        Err(e) => return Err(e),
+    }
+    if is_reserved_lightning_address(&ln_address) {
+        return failed("Lightning address is reserved");
+    }
+    if ln_address_taken_under_alias(app_state, &ln_address, pubkey).await? {
+        return failed("Lightning address already taken");
+    }
+
+    let mut savepoint = (&mut **tx).begin().await?;
+    match UserRepository::create_if_absent(&mut savepoint, pubkey, &ln_address).await {
+        Ok(true) => {
+            savepoint.commit().await?;
+            Ok(Ok(()))
+        }
+        Ok(false) => {
+            savepoint.rollback().await?;
+            Ok(Err((
+                BulkRegisterStatus::Skipped,
+                "User already registered".to_string(),
+            )))
+        }
+        Err(e) if e.is::<LightningAddressTakenError>() => {
+            savepoint.rollback().await?;
+            failed("Lightning address already taken")
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn users_cursor_roundtrip() {
+        let created_at = DateTime::from_timestamp_micros(1_700_000_000_123_456).unwrap();
+        let cursor = encode_users_cursor(created_at, "02abcdef");
+        assert_eq!(
+            decode_users_cursor(&cursor),
+            Some((created_at, "02abcdef".to_string()))
+        );
+    }
+
+    #[test]
+    fn users_cursor_rejects_garbage() {
+        assert_eq!(decode_users_cursor("not-a-cursor"), None);
+        assert_eq!(decode_users_cursor("abc_02abcdef"), None);
+        assert_eq!(decode_users_cursor("1700000000_"), None);
+    }
+}
@@ -1,13 +1,50 @@
+use std::time::{Duration, SystemTime};
+
 use axum::{
+    body::Body,
     extract::{Request, State},
+    http::{HeaderValue, StatusCode},
     middleware::Next,
     response::{IntoResponse, Response},
 };
 
 use crate::{
-    AppState, auth::verify_access_token, db::user_repo::UserRepository, errors::ApiError,
-    types::AuthenticatedUser, utils::verify_user_exists, wide_event::WideEventHandle,
+    AppState,
+    abuse::{AbuseSubject, ensure_not_blocked},
+    auth::verify_access_token,
+    config::Config,
+    db::{feature_flag_repo::FeatureFlagRepository, user_repo::UserRepository},
+    errors::ApiError,
+    types::AuthenticatedUser,
+    utils::{sign_response_body, verify_user_exists},
+    wide_event::WideEventHandle,
 };
+use tower_http::timeout::TimeoutLayer;
+
+/// Header carrying the client's unix time in seconds, see `CLIENT_TIMESTAMP_MAX_SKEW_SECS`.
+pub const CLIENT_TIMESTAMP_HEADER: &str = "x-timestamp";
+
+/// Rejects requests whose `x-timestamp` is further than `max_skew_secs` from server time.
+fn check_client_timestamp(
+    timestamp: Option<&str>,
+    now: u64,
+    max_skew_secs: u64,
+) -> Result<(), ApiError> {
+    let timestamp = timestamp.ok_or_else(|| {
+        ApiError::InvalidArgument(format!("Missing {} header", CLIENT_TIMESTAMP_HEADER))
+    })?;
+    let timestamp: u64 = timestamp.trim().parse().map_err(|_| {
+        ApiError::InvalidArgument(format!(
+            "{} must be a unix timestamp in seconds",
+            CLIENT_TIMESTAMP_HEADER
+        ))
+    })?;
+
+    if timestamp.abs_diff(now) > max_skew_secs {
+        return Err(ApiError::ClockSkew { server_time: now });
+    }
+    Ok(())
+}
 
 pub async fn auth_middleware(
     State(state): State<AppState>,
@@ -16,6 +53,21 @@ pub async fn auth_middleware(
 ) -> Result<Response, Response> {
     let uri_path = request.uri().path().to_string();
 
+    if let Some(max_skew_secs) = state.config.client_timestamp_max_skew_secs {
+        let timestamp = request
+            .headers()
+            .get(CLIENT_TIMESTAMP_HEADER)
+            .and_then(|v| v.to_str().ok());
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        check_client_timestamp(timestamp, now, max_skew_secs).map_err(|error| {
+            tracing::warn!(uri = %uri_path, error = ?error, "Auth failed: Client clock skew");
+            error.into_response()
+        })?;
+    }
+
     let authorization = request
         .headers()
         .get(axum::http::header::AUTHORIZATION)
@@ -36,6 +88,18 @@ pub async fn auth_middleware(
         error.into_response()
     })?;
 
+    // A token minted from an action-bound k1 only works on that action's route
+    if let Some(action) = &authenticated_user.action
+        && uri_path.trim_start_matches('/') != action
+    {
+        tracing::warn!(uri = %uri_path, action = %action, "Auth failed: Token bound to another action");
+        return Err(ApiError::ActionNotAllowed(action.clone()).into_response());
+    }
+
+    ensure_not_blocked(&state, &[AbuseSubject::Pubkey(&authenticated_user.key)])
+        .await
+        .map_err(IntoResponse::into_response)?;
+
     if let Some(event) = request.extensions().get::<WideEventHandle>() {
         event.set_user(&authenticated_user.key);
     }
@@ -132,3 +196,112 @@ pub async fn email_verified_middleware(
 
     Ok(next.run(request).await)
 }
+
+/// Answers 404 when the route's feature flag is turned off for the caller.
+///
+/// Routes stay reachable while the flag is not configured. To ship one dark, set the flag to
+/// false in `FEATURE_FLAGS` and roll it out with per-user overrides. Runs behind
+/// `auth_middleware`, with the app state paired with the flag name as its state.
+pub async fn feature_flag_middleware(
+    State((state, flag)): State<(AppState, &'static str)>,
+    request: Request,
+    next: Next,
+) -> Result<Response, Response> {
+    let authenticated_user = match request.extensions().get::<AuthenticatedUser>() {
+        Some(payload) => payload,
+        None => {
+            return Err(ApiError::ServerErr(
+                "Authentication failed. Please try again.".to_string(),
+            )
+            .into_response());
+        }
+    };
+
+    let global = state
+        .config
+        .feature_flags()
+        .map_err(|e| ApiError::from(e).into_response())?;
+    let enabled = FeatureFlagRepository::new(&state.db_pool)
+        .is_enabled_or(&global, &authenticated_user.key, flag, true)
+        .await
+        .map_err(|e| {
+            tracing::error!(error = %e, flag, "Feature flag check failed");
+            ApiError::ServerErr("Failed to check feature flag".to_string()).into_response()
+        })?;
+
+    if !enabled {
+        return Err(ApiError::RouteNotFound.into_response());
+    }
+
+    Ok(next.run(request).await)
+}
+
+/// Header carrying the server's signature over the response body.
+pub const SERVER_SIGNATURE_HEADER: &str = "x-server-sig";
+
+/// Signs the response body when `RESPONSE_SIGNING_KEY` is configured.
+///
+/// Clients pin the key published by `/v0/info` and verify the `x-server-sig` header.
+pub async fn sign_response_middleware(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let response = next.run(request).await;
+
+    let secret_key = match state.config.response_signing_key() {
+        Ok(Some(secret_key)) => secret_key,
+        Ok(None) => return response,
+        Err(e) => {
+            tracing::error!("Invalid response signing key: {}", e);
+            return ApiError::ServerErr("Invalid server configuration".to_string()).into_response();
+        }
+    };
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::error!("Failed to buffer response for signing: {}", e);
+            return ApiError::ServerErr("Failed to sign response".to_string()).into_response();
+        }
+    };
+
+    let signature = sign_response_body(&secret_key, &bytes);
+    parts.headers.insert(
+        SERVER_SIGNATURE_HEADER,
+        HeaderValue::from_str(&signature).expect("hex is a valid header value"),
+    );
+
+    Response::from_parts(parts, Body::from(bytes))
+}
+
+/// Fallback for paths no route matches, so clients get the usual JSON error body.
+pub async fn route_not_found() -> ApiError {
+    ApiError::RouteNotFound
+}
+
+/// Fallback for requests whose method a route doesn't support.
+///
+/// Axum still adds the `Allow` header listing the route's methods.
+pub async fn method_not_allowed() -> ApiError {
+    ApiError::MethodNotAllowed
+}
+
+/// Answers 504 when a request runs past `REQUEST_TIMEOUT_SECS`, so a hung S3 or push call
+/// cannot hold the connection open.
+pub fn request_timeout_layer(config: &Config) -> TimeoutLayer {
+    gateway_timeout_layer(config.request_timeout())
+}
+
+/// Timeout for the LNURL-pay callback, which long-polls for the device's invoice.
+///
+/// It is kept longer than `LNURLP_INVOICE_TIMEOUT_SECS` so the handler reports its own
+/// timeout as an LNURL error instead of being cut off with a bare 504.
+pub fn lnurlp_timeout_layer(config: &Config) -> TimeoutLayer {
+    gateway_timeout_layer(config.lnurlp_route_timeout())
+}
+
+fn gateway_timeout_layer(timeout: Duration) -> TimeoutLayer {
+    TimeoutLayer::with_status_code(StatusCode::GATEWAY_TIMEOUT, timeout)
+}
@@ -1,19 +1,31 @@
+use crate::config::Config;
+use crate::db::abuse_report_repo::AbuseReportRepository;
 use crate::db::backup_repo::BackupRepository;
+use crate::db::feature_flag_repo::FeatureFlagRepository;
 use crate::db::heartbeat_repo::HeartbeatRepository;
 use crate::db::job_status_repo::JobStatusRepository;
+use crate::db::lightning_address_history_repo::LightningAddressHistoryRepository;
 use crate::db::mailbox_authorization_repo::MailboxAuthorizationRepository;
 use crate::db::push_token_repo::PushTokenRepository;
 use crate::db::user_repo::UserRepository;
+use crate::routes::public_api_v0::{
+    LNURLP_MAX_SENDABLE, LNURLP_MIN_SENDABLE, ensure_valid_lightning_address,
+    ln_address_taken_under_alias, lnurlp_url,
+};
 use crate::wide_event::WideEventHandle;
 // use crate::push::{PushNotificationData, send_push_notification};
 use crate::s3_client::S3BackupClient;
 use crate::types::{
-    AuthorizeMailboxPayload, BackupInfo, BackupSettingsPayload, CompleteUploadPayload,
-    DefaultSuccessPayload, DeleteBackupPayload, DownloadUrlResponse, GetDownloadUrlPayload,
-    HeartbeatResponsePayload, LightningAddressSuggestionsPayload,
-    LightningAddressSuggestionsResponse, ReportJobStatusPayload, ReportStatus,
-    SubmitInvoicePayload, UserInfoResponse,
+    AuthorizeMailboxPayload, BackupExistsResponse, BackupInfo, BackupSettingsPayload,
+    BackupUsageResponse, CompleteUploadPayload, DefaultSuccessPayload, DeleteBackupPayload,
+    DownloadUrlResponse, FeatureFlagsResponse, GetDownloadUrlPayload, HeartbeatResponsePayload,
+    LightningAddressSuggestionsPayload, LightningAddressSuggestionsResponse, LnurlMetadataResponse,
+    LnurlpSuccessAction, PushTokenInfo, ReportAbusePayload, ReportJobStatusPayload, ReportStatus,
+    RevokePushTokenPayload, SubmitInvoicePayload, UpdateDefaultSendablePayload,
+    UpdateSuccessActionPayload, UpdateTimezonePayload, UserInfoResponse,
+    VerifyOffboardingSignaturePayload, VerifyOffboardingSignatureResponse,
 };
+use crate::utils::{bolt11_description_hash, encode_lnurl, verify_address_signature};
 use crate::{
     AppState,
     errors::ApiError,
@@ -24,12 +36,18 @@ use crate::{
 };
 use axum::{Extension, Json, extract::State};
 use chrono::Utc;
+use chrono_tz::Tz;
 use validator::Validate;
 
 const MAX_MAILBOX_AUTH_TTL_SECS: i64 = 90 * 24 * 60 * 60;
 const LN_SUGGESTIONS_MIN_USERNAME_LEN: usize = 2;
 const LN_SUGGESTIONS_MAX_QUERY_LEN: usize = 64;
 const LN_SUGGESTIONS_LIMIT: i64 = 8;
+const PUSH_TOKEN_VISIBLE_CHARS: usize = 6;
+/// A user can file one abuse report per hour, repeats neither add rows nor extend the cap.
+const ABUSE_REPORT_INTERVAL_SECS: u64 = 60 * 60;
+/// LUD-09 caps success action texts at 144 characters.
+const SUCCESS_ACTION_MAX_TEXT_LEN: usize = 144;
 const NON_LN_SUGGESTION_PREFIXES: [&str; 9] = [
     "bc1", "tb1", "bcrt1", "lnbc", "lntb", "lnbcrt", "ark", "tark", "lno",
 ];
@@ -121,6 +139,57 @@ pub async fn register_push_token(
     Ok(Json(DefaultSuccessPayload { success: true }))
 }
 
+/// Keeps only the tail of a push token so users can tell tokens apart without exposing them.
+fn mask_push_token(push_token: &str) -> String {
+    let chars: Vec<char> = push_token.chars().collect();
+    let visible = chars.len().saturating_sub(PUSH_TOKEN_VISIBLE_CHARS);
+    let tail: String = chars[visible..].iter().collect();
+    format!("****{}", tail)
+}
+
+pub async fn list_push_tokens(
+    State(app_state): State<AppState>,
+    Extension(auth_payload): Extension<AuthenticatedUser>,
+) -> anyhow::Result<Json<Vec<PushTokenInfo>>, ApiError> {
+    let push_tokens = PushTokenRepository::new(&app_state.db_pool)
+        .list_by_pubkey(&auth_payload.key)
+        .await?
+        .into_iter()
+        .map(|record| PushTokenInfo {
+            id: record.id,
+            masked_token: mask_push_token(&record.push_token),
+            created_at: record.created_at.to_rfc3339(),
+            updated_at: record.updated_at.to_rfc3339(),
+            device_model: record.device_model,
+            os_name: record.os_name,
+            os_version: record.os_version,
+            last_seen_at: record.device_updated_at.map(|at| at.to_rfc3339()),
+        })
+        .collect();
+
+    Ok(Json(push_tokens))
+}
+
+pub async fn revoke_push_token(
+    State(app_state): State<AppState>,
+    Extension(auth_payload): Extension<AuthenticatedUser>,
+    event: Option<Extension<WideEventHandle>>,
+    Json(payload): Json<RevokePushTokenPayload>,
+) -> anyhow::Result<Json<DefaultSuccessPayload>, ApiError> {
+    if let Some(Extension(event)) = event {
+        event.add_context("push_token_id", payload.id);
+    }
+
+    let deleted = PushTokenRepository::new(&app_state.db_pool)
+        .delete_by_id(&auth_payload.key, payload.id)
+        .await?;
+    if !deleted {
+        return Err(ApiError::NotFound("Push token not found".to_string()));
+    }
+
+    Ok(Json(DefaultSuccessPayload { success: true }))
+}
+
 /// Stores or refreshes mailbox authorization for a user.
 pub async fn authorize_mailbox(
     State(app_state): State<AppState>,
@@ -128,9 +197,7 @@ pub async fn authorize_mailbox(
     event: Option<Extension<WideEventHandle>>,
     Json(payload): Json<AuthorizeMailboxPayload>,
 ) -> anyhow::Result<Json<DefaultSuccessPayload>, ApiError> {
-    payload
-        .validate()
-        .map_err(|e| ApiError::InvalidArgument(e.to_string()))?;
+    payload.validate()?;
 
     let now = Utc::now().timestamp();
     if payload.expiry <= now {
@@ -205,6 +272,10 @@ pub async fn submit_invoice(
         event.add_context("transaction_id", &payload.transaction_id);
     }
 
+    if state.config.lnurlp_verify_description_hash {
+        verify_invoice_description_hash(&state, &payload).await?;
+    }
+
     state
         .invoice_store
         .store(&payload.transaction_id, &payload.invoice)
@@ -217,6 +288,39 @@ pub async fn submit_invoice(
     Ok(Json(DefaultSuccessPayload { success: true }))
 }
 
+/// Rejects an invoice whose description hash doesn't match the LNURL metadata the payer saw.
+///
+/// Only LNURL-pay callbacks record an expected hash, invoices for other requests pass as is.
+async fn verify_invoice_description_hash(
+    state: &AppState,
+    payload: &SubmitInvoicePayload,
+) -> Result<(), ApiError> {
+    let expected = state
+        .invoice_store
+        .expected_description_hash(&payload.transaction_id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to load expected description hash: {}", e);
+            ApiError::ServerErr("Failed to store invoice".to_string())
+        })?;
+    let Some(expected) = expected else {
+        return Ok(());
+    };
+
+    let description_hash = bolt11_description_hash(&payload.invoice)
+        .map_err(|e| ApiError::InvalidArgument(format!("Invalid invoice: {}", e)))?;
+    if description_hash.map(|hash| hash.to_string()).as_deref() != Some(expected.as_str()) {
+        tracing::warn!(
+            transaction_id = %payload.transaction_id,
+            "Submitted invoice doesn't commit to the LNURL metadata"
+        );
+        return Err(ApiError::InvalidArgument(
+            "Invoice description hash doesn't match the LNURL metadata".to_string(),
+        ));
+    }
+    Ok(())
+}
+
 /// Returns autocomplete suggestions for a partial lightning address query.
 pub async fn ln_address_suggestions(
     State(state): State<AppState>,
@@ -291,6 +395,36 @@ pub async fn get_user_info(
     Ok(Json(UserInfoResponse { lightning_address }))
 }
 
+/// Returns the user's LNURL alongside their lightning address.
+///
+/// The LNURL is derived from the current `lnurl_domain`, so clients don't have to encode it
+/// themselves.
+pub async fn lnurl_metadata(
+    State(state): State<AppState>,
+    Extension(auth_payload): Extension<AuthenticatedUser>,
+) -> anyhow::Result<Json<LnurlMetadataResponse>, ApiError> {
+    let user_repo = UserRepository::new(&state.db_pool);
+
+    let user = user_repo
+        .find_by_pubkey(&auth_payload.key)
+        .await?
+        .ok_or(ApiError::NotFound("User not found".to_string()))?;
+
+    let lightning_address = user.lightning_address.ok_or(ApiError::NotFound(
+        "User does not have a lightning address".to_string(),
+    ))?;
+    let username = lightning_address
+        .split_once('@')
+        .map_or(lightning_address.as_str(), |(username, _)| username);
+
+    let lnurl = encode_lnurl(&lnurlp_url(&state.lnurl_domain, username))?;
+
+    Ok(Json(LnurlMetadataResponse {
+        lnurl,
+        lightning_address,
+    }))
+}
+
 /// Updates a user's lightning address.
 ///
 /// This endpoint allows a user to update their lightning address.
@@ -299,14 +433,32 @@ pub async fn update_ln_address(
     Extension(auth_payload): Extension<AuthenticatedUser>,
     Json(payload): Json<UpdateLnAddressPayload>,
 ) -> anyhow::Result<Json<DefaultSuccessPayload>, ApiError> {
-    if let Err(e) = payload.validate() {
-        return Err(ApiError::InvalidArgument(e.to_string()));
+    payload.validate()?;
+    let ln_address = state
+        .config
+        .canonical_lightning_address(&payload.ln_address);
+    ensure_valid_lightning_address(&state.config, &ln_address)?;
+
+    if crate::types::is_reserved_lightning_address(&ln_address) {
+        return Err(ApiError::InvalidArgument(
+            "Lightning address is reserved".to_string(),
+        ));
+    }
+
+    if ln_address_taken_under_alias(&state, &ln_address, &auth_payload.key).await? {
+        return Err(ApiError::InvalidArgument(
+            "Lightning address already taken".to_string(),
+        ));
     }
 
     let user_repo = UserRepository::new(&state.db_pool);
+    let previous_address = user_repo
+        .find_by_pubkey(&auth_payload.key)
+        .await?
+        .and_then(|user| user.lightning_address);
 
     let result = user_repo
-        .update_lightning_address(&auth_payload.key, &payload.ln_address)
+        .update_lightning_address(&auth_payload.key, &ln_address)
         .await;
 
     if let Err(e) = result {
@@ -318,9 +470,188 @@ pub async fn update_ln_address(
         return Err(e.into());
     }
 
+    let history_repo = LightningAddressHistoryRepository::new(&state.db_pool);
+    // A reclaimed address belongs to its new owner, not whoever retired it
+    history_repo.forget(&ln_address).await?;
+    if state.config.ln_address_history_enabled
+        && let Some(previous_address) = previous_address
+        && previous_address != ln_address
+    {
+        history_repo
+            .retire(&auth_payload.key, &previous_address)
+            .await?;
+    }
+
     Ok(Json(DefaultSuccessPayload { success: true }))
 }
 
+/// Sets the amount suggested to payers in the LNURL-pay metadata.
+///
+/// The hint is purely informational; payers can still send any amount within the limits.
+pub async fn update_default_sendable(
+    State(state): State<AppState>,
+    Extension(auth_payload): Extension<AuthenticatedUser>,
+    Json(payload): Json<UpdateDefaultSendablePayload>,
+) -> anyhow::Result<Json<DefaultSuccessPayload>, ApiError> {
+    if let Some(amount) = payload.default_sendable_msat
+        && !(LNURLP_MIN_SENDABLE..=LNURLP_MAX_SENDABLE).contains(&amount)
+    {
+        return Err(ApiError::InvalidArgument(format!(
+            "Suggested amount must be between {} and {} mSats",
+            LNURLP_MIN_SENDABLE, LNURLP_MAX_SENDABLE
+        )));
+    }
+
+    UserRepository::new(&state.db_pool)
+        .update_default_sendable_msat(
+            &auth_payload.key,
+            payload.default_sendable_msat.map(|amount| amount as i64),
+        )
+        .await?;
+
+    Ok(Json(DefaultSuccessPayload { success: true }))
+}
+
+fn validate_success_action(
+    success_action: &LnurlpSuccessAction,
+    lnurl_domain: &str,
+) -> Result<(), ApiError> {
+    let (label, text) = match success_action {
+        LnurlpSuccessAction::Message { message } => ("Message", message),
+        LnurlpSuccessAction::Url { description, url } => {
+            let parsed = reqwest::Url::parse(url)
+                .map_err(|_| ApiError::InvalidArgument(format!("Invalid URL: {}", url)))?;
+            if parsed.scheme() != "https" {
+                return Err(ApiError::InvalidArgument(
+                    "Success action URL must use https".to_string(),
+                ));
+            }
+            // LUD-09 requires the URL to be on the same domain as the callback
+            let callback = reqwest::Url::parse(&format!("https://{}/", lnurl_domain))
+                .map_err(|_| ApiError::ServerErr("Invalid LNURL domain".to_string()))?;
+            if parsed.host_str() != callback.host_str() || parsed.port() != callback.port() {
+                return Err(ApiError::InvalidArgument(format!(
+                    "Success action URL must be on {}",
+                    lnurl_domain
+                )));
+            }
+            ("Description", description)
+        }
+    };
+
+    if text.trim().is_empty() || text.chars().count() > SUCCESS_ACTION_MAX_TEXT_LEN {
+        return Err(ApiError::InvalidArgument(format!(
+            "{} must be between 1 and {} characters",
+            label, SUCCESS_ACTION_MAX_TEXT_LEN
+        )));
+    }
+
+    Ok(())
+}
+
+/// Sets the success action shown to the user's LNURL-pay payers once they paid.
+pub async fn update_success_action(
+    State(state): State<AppState>,
+    Extension(auth_payload): Extension<AuthenticatedUser>,
+    Json(payload): Json<UpdateSuccessActionPayload>,
+) -> anyhow::Result<Json<DefaultSuccessPayload>, ApiError> {
+    if let Some(success_action) = &payload.success_action {
+        validate_success_action(success_action, &state.lnurl_domain)?;
+    }
+
+    UserRepository::new(&state.db_pool)
+        .update_lnurlp_success_action(&auth_payload.key, payload.success_action.as_ref())
+        .await?;
+
+    Ok(Json(DefaultSuccessPayload { success: true }))
+}
+
+/// Sets the timezone used to schedule the user's notifications, such as quiet hours.
+pub async fn update_timezone(
+    State(state): State<AppState>,
+    Extension(auth_payload): Extension<AuthenticatedUser>,
+    Json(payload): Json<UpdateTimezonePayload>,
+) -> anyhow::Result<Json<DefaultSuccessPayload>, ApiError> {
+    let timezone = match payload.timezone.as_deref() {
+        Some(name) => Some(
+            name.parse::<Tz>()
+                .map_err(|_| ApiError::InvalidArgument(format!("Unknown timezone: {}", name)))?,
+        ),
+        None => None,
+    };
+
+    UserRepository::new(&state.db_pool)
+        .update_timezone(&auth_payload.key, timezone.map(Tz::name))
+        .await?;
+
+    Ok(Json(DefaultSuccessPayload { success: true }))
+}
+
+/// Returns the feature flags for the user, with their rollout overrides applied.
+pub async fn get_feature_flags(
+    State(state): State<AppState>,
+    Extension(auth_payload): Extension<AuthenticatedUser>,
+) -> anyhow::Result<Json<FeatureFlagsResponse>, ApiError> {
+    let global = state.config.feature_flags()?;
+    let feature_flags = FeatureFlagRepository::new(&state.db_pool)
+        .resolve(&global, &auth_payload.key)
+        .await?;
+
+    Ok(Json(FeatureFlagsResponse { feature_flags }))
+}
+
+/// Rejects backup versions outside the rolling range clients are expected to cycle through.
+fn check_backup_version(config: &Config, backup_version: i32) -> Result<(), ApiError> {
+    if !(1..=config.max_backup_version).contains(&backup_version) {
+        return Err(ApiError::InvalidArgument(format!(
+            "Backup version must be between 1 and {}",
+            config.max_backup_version
+        )));
+    }
+    Ok(())
+}
+
+/// Checks that a client-reported backup checksum is a hex SHA-256 and lowercases it, so
+/// checksums compare equal regardless of the client's hex casing.
+fn normalize_backup_checksum(checksum: Option<String>) -> Result<Option<String>, ApiError> {
+    let Some(checksum) = checksum else {
+        return Ok(None);
+    };
+    if checksum.len() != 64 || !checksum.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(ApiError::InvalidArgument(
+            "Checksum must be a hex-encoded SHA-256 hash".to_string(),
+        ));
+    }
+    Ok(Some(checksum.to_ascii_lowercase()))
+}
+
+/// Rejects an upload that would take the user past `MAX_BACKUPS_PER_USER` or
+/// `MAX_TOTAL_BACKUP_BYTES_PER_USER`, given the usage of their other backups.
+fn check_backup_quota(
+    config: &Config,
+    other_backups: u64,
+    other_bytes: u64,
+    backup_size: u64,
+) -> Result<(), ApiError> {
+    if let Some(max) = config.max_backups_per_user
+        && other_backups >= u64::from(max)
+    {
+        return Err(ApiError::QuotaExceeded(format!(
+            "Backup quota exceeded: {} of {} backups used",
+            other_backups, max
+        )));
+    }
+    if let Some(max) = config.max_total_backup_bytes_per_user
+        && other_bytes.saturating_add(backup_size) > max
+    {
+        return Err(ApiError::QuotaExceeded(format!(
+            "Backup storage quota exceeded: {} of {} bytes used by other backups, this backup needs {} bytes",
+            other_bytes, max, backup_size
+        )));
+    }
+    Ok(())
+}
+
 pub async fn get_upload_url(
     State(state): State<AppState>,
     Extension(auth_payload): Extension<AuthenticatedUser>,
@@ -330,16 +661,33 @@ pub async fn get_upload_url(
     if let Some(Extension(event)) = event {
         event.add_context("backup_version", payload.backup_version);
     }
+    check_backup_version(&state.config, payload.backup_version)?;
+
+    if let Some(checksum) = normalize_backup_checksum(payload.checksum)? {
+        let stored = BackupRepository::new(&state.db_pool)
+            .find_checksum_by_version(&auth_payload.key, payload.backup_version)
+            .await?;
+        if stored.as_deref() == Some(checksum.as_str()) {
+            // The skipped upload still counts as a backup, so the user doesn't look inactive
+            let mut tx = state.db_pool.begin().await?;
+            BackupRepository::touch_last_backup_at_tx(&mut tx, &auth_payload.key).await?;
+            BackupRepository::set_latest_version_tx(
+                &mut tx,
+                &auth_payload.key,
+                payload.backup_version,
+            )
+            .await?;
+            tx.commit().await?;
+
+            return Ok(Json(UploadUrlResponse::Unchanged));
+        }
+    }
 
-    let s3_client = S3BackupClient::new(state.config.s3_bucket_name.clone()).await?;
-    let s3_key = format!(
-        "{}/backup_v{}.db",
-        auth_payload.key.clone(),
-        payload.backup_version
-    );
+    let s3_client = S3BackupClient::from_config(&state.config).await?;
+    let s3_key = s3_client.backup_key(&auth_payload.key, payload.backup_version);
     let upload_url = s3_client.generate_upload_url(&s3_key).await?;
 
-    Ok(Json(UploadUrlResponse { upload_url, s3_key }))
+    Ok(Json(UploadUrlResponse::Upload { upload_url, s3_key }))
 }
 
 pub async fn complete_upload(
@@ -352,20 +700,74 @@ pub async fn complete_upload(
         event.add_context("backup_version", payload.backup_version);
         event.add_context("backup_size_bytes", payload.backup_size);
     }
+    check_backup_version(&state.config, payload.backup_version)?;
+    let checksum = normalize_backup_checksum(payload.checksum)?;
 
-    let backup_repo = BackupRepository::new(&state.db_pool);
-    backup_repo
-        .upsert_metadata(
-            &auth_payload.key,
-            &payload.s3_key,
-            payload.backup_size,
-            payload.backup_version,
-        )
+    let mut tx = state.db_pool.begin().await?;
+
+    let (other_backups, other_bytes) = BackupRepository::usage_excluding_version_tx(
+        &mut tx,
+        &auth_payload.key,
+        payload.backup_version,
+    )
+    .await?;
+    if let Err(e) = check_backup_quota(
+        &state.config,
+        other_backups,
+        other_bytes,
+        payload.backup_size,
+    ) {
+        tx.rollback().await?;
+        discard_rejected_upload(&state, &auth_payload.key, payload.backup_version).await;
+        return Err(e);
+    }
+
+    BackupRepository::upsert_metadata_tx(
+        &mut tx,
+        &auth_payload.key,
+        &payload.s3_key,
+        payload.backup_size,
+        payload.backup_version,
+        checksum.as_deref(),
+    )
+    .await?;
+    BackupRepository::touch_last_backup_at_tx(&mut tx, &auth_payload.key).await?;
+    BackupRepository::set_latest_version_tx(&mut tx, &auth_payload.key, payload.backup_version)
         .await?;
 
+    tx.commit().await?;
+
     Ok(Json(DefaultSuccessPayload { success: true }))
 }
 
+/// Deletes the object a backup rejected by `complete_upload` was uploaded to, so it doesn't
+/// take up storage outside the quota.
+///
+/// The key is derived on the server, never taken from the request. An object a stored row
+/// still points at is left alone, it may be the user's accepted backup.
+async fn discard_rejected_upload(state: &AppState, pubkey: &str, backup_version: i32) {
+    let result = async {
+        let s3_client = S3BackupClient::from_config(&state.config).await?;
+        let s3_key = s3_client.backup_key(pubkey, backup_version);
+        let stored_key = BackupRepository::new(&state.db_pool)
+            .find_s3_key_by_version(pubkey, backup_version)
+            .await?;
+        if stored_key.as_deref() != Some(s3_key.as_str()) {
+            s3_client.delete_object(&s3_key).await?;
+        }
+        anyhow::Ok(())
+    }
+    .await;
+
+    if let Err(e) = result {
+        tracing::warn!(
+            backup_version,
+            "Failed to delete backup rejected by quota: {}",
+            e
+        );
+    }
+}
+
 pub async fn list_backups(
     State(state): State<AppState>,
     Extension(auth_payload): Extension<AuthenticatedUser>,
@@ -375,6 +777,45 @@ pub async fn list_backups(
     Ok(Json(backups))
 }
 
+/// Reports whether the user has a backup and which version is the latest.
+pub async fn backup_exists(
+    State(state): State<AppState>,
+    Extension(auth_payload): Extension<AuthenticatedUser>,
+) -> Result<Json<BackupExistsResponse>, ApiError> {
+    let backup_repo = BackupRepository::new(&state.db_pool);
+    let latest_version = backup_repo.find_latest_version(&auth_payload.key).await?;
+    Ok(Json(BackupExistsResponse {
+        exists: latest_version.is_some(),
+        latest_version,
+    }))
+}
+
+/// Feature flag that hides `/backup/usage` for users it is turned off for.
+pub const BACKUP_USAGE_FLAG: &str = "backup_usage";
+
+/// Reports how much backup storage the user is using and what is left of their quota.
+pub async fn backup_usage(
+    State(state): State<AppState>,
+    Extension(auth_payload): Extension<AuthenticatedUser>,
+) -> Result<Json<BackupUsageResponse>, ApiError> {
+    let backup_repo = BackupRepository::new(&state.db_pool);
+    let usage = backup_repo.usage(&auth_payload.key).await?;
+    Ok(Json(BackupUsageResponse {
+        total_bytes: usage.total_bytes,
+        backup_count: usage.backup_count,
+        oldest_backup_at: usage.oldest_backup_at.map(|at| at.to_rfc3339()),
+        newest_backup_at: usage.newest_backup_at.map(|at| at.to_rfc3339()),
+        remaining_bytes: state
+            .config
+            .max_total_backup_bytes_per_user
+            .map(|max| max.saturating_sub(usage.total_bytes)),
+        remaining_backups: state
+            .config
+            .max_backups_per_user
+            .map(|max| u64::from(max).saturating_sub(usage.backup_count)),
+    }))
+}
+
 pub async fn get_download_url(
     State(state): State<AppState>,
     Extension(auth_payload): Extension<AuthenticatedUser>,
@@ -399,7 +840,7 @@ pub async fn get_download_url(
             .ok_or(ApiError::NotFound("Backup not found".to_string()))?
     };
 
-    let s3_client = S3BackupClient::new(state.config.s3_bucket_name.clone()).await?;
+    let s3_client = S3BackupClient::from_config(&state.config).await?;
     let download_url = s3_client.generate_download_url(&s3_key).await?;
 
     Ok(Json(DownloadUrlResponse {
@@ -425,7 +866,7 @@ pub async fn delete_backup(
         .await?
         .ok_or(ApiError::NotFound("Backup not found".to_string()))?;
 
-    let s3_client = S3BackupClient::new(state.config.s3_bucket_name.clone()).await?;
+    let s3_client = S3BackupClient::from_config(&state.config).await?;
     s3_client.delete_object(&s3_key).await?;
 
     backup_repo
@@ -514,9 +955,45 @@ pub async fn deregister(
 
     tx.commit().await?;
 
+    // The deregistration already happened, and a leftover code expires with its TTL
+    if let Err(e) = state.email_verification_store.clear(&pubkey).await {
+        tracing::warn!(
+            "Failed to clear pending email verification for {}: {}",
+            pubkey,
+            e
+        );
+    }
+
     Ok(Json(DefaultSuccessPayload { success: true }))
 }
 
+/// Checks an offboarding address signature without creating an offboarding request.
+///
+/// Lets the client validate the signature it produced before committing to offboarding.
+pub async fn verify_offboarding_signature(
+    State(state): State<AppState>,
+    Extension(auth_payload): Extension<AuthenticatedUser>,
+    event: Option<Extension<WideEventHandle>>,
+    Json(payload): Json<VerifyOffboardingSignaturePayload>,
+) -> anyhow::Result<Json<VerifyOffboardingSignatureResponse>, ApiError> {
+    payload.validate()?;
+
+    let network = state.config.network()?;
+    let valid = verify_address_signature(
+        network,
+        &auth_payload.key,
+        &payload.address,
+        &payload.address_signature,
+    )
+    .await?;
+
+    if let Some(Extension(event)) = event {
+        event.add_context("offboarding_signature_valid", valid);
+    }
+
+    Ok(Json(VerifyOffboardingSignatureResponse { valid }))
+}
+
 pub async fn heartbeat_response(
     State(state): State<AppState>,
     Extension(_auth_payload): Extension<AuthenticatedUser>,
@@ -551,3 +1028,36 @@ pub async fn report_last_login(
 
     Ok(Json(DefaultSuccessPayload { success: true }))
 }
+
+/// Records a complaint about unwanted invoice requests and lowers the user's daily cap for a
+/// while, so fewer payment requests can wake their device.
+pub async fn report_abuse(
+    State(state): State<AppState>,
+    Extension(auth_payload): Extension<AuthenticatedUser>,
+    Json(payload): Json<ReportAbusePayload>,
+) -> anyhow::Result<Json<DefaultSuccessPayload>, ApiError> {
+    payload.validate()?;
+
+    if let Some(retry_after) = state
+        .abuse_store
+        .claim_report_window(&auth_payload.key, ABUSE_REPORT_INTERVAL_SECS)
+        .await?
+    {
+        return Err(ApiError::TooManyRequests(retry_after));
+    }
+
+    let report_id = AbuseReportRepository::new(&state.db_pool)
+        .create(&auth_payload.key, payload.reason.as_deref())
+        .await?;
+
+    if state.config.lnurlp_reported_daily_request_cap > 0 {
+        state
+            .invoice_store
+            .tighten_daily_cap(&auth_payload.key, state.config.lnurlp_reported_cap_secs)
+            .await?;
+    }
+
+    tracing::info!(report_id, "Abuse reported by a recipient");
+
+    Ok(Json(DefaultSuccessPayload { success: true }))
+}
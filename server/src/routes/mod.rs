@@ -1,3 +1,4 @@
+pub mod admin_api;
 pub mod app_middleware;
 pub mod gated_api_v0;
 pub mod public_api_v0;
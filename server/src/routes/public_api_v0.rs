@@ -1,33 +1,59 @@
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
-use std::time::SystemTime;
+use std::time::Instant;
+
+use bitcoin::hashes::{Hash, sha256};
+use chrono::Utc;
 
 use axum::{
     Extension, Json,
-    extract::{Path, Query, State},
-    http::StatusCode,
+    extract::{
+        Path, Query, State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    response::Response,
 };
 use expo_push_notification_client::Priority;
 use rand::Rng;
 use uuid::Uuid;
 
 use serde::{Deserialize, Serialize};
-use tokio::time::sleep;
+use sqlx::{Postgres, Transaction};
+use tokio::{sync::mpsc, task::JoinHandle, time::sleep};
 use validator::Validate;
 
 use crate::{
     AppState,
+    abuse::{AbuseEvent, AbuseSubject, ClientIp, ensure_not_blocked, record_abuse},
     auth::mint_access_token,
-    cache::email_verification_store::EmailVerificationStore,
-    db::{device_repo::DeviceRepository, user_repo::UserRepository},
+    cache::{
+        email_verification_store::EmailVerificationStore,
+        invoice_store::PendingInvoice,
+        k1_store::{K1, current_timestamp, is_valid_k1_action},
+    },
+    config::Config,
+    db::{
+        backup_repo::BackupRepository,
+        device_repo::DeviceRepository,
+        lightning_address_history_repo::LightningAddressHistoryRepository,
+        push_token_repo::PushTokenRepository,
+        user_repo::{User, UserRepository},
+    },
     errors::ApiError,
     push::{PushNotificationData, send_push_notification},
     types::{
         AppVersionCheckPayload, AppVersionInfo, AuthEvent, AuthLoginPayload, AuthLoginResponse,
-        AuthenticatedUser, EmailVerificationResponse, LightningInvoiceRequestNotification,
-        NotificationData, RegisterPayload, RegisterResponse, SendEmailVerificationPayload,
-        VerifyEmailPayload,
+        AuthenticatedUser, DeviceInfo, EmailAvailabilityPayload, EmailAvailabilityResponse,
+        EmailVerificationResponse, HealthResponse, INVALID_LN_USERNAME_MESSAGE, InvoiceStatusFrame,
+        LightningAddressAvailabilityQuery, LightningAddressAvailabilityResponse,
+        LightningInvoiceRequestNotification, LnurlpDefaultResponse, LnurlpInvoiceResponse,
+        LnurlpPreRegisterPayload, LnurlpPreRegisterResponse, LnurlpSuccessAction, NotificationData,
+        RegisterPayload, RegisterResponse, SendEmailVerificationPayload, ServerInfoResponse,
+        ServerTimeResponse, VerifyEmailPayload,
     },
-    utils::{make_k1, verify_auth},
+    utils::{verify_auth, verify_pow},
     wide_event::WideEventHandle,
 };
 
@@ -40,68 +66,167 @@ pub struct GetK1 {
     pub tag: String,
 }
 
-const LNURLP_MIN_SENDABLE: u64 = 330000;
-const LNURLP_MAX_SENDABLE: u64 = 100000000;
+/// Query parameters for `get_k1` carrying an optional proof-of-work solution.
+#[derive(Deserialize, Default)]
+pub struct GetK1Query {
+    /// A challenge previously returned by `get_k1_challenge`.
+    pub pow_challenge: Option<String>,
+    /// A nonce such that `sha256("{pow_challenge}:{pow_nonce}")` meets the difficulty.
+    pub pow_nonce: Option<String>,
+    /// Binds the k1 to one gated route, e.g. `register`. Unbound k1s work for any route.
+    pub action: Option<String>,
+}
+
+/// Represents a proof-of-work challenge that must be solved before requesting a `k1`.
+#[derive(Serialize, Deserialize)]
+pub struct K1PowChallenge {
+    /// A single-use random challenge, valid for a short time.
+    pub challenge: String,
+    /// The number of leading zero bits the solution hash must have.
+    pub difficulty: u8,
+}
+
+const MAX_POW_NONCE_LENGTH: usize = 64;
+const LN_AVAILABILITY_SUGGESTIONS: usize = 3;
+const LN_AVAILABILITY_MAX_ATTEMPTS: usize = 10;
+/// Smallest LNURL-pay amount in millisatoshis (330 sats).
+pub(crate) const LNURLP_MIN_SENDABLE: u64 = 330000;
+/// Largest LNURL-pay amount in millisatoshis (100,000 sats).
+pub(crate) const LNURLP_MAX_SENDABLE: u64 = 100000000;
+const MSATS_PER_SAT: u64 = 1000;
 const COMMENT_ALLOWED_SIZE: u16 = 280;
 const POLL_INTERVAL: Duration = Duration::from_millis(500);
-const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Issues a proof-of-work challenge for `get_k1`.
+///
+/// Only available when `K1_POW_DIFFICULTY` is configured.
+pub async fn get_k1_challenge(
+    State(state): State<AppState>,
+) -> anyhow::Result<Json<K1PowChallenge>, ApiError> {
+    let Some(difficulty) = state.config.k1_pow_difficulty else {
+        return Err(ApiError::NotFound(
+            "Proof of work is not enabled".to_string(),
+        ));
+    };
+
+    let challenge = state.k1_cache.issue_pow_challenge().await.map_err(|e| {
+        tracing::error!("Failed to create proof-of-work challenge: {}", e);
+        ApiError::ServerErr("Failed to create challenge".to_string())
+    })?;
+
+    Ok(Json(K1PowChallenge {
+        challenge,
+        difficulty,
+    }))
+}
+
 /// Generates and returns a new `k1` value for an LNURL-auth flow.
 ///
 /// The `k1` value is a random 32-byte hex-encoded string that is stored in Redis with
 /// a strict TTL so it can be used once for a login or registration attempt.
-pub async fn get_k1(State(state): State<AppState>) -> anyhow::Result<Json<GetK1>, StatusCode> {
-    let k1 = make_k1(&state.k1_cache).await.map_err(|e| {
-        tracing::error!("Failed to create k1: {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
+///
+/// When `K1_POW_DIFFICULTY` is configured, the request must carry a solved challenge
+/// from `get_k1_challenge` to raise the cost of minting many `k1` values.
+///
+/// With `?action=`, the access token later minted from the `k1` is only accepted on that
+/// action's route.
+pub async fn get_k1(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    Query(query): Query<GetK1Query>,
+) -> anyhow::Result<Json<GetK1>, ApiError> {
+    let subjects: Vec<AbuseSubject> = ip.map(AbuseSubject::Ip).into_iter().collect();
+    ensure_not_blocked(&state, &subjects).await?;
+    record_abuse(&state, AbuseEvent::K1Request, &subjects).await?;
+
+    if let Some(difficulty) = state.config.k1_pow_difficulty {
+        let (Some(challenge), Some(nonce)) = (query.pow_challenge, query.pow_nonce) else {
+            return Err(ApiError::InvalidProofOfWork);
+        };
+        if nonce.len() > MAX_POW_NONCE_LENGTH || !verify_pow(&challenge, &nonce, difficulty) {
+            return Err(ApiError::InvalidProofOfWork);
+        }
+
+        let challenge_consumed = state
+            .k1_cache
+            .take_pow_challenge(&challenge)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to consume proof-of-work challenge: {}", e);
+                ApiError::ServerErr("Failed to validate challenge".to_string())
+            })?;
+        if !challenge_consumed {
+            return Err(ApiError::InvalidProofOfWork);
+        }
+    }
+
+    if let Some(action) = &query.action
+        && !is_valid_k1_action(action)
+    {
+        return Err(ApiError::InvalidArgument("Invalid k1 action".to_string()));
+    }
+
+    let k1 = state
+        .k1_cache
+        .issue_k1_for_action(query.action.as_deref())
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to create k1: {}", e);
+            ApiError::ServerErr("Failed to create k1".to_string())
+        })?;
 
     Ok(Json(GetK1 {
-        k1,
+        k1: k1.to_string(),
         tag: "login".to_string(),
     }))
 }
 
 pub async fn auth_login(
     State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
     event: Option<Extension<WideEventHandle>>,
     Json(payload): Json<AuthLoginPayload>,
 ) -> anyhow::Result<Json<AuthLoginResponse>, ApiError> {
-    let k1_consumed = state.k1_cache.take(&payload.k1).await.map_err(|e| {
+    // The pubkey is unproven until its signature checks out, so only the IP is scored for now
+    let ip_subjects: Vec<AbuseSubject> = ip.map(AbuseSubject::Ip).into_iter().collect();
+    ensure_not_blocked(&state, &ip_subjects).await?;
+
+    let k1 = K1::parse(&payload.k1)
+        .map_err(|e| ApiError::InvalidArgument(format!("Invalid k1 format: {}", e)))?;
+
+    let binding = state.k1_cache.take(&k1).await.map_err(|e| {
         tracing::error!(error = %e, "Auth login failed: Unable to consume k1");
         ApiError::ServerErr("Failed to validate k1".to_string())
     })?;
 
-    if !k1_consumed {
+    let Some(binding) = binding else {
         return Err(ApiError::InvalidArgument("Invalid k1".to_string()));
-    }
-
-    let k1_parts: Vec<&str> = payload.k1.split('_').collect();
-    if k1_parts.len() != 2 {
-        return Err(ApiError::InvalidArgument("Invalid k1 format".to_string()));
-    }
-
-    let timestamp = k1_parts[1]
-        .parse::<u64>()
-        .map_err(|_| ApiError::InvalidArgument("Invalid timestamp in k1".to_string()))?;
+    };
 
-    let now = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
+    let now = current_timestamp();
 
-    if now.saturating_sub(timestamp) > 600 {
-        return Err(ApiError::K1Expired);
+    if k1.is_expired(
+        now,
+        state.k1_cache.ttl_seconds(),
+        state.config.k1_clock_skew_tolerance_secs,
+    ) {
+        return Err(ApiError::K1Expired { server_time: now });
     }
 
     let is_valid = verify_auth(payload.k1.clone(), payload.sig.clone(), payload.key.clone())
         .await
-        .map_err(|_| ApiError::InvalidSignature)?;
+        .unwrap_or(false);
 
     if !is_valid {
+        record_abuse(&state, AbuseEvent::FailedSignature, &ip_subjects).await?;
         return Err(ApiError::InvalidSignature);
     }
 
-    let minted = mint_access_token(&state.config, &payload.key)
+    let pubkey_subjects = [AbuseSubject::Pubkey(&payload.key)];
+    ensure_not_blocked(&state, &pubkey_subjects).await?;
+    record_abuse(&state, AbuseEvent::K1Request, &pubkey_subjects).await?;
+
+    let minted = mint_access_token(&state.config, &payload.key, binding.action())
         .map_err(|_| ApiError::ServerErr("Failed to create access token".to_string()))?;
 
     if let Some(Extension(event)) = &event {
@@ -117,40 +242,6 @@ pub async fn auth_login(
     }))
 }
 
-/// Represents the first response in the LNURL-pay protocol.
-///
-/// This response provides the necessary details for a wallet to make a payment,
-/// such as the callback URL, sendable amounts, and metadata.
-#[derive(Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct LnurlpDefaultResponse {
-    /// The URL where the wallet should send the second request.
-    pub callback: String,
-    /// The maximum amount that can be sent in a single payment, in millisatoshis.
-    pub max_sendable: u64,
-    /// The minimum amount that can be sent in a single payment, in millisatoshis.
-    pub min_sendable: u64,
-    /// A JSON string containing metadata about the payment.
-    pub metadata: String,
-    /// The LNURL-pay tag, which is always "payRequest".
-    pub tag: String,
-    /// The maximum length of a comment that can be included with the payment.
-    pub comment_allowed: u16,
-}
-
-/// Represents the second response in the LNURL-pay protocol.
-///
-/// This response contains the BOLT11 invoice that the wallet will use to pay.
-#[derive(Serialize, Deserialize)]
-pub struct LnurlpInvoiceResponse {
-    /// The BOLT11 payment request (invoice).
-    pub pr: String,
-    /// A list of routes for the payment, typically empty.
-    pub routes: Vec<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub ark: Option<String>,
-}
-
 /// Defines the query parameters for an LNURL-pay request.
 #[derive(Deserialize)]
 pub struct LnurlpRequestQuery {
@@ -159,6 +250,11 @@ pub struct LnurlpRequestQuery {
     wallet: Option<String>,
 }
 
+/// Returns the LNURL-pay endpoint a lightning address resolves to.
+pub(crate) fn lnurlp_url(lnurl_domain: &str, username: &str) -> String {
+    format!("https://{}/.well-known/lnurlp/{}", lnurl_domain, username)
+}
+
 /// Handles LNURL-pay requests.
 ///
 /// This endpoint manages the two-step LNURL-pay flow. The first request (without an amount)
@@ -166,6 +262,7 @@ pub struct LnurlpRequestQuery {
 /// notification to the user to generate an invoice, which is then returned to the payer.
 pub async fn lnurlp_request(
     State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
     Path(username): Path<String>,
     Query(query): Query<LnurlpRequestQuery>,
     event: Option<Extension<WideEventHandle>>,
@@ -181,24 +278,30 @@ pub async fn lnurlp_request(
     }
 
     let user_repo = UserRepository::new(&state.db_pool);
-    let user = user_repo
-        .find_by_lightning_address(&lightning_address)
-        .await?
-        .ok_or_else(|| ApiError::InvalidArgument("User not found".to_string()))?;
+    let LnurlpRecipient {
+        user,
+        retired_address,
+    } = find_lnurlp_recipient(&state, &lightning_address).await?;
     let pubkey = user.pubkey.clone();
 
-    if query.amount.is_none() {
-        let metadata = serde_json::json!([
-            ["text/identifier", lightning_address],
-            [
-                "text/plain",
-                format!("Paying satoshis to {}", lightning_address)
-            ]
-        ])
-        .to_string();
+    if retired_address && let Some(Extension(event)) = &event {
+        event.add_context("retired_address", true);
+    }
+
+    // Wallets tend to prefill the max sendable, so surface the recipient's preferred amount
+    let suggested_msat = user_repo
+        .get_default_sendable_msat(&pubkey)
+        .await?
+        .and_then(|msat| u64::try_from(msat).ok());
+    let metadata = lnurlp_metadata(
+        &lightning_address,
+        suggested_msat,
+        state.config.lnurlp_amount_description,
+    );
 
+    if query.amount.is_none() {
         let response = LnurlpDefaultResponse {
-            callback: format!("https://{}/.well-known/lnurlp/{}", lnurl_domain, username),
+            callback: lnurlp_url(lnurl_domain, &username),
             min_sendable: LNURLP_MIN_SENDABLE,
             max_sendable: LNURLP_MAX_SENDABLE,
             metadata,
@@ -212,34 +315,31 @@ pub async fn lnurlp_request(
 
     let amount = query.amount.unwrap();
 
-    if amount < LNURLP_MIN_SENDABLE {
-        return Err(ApiError::InvalidArgument(format!(
-            "Minimum invoice request is {} mSats",
-            LNURLP_MIN_SENDABLE
-        )));
-    }
-
-    if amount > LNURLP_MAX_SENDABLE {
-        return Err(ApiError::InvalidArgument(format!(
-            "Maximum invoice request is {} mSats",
-            LNURLP_MAX_SENDABLE
-        )));
-    }
+    validate_lnurlp_amount(amount, state.config.lnurlp_require_whole_sats)?;
 
     if let Some(wallet) = &query.wallet
         && wallet == "noahwallet"
+        && state.config.ark_enabled
         && let Some(ark_address) = &user.ark_address
     {
         let response = LnurlpInvoiceResponse {
             pr: "".to_string(),
             routes: vec![],
             ark: Some(ark_address.clone()),
+            success_action: retired_address
+                .then(|| retired_address_notice(user.lightning_address.as_deref())),
         };
         return Ok(Json(
             serde_json::to_value(response).map_err(|e| ApiError::SerializeErr(e.to_string()))?,
         ));
     }
 
+    let subjects: Vec<AbuseSubject> = ip.map(AbuseSubject::Ip).into_iter().collect();
+    ensure_not_blocked(&state, &subjects).await?;
+    record_abuse(&state, AbuseEvent::InvoiceRequest, &subjects).await?;
+
+    check_lnurlp_daily_cap(&state, &pubkey).await?;
+
     // Generate a unique transaction ID for this payment request
     let transaction_id = Uuid::new_v4().to_string();
 
@@ -248,36 +348,370 @@ pub async fn lnurlp_request(
         event.add_context("has_ark_address", user.ark_address.is_some());
     }
 
-    let state_clone = state.clone();
-    let transaction_id_clone = transaction_id.clone();
+    let timeout = state.config.lnurlp_invoice_timeout();
+
+    // The payer's wallet checks the invoice against the metadata it was shown
+    let description_hash = lnurlp_metadata_hash(&metadata);
+    if state.config.lnurlp_verify_description_hash {
+        state
+            .invoice_store
+            .expect_description_hash(
+                &transaction_id,
+                &description_hash.to_string(),
+                timeout.as_secs(),
+            )
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to record expected description hash: {}", e);
+                ApiError::ServerErr("Failed to process payment request".to_string())
+            })?;
+    }
+
+    request_invoice_from_device(
+        &state,
+        pubkey.clone(),
+        transaction_id.clone(),
+        amount,
+        Some(description_hash),
+    );
+
+    tracing::debug!(
+        "Polling for invoice with a {}s timeout...",
+        timeout.as_secs()
+    );
+
+    let Some(invoice) = wait_for_invoice(&state, &transaction_id, timeout).await? else {
+        tracing::error!(
+            "Invoice request timed out after {}s for transaction_id: {}",
+            timeout.as_secs(),
+            transaction_id
+        );
+        return Err(ApiError::ServerErr("Request timed out".to_string()));
+    };
+
+    let response = LnurlpInvoiceResponse {
+        pr: invoice,
+        routes: vec![],
+        ark: user.ark_address,
+        success_action: if retired_address {
+            Some(retired_address_notice(user.lightning_address.as_deref()))
+        } else {
+            user_repo.get_lnurlp_success_action(&pubkey).await?
+        },
+    };
+    Ok(Json(
+        serde_json::to_value(response).map_err(|e| ApiError::SerializeErr(e.to_string()))?,
+    ))
+}
+
+/// The user an LNURL-pay request resolved to.
+struct LnurlpRecipient {
+    user: User,
+    /// Whether the request used an address the user has since replaced.
+    retired_address: bool,
+}
+
+/// Finds the owner of `lightning_address`, following retired addresses within the grace period.
+///
+/// Addresses still stored under one of `ACCEPTED_LNURL_DOMAINS` resolve too.
+async fn find_lnurlp_recipient(
+    state: &AppState,
+    lightning_address: &str,
+) -> Result<LnurlpRecipient, ApiError> {
+    let user_repo = UserRepository::new(&state.db_pool);
+    let aliases = state.config.lightning_address_aliases(lightning_address);
+    for address in &aliases {
+        if let Some(user) = user_repo.find_by_lightning_address(address).await? {
+            return Ok(LnurlpRecipient {
+                user,
+                retired_address: false,
+            });
+        }
+    }
+
+    let not_found = || ApiError::InvalidArgument("User not found".to_string());
+    if !state.config.ln_address_history_enabled {
+        return Err(not_found());
+    }
+
+    let history_repo = LightningAddressHistoryRepository::new(&state.db_pool);
+    let mut retired = None;
+    for address in &aliases {
+        retired = history_repo.find(address).await?;
+        if retired.is_some() {
+            break;
+        }
+    }
+    let Some(retired) = retired else {
+        return Err(not_found());
+    };
+
+    let grace = chrono::Duration::days(state.config.ln_address_retired_grace_days.into());
+    if Utc::now() - retired.retired_at > grace {
+        return Err(ApiError::InvalidArgument(
+            "Lightning address has been retired".to_string(),
+        ));
+    }
+
+    let user = user_repo
+        .find_by_pubkey(&retired.pubkey)
+        .await?
+        .ok_or_else(not_found)?;
+    Ok(LnurlpRecipient {
+        user,
+        retired_address: true,
+    })
+}
+
+/// Tells the payer that the address they used was replaced by `current_address`.
+fn retired_address_notice(current_address: Option<&str>) -> LnurlpSuccessAction {
+    let message = match current_address {
+        Some(address) => format!(
+            "This lightning address has changed to {}. Please update your contacts.",
+            address
+        ),
+        None => "This lightning address is no longer in use.".to_string(),
+    };
+    LnurlpSuccessAction::Message { message }
+}
+
+/// Builds the LUD-06 metadata string for a lightning address.
+///
+/// Wallets hash this exact string to check the invoice's description hash, so it may only depend
+/// on what is known before the payer picks an amount. With `amount_description`, the recipient's
+/// suggested amount is shown as e.g. "Pay 1,234 sats to alice@noah".
+pub(crate) fn lnurlp_metadata(
+    lightning_address: &str,
+    suggested_msat: Option<u64>,
+    amount_description: bool,
+) -> String {
+    let description = match suggested_msat {
+        Some(msat) if amount_description => format!(
+            "Pay {} sats to {}",
+            format_sats(msat / MSATS_PER_SAT),
+            lightning_address
+        ),
+        Some(msat) => format!(
+            "Paying satoshis to {}. Suggested: {} sats",
+            lightning_address,
+            msat / MSATS_PER_SAT
+        ),
+        None => format!("Paying satoshis to {}", lightning_address),
+    };
+
+    serde_json::json!([
+        ["text/identifier", lightning_address],
+        ["text/plain", description]
+    ])
+    .to_string()
+}
+
+/// SHA256 of the metadata string, which LUD-06 requires as the invoice's description hash.
+pub(crate) fn lnurlp_metadata_hash(metadata: &str) -> sha256::Hash {
+    sha256::Hash::hash(metadata.as_bytes())
+}
+
+/// Formats a sat amount with thousands separators.
+fn format_sats(sats: u64) -> String {
+    let digits = sats.to_string();
+    let mut formatted = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(c);
+    }
+    formatted
+}
+
+/// Checks an LNURL-pay `amount`, which LUD-06 defines in millisatoshis.
+///
+/// With `require_whole_sats`, amounts that aren't a multiple of 1000 mSats are rejected since
+/// the device creates invoices in whole sats.
+pub(crate) fn validate_lnurlp_amount(
+    amount_msat: u64,
+    require_whole_sats: bool,
+) -> Result<(), ApiError> {
+    if amount_msat == 0 {
+        return Err(ApiError::InvalidArgument(
+            "Amount must be greater than zero".to_string(),
+        ));
+    }
+
+    if amount_msat < LNURLP_MIN_SENDABLE {
+        // A sat amount sent by mistake usually lands in range once converted to mSats
+        let hint = if amount_msat.saturating_mul(MSATS_PER_SAT) >= LNURLP_MIN_SENDABLE {
+            " (amounts are in mSats, not sats)"
+        } else {
+            ""
+        };
+        return Err(ApiError::InvalidArgument(format!(
+            "Minimum invoice request is {} mSats{}",
+            LNURLP_MIN_SENDABLE, hint
+        )));
+    }
+
+    if amount_msat > LNURLP_MAX_SENDABLE {
+        return Err(ApiError::InvalidArgument(format!(
+            "Maximum invoice request is {} mSats",
+            LNURLP_MAX_SENDABLE
+        )));
+    }
+
+    if require_whole_sats && !amount_msat.is_multiple_of(MSATS_PER_SAT) {
+        return Err(ApiError::InvalidArgument(format!(
+            "Amount must be a whole number of sats (a multiple of {} mSats)",
+            MSATS_PER_SAT
+        )));
+    }
+
+    Ok(())
+}
+
+/// Returns the daily cap on invoice requests for `pubkey`, 0 meaning uncapped.
+///
+/// A recipient who reported abuse gets the lower reported cap until the tightening expires.
+pub(crate) async fn effective_lnurlp_daily_cap(
+    state: &AppState,
+    pubkey: &str,
+) -> anyhow::Result<u64> {
+    let cap = state.config.lnurlp_daily_request_cap;
+    let reported_cap = state.config.lnurlp_reported_daily_request_cap;
+    if reported_cap == 0 || !state.invoice_store.is_daily_cap_tightened(pubkey).await? {
+        return Ok(cap);
+    }
+
+    Ok(if cap == 0 {
+        reported_cap
+    } else {
+        cap.min(reported_cap)
+    })
+}
+
+/// Enforces the per-recipient daily cap on invoice requests before their device is woken up.
+async fn check_lnurlp_daily_cap(state: &AppState, pubkey: &str) -> Result<(), ApiError> {
+    let cap = effective_lnurlp_daily_cap(state, pubkey)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to look up invoice request cap: {}", e);
+            ApiError::ServerErr("Failed to process payment request".to_string())
+        })?;
+    if cap == 0 {
+        return Ok(());
+    }
+
+    let now = Utc::now();
+    let requests = state
+        .invoice_store
+        .record_daily_request(pubkey, now.date_naive())
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to record invoice request: {}", e);
+            ApiError::ServerErr("Failed to process payment request".to_string())
+        })?;
+
+    if requests > cap {
+        tracing::warn!(
+            requests,
+            cap,
+            "Daily invoice request cap reached for recipient {}",
+            pubkey
+        );
+        let next_day = (now.date_naive() + chrono::Days::new(1))
+            .and_hms_opt(0, 0, 0)
+            .unwrap_or_default()
+            .and_utc();
+        return Err(ApiError::TooManyRequests(
+            (next_day - now).num_seconds().max(1) as u64,
+        ));
+    }
+
+    Ok(())
+}
+
+/// Asks the recipient's device to create an invoice for `transaction_id` via a push notification.
+///
+/// The returned task resolves to true once the push was delivered to at least one device, which
+/// is also recorded for a pre-registered request so pollers can see it.
+fn request_invoice_from_device(
+    state: &AppState,
+    pubkey: String,
+    transaction_id: String,
+    amount: u64,
+    description_hash: Option<sha256::Hash>,
+) -> JoinHandle<bool> {
+    let state = state.clone();
     tokio::spawn(async move {
+        match PushTokenRepository::new(&state.db_pool)
+            .find_by_pubkey(&pubkey)
+            .await
+        {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                tracing::debug!("Invoice recipient has no push token registered");
+                return false;
+            }
+            Err(e) => {
+                tracing::error!("Failed to look up push token: {}", e);
+                return false;
+            }
+        }
+
         let data = PushNotificationData {
             title: None,
             body: None,
             data: serde_json::to_string(&NotificationData::LightningInvoiceRequest(
                 LightningInvoiceRequestNotification {
-                    transaction_id: transaction_id_clone,
+                    transaction_id: transaction_id.clone(),
                     amount,
+                    description_hash: description_hash.map(|hash| hash.to_string()),
                 },
             ))
             .unwrap(),
             priority: Priority::High,
             content_available: true,
         };
-        if let Err(e) = send_push_notification(state_clone, data, Some(pubkey)).await {
-            tracing::error!("Failed to send push notification: {}", e);
+        match send_push_notification(state.clone(), data, Some(pubkey)).await {
+            Ok(summary) if summary.delivered > 0 => {}
+            Ok(summary) => {
+                tracing::warn!(
+                    failed = summary.failed,
+                    "Invoice request push was not delivered to any device"
+                );
+                return false;
+            }
+            Err(e) => {
+                tracing::error!("Failed to send push notification: {}", e);
+                return false;
+            }
         }
-    });
 
-    tracing::debug!("Polling for invoice with a 30s timeout...");
+        if let Err(e) = state.invoice_store.mark_push_sent(&transaction_id).await {
+            tracing::warn!(
+                "Failed to record push for transaction_id {}: {}",
+                transaction_id,
+                e
+            );
+        }
+        true
+    })
+}
 
+/// Polls Redis until the device submits an invoice for `transaction_id`.
+///
+/// Returns `None` if no invoice arrived within `timeout`.
+async fn wait_for_invoice(
+    state: &AppState,
+    transaction_id: &str,
+    timeout: Duration,
+) -> Result<Option<String>, ApiError> {
     let start = std::time::Instant::now();
 
-    let invoice = loop {
-        match state.invoice_store.get(&transaction_id).await {
+    loop {
+        match state.invoice_store.get(transaction_id).await {
             Ok(Some(inv)) => {
                 // Clean up after successful retrieval
-                if let Err(e) = state.invoice_store.remove(&transaction_id).await {
+                if let Err(e) = state.invoice_store.remove(transaction_id).await {
                     tracing::warn!(
                         "Failed to remove invoice for transaction_id {}: {}",
                         transaction_id,
@@ -285,15 +719,11 @@ pub async fn lnurlp_request(
                     );
                 }
 
-                break inv;
+                return Ok(Some(inv));
             }
             Ok(None) => {
-                if start.elapsed() >= TIMEOUT {
-                    tracing::error!(
-                        "Invoice request timed out after 30s for transaction_id: {}",
-                        transaction_id
-                    );
-                    return Err(ApiError::ServerErr("Request timed out".to_string()));
+                if start.elapsed() >= timeout {
+                    return Ok(None);
                 }
                 sleep(POLL_INTERVAL).await;
             }
@@ -304,16 +734,209 @@ pub async fn lnurlp_request(
                 ));
             }
         }
+    }
+}
+
+/// Defines the query parameters for subscribing to an invoice over WebSocket.
+#[derive(Deserialize)]
+pub struct LnurlpInvoiceWsQuery {
+    /// The amount of the payment in millisatoshis.
+    amount: u64,
+}
+
+/// Streams the status of an invoice request over a WebSocket.
+///
+/// First-party clients can use this instead of the LNURL-pay callback to avoid holding an
+/// HTTP request open while the recipient's device creates the invoice. The socket receives
+/// an [`InvoiceStatusFrame`] for every state change and is closed after the final one.
+pub async fn lnurlp_invoice_ws(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    Path(username): Path<String>,
+    Query(query): Query<LnurlpInvoiceWsQuery>,
+    ws: WebSocketUpgrade,
+) -> anyhow::Result<Response, ApiError> {
+    let pubkey = check_invoice_request(&state, ip, &username, query.amount).await?;
+
+    Ok(ws.on_upgrade(move |socket| forward_invoice_status(socket, state, pubkey, query.amount)))
+}
+
+/// Runs the checks shared by the invoice request variants and returns the recipient's pubkey.
+async fn check_invoice_request(
+    state: &AppState,
+    ip: Option<IpAddr>,
+    username: &str,
+    amount: u64,
+) -> Result<String, ApiError> {
+    validate_lnurlp_amount(amount, state.config.lnurlp_require_whole_sats)?;
+
+    let lightning_address = format!("{}@{}", username, state.lnurl_domain);
+    let LnurlpRecipient { user, .. } = find_lnurlp_recipient(state, &lightning_address).await?;
+
+    let subjects: Vec<AbuseSubject> = ip.map(AbuseSubject::Ip).into_iter().collect();
+    ensure_not_blocked(state, &subjects).await?;
+    record_abuse(state, AbuseEvent::InvoiceRequest, &subjects).await?;
+
+    check_lnurlp_daily_cap(state, &user.pubkey).await?;
+
+    Ok(user.pubkey)
+}
+
+/// Starts an invoice request and returns its transaction ID without waiting for the invoice.
+///
+/// For callers that can't hold a connection open while the recipient's device creates the
+/// invoice. They poll `lnurlp_poll` with the returned ID instead.
+pub async fn lnurlp_pre_register(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    Path(username): Path<String>,
+    Json(payload): Json<LnurlpPreRegisterPayload>,
+) -> anyhow::Result<Json<LnurlpPreRegisterResponse>, ApiError> {
+    let pubkey = check_invoice_request(&state, ip, &username, payload.amount).await?;
+
+    let transaction_id = Uuid::new_v4().to_string();
+    state
+        .invoice_store
+        .mark_pending(&transaction_id, state.config.lnurlp_poll_ttl_secs)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to register pending invoice request: {}", e);
+            ApiError::ServerErr("Failed to process payment request".to_string())
+        })?;
+
+    request_invoice_from_device(&state, pubkey, transaction_id.clone(), payload.amount, None);
+
+    Ok(Json(LnurlpPreRegisterResponse { transaction_id }))
+}
+
+/// Returns the invoice for a pre-registered transaction, or a pending status until it arrives.
+///
+/// The invoice is handed out once. Unknown and expired transactions return 404.
+pub async fn lnurlp_poll(
+    State(state): State<AppState>,
+    Path(transaction_id): Path<String>,
+) -> anyhow::Result<Json<InvoiceStatusFrame>, ApiError> {
+    let lookup_failed = |e: anyhow::Error| {
+        tracing::error!("Failed to poll invoice from Redis: {}", e);
+        ApiError::ServerErr("Failed to retrieve invoice".to_string())
+    };
+
+    if let Some(pr) = state
+        .invoice_store
+        .get(&transaction_id)
+        .await
+        .map_err(lookup_failed)?
+    {
+        if let Err(e) = state.invoice_store.remove(&transaction_id).await {
+            tracing::warn!(
+                "Failed to remove invoice for transaction_id {}: {}",
+                transaction_id,
+                e
+            );
+        }
+        return Ok(Json(InvoiceStatusFrame::Invoiced { pr }));
+    }
+
+    match state
+        .invoice_store
+        .pending(&transaction_id)
+        .await
+        .map_err(lookup_failed)?
+    {
+        Some(PendingInvoice::Requested) => Ok(Json(InvoiceStatusFrame::Pending { transaction_id })),
+        Some(PendingInvoice::PushSent) => Ok(Json(InvoiceStatusFrame::PushSent { transaction_id })),
+        None => Err(ApiError::NotFound(
+            "Unknown or expired transaction".to_string(),
+        )),
+    }
+}
+
+async fn forward_invoice_status(
+    mut socket: WebSocket,
+    state: AppState,
+    pubkey: String,
+    amount: u64,
+) {
+    let (frames_tx, mut frames_rx) = mpsc::channel(4);
+    let timeout = state.config.lnurlp_invoice_timeout();
+    let session = tokio::spawn(run_invoice_session(
+        state, pubkey, amount, timeout, frames_tx,
+    ));
+
+    while let Some(frame) = frames_rx.recv().await {
+        let text = match serde_json::to_string(&frame) {
+            Ok(text) => text,
+            Err(e) => {
+                tracing::error!("Failed to serialize invoice status frame: {}", e);
+                break;
+            }
+        };
+        if socket.send(Message::Text(text.into())).await.is_err() {
+            tracing::debug!("Payer disconnected before the invoice request finished");
+            break;
+        }
+    }
+
+    // Stop polling if the payer went away early
+    session.abort();
+    let _ = socket.send(Message::Close(None)).await;
+}
+
+/// Requests an invoice from the recipient's device and reports each status change on `frames`.
+pub(crate) async fn run_invoice_session(
+    state: AppState,
+    pubkey: String,
+    amount: u64,
+    timeout: Duration,
+    frames: mpsc::Sender<InvoiceStatusFrame>,
+) {
+    let transaction_id = Uuid::new_v4().to_string();
+    if frames
+        .send(InvoiceStatusFrame::Pending {
+            transaction_id: transaction_id.clone(),
+        })
+        .await
+        .is_err()
+    {
+        return;
+    }
+
+    let mut push =
+        request_invoice_from_device(&state, pubkey, transaction_id.clone(), amount, None);
+    let wait = wait_for_invoice(&state, &transaction_id, timeout);
+    tokio::pin!(wait);
+
+    let result = tokio::select! {
+        result = &mut wait => result,
+        sent = &mut push => {
+            if sent.unwrap_or(false)
+                && frames
+                    .send(InvoiceStatusFrame::PushSent {
+                        transaction_id: transaction_id.clone(),
+                    })
+                    .await
+                    .is_err()
+            {
+                return;
+            }
+            wait.await
+        }
     };
 
-    let response = LnurlpInvoiceResponse {
-        pr: invoice,
-        routes: vec![],
-        ark: user.ark_address,
+    let frame = match result {
+        Ok(Some(pr)) => InvoiceStatusFrame::Invoiced { pr },
+        Ok(None) => {
+            tracing::warn!(
+                "Invoice request timed out for transaction_id: {}",
+                transaction_id
+            );
+            InvoiceStatusFrame::Timeout
+        }
+        Err(e) => InvoiceStatusFrame::Error {
+            reason: e.to_string(),
+        },
     };
-    Ok(Json(
-        serde_json::to_value(response).map_err(|e| ApiError::SerializeErr(e.to_string()))?,
-    ))
+    let _ = frames.send(frame).await;
 }
 
 /// Handles user registration via LNURL-auth.
@@ -327,57 +950,34 @@ pub async fn register(
     event: Option<Extension<WideEventHandle>>,
     Json(payload): Json<RegisterPayload>,
 ) -> anyhow::Result<Json<RegisterResponse>, ApiError> {
-    if payload.ln_address.is_some()
-        && payload.validate().is_err()
-        && let Err(e) = payload.validate()
-    {
-        return Err(ApiError::InvalidArgument(e.to_string()));
+    if payload.ln_address.is_some() {
+        payload.validate()?;
+    }
+
+    if payload.ark_address.is_some() && !state.config.ark_enabled {
+        return Err(ApiError::InvalidArgument(
+            "Ark addresses are not supported by this server".to_string(),
+        ));
     }
 
     let user_repo = UserRepository::new(&state.db_pool);
 
     if let Some(user) = user_repo.find_by_pubkey(&auth_payload.key).await? {
-        if let Some(Extension(event)) = &event {
-            event.add_context("is_new_user", false);
-            event.set_ln_address(user.lightning_address.as_deref().unwrap_or(""));
-        }
-
-        if let Some(ark_address) = &payload.ark_address
-            && let Err(e) = user_repo
-                .update_ark_address(&auth_payload.key, ark_address)
-                .await
-        {
-            if e.is::<crate::db::user_repo::DuplicateArkAddressError>() {
-                // If address is taken, we can either return error or just ignore and keep old one.
-                // Returning error is safer to let client know.
-                return Err(ApiError::InvalidArgument(
-                    "Ark address already taken".to_string(),
-                ));
-            }
-            return Err(e.into());
-        }
-
-        if let Some(device_info) = payload.device_info {
-            // For existing users, we'll just register the device in its own transaction
-            let mut tx = state.db_pool.begin().await?;
-            DeviceRepository::upsert(&mut tx, &auth_payload.key, &device_info).await?;
-            tx.commit().await?;
-        }
-
-        return Ok(Json(RegisterResponse {
-            status: "OK".to_string(),
-            event: None,
-            reason: Some("User already registered".to_string()),
-            lightning_address: user.lightning_address,
-            is_email_verified: user.is_email_verified,
-        }));
+        return register_existing_user(
+            &state,
+            &auth_payload.key,
+            user,
+            payload.ark_address,
+            payload.device_info,
+            event.as_ref(),
+        )
+        .await;
     }
 
-    let ln_address = payload.ln_address.unwrap_or_else(|| {
-        let number = rand::rng().random_range(0..100);
-        let random_word = random_word::get(random_word::Lang::En);
-        format!("{}{}@{}", random_word, number, state.lnurl_domain)
-    });
+    let ln_address = payload
+        .ln_address
+        .map(|address| state.config.canonical_lightning_address(&address))
+        .unwrap_or_else(|| format!("{}@{}", random_ln_username(), state.lnurl_domain));
 
     if let Some(Extension(event)) = &event {
         event.add_context("is_new_user", true);
@@ -386,15 +986,24 @@ pub async fn register(
         event.add_context("has_device_info", payload.device_info.is_some());
     }
 
-    if !crate::types::is_valid_lightning_address(&ln_address) {
+    ensure_valid_lightning_address(&state.config, &ln_address)?;
+
+    if crate::types::is_reserved_lightning_address(&ln_address) {
         return Err(ApiError::InvalidArgument(
-            "Invalid lightning address".to_string(),
+            "Lightning address is reserved".to_string(),
+        ));
+    }
+
+    if ln_address_taken_under_alias(&state, &ln_address, &auth_payload.key).await? {
+        return Err(ApiError::InvalidArgument(
+            "Lightning address already taken".to_string(),
         ));
     }
 
     // Create a new user in a transaction
     let mut tx = state.db_pool.begin().await?;
-    let result = UserRepository::create(
+    let result = create_user_tx(
+        &state,
         &mut tx,
         &auth_payload.key,
         &ln_address,
@@ -403,6 +1012,20 @@ pub async fn register(
     .await;
 
     if let Err(e) = result {
+        // A concurrent registration for the same pubkey, e.g. a double tap, got there first
+        tx.rollback().await?;
+        if let Some(user) = user_repo.find_by_pubkey(&auth_payload.key).await? {
+            return register_existing_user(
+                &state,
+                &auth_payload.key,
+                user,
+                payload.ark_address,
+                payload.device_info,
+                event.as_ref(),
+            )
+            .await;
+        }
+
         if e.is::<crate::db::user_repo::LightningAddressTakenError>() {
             return Err(ApiError::InvalidArgument(
                 "Lightning address already taken".to_string(),
@@ -427,10 +1050,275 @@ pub async fn register(
         event: Some(AuthEvent::Registered),
         reason: None,
         lightning_address: Some(ln_address),
+        ark_address: payload.ark_address,
+        backup_enabled: false,
         is_email_verified: false,
     }))
 }
 
+/// Rejects addresses whose username would break LNURL URLs, explaining what is accepted.
+pub(crate) fn ensure_valid_lightning_address(
+    config: &Config,
+    address: &str,
+) -> Result<(), ApiError> {
+    if !crate::types::is_valid_lightning_address(address) {
+        return Err(ApiError::InvalidArgument(
+            INVALID_LN_USERNAME_MESSAGE.to_string(),
+        ));
+    }
+
+    let username = address
+        .split_once('@')
+        .map_or(address, |(username, _)| username);
+    if username.len() > config.ln_username_max_length {
+        return Err(ApiError::InvalidArgument(format!(
+            "Lightning address usernames can be at most {} characters, e.g. satoshi.n",
+            config.ln_username_max_length
+        )));
+    }
+    Ok(())
+}
+
+/// Whether another user holds the same username under one of `ACCEPTED_LNURL_DOMAINS`.
+///
+/// LNURL-pay resolves the primary domain first, so taking the username there would divert
+/// payments meant for the user still on the old domain.
+pub(crate) async fn ln_address_taken_under_alias(
+    state: &AppState,
+    ln_address: &str,
+    pubkey: &str,
+) -> Result<bool, ApiError> {
+    let user_repo = UserRepository::new(&state.db_pool);
+    for alias in state
+        .config
+        .lightning_address_aliases(ln_address)
+        .iter()
+        .skip(1)
+    {
+        if let Some(owner) = user_repo.find_pubkey_by_lightning_address(alias).await?
+            && owner != pubkey
+        {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Inserts a new user, first checking that nobody else holds the ark address when
+/// `ARK_ADDRESS_UNIQUE` is set.
+async fn create_user_tx(
+    state: &AppState,
+    tx: &mut Transaction<'_, Postgres>,
+    pubkey: &str,
+    ln_address: &str,
+    ark_address: Option<&str>,
+) -> anyhow::Result<()> {
+    if let Some(ark_address) = ark_address
+        && state.config.ark_address_unique
+    {
+        UserRepository::ensure_ark_address_available_tx(tx, pubkey, ark_address).await?;
+    }
+    UserRepository::create(tx, pubkey, ln_address, ark_address).await
+}
+
+/// Answers a registration for a pubkey that already has an account, updating its ark address
+/// and device info instead of creating the user.
+async fn register_existing_user(
+    state: &AppState,
+    pubkey: &str,
+    user: User,
+    ark_address: Option<String>,
+    device_info: Option<DeviceInfo>,
+    event: Option<&Extension<WideEventHandle>>,
+) -> anyhow::Result<Json<RegisterResponse>, ApiError> {
+    let user_repo = UserRepository::new(&state.db_pool);
+
+    if let Some(Extension(event)) = event {
+        event.add_context("is_new_user", false);
+        event.set_ln_address(user.lightning_address.as_deref().unwrap_or(""));
+    }
+
+    if let Some(ark_address) = &ark_address
+        && let Err(e) = user_repo
+            .update_ark_address(pubkey, ark_address, state.config.ark_address_unique)
+            .await
+    {
+        if e.is::<crate::db::user_repo::DuplicateArkAddressError>() {
+            // If address is taken, we can either return error or just ignore and keep old one.
+            // Returning error is safer to let client know.
+            return Err(ApiError::InvalidArgument(
+                "Ark address already taken".to_string(),
+            ));
+        }
+        return Err(e.into());
+    }
+
+    if let Some(device_info) = device_info {
+        // For existing users, we'll just register the device in its own transaction
+        let mut tx = state.db_pool.begin().await?;
+        DeviceRepository::upsert(&mut tx, pubkey, &device_info).await?;
+        tx.commit().await?;
+    }
+
+    let backup_enabled = BackupRepository::new(&state.db_pool)
+        .get_settings(pubkey)
+        .await?
+        .unwrap_or(false);
+
+    Ok(Json(RegisterResponse {
+        status: "OK".to_string(),
+        event: None,
+        reason: Some("User already registered".to_string()),
+        lightning_address: user.lightning_address,
+        ark_address: ark_address.or(user.ark_address),
+        backup_enabled,
+        is_email_verified: user.is_email_verified,
+    }))
+}
+
+fn random_ln_username() -> String {
+    let number = rand::rng().random_range(0..100);
+    let random_word = random_word::get(random_word::Lang::En);
+    format!("{}{}", random_word, number)
+}
+
+async fn is_ln_username_available(
+    user_repo: &UserRepository<'_>,
+    config: &Config,
+    username: &str,
+) -> Result<bool, ApiError> {
+    if crate::types::is_reserved_ln_username(username) {
+        return Ok(false);
+    }
+    let ln_address = format!("{}@{}", username, config.lnurl_domain);
+    for address in config.lightning_address_aliases(&ln_address) {
+        if user_repo
+            .find_pubkey_by_lightning_address(&address)
+            .await?
+            .is_some()
+        {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Checks whether a lightning address username is still free to register.
+///
+/// When the username is taken or reserved, a few available alternatives are suggested,
+/// mixing variants of the requested username with freshly generated ones.
+pub async fn ln_address_available(
+    State(state): State<AppState>,
+    Query(query): Query<LightningAddressAvailabilityQuery>,
+) -> anyhow::Result<Json<LightningAddressAvailabilityResponse>, ApiError> {
+    let username = query.username.trim();
+    ensure_valid_lightning_address(
+        &state.config,
+        &format!("{}@{}", username, state.lnurl_domain),
+    )?;
+
+    let user_repo = UserRepository::new(&state.db_pool);
+    if is_ln_username_available(&user_repo, &state.config, username).await? {
+        return Ok(Json(LightningAddressAvailabilityResponse {
+            available: true,
+            suggestions: vec![],
+        }));
+    }
+
+    let mut suggestions = Vec::new();
+    for attempt in 0..LN_AVAILABILITY_MAX_ATTEMPTS {
+        if suggestions.len() >= LN_AVAILABILITY_SUGGESTIONS {
+            break;
+        }
+        let candidate = if attempt % 2 == 0 {
+            format!("{}{}", username, rand::rng().random_range(0..1000))
+        } else {
+            random_ln_username()
+        };
+        let ln_address = format!("{}@{}", candidate, state.lnurl_domain);
+        if !suggestions.contains(&ln_address)
+            && is_ln_username_available(&user_repo, &state.config, &candidate).await?
+        {
+            suggestions.push(ln_address);
+        }
+    }
+
+    Ok(Json(LightningAddressAvailabilityResponse {
+        available: false,
+        suggestions,
+    }))
+}
+
+/// Returns static information about the server, including the key that signs responses
+/// and the global feature flags.
+pub async fn server_info(
+    State(state): State<AppState>,
+) -> anyhow::Result<Json<ServerInfoResponse>, ApiError> {
+    let signing_pubkey = state
+        .config
+        .response_signing_key()
+        .map_err(|e| {
+            tracing::error!("Invalid response signing key: {}", e);
+            ApiError::ServerErr("Invalid server configuration".to_string())
+        })?
+        .map(|secret_key| {
+            bitcoin::secp256k1::PublicKey::from_secret_key(
+                &bitcoin::secp256k1::Secp256k1::signing_only(),
+                &secret_key,
+            )
+            .to_string()
+        });
+
+    let feature_flags = state.config.feature_flags().map_err(|e| {
+        tracing::error!("Invalid feature flags: {}", e);
+        ApiError::ServerErr("Invalid server configuration".to_string())
+    })?;
+
+    Ok(Json(ServerInfoResponse {
+        network: state.config.server_network.clone(),
+        signing_pubkey,
+        feature_flags: feature_flags.as_map().clone(),
+    }))
+}
+
+/// Reports the server clock and k1 lifetime, so clients can warn about a skewed device clock.
+pub async fn server_time(State(state): State<AppState>) -> Json<ServerTimeResponse> {
+    Json(ServerTimeResponse {
+        server_time: current_timestamp(),
+        k1_ttl_secs: state.k1_cache.ttl_seconds(),
+        k1_clock_skew_tolerance_secs: state.config.k1_clock_skew_tolerance_secs,
+    })
+}
+
+/// Build identifier reported by `/health`, set through the `GIT_COMMIT` env var at compile time.
+const BUILD_COMMIT: &str = match option_env!("GIT_COMMIT") {
+    Some(commit) => commit,
+    None => "unknown",
+};
+
+/// State behind the `/health` probe, kept apart from `AppState` so the probe never touches
+/// Postgres or Redis.
+#[derive(Clone)]
+pub struct HealthState {
+    pub started_at: Instant,
+    pub network: String,
+    /// Cleared when the S3 self-test fails.
+    pub s3_healthy: Arc<AtomicBool>,
+}
+
+/// Liveness probe. Always 200 while the process serves requests, S3 trouble only shows up in
+/// the body so a broken bucket doesn't get the instance restarted.
+pub async fn health_check(State(health): State<HealthState>) -> Json<HealthResponse> {
+    Json(HealthResponse {
+        status: "ok".to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        commit: BUILD_COMMIT.to_string(),
+        uptime_secs: health.started_at.elapsed().as_secs(),
+        network: health.network,
+        s3_healthy: health.s3_healthy.load(Ordering::Relaxed),
+    })
+}
+
 pub async fn check_app_version(
     State(state): State<AppState>,
     Json(payload): Json<AppVersionCheckPayload>,
@@ -473,9 +1361,7 @@ pub async fn send_verification_email(
         let domain = payload.email.split('@').nth(1).unwrap_or("unknown");
         event.add_context("email_domain", domain);
     }
-    if let Err(e) = payload.validate() {
-        return Err(ApiError::InvalidArgument(e.to_string()));
-    }
+    payload.validate()?;
 
     let user_repo = UserRepository::new(&state.db_pool);
     let user = user_repo
@@ -483,18 +1369,110 @@ pub async fn send_verification_email(
         .await?
         .ok_or_else(|| ApiError::UserNotFound)?;
 
-    if user.is_email_verified {
+    // A verified account asking for a different address starts an email change. The current
+    // address stays on the account until the new one is confirmed in `verify_email`.
+    let is_current_email = user
+        .email
+        .as_deref()
+        .is_some_and(|current| current.trim().eq_ignore_ascii_case(payload.email.trim()));
+    if user.is_email_verified && is_current_email {
+        // Asking for the current address again abandons a pending email change
+        state
+            .email_verification_store
+            .clear(&auth_payload.key)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to clear pending email verification: {}", e);
+                ApiError::ServerErr("Failed to send verification email".to_string())
+            })?;
         return Ok(Json(EmailVerificationResponse {
             success: true,
             message: Some("Email already verified".to_string()),
         }));
     }
+    if let Some(Extension(event)) = &event {
+        event.add_context("is_email_change", user.is_email_verified);
+    }
+    if user.is_email_verified
+        && user_repo
+            .is_verified_email_taken(&payload.email, &auth_payload.key)
+            .await?
+    {
+        return Err(email_in_use_error());
+    }
+
+    send_verification_code(&state, &auth_payload.key, &payload.email).await?;
+
+    Ok(Json(EmailVerificationResponse {
+        success: true,
+        message: Some("Verification code sent".to_string()),
+    }))
+}
+
+/// Sends a fresh code for the email the user is already verifying.
+///
+/// Unlike `send_verification_email` this never starts verifying another address. It shares the
+/// resend cooldown and hourly budgets with `send_verification_email`.
+pub async fn resend_verification_email(
+    State(state): State<AppState>,
+    Extension(auth_payload): Extension<AuthenticatedUser>,
+    event: Option<Extension<WideEventHandle>>,
+) -> anyhow::Result<Json<EmailVerificationResponse>, ApiError> {
+    let email = state
+        .email_verification_store
+        .get_email(&auth_payload.key)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to load pending email: {}", e);
+            ApiError::ServerErr("Failed to send verification email".to_string())
+        })?
+        .ok_or_else(|| {
+            ApiError::InvalidArgument("No pending email verification to resend".to_string())
+        })?;
+    if let Some(Extension(event)) = &event {
+        let domain = email.split('@').nth(1).unwrap_or("unknown");
+        event.add_context("email_domain", domain);
+    }
+
+    send_verification_code(&state, &auth_payload.key, &email).await?;
+
+    Ok(Json(EmailVerificationResponse {
+        success: true,
+        message: Some("Verification code sent".to_string()),
+    }))
+}
+
+/// Rejects moving a verified account to an address another account has verified.
+///
+/// Accounts may share an address when they first verify it (see migration 0007), but an email
+/// change never takes over an address that is already in use.
+fn email_in_use_error() -> ApiError {
+    ApiError::InvalidArgument("Email already in use by another account".to_string())
+}
+
+/// Emails a new code for `email`, replacing any pending verification of `pubkey`.
+async fn send_verification_code(
+    state: &AppState,
+    pubkey: &str,
+    email: &str,
+) -> Result<(), ApiError> {
+    let retry_after = state
+        .email_verification_store
+        .throttle_send(pubkey, email)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to check verification email throttle: {}", e);
+            ApiError::ServerErr("Failed to send verification email".to_string())
+        })?;
+    if let Some(retry_after) = retry_after {
+        return Err(ApiError::TooManyRequests(retry_after));
+    }
 
     let code = EmailVerificationStore::generate_code();
 
     state
         .email_verification_store
-        .store(&auth_payload.key, &payload.email, &code)
+        .store(pubkey, email, &code)
         .await
         .map_err(|e| {
             tracing::error!("Failed to store verification code: {}", e);
@@ -503,23 +1481,16 @@ pub async fn send_verification_email(
 
     state
         .email_client
-        .send_verification_email(&payload.email, &code)
+        .send_verification_email(email, &code)
         .await
         .map_err(|e| {
             tracing::error!("Failed to send verification email: {}", e);
             ApiError::ServerErr("Failed to send verification email".to_string())
         })?;
 
-    tracing::info!(
-        "Verification email sent to {} for user {}",
-        payload.email,
-        auth_payload.key
-    );
+    tracing::info!("Verification email sent to {} for user {}", email, pubkey);
 
-    Ok(Json(EmailVerificationResponse {
-        success: true,
-        message: Some("Verification code sent".to_string()),
-    }))
+    Ok(())
 }
 
 /// Verifies the email verification code.
@@ -536,7 +1507,17 @@ pub async fn verify_email(
         .await?
         .ok_or_else(|| ApiError::UserNotFound)?;
 
-    if user.is_email_verified {
+    let has_pending_email = state
+        .email_verification_store
+        .get_email(&auth_payload.key)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to load pending email: {}", e);
+            ApiError::ServerErr("Failed to verify code".to_string())
+        })?
+        .is_some();
+
+    if user.is_email_verified && !has_pending_email {
         if let Some(Extension(event)) = &event {
             event.add_context("verification_result", "already_verified");
         }
@@ -561,6 +1542,17 @@ pub async fn verify_email(
 
     match email {
         Some(verified_email) => {
+            // Another account may have verified the address while this change was pending
+            if user.is_email_verified
+                && user_repo
+                    .is_verified_email_taken(&verified_email, &auth_payload.key)
+                    .await?
+            {
+                if let Some(Extension(event)) = &event {
+                    event.add_context("verification_result", "email_in_use");
+                }
+                return Err(email_in_use_error());
+            }
             if let Some(Extension(event)) = &event {
                 event.add_context("verification_result", "success");
                 let domain = verified_email.split('@').nth(1).unwrap_or("unknown");
@@ -571,11 +1563,20 @@ pub async fn verify_email(
                 .await?;
             user_repo.set_email_verified(&auth_payload.key).await?;
 
-            tracing::info!(
-                "Email {} verified for user {}",
-                verified_email,
-                auth_payload.key
-            );
+            if user.is_email_verified {
+                tracing::info!(
+                    "Email changed from {} to {} for user {}",
+                    user.email.as_deref().unwrap_or(""),
+                    verified_email,
+                    auth_payload.key
+                );
+            } else {
+                tracing::info!(
+                    "Email {} verified for user {}",
+                    verified_email,
+                    auth_payload.key
+                );
+            }
 
             Ok(Json(EmailVerificationResponse {
                 success: true,
@@ -592,3 +1593,34 @@ pub async fn verify_email(
         }
     }
 }
+
+/// Reports whether another account has already verified an email address.
+///
+/// Lets the client warn before sending a code. The caller's own address counts as available,
+/// and the response never says which account holds a taken one. Checks are limited per
+/// account on top of the route's IP limit to make enumerating addresses impractical.
+pub async fn email_availability(
+    State(state): State<AppState>,
+    Extension(auth_payload): Extension<AuthenticatedUser>,
+    Json(payload): Json<EmailAvailabilityPayload>,
+) -> anyhow::Result<Json<EmailAvailabilityResponse>, ApiError> {
+    payload.validate()?;
+
+    let retry_after = state
+        .email_verification_store
+        .throttle_availability(&auth_payload.key)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to check email availability throttle: {}", e);
+            ApiError::ServerErr("Failed to check email availability".to_string())
+        })?;
+    if let Some(retry_after) = retry_after {
+        return Err(ApiError::TooManyRequests(retry_after));
+    }
+
+    let taken = UserRepository::new(&state.db_pool)
+        .is_verified_email_taken(&payload.email, &auth_payload.key)
+        .await?;
+
+    Ok(Json(EmailAvailabilityResponse { available: !taken }))
+}
@@ -1,30 +1,214 @@
-use aws_config::BehaviorVersion;
 use aws_config::meta::region::RegionProviderChain;
+use aws_config::{BehaviorVersion, SdkConfig};
 use aws_sdk_s3::Client;
+use aws_sdk_s3::config::ProvideCredentials;
+use aws_sdk_s3::error::{DisplayErrorContext, ProvideErrorMetadata, SdkError};
 use aws_sdk_s3::presigning::PresigningConfig;
+use bitcoin::hashes::{Hash, sha256};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 
+use crate::config::Config;
+use crate::errors::ApiError;
+
+const CREDENTIALS_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+const LIST_PAGE_SIZE: i32 = 1000;
+const HASHED_PUBKEY_LEN: usize = 32;
+const CREDENTIALS_HINT: &str = "Set AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY, or \
+    AWS_CREDENTIALS_LAZY=true if credentials are only available at request time";
+
+/// S3 failures that callers handle differently, classified from the AWS error code.
+#[derive(Debug, thiserror::Error)]
+pub enum S3Error {
+    #[error("S3 object not found: {0}")]
+    NoSuchKey(String),
+    #[error("S3 access denied: {0}")]
+    AccessDenied(String),
+    #[error("S3 request timed out: {0}")]
+    Timeout(String),
+    #[error("S3 error: {0}")]
+    Other(String),
+}
+
+impl S3Error {
+    fn from_code(code: Option<&str>, message: String) -> Self {
+        match code {
+            Some("NoSuchKey" | "NotFound") => S3Error::NoSuchKey(message),
+            Some(
+                "AccessDenied"
+                | "AllAccessDisabled"
+                | "InvalidAccessKeyId"
+                | "SignatureDoesNotMatch"
+                | "ExpiredToken",
+            ) => S3Error::AccessDenied(message),
+            Some("RequestTimeout") => S3Error::Timeout(message),
+            _ => S3Error::Other(message),
+        }
+    }
+}
+
+impl<E, R> From<SdkError<E, R>> for S3Error
+where
+    E: ProvideErrorMetadata + std::error::Error + 'static,
+    R: std::fmt::Debug,
+{
+    fn from(err: SdkError<E, R>) -> Self {
+        let message = DisplayErrorContext(&err).to_string();
+        match &err {
+            SdkError::TimeoutError(_) => S3Error::Timeout(message),
+            SdkError::DispatchFailure(failure) if failure.is_timeout() => S3Error::Timeout(message),
+            _ => S3Error::from_code(err.code(), message),
+        }
+    }
+}
+
+impl From<S3Error> for ApiError {
+    fn from(err: S3Error) -> Self {
+        match err {
+            S3Error::NoSuchKey(_) => ApiError::NotFound("Backup not found".to_string()),
+            S3Error::AccessDenied(e) => {
+                // Every backup request fails until credentials or the bucket policy are fixed
+                tracing::error!(
+                    "S3 denied access to the backup bucket, check credentials and bucket policy: {}",
+                    e
+                );
+                ApiError::ServerErr(
+                    "Backup storage is temporarily unavailable. Please try again later."
+                        .to_string(),
+                )
+            }
+            S3Error::Timeout(_) => {
+                ApiError::ServerErr("Backup storage timed out. Please try again.".to_string())
+            }
+            S3Error::Other(_) => ApiError::Anyhow(err.into()),
+        }
+    }
+}
+
 pub struct S3BackupClient {
     client: Client,
     bucket: String,
+    key_prefix: String,
+    hash_pubkeys: bool,
+}
+
+async fn load_aws_config() -> SdkConfig {
+    let region_provider = RegionProviderChain::default_provider().or_else("us-east-2");
+    aws_config::defaults(BehaviorVersion::latest())
+        .region(region_provider)
+        .load()
+        .await
+}
+
+/// Fails fast if the AWS SDK can't resolve a region and credentials for S3.
+///
+/// Without this the server starts fine and every backup request fails at runtime.
+pub async fn check_aws_credentials() -> Result<(), anyhow::Error> {
+    ensure_aws_credentials(&load_aws_config().await).await
+}
+
+async fn ensure_aws_credentials(config: &SdkConfig) -> Result<(), anyhow::Error> {
+    if config.region().is_none() {
+        anyhow::bail!("No AWS region configured, set AWS_REGION");
+    }
+
+    let Some(provider) = config.credentials_provider() else {
+        anyhow::bail!(
+            "No AWS credentials provider available. {}",
+            CREDENTIALS_HINT
+        );
+    };
+
+    match tokio::time::timeout(CREDENTIALS_CHECK_TIMEOUT, provider.provide_credentials()).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(e)) => anyhow::bail!(
+            "Failed to resolve AWS credentials ({}). {}",
+            e,
+            CREDENTIALS_HINT
+        ),
+        Err(_) => anyhow::bail!("Timed out resolving AWS credentials. {}", CREDENTIALS_HINT),
+    }
+}
+
+/// Checks that the backup bucket is reachable and records the result in `s3_healthy`, which
+/// `/health` reports.
+pub async fn refresh_s3_health(config: &Config, s3_healthy: &AtomicBool) -> anyhow::Result<()> {
+    let s3_client = S3BackupClient::from_config(config).await?;
+    match s3_client.check_bucket().await {
+        Ok(()) => {
+            tracing::info!("S3 bucket is reachable");
+            s3_healthy.store(true, Ordering::Relaxed);
+        }
+        Err(e) => {
+            tracing::error!(
+                "S3 self-test failed, backups will not work until this is fixed: {}",
+                e
+            );
+            s3_healthy.store(false, Ordering::Relaxed);
+        }
+    }
+    Ok(())
 }
 
 impl S3BackupClient {
-    pub async fn new(bucket_name: String) -> Result<Self, anyhow::Error> {
-        let region_provider = RegionProviderChain::default_provider().or_else("us-east-2");
-        let config = aws_config::defaults(BehaviorVersion::latest())
-            .region(region_provider)
-            .load()
-            .await;
+    /// Creates a client for `bucket_name` whose backup keys live under `key_prefix`.
+    pub async fn new(
+        bucket_name: String,
+        key_prefix: &str,
+        hash_pubkeys: bool,
+    ) -> Result<Self, anyhow::Error> {
+        let config = load_aws_config().await;
         let client = Client::new(&config);
         Ok(Self {
             client,
             bucket: bucket_name,
+            key_prefix: normalize_key_prefix(key_prefix),
+            hash_pubkeys,
         })
     }
 
-    pub async fn generate_upload_url(&self, key: &str) -> Result<String, anyhow::Error> {
-        let presigning_config = PresigningConfig::expires_in(Duration::from_secs(900))?; // 15 minutes
+    /// Creates a client for the configured bucket and key layout.
+    pub async fn from_config(config: &Config) -> Result<Self, anyhow::Error> {
+        Self::new(
+            config.s3_bucket_name.clone(),
+            &config.s3_key_prefix,
+            config.s3_hash_pubkeys,
+        )
+        .await
+    }
+
+    /// Key prefix every new backup is stored under, empty or ending in `/`.
+    pub fn key_prefix(&self) -> &str {
+        &self.key_prefix
+    }
+
+    /// Returns the object key for a user's backup version.
+    ///
+    /// Keys are stored in `backup_metadata`, so backups written before the prefix or hashing
+    /// changed stay readable under their original key.
+    pub fn backup_key(&self, pubkey: &str, backup_version: i32) -> String {
+        let owner = if self.hash_pubkeys {
+            hashed_pubkey(pubkey)
+        } else {
+            pubkey.to_string()
+        };
+        format!("{}{}/backup_v{}.db", self.key_prefix, owner, backup_version)
+    }
+
+    /// Checks that the configured bucket exists and is reachable with the current credentials.
+    pub async fn check_bucket(&self) -> Result<(), anyhow::Error> {
+        self.client
+            .head_bucket()
+            .bucket(&self.bucket)
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("head_bucket failed: {}", e.into_service_error()))?;
+        Ok(())
+    }
+
+    pub async fn generate_upload_url(&self, key: &str) -> Result<String, S3Error> {
+        let presigning_config = PresigningConfig::expires_in(Duration::from_secs(900)) // 15 minutes
+            .map_err(|e| S3Error::Other(e.to_string()))?;
         let presigned_request = self
             .client
             .put_object()
@@ -35,8 +219,9 @@ impl S3BackupClient {
         Ok(presigned_request.uri().to_string())
     }
 
-    pub async fn generate_download_url(&self, key: &str) -> Result<String, anyhow::Error> {
-        let presigning_config = PresigningConfig::expires_in(Duration::from_secs(300))?; // 5 minutes
+    pub async fn generate_download_url(&self, key: &str) -> Result<String, S3Error> {
+        let presigning_config = PresigningConfig::expires_in(Duration::from_secs(300)) // 5 minutes
+            .map_err(|e| S3Error::Other(e.to_string()))?;
         let presigned_request = self
             .client
             .get_object()
@@ -47,7 +232,90 @@ impl S3BackupClient {
         Ok(presigned_request.uri().to_string())
     }
 
-    pub async fn delete_object(&self, key: &str) -> Result<(), anyhow::Error> {
+    /// Every prefix a user's backups may have been written under: the current layout and the
+    /// layouts without `S3_KEY_PREFIX` or with the other `S3_HASH_PUBKEYS` setting.
+    ///
+    /// Objects left behind by a rekey sit under one of these even when no row points at them.
+    pub fn user_key_prefixes(&self, pubkey: &str) -> Vec<String> {
+        let mut prefixes = Vec::new();
+        for key_prefix in [self.key_prefix.as_str(), ""] {
+            for owner in [hashed_pubkey(pubkey), pubkey.to_string()] {
+                let prefix = format!("{}{}/", key_prefix, owner);
+                if !prefixes.contains(&prefix) {
+                    prefixes.push(prefix);
+                }
+            }
+        }
+        prefixes
+    }
+
+    /// Lists every object key under the key prefix, following continuation tokens page by page.
+    pub async fn list_keys(&self) -> Result<Vec<String>, anyhow::Error> {
+        self.list_keys_under(&self.key_prefix).await
+    }
+
+    /// Lists every object key starting with `prefix`, which may lie outside the key prefix.
+    pub async fn list_keys_under(&self, prefix: &str) -> Result<Vec<String>, anyhow::Error> {
+        let mut pages = self
+            .client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .set_prefix((!prefix.is_empty()).then(|| prefix.to_string()))
+            .max_keys(LIST_PAGE_SIZE)
+            .into_paginator()
+            .send();
+
+        let mut keys = Vec::new();
+        while let Some(page) = pages.next().await {
+            let page = page.map_err(|e| {
+                anyhow::anyhow!("list_objects_v2 failed: {}", e.into_service_error())
+            })?;
+            keys.extend(
+                page.contents()
+                    .iter()
+                    .filter_map(|object| object.key().map(String::from)),
+            );
+        }
+        Ok(keys)
+    }
+
+    /// Checks whether an object exists, for keys outside the listed prefix.
+    pub async fn object_exists(&self, key: &str) -> Result<bool, anyhow::Error> {
+        match self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(e) => {
+                let e = e.into_service_error();
+                if e.is_not_found() {
+                    Ok(false)
+                } else {
+                    Err(anyhow::anyhow!("head_object failed: {}", e))
+                }
+            }
+        }
+    }
+
+    /// Copies an object within the bucket, failing instead of overwriting an existing `to_key`.
+    pub async fn copy_object(&self, from_key: &str, to_key: &str) -> Result<(), anyhow::Error> {
+        self.client
+            .copy_object()
+            .bucket(&self.bucket)
+            .copy_source(format!("{}/{}", self.bucket, from_key))
+            .key(to_key)
+            .if_none_match("*")
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("copy_object failed: {}", e.into_service_error()))?;
+        Ok(())
+    }
+
+    pub async fn delete_object(&self, key: &str) -> Result<(), S3Error> {
         self.client
             .delete_object()
             .bucket(&self.bucket)
@@ -57,3 +325,153 @@ impl S3BackupClient {
         Ok(())
     }
 }
+
+/// Stands in for a pubkey in object keys, so bucket listings and access logs don't reveal it.
+///
+/// Truncated to 128 bits, which is plenty to avoid collisions between users.
+fn hashed_pubkey(pubkey: &str) -> String {
+    let hash = sha256::Hash::hash(pubkey.as_bytes()).to_string();
+    hash[..HASHED_PUBKEY_LEN].to_string()
+}
+
+/// Trims surrounding slashes and ends a non-empty prefix with a single `/`.
+fn normalize_key_prefix(prefix: &str) -> String {
+    let prefix = prefix.trim_matches('/');
+    if prefix.is_empty() {
+        String::new()
+    } else {
+        format!("{}/", prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_config::Region;
+    use aws_sdk_s3::config::{Credentials, SharedCredentialsProvider};
+    use aws_sdk_s3::error::ErrorMetadata;
+    use aws_sdk_s3::operation::delete_object::DeleteObjectError;
+    use aws_sdk_s3::operation::get_object::GetObjectError;
+    use aws_sdk_s3::types::error::NoSuchKey;
+
+    fn service_error(code: &str) -> SdkError<DeleteObjectError, ()> {
+        let meta = ErrorMetadata::builder()
+            .code(code)
+            .message("mocked")
+            .build();
+        SdkError::service_error(DeleteObjectError::generic(meta), ())
+    }
+
+    #[test]
+    fn s3_errors_are_classified_by_kind() {
+        let no_such_key: SdkError<GetObjectError, ()> = SdkError::service_error(
+            GetObjectError::NoSuchKey(
+                NoSuchKey::builder()
+                    .meta(ErrorMetadata::builder().code("NoSuchKey").build())
+                    .build(),
+            ),
+            (),
+        );
+        assert!(matches!(S3Error::from(no_such_key), S3Error::NoSuchKey(_)));
+        assert!(matches!(
+            S3Error::from(service_error("NotFound")),
+            S3Error::NoSuchKey(_)
+        ));
+        assert!(matches!(
+            S3Error::from(service_error("AccessDenied")),
+            S3Error::AccessDenied(_)
+        ));
+        assert!(matches!(
+            S3Error::from(service_error("RequestTimeout")),
+            S3Error::Timeout(_)
+        ));
+        assert!(matches!(
+            S3Error::from(SdkError::<DeleteObjectError, ()>::timeout_error("mocked")),
+            S3Error::Timeout(_)
+        ));
+        assert!(matches!(
+            S3Error::from(service_error("InternalError")),
+            S3Error::Other(_)
+        ));
+    }
+
+    #[test]
+    fn s3_errors_map_to_api_errors() {
+        assert!(matches!(
+            ApiError::from(S3Error::NoSuchKey("mocked".to_string())),
+            ApiError::NotFound(_)
+        ));
+        assert!(matches!(
+            ApiError::from(S3Error::AccessDenied("mocked".to_string())),
+            ApiError::ServerErr(_)
+        ));
+        assert!(matches!(
+            ApiError::from(S3Error::Timeout("mocked".to_string())),
+            ApiError::ServerErr(_)
+        ));
+        assert!(matches!(
+            ApiError::from(S3Error::Other("mocked".to_string())),
+            ApiError::Anyhow(_)
+        ));
+    }
+
+    #[test]
+    fn key_prefix_is_normalized() {
+        assert_eq!(normalize_key_prefix(""), "");
+        assert_eq!(normalize_key_prefix("/"), "");
+        assert_eq!(normalize_key_prefix("staging"), "staging/");
+        assert_eq!(normalize_key_prefix("/tenants/acme/"), "tenants/acme/");
+    }
+
+    #[test]
+    fn hashed_pubkey_hides_the_pubkey() {
+        let pubkey = "02a1633cafcc01ebfb6d78e39f687a1f0995c62fc95f51ead10a02ee0be551b5dc";
+        let hashed = hashed_pubkey(pubkey);
+
+        assert_eq!(hashed.len(), HASHED_PUBKEY_LEN);
+        assert!(!pubkey.contains(&hashed));
+        assert_eq!(hashed, hashed_pubkey(pubkey));
+        assert_ne!(hashed, hashed_pubkey("03ffff"));
+    }
+
+    #[tokio::test]
+    async fn ensure_aws_credentials_fails_without_credentials() {
+        let config = SdkConfig::builder()
+            .region(Region::new("us-east-2"))
+            .build();
+
+        assert!(ensure_aws_credentials(&config).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn ensure_aws_credentials_fails_without_region() {
+        let config = SdkConfig::builder()
+            .credentials_provider(SharedCredentialsProvider::new(Credentials::new(
+                "test-key",
+                "test-secret",
+                None,
+                None,
+                "test",
+            )))
+            .build();
+
+        let error = ensure_aws_credentials(&config).await.unwrap_err();
+        assert!(error.to_string().contains("AWS_REGION"));
+    }
+
+    #[tokio::test]
+    async fn ensure_aws_credentials_accepts_static_credentials() {
+        let config = SdkConfig::builder()
+            .region(Region::new("us-east-2"))
+            .credentials_provider(SharedCredentialsProvider::new(Credentials::new(
+                "test-key",
+                "test-secret",
+                None,
+                None,
+                "test",
+            )))
+            .build();
+
+        assert!(ensure_aws_credentials(&config).await.is_ok());
+    }
+}
@@ -0,0 +1,618 @@
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::http::{self, Request, StatusCode};
+use futures_util::StreamExt;
+use http_body_util::BodyExt;
+use serde_json::json;
+use tower::ServiceExt;
+
+use crate::cache::admin_command_bus::AdminCommand;
+use crate::db::admin_audit_repo::audit_subject_hash;
+use crate::db::backup_repo::BackupRepository;
+use crate::db::feature_flag_repo::FeatureFlagRepository;
+use crate::db::migrations::{MigrationStatus, read_migration_status};
+use crate::db::user_repo::{PurgedUserRows, UserRepository};
+use crate::notification_coordinator::DispatchSummary;
+use crate::routes::admin_api::{
+    ActiveUsersResponse, AdminCommandResponse, BulkRegisterResponse, BulkRegisterStatus,
+    ListUsersResponse, PurgeUserResponse, TOTAL_COUNT_HEADER,
+};
+use crate::s3_client::S3BackupClient;
+use crate::tests::common::{TestUser, setup_admin_test_app, setup_test_admin_command_bus};
+
+async fn get_users_page(app: &axum::Router, uri: &str) -> (StatusCode, Option<i64>, Vec<u8>) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(http::Method::GET)
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let status = response.status();
+    let total = response
+        .headers()
+        .get(TOTAL_COUNT_HEADER)
+        .map(|value| value.to_str().unwrap().parse().unwrap());
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, total, body.to_vec())
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_admin_list_users_paginates() {
+    let (app, app_state, _guard) = setup_admin_test_app().await;
+
+    let users = [
+        TestUser::new_with_key(&[0x01; 32]),
+        TestUser::new_with_key(&[0x02; 32]),
+        TestUser::new_with_key(&[0x03; 32]),
+    ];
+    for (i, user) in users.iter().enumerate() {
+        let mut tx = app_state.db_pool.begin().await.unwrap();
+        UserRepository::create(
+            &mut tx,
+            &user.pubkey().to_string(),
+            &format!("user{}@localhost", i),
+            None,
+        )
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+    }
+
+    BackupRepository::new(&app_state.db_pool)
+        .upsert_settings(&users[0].pubkey().to_string(), true)
+        .await
+        .unwrap();
+
+    let (status, total, body) = get_users_page(&app, "/admin/users?limit=2").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(total, Some(3));
+    let first_page: ListUsersResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(first_page.users.len(), 2);
+    assert_eq!(first_page.users[0].pubkey, users[0].pubkey().to_string());
+    assert!(first_page.users[0].backup_enabled);
+    assert!(!first_page.users[1].backup_enabled);
+    let cursor = first_page.next_cursor.expect("expected a next cursor");
+
+    let (status, total, body) =
+        get_users_page(&app, &format!("/admin/users?limit=2&cursor={}", cursor)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(total, Some(3));
+    let second_page: ListUsersResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(second_page.users.len(), 1);
+    assert_eq!(second_page.users[0].pubkey, users[2].pubkey().to_string());
+    assert_eq!(
+        second_page.users[0].lightning_address.as_deref(),
+        Some("user2@localhost")
+    );
+    assert!(second_page.next_cursor.is_none());
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_admin_list_users_rejects_invalid_params() {
+    let (app, _app_state, _guard) = setup_admin_test_app().await;
+
+    let (status, _, _) = get_users_page(&app, "/admin/users?limit=0").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _, _) = get_users_page(&app, "/admin/users?cursor=garbage").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_admin_trigger_maintenance_reports_counts() {
+    let (app, app_state, _guard) = setup_admin_test_app().await;
+
+    for (i, key) in [[0x04; 32], [0x05; 32]].iter().enumerate() {
+        let user = TestUser::new_with_key(key);
+        let mut tx = app_state.db_pool.begin().await.unwrap();
+        UserRepository::create(
+            &mut tx,
+            &user.pubkey().to_string(),
+            &format!("maint{}@localhost", i),
+            None,
+        )
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+    }
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/admin/trigger_maintenance")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let summary: DispatchSummary = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        summary,
+        DispatchSummary {
+            eligible: 2,
+            sent: 0,
+            skipped: 0,
+            no_push_token: 2,
+        }
+    );
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_admin_active_users_counts_buckets() {
+    let (app, app_state, _guard) = setup_admin_test_app().await;
+
+    // Hours since last login for each seeded user, None means never logged in
+    let logins = [Some(1), Some(30), Some(24 * 10), Some(24 * 60), None];
+    for (i, hours_ago) in logins.iter().enumerate() {
+        let user = TestUser::new_with_key(&[0x10 + i as u8; 32]);
+        sqlx::query(
+            "INSERT INTO users (pubkey, lightning_address, last_login_at)
+             VALUES ($1, $2, now() - make_interval(hours => $3))",
+        )
+        .bind(user.pubkey().to_string())
+        .bind(format!("active{}@localhost", i))
+        .bind(*hours_ago)
+        .execute(&app_state.db_pool)
+        .await
+        .unwrap();
+    }
+
+    let (status, _, body) = get_users_page(&app, "/admin/stats/active_users").await;
+    assert_eq!(status, StatusCode::OK);
+    let counts: ActiveUsersResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        counts,
+        ActiveUsersResponse {
+            total: 5,
+            active_24h: 1,
+            active_7d: 2,
+            active_30d: 3,
+        }
+    );
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_admin_sets_feature_flag_override() {
+    let (app, app_state, _guard) = setup_admin_test_app().await;
+
+    let user = TestUser::new_with_key(&[0x20; 32]);
+    let pubkey = user.pubkey().to_string();
+    let mut tx = app_state.db_pool.begin().await.unwrap();
+    UserRepository::create(&mut tx, &pubkey, "flags@localhost", None)
+        .await
+        .unwrap();
+    tx.commit().await.unwrap();
+
+    let set_override = |pubkey: &str, enabled: serde_json::Value| {
+        Request::builder()
+            .method(http::Method::POST)
+            .uri("/admin/feature_flags/override")
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                serde_json::to_vec(&json!({
+                    "pubkey": pubkey,
+                    "flag": "beta_swaps",
+                    "enabled": enabled,
+                }))
+                .unwrap(),
+            ))
+            .unwrap()
+    };
+    let flag_repo = FeatureFlagRepository::new(&app_state.db_pool);
+
+    let response = app
+        .clone()
+        .oneshot(set_override(&pubkey, json!(true)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        flag_repo
+            .find_overrides(&pubkey)
+            .await
+            .unwrap()
+            .get("beta_swaps"),
+        Some(&true)
+    );
+
+    let response = app
+        .clone()
+        .oneshot(set_override(&pubkey, json!(null)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(flag_repo.find_overrides(&pubkey).await.unwrap().is_empty());
+
+    let response = app
+        .oneshot(set_override("02unknown", json!(true)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_admin_migrations_reports_applied_schema() {
+    let (app, _app_state, _guard) = setup_admin_test_app().await;
+
+    let (status, _, body) = get_users_page(&app, "/admin/migrations").await;
+    assert_eq!(status, StatusCode::OK);
+
+    let res: MigrationStatus = serde_json::from_slice(&body).unwrap();
+    assert!(res.up_to_date);
+    assert!(res.pending_versions.is_empty());
+    assert_eq!(res.dirty_version, None);
+    assert!(res.applied_versions.contains(&1));
+    assert_eq!(
+        res.latest_applied_version,
+        res.applied_versions.last().copied()
+    );
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_migration_check_rejects_dirty_schema() {
+    let (_app, app_state, _guard) = setup_admin_test_app().await;
+
+    // Mark the latest migration as failed inside a transaction that is rolled back afterwards
+    let mut tx = app_state.db_pool.begin().await.unwrap();
+    let latest: i64 = sqlx::query_scalar(
+        "UPDATE _sqlx_migrations SET success = false
+         WHERE version = (SELECT MAX(version) FROM _sqlx_migrations)
+         RETURNING version",
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .unwrap();
+
+    let status = read_migration_status(&mut tx).await.unwrap();
+    assert_eq!(status.dirty_version, Some(latest));
+    assert!(!status.up_to_date);
+
+    let err = status.ensure_up_to_date().unwrap_err().to_string();
+    assert!(
+        err.contains(&format!("migration {} is dirty", latest)),
+        "{}",
+        err
+    );
+
+    tx.rollback().await.unwrap();
+
+    crate::db::migrations::migration_status(&app_state.db_pool)
+        .await
+        .unwrap()
+        .ensure_up_to_date()
+        .unwrap();
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_admin_command_reaches_every_instance() {
+    let (app, app_state, _guard) = setup_admin_test_app().await;
+
+    // Two instances sharing the same Redis, each with its own subscription
+    let first = app_state.admin_command_bus.subscribe().await.unwrap();
+    let second = setup_test_admin_command_bus().subscribe().await.unwrap();
+    let mut first = std::pin::pin!(first);
+    let mut second = std::pin::pin!(second);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/admin/commands")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .body(Body::from(
+                    serde_json::to_vec(&json!({ "command": "recheck_s3" })).unwrap(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let res: AdminCommandResponse = serde_json::from_slice(&body).unwrap();
+    assert!(res.receivers >= 2);
+
+    for stream in [&mut first, &mut second] {
+        let command = tokio::time::timeout(Duration::from_secs(5), stream.next())
+            .await
+            .expect("command was not delivered");
+        assert_eq!(command, Some(AdminCommand::RecheckS3));
+    }
+}
+
+#[test]
+fn test_admin_command_rejects_commands_not_on_the_bus() {
+    assert_eq!(
+        serde_json::from_value::<AdminCommand>(json!({ "command": "recheck_s3" })).unwrap(),
+        AdminCommand::RecheckS3
+    );
+    for command in ["broadcast", "reload_config", "flush_caches"] {
+        assert!(serde_json::from_value::<AdminCommand>(json!({ "command": command })).is_err());
+    }
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_admin_purge_user_removes_all_user_data() {
+    let (app, app_state, _guard) = setup_admin_test_app().await;
+
+    let pubkey = TestUser::new().pubkey().to_string();
+    let mut tx = app_state.db_pool.begin().await.unwrap();
+    UserRepository::create(&mut tx, &pubkey, "purge@localhost", None)
+        .await
+        .unwrap();
+    tx.commit().await.unwrap();
+
+    let s3_client = S3BackupClient::from_config(&app_state.config)
+        .await
+        .unwrap();
+    let s3_key = s3_client.backup_key(&pubkey, 1);
+    let backup_repo = BackupRepository::new(&app_state.db_pool);
+    backup_repo
+        .upsert_metadata(&pubkey, &s3_key, 1024, 1)
+        .await
+        .unwrap();
+    backup_repo.upsert_settings(&pubkey, true).await.unwrap();
+    FeatureFlagRepository::new(&app_state.db_pool)
+        .set_override(&pubkey, "beta_swaps", Some(true))
+        .await
+        .unwrap();
+    for statement in [
+        "INSERT INTO devices (pubkey, os_name) VALUES ($1, 'ios')",
+        "INSERT INTO push_tokens (pubkey, push_token) VALUES ($1, 'ExponentPushToken[purge]')",
+        "INSERT INTO job_status_reports (pubkey, notification_k1, report_type, status)
+         VALUES ($1, 'purge-k1', 'Backup', 'Pending')",
+        "INSERT INTO heartbeat_notifications (pubkey, notification_id) VALUES ($1, 'purge-hb')",
+        "INSERT INTO mailbox_authorizations (pubkey, mailbox_id, enabled)
+         VALUES ($1, 'purge-mailbox', FALSE)",
+        "INSERT INTO abuse_reports (pubkey, reason) VALUES ($1, 'purge-spam')",
+        "WITH job AS (
+             INSERT INTO broadcast_jobs (notification_type, priority, status)
+             VALUES ('purge-test', 'normal', 'completed')
+             RETURNING id
+         )
+         INSERT INTO broadcast_job_recipients (job_id, pubkey) SELECT id, $1 FROM job",
+    ] {
+        sqlx::query(statement)
+            .bind(&pubkey)
+            .execute(&app_state.db_pool)
+            .await
+            .unwrap();
+    }
+
+    // A copy an earlier rekey left behind, which no row points at
+    let legacy_key = format!("{}/backup_v0.db", pubkey);
+    let upload_url = s3_client.generate_upload_url(&legacy_key).await.unwrap();
+    let _ = reqwest::Client::new()
+        .put(upload_url)
+        .body("legacy")
+        .send()
+        .await;
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/admin/purge_user")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .body(Body::from(
+                    serde_json::to_vec(&json!({ "identifier": "purge@localhost" })).unwrap(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    // Note: This test may fail in CI without proper AWS credentials
+    if response.status() == StatusCode::OK {
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let summary: PurgeUserResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(summary.pubkey, pubkey);
+        assert_eq!(summary.s3_objects_deleted, 2);
+        assert_eq!(
+            summary.rows,
+            PurgedUserRows {
+                devices: 1,
+                push_tokens: 1,
+                backup_metadata: 1,
+                backup_settings: 1,
+                job_status_reports: 1,
+                heartbeat_notifications: 1,
+                mailbox_authorizations: 1,
+                feature_flag_overrides: 1,
+                lightning_address_history: 0,
+                abuse_reports: 1,
+                broadcast_job_recipients: 1,
+            }
+        );
+
+        for table in [
+            "users",
+            "devices",
+            "push_tokens",
+            "backup_metadata",
+            "backup_settings",
+            "job_status_reports",
+            "heartbeat_notifications",
+            "mailbox_authorizations",
+            "feature_flag_overrides",
+            "abuse_reports",
+            "broadcast_job_recipients",
+        ] {
+            let remaining: i64 =
+                sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {} WHERE pubkey = $1", table))
+                    .bind(&pubkey)
+                    .fetch_one(&app_state.db_pool)
+                    .await
+                    .unwrap();
+            assert_eq!(remaining, 0, "{} still has rows for the user", table);
+        }
+        assert!(!s3_client.object_exists(&s3_key).await.unwrap());
+        assert!(!s3_client.object_exists(&legacy_key).await.unwrap());
+
+        let audited: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM admin_audit_log
+             WHERE action = 'purge_user' AND subject_hash = $1",
+        )
+        .bind(audit_subject_hash(&pubkey))
+        .fetch_one(&app_state.db_pool)
+        .await
+        .unwrap();
+        assert_eq!(audited, 1);
+    } else {
+        // Without S3 the purge stops before touching the database
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(
+            UserRepository::new(&app_state.db_pool)
+                .find_by_pubkey(&pubkey)
+                .await
+                .unwrap()
+                .is_some()
+        );
+    }
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_admin_purge_user_rejects_unknown_user() {
+    let (app, _app_state, _guard) = setup_admin_test_app().await;
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/admin/purge_user")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .body(Body::from(
+                    serde_json::to_vec(&json!({ "identifier": "nobody@localhost" })).unwrap(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_admin_bulk_register_reports_partial_success() {
+    let (app, app_state, _guard) = setup_admin_test_app().await;
+
+    let existing = TestUser::new_with_key(&[0x81; 32]).pubkey().to_string();
+    let mut tx = app_state.db_pool.begin().await.unwrap();
+    UserRepository::create(&mut tx, &existing, "existing@localhost", None)
+        .await
+        .unwrap();
+    tx.commit().await.unwrap();
+
+    let first = TestUser::new_with_key(&[0x82; 32]).pubkey().to_string();
+    let second = TestUser::new_with_key(&[0x83; 32]).pubkey().to_string();
+    let third = TestUser::new_with_key(&[0x84; 32]).pubkey().to_string();
+    let users = json!([
+        { "pubkey": first, "ln_address": "first@localhost" },
+        { "pubkey": existing, "ln_address": "again@localhost" },
+        { "pubkey": second, "ln_address": "Not Valid@localhost" },
+        { "pubkey": third, "ln_address": "existing@localhost" },
+        { "pubkey": second, "ln_address": "second@localhost" },
+        // The same key in uppercase hex is the same user
+        { "pubkey": first.to_uppercase(), "ln_address": "upper@localhost" },
+    ]);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/admin/bulk_register")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .body(Body::from(
+                    serde_json::to_vec(&json!({ "users": users })).unwrap(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let summary: BulkRegisterResponse = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(
+        (summary.created, summary.skipped, summary.failed),
+        (2, 2, 2)
+    );
+    let statuses: Vec<BulkRegisterStatus> = summary.results.iter().map(|r| r.status).collect();
+    assert_eq!(
+        statuses,
+        vec![
+            BulkRegisterStatus::Created,
+            BulkRegisterStatus::Skipped,
+            BulkRegisterStatus::Failed,
+            BulkRegisterStatus::Failed,
+            BulkRegisterStatus::Created,
+            BulkRegisterStatus::Skipped,
+        ]
+    );
+    assert_eq!(
+        summary.results[3].reason.as_deref(),
+        Some("Lightning address already taken")
+    );
+
+    // Rejected users didn't undo the ones around them
+    let user_repo = UserRepository::new(&app_state.db_pool);
+    for (pubkey, address) in [(&first, "first@localhost"), (&second, "second@localhost")] {
+        let user = user_repo.find_by_pubkey(pubkey).await.unwrap().unwrap();
+        assert_eq!(user.lightning_address.as_deref(), Some(address));
+    }
+    assert!(user_repo.find_by_pubkey(&third).await.unwrap().is_none());
+    let existing_user = user_repo.find_by_pubkey(&existing).await.unwrap().unwrap();
+    assert_eq!(
+        existing_user.lightning_address.as_deref(),
+        Some("existing@localhost")
+    );
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_create_if_absent_skips_a_registered_pubkey() {
+    let (_app, app_state, _guard) = setup_admin_test_app().await;
+    let pubkey = TestUser::new_with_key(&[0x85; 32]).pubkey().to_string();
+
+    let mut tx = app_state.db_pool.begin().await.unwrap();
+    assert!(
+        UserRepository::create_if_absent(&mut tx, &pubkey, "absent@localhost")
+            .await
+            .unwrap()
+    );
+    // A second insert of the same pubkey is skipped instead of failing the transaction
+    assert!(
+        !UserRepository::create_if_absent(&mut tx, &pubkey, "other@localhost")
+            .await
+            .unwrap()
+    );
+    tx.commit().await.unwrap();
+
+    let user = UserRepository::new(&app_state.db_pool)
+        .find_by_pubkey(&pubkey)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(user.lightning_address.as_deref(), Some("absent@localhost"));
+}
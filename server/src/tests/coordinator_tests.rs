@@ -1,9 +1,19 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::db::backup_repo::BackupRepository;
+use crate::db::broadcast_job_repo::{
+    BROADCAST_JOB_LEASE_SECS, BROADCAST_JOB_MAX_RESUME_AGE_SECS, BroadcastJobClaim,
+    BroadcastJobRepository,
+};
 use crate::db::notification_tracking_repo::NotificationTrackingRepository;
 use crate::db::user_repo::UserRepository;
-use crate::notification_coordinator::{NotificationCoordinator, NotificationRequest};
-use crate::tests::common::{TestUser, setup_test_app};
-use crate::types::NotificationRequestData;
-use chrono::{Duration, Utc};
+use crate::notification_coordinator::{
+    NotificationCoordinator, NotificationRequest, SendOutcome, resume_interrupted_broadcasts,
+};
+use crate::tests::common::{TestUser, setup_public_test_app_with_config, setup_test_app};
+use crate::types::{BroadcastJobPriority, BroadcastJobStatus, NotificationRequestData};
+use chrono::{Duration, Timelike, Utc};
 use expo_push_notification_client::Priority;
 use uuid::Uuid;
 
@@ -42,8 +52,8 @@ async fn test_normal_priority_respects_spacing() {
         target_pubkey: Some(pubkey.clone()),
     };
 
-    let result = coordinator.send_notification(request).await;
-    assert!(result.is_ok());
+    let outcome = coordinator.send_notification(request).await.unwrap();
+    assert_eq!(outcome, SendOutcome::SkippedSpacing);
 
     let can_send = tracking_repo
         .can_send_notification(&pubkey, 45)
@@ -89,8 +99,9 @@ async fn test_critical_priority_bypasses_spacing() {
         target_pubkey: Some(pubkey.clone()),
     };
 
-    let result = coordinator.send_notification(request).await;
-    assert!(result.is_ok());
+    // Not skipped by spacing, the user simply has no device to reach
+    let outcome = coordinator.send_notification(request).await.unwrap();
+    assert_eq!(outcome, SendOutcome::NoToken);
 }
 
 #[tracing_test::traced_test]
@@ -231,3 +242,567 @@ async fn test_spacing_configuration_from_config() {
         .unwrap();
     assert!(can_send, "Should be able to send at 45 minute boundary");
 }
+
+async fn explain(tx: &mut sqlx::Transaction<'_, sqlx::Postgres>, query: &str) -> String {
+    let plan: Vec<String> = sqlx::query_scalar(&format!("EXPLAIN {}", query))
+        .fetch_all(&mut **tx)
+        .await
+        .unwrap();
+    plan.join("\n")
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_spacing_lookups_use_indexes() {
+    let (_, app_state, _guard) = setup_test_app().await;
+
+    // Test tables are tiny, so rule out sequential scans to see which index the planner picks
+    let mut tx = app_state.db_pool.begin().await.unwrap();
+    sqlx::query("SET LOCAL enable_seqscan = off")
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+
+    let heartbeat_plan = explain(
+        &mut tx,
+        "SELECT MAX(sent_at) FROM heartbeat_notifications WHERE pubkey = 'pubkey'",
+    )
+    .await;
+    assert!(
+        heartbeat_plan.contains("idx_heartbeat_notifications_pubkey_sent_at"),
+        "{heartbeat_plan}"
+    );
+
+    let job_status_plan = explain(
+        &mut tx,
+        "SELECT MAX(created_at) FROM job_status_reports WHERE pubkey = 'pubkey'",
+    )
+    .await;
+    assert!(
+        job_status_plan.contains("idx_job_status_reports_pubkey_created_at"),
+        "{job_status_plan}"
+    );
+
+    tx.rollback().await.unwrap();
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_reachable_eligible_users_require_push_token() {
+    let (_, app_state, _guard) = setup_test_app().await;
+
+    let with_token = TestUser::new_with_key(&[0x21; 32]).pubkey().to_string();
+    let without_token = TestUser::new_with_key(&[0x22; 32]).pubkey().to_string();
+    let recently_notified = TestUser::new_with_key(&[0x23; 32]).pubkey().to_string();
+
+    let mut tx = app_state.db_pool.begin().await.unwrap();
+    for (i, pubkey) in [&with_token, &without_token, &recently_notified]
+        .iter()
+        .enumerate()
+    {
+        UserRepository::create(&mut tx, pubkey, &format!("reach{}@test.com", i), None)
+            .await
+            .unwrap();
+    }
+    tx.commit().await.unwrap();
+
+    for pubkey in [&with_token, &recently_notified] {
+        sqlx::query("INSERT INTO push_tokens (pubkey, push_token) VALUES ($1, $2)")
+            .bind(pubkey)
+            .bind(format!("ExponentPushToken[{}]", &pubkey[..8]))
+            .execute(&app_state.db_pool)
+            .await
+            .unwrap();
+    }
+
+    sqlx::query(
+        "INSERT INTO job_status_reports (pubkey, notification_k1, report_type, status, created_at)
+         VALUES ($1, $2, $3, $4, $5)",
+    )
+    .bind(&recently_notified)
+    .bind(format!("k1-{}", Uuid::new_v4()))
+    .bind("Backup")
+    .bind("Pending")
+    .bind(Utc::now() - Duration::minutes(5))
+    .execute(&app_state.db_pool)
+    .await
+    .unwrap();
+
+    let tracking_repo = NotificationTrackingRepository::new(&app_state.db_pool);
+
+    let reachable = tracking_repo
+        .get_reachable_eligible_users(45, false)
+        .await
+        .unwrap();
+    assert_eq!(reachable, vec![with_token]);
+
+    let unreachable = tracking_repo
+        .count_unreachable_eligible_users(45, false)
+        .await
+        .unwrap();
+    assert_eq!(unreachable, 1);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_interrupted_broadcast_resumes_without_duplicates() {
+    let (_, app_state, _guard) = setup_test_app().await;
+
+    let pubkeys: Vec<String> = [[0x31; 32], [0x32; 32], [0x33; 32]]
+        .iter()
+        .map(|key| TestUser::new_with_key(key).pubkey().to_string())
+        .collect();
+    let mut tx = app_state.db_pool.begin().await.unwrap();
+    for (i, pubkey) in pubkeys.iter().enumerate() {
+        UserRepository::create(&mut tx, pubkey, &format!("resume{}@test.com", i), None)
+            .await
+            .unwrap();
+    }
+    tx.commit().await.unwrap();
+
+    // Simulate a maintenance broadcast that was cut off after reaching the first user
+    let job_repo = BroadcastJobRepository::new(&app_state.db_pool);
+    let claim = job_repo
+        .start_or_resume("maintenance", BroadcastJobPriority::High, "crashed-run")
+        .await
+        .unwrap();
+    let BroadcastJobClaim::Started(job_id) = claim else {
+        panic!("expected a new job, got {:?}", claim);
+    };
+    assert!(job_repo.claim_recipient(job_id, &pubkeys[0]).await.unwrap());
+
+    // While the lease is live the job belongs to its run and is left alone
+    assert_eq!(
+        job_repo
+            .start_or_resume("maintenance", BroadcastJobPriority::High, "cron-tick")
+            .await
+            .unwrap(),
+        BroadcastJobClaim::Busy
+    );
+    resume_interrupted_broadcasts(app_state.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        job_repo.get_status(job_id).await.unwrap(),
+        BroadcastJobStatus::Running
+    );
+
+    job_repo
+        .expire_lease(job_id, BROADCAST_JOB_LEASE_SECS + 1)
+        .await
+        .unwrap();
+    resume_interrupted_broadcasts(app_state.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        job_repo.get_status(job_id).await.unwrap(),
+        BroadcastJobStatus::Completed
+    );
+    assert!(job_repo.processed_pubkeys(job_id).await.unwrap().is_empty());
+
+    // The resumed run only visited the two remaining users
+    assert!(logs_contain("already_processed=1"));
+
+    // With nothing running, the next broadcast starts a fresh job
+    let coordinator = NotificationCoordinator::new(app_state.clone());
+    let outcome = coordinator
+        .send_notification(NotificationRequest {
+            priority: Priority::High,
+            data: NotificationRequestData::Maintenance,
+            target_pubkey: None,
+        })
+        .await
+        .unwrap();
+    let SendOutcome::Broadcast(summary) = outcome else {
+        panic!("expected a broadcast outcome, got {:?}", outcome);
+    };
+    assert_eq!(summary.eligible, 3);
+    assert_eq!(job_repo.find_expired().await.unwrap(), vec![]);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_stale_broadcast_job_is_failed_instead_of_resumed() {
+    let (_, app_state, _guard) = setup_test_app().await;
+
+    let pubkeys: Vec<String> = [[0x34; 32], [0x35; 32]]
+        .iter()
+        .map(|key| TestUser::new_with_key(key).pubkey().to_string())
+        .collect();
+    let mut tx = app_state.db_pool.begin().await.unwrap();
+    for (i, pubkey) in pubkeys.iter().enumerate() {
+        UserRepository::create(&mut tx, pubkey, &format!("stale{}@test.com", i), None)
+            .await
+            .unwrap();
+    }
+    tx.commit().await.unwrap();
+
+    // A run that died long ago reached the first user
+    let job_repo = BroadcastJobRepository::new(&app_state.db_pool);
+    let BroadcastJobClaim::Started(stale_job_id) = job_repo
+        .start_or_resume("maintenance", BroadcastJobPriority::High, "crashed-run")
+        .await
+        .unwrap()
+    else {
+        panic!("expected a new job");
+    };
+    assert!(
+        job_repo
+            .claim_recipient(stale_job_id, &pubkeys[0])
+            .await
+            .unwrap()
+    );
+    job_repo
+        .expire_lease(stale_job_id, BROADCAST_JOB_MAX_RESUME_AGE_SECS + 1)
+        .await
+        .unwrap();
+
+    // A new tick doesn't join the stale run, so the first user is notified again
+    let coordinator = NotificationCoordinator::new(app_state.clone());
+    let outcome = coordinator
+        .send_notification(NotificationRequest {
+            priority: Priority::High,
+            data: NotificationRequestData::Maintenance,
+            target_pubkey: None,
+        })
+        .await
+        .unwrap();
+    let SendOutcome::Broadcast(summary) = outcome else {
+        panic!("expected a broadcast outcome, got {:?}", outcome);
+    };
+    assert_eq!(summary.eligible, 2);
+    assert!(!logs_contain("Resuming interrupted"));
+    assert_eq!(
+        job_repo.get_status(stale_job_id).await.unwrap(),
+        BroadcastJobStatus::Failed
+    );
+}
+
+async fn send_with_quiet_hours(
+    pubkey: &str,
+    quiet_hours: (u32, u32),
+    priority: Priority,
+) -> SendOutcome {
+    let mut config = TestUser::get_config();
+    config.quiet_hours_start = Some(quiet_hours.0);
+    config.quiet_hours_end = Some(quiet_hours.1);
+    let (_, app_state, _guard) = setup_public_test_app_with_config(config).await;
+
+    let mut tx = app_state.db_pool.begin().await.unwrap();
+    UserRepository::create(&mut tx, pubkey, "quiet@test.com", None)
+        .await
+        .unwrap();
+    tx.commit().await.unwrap();
+    sqlx::query("UPDATE users SET timezone = 'Asia/Tokyo' WHERE pubkey = $1")
+        .bind(pubkey)
+        .execute(&app_state.db_pool)
+        .await
+        .unwrap();
+
+    NotificationCoordinator::new(app_state.clone())
+        .send_notification(NotificationRequest {
+            priority,
+            data: NotificationRequestData::BackupTrigger,
+            target_pubkey: Some(pubkey.to_string()),
+        })
+        .await
+        .unwrap()
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_quiet_hours_defer_normal_notifications() {
+    let pubkey = TestUser::new_with_key(&[0x41; 32]).pubkey().to_string();
+    let local_hour = Utc::now().with_timezone(&chrono_tz::Asia::Tokyo).hour();
+
+    // Inside the user's local quiet hours, normal sends are held back
+    let in_window = (local_hour, (local_hour + 2) % 24);
+    let outcome = send_with_quiet_hours(&pubkey, in_window, Priority::Normal).await;
+    assert_eq!(outcome, SendOutcome::SkippedQuietHours);
+
+    // High priority notifications still go through
+    let outcome = send_with_quiet_hours(&pubkey, in_window, Priority::High).await;
+    assert_eq!(outcome, SendOutcome::NoToken);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_quiet_hours_allow_sends_outside_window() {
+    let pubkey = TestUser::new_with_key(&[0x42; 32]).pubkey().to_string();
+    let local_hour = Utc::now().with_timezone(&chrono_tz::Asia::Tokyo).hour();
+
+    let out_of_window = ((local_hour + 2) % 24, (local_hour + 3) % 24);
+    let outcome = send_with_quiet_hours(&pubkey, out_of_window, Priority::Normal).await;
+    assert_eq!(outcome, SendOutcome::NoToken);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_rapid_identical_sends_are_deduplicated() {
+    // Stand-in push endpoint that counts deliveries
+    let deliveries = Arc::new(AtomicUsize::new(0));
+    let counter = deliveries.clone();
+    let push_endpoint = axum::Router::new().route(
+        "/push",
+        axum::routing::post(move || {
+            let counter = counter.clone();
+            async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, push_endpoint).await.unwrap() });
+
+    let mut config = TestUser::get_config();
+    config.push_dedupe_window_secs = 600;
+    let (_, app_state, _guard) = setup_public_test_app_with_config(config).await;
+
+    // Setup flushes Redis, so no dedupe entry from an earlier run is left for this user
+    let pubkey = TestUser::new_with_key(&[0x43; 32]).pubkey().to_string();
+    let mut tx = app_state.db_pool.begin().await.unwrap();
+    UserRepository::create(&mut tx, &pubkey, "dedupe@test.com", None)
+        .await
+        .unwrap();
+    tx.commit().await.unwrap();
+    sqlx::query("INSERT INTO push_tokens (pubkey, push_token) VALUES ($1, $2)")
+        .bind(&pubkey)
+        .bind(format!("http://{}/push", addr))
+        .execute(&app_state.db_pool)
+        .await
+        .unwrap();
+
+    // High priority bypasses spacing, so only the dedupe window stands between the two sends
+    let coordinator = NotificationCoordinator::new(app_state.clone());
+    let send = || {
+        coordinator.send_notification(NotificationRequest {
+            priority: Priority::High,
+            data: NotificationRequestData::Maintenance,
+            target_pubkey: Some(pubkey.clone()),
+        })
+    };
+
+    // UnifiedPush deliveries have no Expo ticket
+    assert_eq!(send().await.unwrap(), SendOutcome::Sent { ticket_id: None });
+    assert_eq!(send().await.unwrap(), SendOutcome::SkippedDuplicate);
+
+    assert_eq!(deliveries.load(Ordering::SeqCst), 1);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_failed_send_does_not_hold_the_dedupe_window() {
+    // Stand-in push endpoint that counts deliveries
+    let deliveries = Arc::new(AtomicUsize::new(0));
+    let counter = deliveries.clone();
+    let push_endpoint = axum::Router::new().route(
+        "/push",
+        axum::routing::post(move || {
+            let counter = counter.clone();
+            async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, push_endpoint).await.unwrap() });
+
+    // Nothing listens on this port, so sends to it fail
+    let unreachable = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    let mut config = TestUser::get_config();
+    config.push_dedupe_window_secs = 600;
+    let (_, app_state, _guard) = setup_public_test_app_with_config(config).await;
+
+    let pubkey = TestUser::new_with_key(&[0x44; 32]).pubkey().to_string();
+    let mut tx = app_state.db_pool.begin().await.unwrap();
+    UserRepository::create(&mut tx, &pubkey, "retry@test.com", None)
+        .await
+        .unwrap();
+    tx.commit().await.unwrap();
+    sqlx::query("INSERT INTO push_tokens (pubkey, push_token) VALUES ($1, $2)")
+        .bind(&pubkey)
+        .bind(format!("http://{}/push", unreachable))
+        .execute(&app_state.db_pool)
+        .await
+        .unwrap();
+
+    let coordinator = NotificationCoordinator::new(app_state.clone());
+    let send = || {
+        coordinator.send_notification(NotificationRequest {
+            priority: Priority::High,
+            data: NotificationRequestData::Maintenance,
+            target_pubkey: Some(pubkey.clone()),
+        })
+    };
+
+    send().await.unwrap();
+    assert_eq!(deliveries.load(Ordering::SeqCst), 0);
+
+    // The retry goes through once the device is reachable again
+    sqlx::query("UPDATE push_tokens SET push_token = $1 WHERE pubkey = $2")
+        .bind(format!("http://{}/push", addr))
+        .bind(&pubkey)
+        .execute(&app_state.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(send().await.unwrap(), SendOutcome::Sent { ticket_id: None });
+    assert_eq!(deliveries.load(Ordering::SeqCst), 1);
+
+    // A second identical send within the window is still dropped
+    assert_eq!(send().await.unwrap(), SendOutcome::SkippedDuplicate);
+    assert_eq!(deliveries.load(Ordering::SeqCst), 1);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_backup_trigger_broadcast_skips_users_with_backups_disabled() {
+    // Stand-in push endpoint that counts deliveries
+    let deliveries = Arc::new(AtomicUsize::new(0));
+    let counter = deliveries.clone();
+    let push_endpoint = axum::Router::new().route(
+        "/push",
+        axum::routing::post(move || {
+            let counter = counter.clone();
+            async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, push_endpoint).await.unwrap() });
+
+    let (_, app_state, _guard) = setup_test_app().await;
+
+    let enabled = TestUser::new_with_key(&[0x51; 32]).pubkey().to_string();
+    let disabled = TestUser::new_with_key(&[0x52; 32]).pubkey().to_string();
+    let mut tx = app_state.db_pool.begin().await.unwrap();
+    for (pubkey, email) in [
+        (&enabled, "enabled@test.com"),
+        (&disabled, "disabled@test.com"),
+    ] {
+        UserRepository::create(&mut tx, pubkey, email, None)
+            .await
+            .unwrap();
+    }
+    tx.commit().await.unwrap();
+
+    let backup_repo = BackupRepository::new(&app_state.db_pool);
+    backup_repo.upsert_settings(&enabled, true).await.unwrap();
+    backup_repo.upsert_settings(&disabled, false).await.unwrap();
+    for pubkey in [&enabled, &disabled] {
+        sqlx::query("INSERT INTO push_tokens (pubkey, push_token) VALUES ($1, $2)")
+            .bind(pubkey)
+            .bind(format!("http://{}/push", addr))
+            .execute(&app_state.db_pool)
+            .await
+            .unwrap();
+    }
+
+    // High priority bypasses spacing, so the first broadcast doesn't hold back the second
+    let coordinator = NotificationCoordinator::new(app_state.clone());
+    let broadcast = |data| {
+        coordinator.send_notification(NotificationRequest {
+            priority: Priority::High,
+            data,
+            target_pubkey: None,
+        })
+    };
+
+    let outcome = broadcast(NotificationRequestData::BackupTrigger)
+        .await
+        .unwrap();
+    let SendOutcome::Broadcast(summary) = outcome else {
+        panic!("expected a broadcast outcome, got {:?}", outcome);
+    };
+    assert_eq!(summary.eligible, 1);
+    assert_eq!(summary.sent, 1);
+    assert_eq!(deliveries.load(Ordering::SeqCst), 1);
+
+    let outcome = broadcast(NotificationRequestData::Maintenance)
+        .await
+        .unwrap();
+    let SendOutcome::Broadcast(summary) = outcome else {
+        panic!("expected a broadcast outcome, got {:?}", outcome);
+    };
+    assert_eq!(summary.eligible, 2);
+    assert_eq!(summary.sent, 2);
+    assert_eq!(deliveries.load(Ordering::SeqCst), 3);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_overlapping_broadcasts_run_one_at_a_time() {
+    // Stand-in push endpoint that records the most deliveries in flight at once
+    let in_flight = Arc::new(AtomicUsize::new(0));
+    let max_in_flight = Arc::new(AtomicUsize::new(0));
+    let (current, max) = (in_flight.clone(), max_in_flight.clone());
+    let push_endpoint = axum::Router::new().route(
+        "/push",
+        axum::routing::post(move || {
+            let (current, max) = (current.clone(), max.clone());
+            async move {
+                let now = current.fetch_add(1, Ordering::SeqCst) + 1;
+                max.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+                current.fetch_sub(1, Ordering::SeqCst);
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, push_endpoint).await.unwrap() });
+
+    let mut config = TestUser::get_config();
+    config.max_concurrent_broadcasts = 1;
+    let (_, app_state, _guard) = setup_public_test_app_with_config(config).await;
+
+    let pubkey = TestUser::new().pubkey().to_string();
+    let mut tx = app_state.db_pool.begin().await.unwrap();
+    UserRepository::create(&mut tx, &pubkey, "overlap@test.com", None)
+        .await
+        .unwrap();
+    tx.commit().await.unwrap();
+    BackupRepository::new(&app_state.db_pool)
+        .upsert_settings(&pubkey, true)
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO push_tokens (pubkey, push_token) VALUES ($1, $2)")
+        .bind(&pubkey)
+        .bind(format!("http://{}/push", addr))
+        .execute(&app_state.db_pool)
+        .await
+        .unwrap();
+
+    let coordinator = NotificationCoordinator::new(app_state.clone());
+    let broadcast = |data| {
+        coordinator.send_notification(NotificationRequest {
+            priority: Priority::High,
+            data,
+            target_pubkey: None,
+        })
+    };
+
+    let (maintenance, backup) = tokio::join!(
+        broadcast(NotificationRequestData::Maintenance),
+        broadcast(NotificationRequestData::BackupTrigger),
+    );
+    for outcome in [maintenance.unwrap(), backup.unwrap()] {
+        let SendOutcome::Broadcast(summary) = outcome else {
+            panic!("expected a broadcast outcome, got {:?}", outcome);
+        };
+        assert_eq!(summary.sent, 1);
+    }
+
+    // The second broadcast only started once the first had delivered
+    assert_eq!(max_in_flight.load(Ordering::SeqCst), 1);
+    assert!(logs_contain("behind a running broadcast"));
+}
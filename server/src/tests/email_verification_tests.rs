@@ -4,7 +4,10 @@ use http_body_util::BodyExt;
 use serde_json::json;
 use tower::ServiceExt;
 
-use crate::tests::common::{TestUser, create_test_user, setup_test_app};
+use crate::db::user_repo::UserRepository;
+use crate::tests::common::{
+    TestUser, create_test_user, setup_test_app, setup_test_app_with_config,
+};
 use crate::types::EmailVerificationResponse;
 
 #[tracing_test::traced_test]
@@ -77,6 +80,12 @@ async fn test_send_verification_email_invalid_email() {
         .unwrap();
 
     assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["code"], "INVALID_ARGUMENT");
+    let email_errors = body["field_errors"]["email"].as_array().unwrap();
+    assert!(!email_errors.is_empty());
 }
 
 #[tracing_test::traced_test]
@@ -112,7 +121,7 @@ async fn test_send_verification_email_already_verified() {
                 )
                 .body(Body::from(
                     serde_json::to_vec(&json!({
-                        "email": "another@example.com"
+                        "email": "verified@example.com"
                     }))
                     .unwrap(),
                 ))
@@ -183,6 +192,326 @@ async fn test_send_verification_email_duplicate_email_allowed() {
     assert_eq!(res.message, Some("Verification code sent".to_string()));
 }
 
+async fn send_verification(
+    app: &axum::Router,
+    access_token: &str,
+    email: &str,
+) -> axum::response::Response {
+    app.clone()
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/email/send_verification")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .header(
+                    http::header::AUTHORIZATION,
+                    format!("Bearer {}", access_token),
+                )
+                .body(Body::from(
+                    serde_json::to_vec(&json!({ "email": email })).unwrap(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_send_verification_email_throttled() {
+    let (app, app_state, _guard) = setup_test_app().await;
+
+    let users: Vec<TestUser> = (1..=4u8)
+        .map(|i| TestUser::new_with_key(&[i; 32]))
+        .collect();
+    for user in &users {
+        create_test_user(&app_state, user, None).await;
+    }
+
+    // Resending right away is blocked by the per-pubkey cooldown
+    let access_token = users[0].access_token(&app_state);
+    let response = send_verification(&app, &access_token, "victim@example.com").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = send_verification(&app, &access_token, "victim@example.com").await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: u64 = response
+        .headers()
+        .get(http::header::RETRY_AFTER)
+        .expect("missing Retry-After header")
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((1..=60).contains(&retry_after));
+
+    // Rotating pubkeys doesn't get around the per-address budget
+    for user in &users[1..3] {
+        let response =
+            send_verification(&app, &user.access_token(&app_state), "victim@example.com").await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+    let response = send_verification(
+        &app,
+        &users[3].access_token(&app_state),
+        "Victim@Example.com",
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(response.headers().contains_key(http::header::RETRY_AFTER));
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(error["code"], "TOO_MANY_REQUESTS");
+}
+
+async fn insert_verified_user(app_state: &crate::AppState, user: &TestUser, email: &str) {
+    sqlx::query(
+        "INSERT INTO users (pubkey, lightning_address, email, is_email_verified) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(user.pubkey().to_string())
+    .bind("test@localhost")
+    .bind(email)
+    .bind(true)
+    .execute(&app_state.db_pool)
+    .await
+    .unwrap();
+}
+
+async fn stored_email(app_state: &crate::AppState, user: &TestUser) -> (Option<String>, bool) {
+    sqlx::query_as::<_, (Option<String>, bool)>(
+        "SELECT email, is_email_verified FROM users WHERE pubkey = $1",
+    )
+    .bind(user.pubkey().to_string())
+    .fetch_one(&app_state.db_pool)
+    .await
+    .unwrap()
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_change_verified_email() {
+    let (app, app_state, _guard) = setup_test_app().await;
+
+    let user = TestUser::new();
+    insert_verified_user(&app_state, &user, "old@example.com").await;
+    let access_token = user.access_token(&app_state);
+
+    let response = send_verification(&app, &access_token, "new@example.com").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let res: EmailVerificationResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(res.message, Some("Verification code sent".to_string()));
+
+    // The old address stays active until the new one is confirmed
+    assert_eq!(
+        stored_email(&app_state, &user).await,
+        (Some("old@example.com".to_string()), true)
+    );
+
+    let code = app_state
+        .email_verification_store
+        .get_code(&user.pubkey().to_string())
+        .await
+        .unwrap()
+        .expect("expected a pending verification code");
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/email/verify")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .header(
+                    http::header::AUTHORIZATION,
+                    format!("Bearer {}", access_token),
+                )
+                .body(Body::from(
+                    serde_json::to_vec(&json!({ "code": code })).unwrap(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let res: EmailVerificationResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(res.message, Some("Email verified successfully".to_string()));
+
+    assert_eq!(
+        stored_email(&app_state, &user).await,
+        (Some("new@example.com".to_string()), true)
+    );
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_change_email_to_address_already_in_use() {
+    let (app, app_state, _guard) = setup_test_app().await;
+
+    let user = TestUser::new();
+    insert_verified_user(&app_state, &user, "verified@example.com").await;
+    let access_token = user.access_token(&app_state);
+
+    // The account already uses this address, so no change is started
+    let response = send_verification(&app, &access_token, "Verified@Example.com").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let res: EmailVerificationResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(res.message, Some("Email already verified".to_string()));
+    assert!(
+        app_state
+            .email_verification_store
+            .get_email(&user.pubkey().to_string())
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    // Another account has verified this address, so the change is refused
+    let other_user = TestUser::new_with_key(&[0x41; 32]);
+    sqlx::query(
+        "INSERT INTO users (pubkey, lightning_address, email, is_email_verified) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(other_user.pubkey().to_string())
+    .bind("other@localhost")
+    .bind("shared@example.com")
+    .bind(true)
+    .execute(&app_state.db_pool)
+    .await
+    .unwrap();
+
+    let response = send_verification(&app, &access_token, "Shared@Example.com").await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(error["code"], "INVALID_ARGUMENT");
+    assert_eq!(error["message"], "Email already in use by another account");
+    assert!(
+        app_state
+            .email_verification_store
+            .get_email(&user.pubkey().to_string())
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    // A change started before the other account verified the address is refused on verify
+    app_state
+        .email_verification_store
+        .store(&user.pubkey().to_string(), "shared@example.com", "123456")
+        .await
+        .unwrap();
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/email/verify")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .header(
+                    http::header::AUTHORIZATION,
+                    format!("Bearer {}", access_token),
+                )
+                .body(Body::from(json!({ "code": "123456" }).to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        stored_email(&app_state, &user).await,
+        (Some("verified@example.com".to_string()), true)
+    );
+}
+
+async fn insert_email_owner(app_state: &crate::AppState, owner: &TestUser, email: &str) {
+    sqlx::query(
+        "INSERT INTO users (pubkey, lightning_address, email, is_email_verified) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(owner.pubkey().to_string())
+    .bind("owner@localhost")
+    .bind(email)
+    .bind(true)
+    .execute(&app_state.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_claim_email_while_owner_is_active_fails() {
+    let (app, app_state, _guard) = setup_test_app().await;
+
+    let owner = TestUser::new_with_key(&[0x46; 32]);
+    insert_email_owner(&app_state, &owner, "claimed@example.com").await;
+
+    let user = TestUser::new();
+    insert_verified_user(&app_state, &user, "mine@example.com").await;
+    let access_token = user.access_token(&app_state);
+
+    let response = send_verification(&app, &access_token, "claimed@example.com").await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = check_email_availability(&app, &access_token, "claimed@example.com").await;
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let res: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(res, json!({ "available": false }));
+    assert_eq!(
+        stored_email(&app_state, &user).await,
+        (Some("mine@example.com".to_string()), true)
+    );
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_claim_email_after_owner_is_deleted() {
+    let (app, app_state, _guard) = setup_test_app().await;
+
+    let owner = TestUser::new_with_key(&[0x47; 32]);
+    insert_email_owner(&app_state, &owner, "claimed@example.com").await;
+
+    let user = TestUser::new();
+    insert_verified_user(&app_state, &user, "mine@example.com").await;
+    let access_token = user.access_token(&app_state);
+
+    // Deleting the owner's account takes the address with it
+    let mut tx = app_state.db_pool.begin().await.unwrap();
+    UserRepository::purge_tx(&mut tx, &owner.pubkey().to_string())
+        .await
+        .unwrap()
+        .expect("owner should exist");
+    tx.commit().await.unwrap();
+
+    let response = send_verification(&app, &access_token, "claimed@example.com").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let code = app_state
+        .email_verification_store
+        .get_code(&user.pubkey().to_string())
+        .await
+        .unwrap()
+        .expect("expected a pending verification code");
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/email/verify")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .header(
+                    http::header::AUTHORIZATION,
+                    format!("Bearer {}", access_token),
+                )
+                .body(Body::from(json!({ "code": code }).to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        stored_email(&app_state, &user).await,
+        (Some("claimed@example.com".to_string()), true)
+    );
+}
+
 #[tracing_test::traced_test]
 #[tokio::test]
 async fn test_verify_email_success() {
@@ -241,6 +570,37 @@ async fn test_verify_email_success() {
 
     assert_eq!(user_record.0, Some(email.to_string()));
     assert!(user_record.1);
+
+    // Using the code clears the pending verification
+    let pubkey = user.pubkey().to_string();
+    let store = &app_state.email_verification_store;
+    assert_eq!(store.get_code(&pubkey).await.unwrap(), None);
+    assert_eq!(store.get_email(&pubkey).await.unwrap(), None);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_verification_code_expires_after_configured_ttl() {
+    let mut config = TestUser::get_config();
+    config.email_code_ttl_secs = 1;
+    let (_app, app_state, _guard) = setup_test_app_with_config(config).await;
+
+    let pubkey = TestUser::new().pubkey().to_string();
+    let store = &app_state.email_verification_store;
+    store
+        .store(&pubkey, "test@example.com", "123456")
+        .await
+        .unwrap();
+    assert_eq!(
+        store.get_code(&pubkey).await.unwrap().as_deref(),
+        Some("123456")
+    );
+
+    tokio::time::sleep(std::time::Duration::from_millis(2100)).await;
+
+    assert_eq!(store.get_code(&pubkey).await.unwrap(), None);
+    assert_eq!(store.get_email(&pubkey).await.unwrap(), None);
+    assert_eq!(store.verify(&pubkey, "123456", false).await.unwrap(), None);
 }
 
 #[tracing_test::traced_test]
@@ -563,3 +923,174 @@ async fn test_send_verification_unregistered_user() {
     // User not found returns 401 UNAUTHORIZED from the middleware
     assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
 }
+
+async fn check_email_availability(
+    app: &axum::Router,
+    access_token: &str,
+    email: &str,
+) -> axum::response::Response {
+    app.clone()
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/email/availability")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .header(
+                    http::header::AUTHORIZATION,
+                    format!("Bearer {}", access_token),
+                )
+                .body(Body::from(
+                    serde_json::to_vec(&json!({ "email": email })).unwrap(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_email_availability_reports_taken_without_owner() {
+    let (app, app_state, _guard) = setup_test_app().await;
+
+    let owner = TestUser::new_with_key(&[0xef; 32]);
+    sqlx::query(
+        "INSERT INTO users (pubkey, lightning_address, email, is_email_verified) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(owner.pubkey().to_string())
+    .bind("owner@localhost")
+    .bind("taken@example.com")
+    .bind(true)
+    .execute(&app_state.db_pool)
+    .await
+    .unwrap();
+
+    let user = TestUser::new();
+    create_test_user(&app_state, &user, None).await;
+    let access_token = user.access_token(&app_state);
+
+    let response = check_email_availability(&app, &access_token, "free@example.com").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let res: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(res, json!({ "available": true }));
+
+    // Only the flag comes back, nothing that identifies the owning account
+    let response = check_email_availability(&app, &access_token, "Taken@Example.com").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let res: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(res, json!({ "available": false }));
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    assert!(!body.contains(&owner.pubkey().to_string()));
+    assert!(!body.contains("owner@localhost"));
+
+    // The owner's own address is available to them
+    let owner_token = owner.access_token(&app_state);
+    let response = check_email_availability(&app, &owner_token, "taken@example.com").await;
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let res: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(res, json!({ "available": true }));
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_email_availability_throttled() {
+    let (app, app_state, _guard) = setup_test_app().await;
+
+    let user = TestUser::new();
+    create_test_user(&app_state, &user, None).await;
+    let access_token = user.access_token(&app_state);
+
+    for i in 0..10 {
+        let email = format!("probe{}@example.com", i);
+        let response = check_email_availability(&app, &access_token, &email).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    let response = check_email_availability(&app, &access_token, "probe@example.com").await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(response.headers().contains_key(http::header::RETRY_AFTER));
+}
+
+async fn resend_verification(app: &axum::Router, access_token: &str) -> axum::response::Response {
+    app.clone()
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/email/resend_verification")
+                .header(
+                    http::header::AUTHORIZATION,
+                    format!("Bearer {}", access_token),
+                )
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_resend_verification_with_pending_email() {
+    let (app, app_state, _guard) = setup_test_app().await;
+
+    let user = TestUser::new_with_key(&[0x31; 32]);
+    let pubkey = user.pubkey().to_string();
+    create_test_user(&app_state, &user, None).await;
+    let access_token = user.access_token(&app_state);
+
+    // Never produced by generate_code, so a fresh code is always different
+    let store = &app_state.email_verification_store;
+    store
+        .store(&pubkey, "resend@example.com", "000001")
+        .await
+        .unwrap();
+
+    let response = resend_verification(&app, &access_token).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let res: EmailVerificationResponse = serde_json::from_slice(&body).unwrap();
+    assert!(res.success);
+
+    assert_eq!(
+        store.get_email(&pubkey).await.unwrap().as_deref(),
+        Some("resend@example.com")
+    );
+    let code = store.get_code(&pubkey).await.unwrap().unwrap();
+    assert_ne!(code, "000001");
+
+    // Resending again right away is blocked by the same cooldown as send_verification
+    let response = resend_verification(&app, &access_token).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(store.get_code(&pubkey).await.unwrap(), Some(code));
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_resend_verification_without_pending_email() {
+    let (app, app_state, _guard) = setup_test_app().await;
+
+    let user = TestUser::new_with_key(&[0x32; 32]);
+    let pubkey = user.pubkey().to_string();
+    create_test_user(&app_state, &user, None).await;
+    app_state
+        .email_verification_store
+        .clear(&pubkey)
+        .await
+        .unwrap();
+
+    let response = resend_verification(&app, &user.access_token(&app_state)).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(error["code"], "INVALID_ARGUMENT");
+    assert!(
+        app_state
+            .email_verification_store
+            .get_code(&pubkey)
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
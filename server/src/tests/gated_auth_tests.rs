@@ -5,8 +5,12 @@ use http_body_util::BodyExt;
 use serde_json::json;
 use tower::ServiceExt;
 
-use crate::tests::common::{TestUser, create_test_user, setup_test_app};
-use crate::types::{AuthLoginResponse, RegisterResponse};
+use crate::app_middleware::CLIENT_TIMESTAMP_HEADER;
+use crate::cache::k1_store::K1;
+use crate::tests::common::{
+    TestUser, create_test_user, setup_test_app, setup_test_app_with_config,
+};
+use crate::types::{ApiErrorResponse, AuthLoginResponse, RegisterResponse};
 use crate::utils::make_k1;
 
 #[tracing_test::traced_test]
@@ -18,7 +22,7 @@ async fn test_auth_login_success() {
     let k1 = make_k1(&app_state.k1_cache)
         .await
         .expect("failed to create k1");
-    let auth_payload = user.auth_payload(&k1);
+    let auth_payload = user.auth_payload(&k1.to_string());
 
     let response = app
         .oneshot(
@@ -51,7 +55,7 @@ async fn test_auth_login_reused_k1_is_rejected() {
     let k1 = make_k1(&app_state.k1_cache)
         .await
         .expect("failed to create k1");
-    let auth_payload = user.auth_payload(&k1);
+    let auth_payload = user.auth_payload(&k1.to_string());
 
     let first_response = app
         .clone()
@@ -122,6 +126,123 @@ async fn test_register_new_user() {
     assert_eq!(res.lightning_address, Some("test@localhost".to_string()));
 }
 
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_register_without_ln_address_returns_assigned_defaults() {
+    let (app, app_state, _guard) = setup_test_app().await;
+
+    let user = TestUser::new();
+    let access_token = user.access_token(&app_state);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/register")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .header(
+                    http::header::AUTHORIZATION,
+                    format!("Bearer {}", access_token),
+                )
+                .body(Body::from(
+                    serde_json::to_vec(&json!({
+                        "ark_address": "tark1registerdefaults"
+                    }))
+                    .unwrap(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let res: RegisterResponse = serde_json::from_slice(&body).unwrap();
+
+    let assigned = res
+        .lightning_address
+        .expect("expected an assigned lightning address");
+    assert!(assigned.ends_with("@localhost"));
+    assert_eq!(res.ark_address.as_deref(), Some("tark1registerdefaults"));
+    assert!(!res.backup_enabled);
+    assert!(!res.is_email_verified);
+
+    let stored: Option<String> =
+        sqlx::query_scalar("SELECT lightning_address FROM users WHERE pubkey = $1")
+            .bind(user.pubkey().to_string())
+            .fetch_one(&app_state.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(stored, Some(assigned));
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_concurrent_registrations_for_same_pubkey_both_succeed() {
+    let (app, app_state, _guard) = setup_test_app().await;
+
+    let user = TestUser::new();
+    let access_token = user.access_token(&app_state);
+
+    let register = || {
+        let app = app.clone();
+        let access_token = access_token.clone();
+        async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .method(http::Method::POST)
+                        .uri("/register")
+                        .header(http::header::CONTENT_TYPE, "application/json")
+                        .header(
+                            http::header::AUTHORIZATION,
+                            format!("Bearer {}", access_token),
+                        )
+                        .body(Body::from(
+                            serde_json::to_vec(&json!({
+                                "ark_address": "tark1doubletap"
+                            }))
+                            .unwrap(),
+                        ))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            (status, body)
+        }
+    };
+
+    // A double tap fires both before either has created the user
+    let ((first_status, first_body), (second_status, second_body)) =
+        tokio::join!(register(), register());
+    assert_eq!(first_status, StatusCode::OK);
+    assert_eq!(second_status, StatusCode::OK);
+
+    let first: RegisterResponse = serde_json::from_slice(&first_body).unwrap();
+    let second: RegisterResponse = serde_json::from_slice(&second_body).unwrap();
+    assert_eq!(
+        [first.event.is_some(), second.event.is_some()]
+            .iter()
+            .filter(|registered| **registered)
+            .count(),
+        1
+    );
+    assert!(first.lightning_address.is_some());
+    assert_eq!(first.lightning_address, second.lightning_address);
+    assert_eq!(first.ark_address.as_deref(), Some("tark1doubletap"));
+    assert_eq!(second.ark_address.as_deref(), Some("tark1doubletap"));
+
+    let users: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users WHERE pubkey = $1")
+        .bind(user.pubkey().to_string())
+        .fetch_one(&app_state.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(users, 1);
+}
+
 #[tracing_test::traced_test]
 #[tokio::test]
 async fn test_register_existing_user() {
@@ -180,7 +301,7 @@ async fn test_auth_login_invalid_signature() {
     let k1 = make_k1(&app_state.k1_cache)
         .await
         .expect("failed to create k1");
-    let mut auth_payload = user.auth_payload(&k1);
+    let mut auth_payload = user.auth_payload(&k1.to_string());
     auth_payload.sig = "invalid_sig".to_string();
 
     let response = app
@@ -207,7 +328,7 @@ async fn test_auth_login_invalid_k1() {
     let k1 = make_k1(&app_state.k1_cache)
         .await
         .expect("failed to create k1");
-    let mut auth_payload = user.auth_payload(&k1);
+    let mut auth_payload = user.auth_payload(&k1.to_string());
     auth_payload.k1 = "invalid_k1".to_string();
 
     let response = app
@@ -240,7 +361,7 @@ async fn test_auth_login_expired_k1() {
 
     app_state
         .k1_cache
-        .insert_with_timestamp(&k1, old_timestamp)
+        .insert(&K1::parse(&k1).unwrap())
         .await
         .expect("failed to insert expired k1");
 
@@ -262,6 +383,46 @@ async fn test_auth_login_expired_k1() {
     assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
 }
 
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_auth_login_future_dated_k1_reports_server_time() {
+    let (app, app_state, _guard) = setup_test_app().await;
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let k1 = K1::new([0x5a; 32], now + 3600);
+
+    app_state
+        .k1_cache
+        .insert(&k1)
+        .await
+        .expect("failed to insert future-dated k1");
+
+    let user = TestUser::new();
+    let auth_payload = user.auth_payload(&k1.to_string());
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/auth/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .body(Body::from(serde_json::to_vec(&auth_payload).unwrap()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let err: ApiErrorResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(err.code, "K1_EXPIRED");
+    let server_time = err.server_time.expect("expected server time");
+    assert!(server_time >= now && server_time < now + 60);
+}
+
 #[tracing_test::traced_test]
 #[tokio::test]
 async fn test_register_push_token() {
@@ -708,3 +869,191 @@ async fn test_reauthorize_with_new_mailbox_resets_checkpoint() {
     assert_eq!(record.last_checkpoint, 0);
     assert_eq!(record.auth_version, 2);
 }
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_client_timestamp_skew_is_enforced_when_configured() {
+    let mut config = TestUser::get_config();
+    config.client_timestamp_max_skew_secs = Some(60);
+    let (app, app_state, _guard) = setup_test_app_with_config(config).await;
+
+    let user = TestUser::new();
+    create_test_user(&app_state, &user, None).await;
+    let access_token = user.access_token(&app_state);
+
+    let user_info = |timestamp: Option<i64>| {
+        let app = app.clone();
+        let access_token = access_token.clone();
+        async move {
+            let mut request = Request::builder()
+                .method(http::Method::POST)
+                .uri("/user_info")
+                .header(
+                    http::header::AUTHORIZATION,
+                    format!("Bearer {}", access_token),
+                );
+            if let Some(timestamp) = timestamp {
+                request = request.header(CLIENT_TIMESTAMP_HEADER, timestamp.to_string());
+            }
+            let response = app
+                .oneshot(request.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            (status, body)
+        }
+    };
+
+    let now = Utc::now().timestamp();
+
+    let (status, _) = user_info(Some(now - 30)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    for timestamp in [now - 120, now + 120] {
+        let (status, body) = user_info(Some(timestamp)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "timestamp {}", timestamp);
+        let err: ApiErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(err.code, "CLOCK_SKEW");
+        let server_time = err.server_time.unwrap() as i64;
+        assert!((server_time - now).abs() <= 5);
+    }
+
+    let (status, _) = user_info(None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_client_timestamp_is_optional_by_default() {
+    let (app, app_state, _guard) = setup_test_app().await;
+
+    let user = TestUser::new();
+    create_test_user(&app_state, &user, None).await;
+    let access_token = user.access_token(&app_state);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/user_info")
+                .header(
+                    http::header::AUTHORIZATION,
+                    format!("Bearer {}", access_token),
+                )
+                .header(CLIENT_TIMESTAMP_HEADER, "0")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+/// Fetches a k1 bound to `action` and exchanges it for an access token.
+async fn login_with_bound_k1(app: &axum::Router, user: &TestUser, action: &str) -> String {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri(format!("/getk1?action={}", action))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let k1: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+    let auth_payload = user.auth_payload(k1["k1"].as_str().unwrap());
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/auth/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .body(Body::from(serde_json::to_vec(&auth_payload).unwrap()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let res: AuthLoginResponse = serde_json::from_slice(&body).unwrap();
+    res.access_token
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_k1_bound_to_action_allows_that_action() {
+    let (app, _app_state, _guard) = setup_test_app().await;
+
+    let user = TestUser::new();
+    let access_token = login_with_bound_k1(&app, &user, "register").await;
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/register")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .header(
+                    http::header::AUTHORIZATION,
+                    format!("Bearer {}", access_token),
+                )
+                .body(Body::from(
+                    serde_json::to_vec(&json!({ "ln_address": "bound@localhost" })).unwrap(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_k1_bound_to_action_rejects_other_actions() {
+    let (app, app_state, _guard) = setup_test_app().await;
+
+    let user = TestUser::new();
+    create_test_user(&app_state, &user, None).await;
+    let access_token = login_with_bound_k1(&app, &user, "register").await;
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/user_info")
+                .header(
+                    http::header::AUTHORIZATION,
+                    format!("Bearer {}", access_token),
+                )
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let error: ApiErrorResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(error.code, "ACTION_NOT_ALLOWED");
+
+    // Actions must name a route
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/getk1?action=%2Fregister")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
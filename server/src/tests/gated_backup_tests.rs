@@ -5,8 +5,13 @@ use serde_json::json;
 use tower::ServiceExt;
 
 use crate::db::backup_repo::BackupRepository;
-use crate::tests::common::{TestUser, create_test_user, setup_test_app};
-use crate::types::{BackupInfo, DownloadUrlResponse, UploadUrlResponse};
+use crate::s3_client::S3BackupClient;
+use crate::tests::common::{
+    TestUser, create_test_user, setup_test_app, setup_test_app_with_config,
+};
+use crate::types::{
+    BackupExistsResponse, BackupInfo, BackupUsageResponse, DownloadUrlResponse, UploadUrlResponse,
+};
 
 #[tracing_test::traced_test]
 #[tokio::test]
@@ -42,16 +47,129 @@ async fn test_get_upload_url() {
     if response.status() == StatusCode::OK {
         let body = response.into_body().collect().await.unwrap().to_bytes();
         let res: UploadUrlResponse = serde_json::from_slice(&body).unwrap();
-        assert!(!res.upload_url.is_empty());
-        assert!(!res.s3_key.is_empty());
-        assert!(res.s3_key.contains(&user.pubkey().to_string()));
-        assert!(res.s3_key.contains("backup_v1.db"));
+        let UploadUrlResponse::Upload { upload_url, s3_key } = res else {
+            panic!("expected an upload URL, got {:?}", res);
+        };
+        assert!(!upload_url.is_empty());
+        assert!(!s3_key.is_empty());
+        assert!(s3_key.contains(&user.pubkey().to_string()));
+        assert!(s3_key.contains("backup_v1.db"));
     } else {
         // If S3 is not available, we expect an internal server error
         assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
     }
 }
 
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_upload_url_key_uses_configured_prefix() {
+    let mut config = TestUser::get_config();
+    config.s3_key_prefix = "/staging/".to_string();
+    let (app, app_state, _guard) = setup_test_app_with_config(config).await;
+    let user = TestUser::new();
+    create_test_user(&app_state, &user, None).await;
+    let access_token = user.access_token(&app_state);
+    let expected_key = format!("staging/{}/backup_v1.db", user.pubkey());
+
+    let s3_client = S3BackupClient::from_config(&app_state.config)
+        .await
+        .unwrap();
+    assert_eq!(
+        s3_client.backup_key(&user.pubkey().to_string(), 1),
+        expected_key
+    );
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/backup/upload_url")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .header(
+                    http::header::AUTHORIZATION,
+                    format!("Bearer {}", access_token),
+                )
+                .body(Body::from(
+                    serde_json::to_vec(&json!({
+                        "backup_version": 1
+                    }))
+                    .unwrap(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    // Presigning needs AWS credentials, which may be missing in CI
+    if response.status() == StatusCode::OK {
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let res: UploadUrlResponse = serde_json::from_slice(&body).unwrap();
+        let UploadUrlResponse::Upload { upload_url, s3_key } = res else {
+            panic!("expected an upload URL, got {:?}", res);
+        };
+        assert_eq!(s3_key, expected_key);
+        assert!(upload_url.contains(&expected_key));
+    } else {
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_upload_url_key_hides_pubkey_when_hashing() {
+    let mut config = TestUser::get_config();
+    config.s3_hash_pubkeys = true;
+    let (app, app_state, _guard) = setup_test_app_with_config(config).await;
+    let user = TestUser::new();
+    create_test_user(&app_state, &user, None).await;
+    let access_token = user.access_token(&app_state);
+    let pubkey = user.pubkey().to_string();
+
+    let s3_client = S3BackupClient::from_config(&app_state.config)
+        .await
+        .unwrap();
+    let key = s3_client.backup_key(&pubkey, 1);
+    assert!(!key.contains(&pubkey));
+    assert!(key.ends_with("/backup_v1.db"));
+    // Every request for the same user and version derives the same key
+    assert_eq!(key, s3_client.backup_key(&pubkey, 1));
+    assert_ne!(key, s3_client.backup_key(&pubkey, 2));
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/backup/upload_url")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .header(
+                    http::header::AUTHORIZATION,
+                    format!("Bearer {}", access_token),
+                )
+                .body(Body::from(
+                    serde_json::to_vec(&json!({
+                        "backup_version": 1
+                    }))
+                    .unwrap(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    // Presigning needs AWS credentials, which may be missing in CI
+    if response.status() == StatusCode::OK {
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let res: UploadUrlResponse = serde_json::from_slice(&body).unwrap();
+        let UploadUrlResponse::Upload { upload_url, s3_key } = res else {
+            panic!("expected an upload URL, got {:?}", res);
+        };
+        assert_eq!(s3_key, key);
+        assert!(!upload_url.contains(&pubkey));
+    } else {
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
+
 #[tracing_test::traced_test]
 #[tokio::test]
 async fn test_complete_upload() {
@@ -100,6 +218,552 @@ async fn test_complete_upload() {
     assert_eq!(metadata.backup_version, 1);
 }
 
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_upload_url_skips_unchanged_backup() {
+    let (app, app_state, _guard) = setup_test_app().await;
+    let user = TestUser::new();
+    create_test_user(&app_state, &user, None).await;
+    let access_token = user.access_token(&app_state);
+    let checksum = "ab".repeat(32);
+
+    let post = |uri: &'static str, body: serde_json::Value| {
+        let app = app.clone();
+        let access_token = access_token.clone();
+        async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .method(http::Method::POST)
+                        .uri(uri)
+                        .header(http::header::CONTENT_TYPE, "application/json")
+                        .header(
+                            http::header::AUTHORIZATION,
+                            format!("Bearer {}", access_token),
+                        )
+                        .body(Body::from(serde_json::to_vec(&body).unwrap()))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            (status, body)
+        }
+    };
+
+    let (status, _) = post(
+        "/backup/complete_upload",
+        json!({
+            "s3_key": format!("{}/backup_v1.db", user.pubkey()),
+            "backup_version": 1,
+            "backup_size": 1024,
+            "checksum": checksum,
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Make the earlier upload look stale, so the skipped upload has to refresh it
+    sqlx::query(
+        "UPDATE backup_settings
+         SET last_backup_at = now() - interval '30 days', latest_backup_version = NULL
+         WHERE pubkey = $1",
+    )
+    .bind(user.pubkey().to_string())
+    .execute(&app_state.db_pool)
+    .await
+    .unwrap();
+
+    // Same content, hex casing aside, needs no upload
+    let (status, body) = post(
+        "/backup/upload_url",
+        json!({ "backup_version": 1, "checksum": checksum.to_uppercase() }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let res: UploadUrlResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(res, UploadUrlResponse::Unchanged);
+
+    let (recent, latest_version): (bool, Option<i32>) = sqlx::query_as(
+        "SELECT last_backup_at > now() - interval '1 minute', latest_backup_version
+         FROM backup_settings
+         WHERE pubkey = $1",
+    )
+    .bind(user.pubkey().to_string())
+    .fetch_one(&app_state.db_pool)
+    .await
+    .unwrap();
+    assert!(recent);
+    assert_eq!(latest_version, Some(1));
+
+    // Changed content gets an upload URL, or fails presigning without AWS credentials in CI
+    let (status, body) = post(
+        "/backup/upload_url",
+        json!({ "backup_version": 1, "checksum": "cd".repeat(32) }),
+    )
+    .await;
+    if status == StatusCode::OK {
+        let res: UploadUrlResponse = serde_json::from_slice(&body).unwrap();
+        assert!(matches!(res, UploadUrlResponse::Upload { .. }));
+    } else {
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    // Another version has no stored checksum to match
+    let (status, body) = post(
+        "/backup/upload_url",
+        json!({ "backup_version": 2, "checksum": checksum }),
+    )
+    .await;
+    if status == StatusCode::OK {
+        let res: UploadUrlResponse = serde_json::from_slice(&body).unwrap();
+        assert!(matches!(res, UploadUrlResponse::Upload { .. }));
+    } else {
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    let (status, _) = post(
+        "/backup/upload_url",
+        json!({ "backup_version": 1, "checksum": "not-a-hash" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_reupload_without_checksum_clears_stored_checksum() {
+    let (_app, app_state, _guard) = setup_test_app().await;
+    let user = TestUser::new();
+    create_test_user(&app_state, &user, None).await;
+    let pubkey = user.pubkey().to_string();
+    let s3_key = format!("{}/backup_v1.db", pubkey);
+    let checksum = "ab".repeat(32);
+
+    let mut tx = app_state.db_pool.begin().await.unwrap();
+    BackupRepository::upsert_metadata_tx(&mut tx, &pubkey, &s3_key, 1024, 1, Some(&checksum))
+        .await
+        .unwrap();
+    tx.commit().await.unwrap();
+
+    let backup_repo = BackupRepository::new(&app_state.db_pool);
+    assert_eq!(
+        backup_repo
+            .find_checksum_by_version(&pubkey, 1)
+            .await
+            .unwrap(),
+        Some(checksum)
+    );
+
+    backup_repo
+        .upsert_metadata(&pubkey, &s3_key, 2048, 1)
+        .await
+        .unwrap();
+    assert_eq!(
+        backup_repo
+            .find_checksum_by_version(&pubkey, 1)
+            .await
+            .unwrap(),
+        None
+    );
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_backup_version_outside_rolling_range_is_rejected() {
+    let (app, app_state, _guard) = setup_test_app().await;
+    let user = TestUser::new();
+    create_test_user(&app_state, &user, None).await;
+    let access_token = user.access_token(&app_state);
+
+    let post = |uri: &'static str, body: serde_json::Value| {
+        let app = app.clone();
+        let access_token = access_token.clone();
+        async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .method(http::Method::POST)
+                        .uri(uri)
+                        .header(http::header::CONTENT_TYPE, "application/json")
+                        .header(
+                            http::header::AUTHORIZATION,
+                            format!("Bearer {}", access_token),
+                        )
+                        .body(Body::from(serde_json::to_vec(&body).unwrap()))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            response.status()
+        }
+    };
+
+    for version in [0, 3, 9999] {
+        let status = post("/backup/upload_url", json!({ "backup_version": version })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "version {}", version);
+
+        let status = post(
+            "/backup/complete_upload",
+            json!({
+                "s3_key": format!("{}/backup_v{}.db", user.pubkey(), version),
+                "backup_version": version,
+                "backup_size": 1024
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "version {}", version);
+    }
+
+    let status = post(
+        "/backup/complete_upload",
+        json!({
+            "s3_key": format!("{}/backup_v2.db", user.pubkey()),
+            "backup_version": 2,
+            "backup_size": 1024
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let backups = BackupRepository::new(&app_state.db_pool)
+        .list(&user.pubkey().to_string())
+        .await
+        .unwrap();
+    assert_eq!(backups.len(), 1);
+    assert_eq!(backups[0].backup_version, 2);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_max_backup_version_is_configurable() {
+    let mut config = TestUser::get_config();
+    config.max_backup_version = 3;
+    let (app, app_state, _guard) = setup_test_app_with_config(config).await;
+    let user = TestUser::new();
+    create_test_user(&app_state, &user, None).await;
+    let access_token = user.access_token(&app_state);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/backup/complete_upload")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .header(
+                    http::header::AUTHORIZATION,
+                    format!("Bearer {}", access_token),
+                )
+                .body(Body::from(
+                    serde_json::to_vec(&json!({
+                        "s3_key": format!("{}/backup_v3.db", user.pubkey()),
+                        "backup_version": 3,
+                        "backup_size": 1024
+                    }))
+                    .unwrap(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_complete_upload_rejects_backup_over_byte_quota() {
+    let mut config = TestUser::get_config();
+    config.max_total_backup_bytes_per_user = Some(1500);
+    let (app, app_state, _guard) = setup_test_app_with_config(config).await;
+    let user = TestUser::new();
+    create_test_user(&app_state, &user, None).await;
+    let access_token = user.access_token(&app_state);
+
+    let complete_upload = |backup_version: i32, backup_size: u64| {
+        let app = app.clone();
+        let access_token = access_token.clone();
+        let s3_key = format!("{}/backup_v{}.db", user.pubkey(), backup_version);
+        async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .method(http::Method::POST)
+                        .uri("/backup/complete_upload")
+                        .header(http::header::CONTENT_TYPE, "application/json")
+                        .header(
+                            http::header::AUTHORIZATION,
+                            format!("Bearer {}", access_token),
+                        )
+                        .body(Body::from(
+                            serde_json::to_vec(&json!({
+                                "s3_key": s3_key,
+                                "backup_version": backup_version,
+                                "backup_size": backup_size
+                            }))
+                            .unwrap(),
+                        ))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            (
+                status,
+                serde_json::from_slice::<serde_json::Value>(&body).ok(),
+            )
+        }
+    };
+
+    let (status, _) = complete_upload(1, 1024).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = complete_upload(2, 1024).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let body = body.unwrap();
+    assert_eq!(body["code"], "QUOTA_EXCEEDED");
+    assert!(
+        body["message"]
+            .as_str()
+            .unwrap()
+            .contains("1024 of 1500 bytes used"),
+        "{}",
+        body
+    );
+
+    // Replacing an existing version only counts the new size
+    let (status, _) = complete_upload(1, 1400).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let backups = BackupRepository::new(&app_state.db_pool)
+        .list(&user.pubkey().to_string())
+        .await
+        .unwrap();
+    assert_eq!(backups.len(), 1);
+    assert_eq!(backups[0].backup_size, 1400);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_complete_upload_deletes_backup_rejected_by_quota() {
+    let mut config = TestUser::get_config();
+    config.max_total_backup_bytes_per_user = Some(1500);
+    let (app, app_state, _guard) = setup_test_app_with_config(config).await;
+    let user = TestUser::new();
+    create_test_user(&app_state, &user, None).await;
+    let access_token = user.access_token(&app_state);
+    let pubkey = user.pubkey().to_string();
+
+    let s3_client = S3BackupClient::from_config(&app_state.config)
+        .await
+        .unwrap();
+    let accepted_key = s3_client.backup_key(&pubkey, 1);
+    BackupRepository::new(&app_state.db_pool)
+        .upsert_metadata(&pubkey, &accepted_key, 1024, 1)
+        .await
+        .unwrap();
+
+    // Note: S3 is only reachable with proper AWS credentials, the upload fails without them
+    let s3_key = s3_client.backup_key(&pubkey, 2);
+    let upload_url = s3_client.generate_upload_url(&s3_key).await.unwrap();
+    let uploaded = reqwest::Client::new()
+        .put(upload_url)
+        .body("over quota")
+        .send()
+        .await
+        .is_ok_and(|response| response.status().is_success());
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/backup/complete_upload")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .header(
+                    http::header::AUTHORIZATION,
+                    format!("Bearer {}", access_token),
+                )
+                .body(Body::from(
+                    serde_json::to_vec(&json!({
+                        "s3_key": s3_key,
+                        "backup_version": 2,
+                        "backup_size": 1024
+                    }))
+                    .unwrap(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    if uploaded {
+        assert!(!s3_client.object_exists(&s3_key).await.unwrap());
+    }
+    let backups = BackupRepository::new(&app_state.db_pool)
+        .list(&pubkey)
+        .await
+        .unwrap();
+    assert_eq!(backups.len(), 1);
+    assert_eq!(backups[0].backup_version, 1);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_backup_usage_sums_all_versions() {
+    let mut config = TestUser::get_config();
+    config.max_backup_version = 3;
+    config.max_backups_per_user = Some(5);
+    config.max_total_backup_bytes_per_user = Some(10_000);
+    let (app, app_state, _guard) = setup_test_app_with_config(config).await;
+    let user = TestUser::new();
+    create_test_user(&app_state, &user, None).await;
+    let access_token = user.access_token(&app_state);
+    let pubkey = user.pubkey().to_string();
+
+    let backup_repo = BackupRepository::new(&app_state.db_pool);
+    for (version, size, created_at) in [
+        (1, 1000, "2024-01-01T00:00:00Z"),
+        (2, 2000, "2024-02-01T00:00:00Z"),
+        (3, 3000, "2024-03-01T00:00:00Z"),
+    ] {
+        backup_repo
+            .upsert_metadata_with_timestamp(
+                &pubkey,
+                &format!("{}/backup_v{}.db", pubkey, version),
+                size,
+                version,
+                created_at,
+            )
+            .await
+            .unwrap();
+    }
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/backup/usage")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .header(
+                    http::header::AUTHORIZATION,
+                    format!("Bearer {}", access_token),
+                )
+                .body(Body::from(serde_json::to_vec(&json!({})).unwrap()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let res: BackupUsageResponse = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(res.total_bytes, 6000);
+    assert_eq!(res.backup_count, 3);
+    assert_eq!(
+        res.oldest_backup_at
+            .map(|at| chrono::DateTime::parse_from_rfc3339(&at).unwrap()),
+        Some(chrono::DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z").unwrap())
+    );
+    assert_eq!(
+        res.newest_backup_at
+            .map(|at| chrono::DateTime::parse_from_rfc3339(&at).unwrap()),
+        Some(chrono::DateTime::parse_from_rfc3339("2024-03-01T00:00:00Z").unwrap())
+    );
+    assert_eq!(res.remaining_bytes, Some(4000));
+    assert_eq!(res.remaining_backups, Some(2));
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_latest_backup_pointer_wins_over_timestamps() {
+    let (app, app_state, _guard) = setup_test_app().await;
+    let user = TestUser::new();
+    create_test_user(&app_state, &user, None).await;
+    let access_token = user.access_token(&app_state);
+    let pubkey = user.pubkey().to_string();
+
+    let post = |uri: &'static str, body: serde_json::Value| {
+        let app = app.clone();
+        let access_token = access_token.clone();
+        async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .method(http::Method::POST)
+                        .uri(uri)
+                        .header(http::header::CONTENT_TYPE, "application/json")
+                        .header(
+                            http::header::AUTHORIZATION,
+                            format!("Bearer {}", access_token),
+                        )
+                        .body(Body::from(serde_json::to_vec(&body).unwrap()))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            (status, body)
+        }
+    };
+
+    let (status, body) = post("/backup/exists", json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    let res: BackupExistsResponse = serde_json::from_slice(&body).unwrap();
+    assert!(!res.exists);
+    assert_eq!(res.latest_version, None);
+
+    // Version 1 is completed last, so it's the latest
+    for version in [2, 1] {
+        let (status, _) = post(
+            "/backup/complete_upload",
+            json!({
+                "s3_key": format!("{}/backup_v{}.db", pubkey, version),
+                "backup_version": version,
+                "backup_size": 1024
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    // Skewed clocks leave version 2 a millisecond newer by timestamp
+    let backup_repo = BackupRepository::new(&app_state.db_pool);
+    backup_repo
+        .upsert_metadata_with_timestamp(
+            &pubkey,
+            &format!("{}/backup_v1.db", pubkey),
+            1024,
+            1,
+            "2024-01-01T00:00:00.000Z",
+        )
+        .await
+        .unwrap();
+    backup_repo
+        .upsert_metadata_with_timestamp(
+            &pubkey,
+            &format!("{}/backup_v2.db", pubkey),
+            1024,
+            2,
+            "2024-01-01T00:00:00.001Z",
+        )
+        .await
+        .unwrap();
+
+    let (s3_key, _) = backup_repo.find_latest(&pubkey).await.unwrap().unwrap();
+    assert_eq!(s3_key, format!("{}/backup_v1.db", pubkey));
+
+    let (status, body) = post("/backup/exists", json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    let res: BackupExistsResponse = serde_json::from_slice(&body).unwrap();
+    assert!(res.exists);
+    assert_eq!(res.latest_version, Some(1));
+}
+
 #[tracing_test::traced_test]
 #[tokio::test]
 async fn test_complete_upload_upsert() {
@@ -572,3 +1236,111 @@ async fn test_update_backup_settings_disable() {
         .unwrap();
     assert!(!backup_enabled);
 }
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_complete_upload_advances_last_backup_at() {
+    let (app, app_state, _guard) = setup_test_app().await;
+    let user = TestUser::new();
+    create_test_user(&app_state, &user, None).await;
+    let access_token = user.access_token(&app_state);
+    let pubkey = user.pubkey().to_string();
+
+    let backup_repo = BackupRepository::new(&app_state.db_pool);
+    backup_repo.upsert_settings(&pubkey, true).await.unwrap();
+    let previous_backup_at = chrono::Utc::now() - chrono::Duration::days(3);
+    sqlx::query("UPDATE backup_settings SET last_backup_at = $1 WHERE pubkey = $2")
+        .bind(previous_backup_at)
+        .bind(&pubkey)
+        .execute(&app_state.db_pool)
+        .await
+        .unwrap();
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/backup/complete_upload")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .header(
+                    http::header::AUTHORIZATION,
+                    format!("Bearer {}", access_token),
+                )
+                .body(Body::from(
+                    serde_json::to_vec(&json!({
+                        "s3_key": format!("{}/backup_v1.db", pubkey),
+                        "backup_version": 1,
+                        "backup_size": 1024
+                    }))
+                    .unwrap(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let last_backup_at = backup_repo
+        .get_last_backup_at(&pubkey)
+        .await
+        .unwrap()
+        .expect("last_backup_at should be set");
+    assert!(last_backup_at > previous_backup_at);
+
+    // Completing an upload must not change the backup toggle
+    assert_eq!(backup_repo.get_settings(&pubkey).await.unwrap(), Some(true));
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_concurrent_uploads_cannot_both_exceed_backup_quota() {
+    let mut config = TestUser::get_config();
+    config.max_backups_per_user = Some(1);
+    let (app, app_state, _guard) = setup_test_app_with_config(config).await;
+    let user = TestUser::new_with_key(&[0x71; 32]);
+    create_test_user(&app_state, &user, None).await;
+    let access_token = user.access_token(&app_state);
+
+    let complete_upload = |backup_version: i32| {
+        let app = app.clone();
+        let access_token = access_token.clone();
+        let s3_key = format!("{}/backup_v{}.db", user.pubkey(), backup_version);
+        async move {
+            app.oneshot(
+                Request::builder()
+                    .method(http::Method::POST)
+                    .uri("/backup/complete_upload")
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .header(
+                        http::header::AUTHORIZATION,
+                        format!("Bearer {}", access_token),
+                    )
+                    .body(Body::from(
+                        serde_json::to_vec(&json!({
+                            "s3_key": s3_key,
+                            "backup_version": backup_version,
+                            "backup_size": 1024
+                        }))
+                        .unwrap(),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+            .status()
+        }
+    };
+
+    // Both uploads read the usage before either commits unless the check is serialized
+    let (first, second) = tokio::join!(complete_upload(1), complete_upload(2));
+    let mut statuses = [first, second];
+    statuses.sort();
+    assert_eq!(statuses, [StatusCode::OK, StatusCode::FORBIDDEN]);
+
+    let backups = BackupRepository::new(&app_state.db_pool)
+        .list(&user.pubkey().to_string())
+        .await
+        .unwrap();
+    assert_eq!(backups.len(), 1);
+}
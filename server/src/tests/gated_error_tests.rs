@@ -1,10 +1,15 @@
+use std::time::Duration;
+
+use axum::Router;
 use axum::body::Body;
 use axum::http::{self, Request, StatusCode};
+use axum::routing::get;
 use http_body_util::BodyExt;
 use serde_json::json;
 use tower::ServiceExt;
 
-use crate::tests::common::{TestUser, create_test_user, setup_test_app};
+use crate::app_middleware::{lnurlp_timeout_layer, request_timeout_layer};
+use crate::tests::common::{TestUser, create_test_user, setup_public_test_app, setup_test_app};
 
 #[tracing_test::traced_test]
 #[tokio::test]
@@ -345,3 +350,126 @@ async fn test_update_backup_settings_missing_enabled() {
         response.status()
     );
 }
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_slow_handler_times_out_with_gateway_timeout() {
+    let mut config = TestUser::get_config();
+    config.request_timeout_secs = 1;
+
+    let app = Router::new()
+        .route(
+            "/slow",
+            get(|| async {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+                StatusCode::OK
+            }),
+        )
+        .route("/fast", get(|| async { StatusCode::OK }))
+        .layer(request_timeout_layer(&config));
+
+    let request = |uri: &str| {
+        Request::builder()
+            .method(http::Method::GET)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let response = app.clone().oneshot(request("/slow")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+
+    let response = app.oneshot(request("/fast")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_lnurlp_route_timeout_outlasts_invoice_wait() {
+    let mut config = TestUser::get_config();
+    config.request_timeout_secs = 1;
+    config.lnurlp_invoice_timeout_secs = 1;
+    assert!(config.lnurlp_route_timeout() > config.lnurlp_invoice_timeout());
+
+    // Stands in for a handler that runs until its own invoice timeout, plus some slack
+    let long_poll = || async {
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        StatusCode::OK
+    };
+    let app = Router::new()
+        .route("/lnurlp", get(long_poll))
+        .layer(lnurlp_timeout_layer(&config))
+        .merge(
+            Router::new()
+                .route("/other", get(long_poll))
+                .layer(request_timeout_layer(&config)),
+        );
+
+    let request = |uri: &str| {
+        Request::builder()
+            .method(http::Method::GET)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let response = app.clone().oneshot(request("/lnurlp")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app.oneshot(request("/other")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_unknown_route_returns_json_not_found() {
+    let (app, _app_state, _guard) = setup_public_test_app().await;
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::GET)
+                .uri("/does/not/exist")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(
+        response.headers().get(http::header::CONTENT_TYPE).unwrap(),
+        "application/json"
+    );
+
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["status"], "ERROR");
+    assert_eq!(body["code"], "ROUTE_NOT_FOUND");
+    assert_eq!(body["message"], "Route not found");
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_wrong_method_returns_json_with_allow_header() {
+    let (app, _app_state, _guard) = setup_test_app().await;
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::GET)
+                .uri("/user_info")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(response.headers().get(http::header::ALLOW).unwrap(), "POST");
+
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["status"], "ERROR");
+    assert_eq!(body["code"], "METHOD_NOT_ALLOWED");
+}
@@ -4,8 +4,17 @@ use http_body_util::BodyExt;
 use serde_json::json;
 use tower::ServiceExt;
 
-use crate::tests::common::{TestUser, setup_test_app};
+use bitcoin::hashes::{Hash, sha256};
+use bitcoin::secp256k1::{Secp256k1, SecretKey};
+use lightning_invoice::{Currency, InvoiceBuilder, PaymentSecret};
+
+use crate::db::abuse_report_repo::AbuseReportRepository;
+use crate::routes::public_api_v0::{
+    effective_lnurlp_daily_cap, lnurlp_metadata, lnurlp_metadata_hash,
+};
+use crate::tests::common::{TestUser, setup_test_app, setup_test_app_with_config};
 use crate::types::DefaultSuccessPayload;
+use crate::utils::bolt11_description_hash;
 
 #[tracing_test::traced_test]
 #[tokio::test]
@@ -241,3 +250,235 @@ async fn test_submit_invoice_overwrites_existing() {
 
     assert_eq!(stored_invoice, Some(second_invoice.to_string()));
 }
+
+/// Builds a signed BOLT11 invoice that commits to a description hash, or to a plain description.
+fn invoice_with_description_hash(description_hash: Option<sha256::Hash>) -> String {
+    let secp = Secp256k1::new();
+    let key = SecretKey::from_slice(&[0x42; 32]).unwrap();
+    let builder = InvoiceBuilder::new(Currency::Bitcoin);
+    let builder = match description_hash {
+        Some(hash) => builder.description_hash(hash),
+        None => builder.description("test".to_string()),
+    };
+    builder
+        .amount_milli_satoshis(1_000_000)
+        .payment_hash(sha256::Hash::hash(b"payment"))
+        .payment_secret(PaymentSecret([0x11; 32]))
+        .current_timestamp()
+        .min_final_cltv_expiry_delta(144)
+        .build_signed(|hash| secp.sign_ecdsa_recoverable(hash, &key))
+        .unwrap()
+        .to_string()
+}
+
+#[test]
+fn test_bolt11_description_hash_is_read_from_invoice() {
+    let metadata = lnurlp_metadata("test@localhost", None, false);
+    let hash = lnurlp_metadata_hash(&metadata);
+    assert_eq!(hash, sha256::Hash::hash(metadata.as_bytes()));
+
+    let invoice = invoice_with_description_hash(Some(hash));
+    assert_eq!(bolt11_description_hash(&invoice).unwrap(), Some(hash));
+    assert_eq!(
+        bolt11_description_hash(&invoice.to_uppercase()).unwrap(),
+        Some(hash)
+    );
+
+    let invoice = invoice_with_description_hash(None);
+    assert_eq!(bolt11_description_hash(&invoice).unwrap(), None);
+
+    // Test vector from the BOLT11 spec
+    let invoice = "lnbc20m1pvjluezpp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqhp58yjmdan79s6qqdhdzgynm4zwqd5d7xmw5fk98klysy043l2ahrqscc6gd6ql3jrc5yzme8v4ntcewwz5cnw92tz0pc8qcuufvq7khhr8wpald05e92xw006sq94mg8v2ndf4sefvf9sygkshp5zfem29trqq2yxxz7";
+    assert_eq!(
+        bolt11_description_hash(invoice)
+            .unwrap()
+            .unwrap()
+            .to_string(),
+        "3925b6f67e2c340036ed12093dd44e0368df1b6ea26c53dbe4811f58fd5db8c1"
+    );
+
+    assert!(bolt11_description_hash("lnbc1000n1test_invoice_data").is_err());
+}
+
+async fn submit_invoice(
+    app: &axum::Router,
+    access_token: &str,
+    transaction_id: &str,
+    invoice: &str,
+) -> StatusCode {
+    app.clone()
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/lnurlp/submit_invoice")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .header(
+                    http::header::AUTHORIZATION,
+                    format!("Bearer {}", access_token),
+                )
+                .body(Body::from(
+                    serde_json::to_vec(&json!({
+                        "transaction_id": transaction_id,
+                        "invoice": invoice
+                    }))
+                    .unwrap(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+        .status()
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_submit_invoice_verifies_description_hash() {
+    let mut config = TestUser::get_config();
+    config.lnurlp_verify_description_hash = true;
+    let (app, app_state, _guard) = setup_test_app_with_config(config).await;
+
+    let user = TestUser::new();
+    let access_token = user.access_token(&app_state);
+
+    sqlx::query("INSERT INTO users (pubkey, lightning_address) VALUES ($1, $2)")
+        .bind(user.pubkey().to_string())
+        .bind("test@localhost")
+        .execute(&app_state.db_pool)
+        .await
+        .unwrap();
+
+    let expected = lnurlp_metadata_hash(&lnurlp_metadata("test@localhost", None, false));
+    let transaction_id = "test-transaction-description-hash";
+    app_state
+        .invoice_store
+        .expect_description_hash(transaction_id, &expected.to_string(), 60)
+        .await
+        .unwrap();
+
+    let other = lnurlp_metadata_hash(&lnurlp_metadata("other@localhost", None, false));
+    let mismatching = invoice_with_description_hash(Some(other));
+    assert_eq!(
+        submit_invoice(&app, &access_token, transaction_id, &mismatching).await,
+        StatusCode::BAD_REQUEST
+    );
+    let missing = invoice_with_description_hash(None);
+    assert_eq!(
+        submit_invoice(&app, &access_token, transaction_id, &missing).await,
+        StatusCode::BAD_REQUEST
+    );
+    assert_eq!(
+        app_state.invoice_store.get(transaction_id).await.unwrap(),
+        None
+    );
+
+    let matching = invoice_with_description_hash(Some(expected));
+    assert_eq!(
+        submit_invoice(&app, &access_token, transaction_id, &matching).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        app_state.invoice_store.get(transaction_id).await.unwrap(),
+        Some(matching)
+    );
+}
+
+async fn report_abuse(app: &axum::Router, access_token: &str, reason: &str) -> StatusCode {
+    app.clone()
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/report_abuse")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .header(
+                    http::header::AUTHORIZATION,
+                    format!("Bearer {}", access_token),
+                )
+                .body(Body::from(json!({ "reason": reason }).to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+        .status()
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_report_abuse_lowers_daily_cap() {
+    let mut config = TestUser::get_config();
+    config.lnurlp_daily_request_cap = 100;
+    config.lnurlp_reported_daily_request_cap = 5;
+    let (app, app_state, _guard) = setup_test_app_with_config(config).await;
+
+    let user = TestUser::new();
+    let pubkey = user.pubkey().to_string();
+    let access_token = user.access_token(&app_state);
+
+    sqlx::query("INSERT INTO users (pubkey, lightning_address) VALUES ($1, $2)")
+        .bind(&pubkey)
+        .bind("test@localhost")
+        .execute(&app_state.db_pool)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        effective_lnurlp_daily_cap(&app_state, &pubkey)
+            .await
+            .unwrap(),
+        100
+    );
+
+    assert_eq!(
+        report_abuse(&app, &access_token, "Hundreds of payment requests per hour").await,
+        StatusCode::OK
+    );
+
+    assert_eq!(
+        effective_lnurlp_daily_cap(&app_state, &pubkey)
+            .await
+            .unwrap(),
+        5
+    );
+
+    let reports = AbuseReportRepository::new(&app_state.db_pool)
+        .list_recent(10)
+        .await
+        .unwrap();
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].pubkey, pubkey);
+    assert_eq!(
+        reports[0].reason.as_deref(),
+        Some("Hundreds of payment requests per hour")
+    );
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_report_abuse_is_throttled_per_reporter() {
+    let (app, app_state, _guard) = setup_test_app().await;
+
+    let user = TestUser::new_with_key(&[0x45; 32]);
+    let access_token = user.access_token(&app_state);
+
+    sqlx::query("INSERT INTO users (pubkey, lightning_address) VALUES ($1, $2)")
+        .bind(user.pubkey().to_string())
+        .bind("reporter@localhost")
+        .execute(&app_state.db_pool)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        report_abuse(&app, &access_token, "First report").await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        report_abuse(&app, &access_token, "Second report").await,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+
+    let reports = AbuseReportRepository::new(&app_state.db_pool)
+        .list_recent(10)
+        .await
+        .unwrap();
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].reason.as_deref(), Some("First report"));
+}
@@ -0,0 +1,88 @@
+use axum::body::Body;
+use axum::http::{self, Request, StatusCode};
+use http_body_util::BodyExt;
+use serde_json::json;
+use tower::ServiceExt;
+
+use crate::tests::common::{TestUser, create_test_user, setup_test_app};
+use crate::types::VerifyOffboardingSignatureResponse;
+
+const REGTEST_ADDRESS: &str = "bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080";
+const MAINNET_ADDRESS: &str = "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4";
+
+async fn post_verify_signature(
+    app: axum::Router,
+    access_token: &str,
+    address: &str,
+    address_signature: &str,
+) -> axum::response::Response {
+    app.oneshot(
+        Request::builder()
+            .method(http::Method::POST)
+            .uri("/offboarding/verify_signature")
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .header(
+                http::header::AUTHORIZATION,
+                format!("Bearer {}", access_token),
+            )
+            .body(Body::from(
+                serde_json::to_vec(&json!({
+                    "address": address,
+                    "address_signature": address_signature,
+                }))
+                .unwrap(),
+            ))
+            .unwrap(),
+    )
+    .await
+    .unwrap()
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_verify_offboarding_signature_valid() {
+    let (app, app_state, _guard) = setup_test_app().await;
+    let user = TestUser::new();
+    create_test_user(&app_state, &user, None).await;
+    let access_token = user.access_token(&app_state);
+
+    let signature = user.sign_message(REGTEST_ADDRESS);
+    let response = post_verify_signature(app, &access_token, REGTEST_ADDRESS, &signature).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let res: VerifyOffboardingSignatureResponse = serde_json::from_slice(&body).unwrap();
+    assert!(res.valid);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_verify_offboarding_signature_from_other_key_is_invalid() {
+    let (app, app_state, _guard) = setup_test_app().await;
+    let user = TestUser::new();
+    create_test_user(&app_state, &user, None).await;
+    let access_token = user.access_token(&app_state);
+
+    let other_user = TestUser::new_with_key(&[0xab; 32]);
+    let signature = other_user.sign_message(REGTEST_ADDRESS);
+    let response = post_verify_signature(app, &access_token, REGTEST_ADDRESS, &signature).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let res: VerifyOffboardingSignatureResponse = serde_json::from_slice(&body).unwrap();
+    assert!(!res.valid);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_verify_offboarding_signature_rejects_wrong_network_address() {
+    let (app, app_state, _guard) = setup_test_app().await;
+    let user = TestUser::new();
+    create_test_user(&app_state, &user, None).await;
+    let access_token = user.access_token(&app_state);
+
+    let signature = user.sign_message(MAINNET_ADDRESS);
+    let response = post_verify_signature(app, &access_token, MAINNET_ADDRESS, &signature).await;
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
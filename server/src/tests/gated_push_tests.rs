@@ -0,0 +1,429 @@
+use axum::Router;
+use axum::body::Body;
+use axum::http::{self, Request, StatusCode};
+use expo_push_notification_client::{ExpoPushTicket, Priority};
+use http_body_util::BodyExt;
+use serde_json::json;
+use tower::ServiceExt;
+
+use crate::AppState;
+use crate::config::TokenPruneSettings;
+use crate::db::device_repo::DeviceRepository;
+use crate::db::push_token_repo::PushTokenRepository;
+use crate::push::{
+    PushNotificationData, PushSendSummary, handle_expo_tickets, send_push_notification,
+    send_push_notification_with_unique_k1,
+};
+use crate::tests::common::{TestUser, create_test_user, setup_test_app};
+use crate::types::{DeviceInfo, NotificationRequestData, PushTokenInfo};
+
+async fn post_json(
+    app: &Router,
+    app_state: &AppState,
+    user: &TestUser,
+    uri: &str,
+    body: serde_json::Value,
+) -> (StatusCode, Vec<u8>) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri(uri)
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .header(
+                    http::header::AUTHORIZATION,
+                    format!("Bearer {}", user.access_token(app_state)),
+                )
+                .body(Body::from(serde_json::to_vec(&body).unwrap()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, body.to_vec())
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_list_push_tokens_masks_tokens() {
+    let (app, app_state, _guard) = setup_test_app().await;
+    let user = TestUser::new();
+    create_test_user(&app_state, &user, None).await;
+
+    let (status, body) = post_json(&app, &app_state, &user, "/push/list", json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    let tokens: Vec<PushTokenInfo> = serde_json::from_slice(&body).unwrap();
+    assert!(tokens.is_empty());
+
+    PushTokenRepository::new(&app_state.db_pool)
+        .upsert(
+            &user.pubkey().to_string(),
+            "ExponentPushToken[abcdefghijklmn]",
+        )
+        .await
+        .unwrap();
+
+    let (status, body) = post_json(&app, &app_state, &user, "/push/list", json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    let tokens: Vec<PushTokenInfo> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(tokens.len(), 1);
+    assert_eq!(tokens[0].masked_token, "****jklmn]");
+    assert!(!tokens[0].created_at.is_empty());
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_revoke_push_token() {
+    let (app, app_state, _guard) = setup_test_app().await;
+    let user = TestUser::new();
+    let other_user = TestUser::new_with_key(&[0xab; 32]);
+    create_test_user(&app_state, &user, None).await;
+    sqlx::query("INSERT INTO users (pubkey, lightning_address) VALUES ($1, $2)")
+        .bind(other_user.pubkey().to_string())
+        .bind("other@localhost")
+        .execute(&app_state.db_pool)
+        .await
+        .unwrap();
+
+    let push_token_repo = PushTokenRepository::new(&app_state.db_pool);
+    let pubkey = user.pubkey().to_string();
+    let other_pubkey = other_user.pubkey().to_string();
+    push_token_repo
+        .upsert(&pubkey, "ExponentPushToken[user]")
+        .await
+        .unwrap();
+    push_token_repo
+        .upsert(&other_pubkey, "ExponentPushToken[other]")
+        .await
+        .unwrap();
+    let token_id = push_token_repo.list_by_pubkey(&pubkey).await.unwrap()[0].id;
+    let other_token_id = push_token_repo.list_by_pubkey(&other_pubkey).await.unwrap()[0].id;
+
+    // Another user's token can't be revoked
+    let (status, _) = post_json(
+        &app,
+        &app_state,
+        &user,
+        "/push/revoke",
+        json!({ "id": other_token_id }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(
+        push_token_repo
+            .find_by_pubkey(&other_pubkey)
+            .await
+            .unwrap()
+            .is_some()
+    );
+
+    let (status, _) = post_json(
+        &app,
+        &app_state,
+        &user,
+        "/push/revoke",
+        json!({ "id": token_id }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(
+        push_token_repo
+            .find_by_pubkey(&pubkey)
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    // Revoking again reports the token as gone
+    let (status, _) = post_json(
+        &app,
+        &app_state,
+        &user,
+        "/push/revoke",
+        json!({ "id": token_id }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_push_tokens_are_linked_to_devices() {
+    let (app, app_state, _guard) = setup_test_app().await;
+    let user = TestUser::new();
+    let pubkey = user.pubkey().to_string();
+    create_test_user(&app_state, &user, None).await;
+
+    let push_token_repo = PushTokenRepository::new(&app_state.db_pool);
+    push_token_repo
+        .upsert(&pubkey, "ExponentPushToken[device]")
+        .await
+        .unwrap();
+
+    // Registered before any device info, so there is nothing to link yet
+    let records = push_token_repo.list_by_pubkey(&pubkey).await.unwrap();
+    assert_eq!(records[0].device_id, None);
+
+    let mut tx = app_state.db_pool.begin().await.unwrap();
+    DeviceRepository::upsert(
+        &mut tx,
+        &pubkey,
+        &DeviceInfo {
+            device_manufacturer: Some("Apple".to_string()),
+            device_model: Some("iPhone 14".to_string()),
+            os_name: Some("iOS".to_string()),
+            os_version: Some("18.1".to_string()),
+            app_version: Some("0.1.0".to_string()),
+        },
+    )
+    .await
+    .unwrap();
+    tx.commit().await.unwrap();
+
+    let device_id = sqlx::query_scalar::<_, i64>("SELECT id FROM devices WHERE pubkey = $1")
+        .bind(&pubkey)
+        .fetch_one(&app_state.db_pool)
+        .await
+        .unwrap();
+    let records = push_token_repo.list_by_pubkey(&pubkey).await.unwrap();
+    assert_eq!(records[0].device_id, Some(device_id));
+
+    // Re-registering the token keeps the association
+    push_token_repo
+        .upsert(&pubkey, "ExponentPushToken[rotated]")
+        .await
+        .unwrap();
+
+    let (status, body) = post_json(&app, &app_state, &user, "/push/list", json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    let tokens: Vec<PushTokenInfo> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(tokens.len(), 1);
+    assert_eq!(tokens[0].device_model.as_deref(), Some("iPhone 14"));
+    assert_eq!(tokens[0].os_name.as_deref(), Some("iOS"));
+    assert_eq!(tokens[0].os_version.as_deref(), Some("18.1"));
+    assert!(tokens[0].last_seen_at.is_some());
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_device_not_registered_deletes_after_threshold() {
+    let (_app, app_state, _guard) = setup_test_app().await;
+    let user = TestUser::new();
+    let pubkey = user.pubkey().to_string();
+    create_test_user(&app_state, &user, None).await;
+
+    let settings = TokenPruneSettings {
+        threshold: 3,
+        window_secs: 3600,
+    };
+    let push_token = "ExponentPushToken[flaky]";
+    let push_token_repo = PushTokenRepository::new(&app_state.db_pool);
+    push_token_repo.upsert(&pubkey, push_token).await.unwrap();
+
+    // A single transient failure keeps the token, and a delivery resets the streak
+    for _ in 0..2 {
+        assert!(
+            !push_token_repo
+                .record_not_registered(push_token, &settings)
+                .await
+                .unwrap()
+        );
+    }
+    push_token_repo
+        .clear_not_registered(&[push_token.to_string()])
+        .await
+        .unwrap();
+    assert!(
+        !push_token_repo
+            .record_not_registered(push_token, &settings)
+            .await
+            .unwrap()
+    );
+    assert!(
+        push_token_repo
+            .find_by_pubkey(&pubkey)
+            .await
+            .unwrap()
+            .is_some()
+    );
+
+    // Failures outside the window start a new streak
+    sqlx::query(
+        "UPDATE push_tokens SET first_not_registered_at = now() - interval '2 hours'
+         WHERE push_token = $1",
+    )
+    .bind(push_token)
+    .execute(&app_state.db_pool)
+    .await
+    .unwrap();
+    assert!(
+        !push_token_repo
+            .record_not_registered(push_token, &settings)
+            .await
+            .unwrap()
+    );
+    assert!(
+        !push_token_repo
+            .record_not_registered(push_token, &settings)
+            .await
+            .unwrap()
+    );
+
+    // The third consecutive failure within the window deletes the token
+    assert!(
+        push_token_repo
+            .record_not_registered(push_token, &settings)
+            .await
+            .unwrap()
+    );
+    assert!(
+        push_token_repo
+            .find_by_pubkey(&pubkey)
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_expo_tickets_are_handled_per_message() {
+    let (_app, app_state, _guard) = setup_test_app().await;
+    let push_token_repo = PushTokenRepository::new(&app_state.db_pool);
+
+    let delivered_user = TestUser::new();
+    let unregistered_user = TestUser::new_with_key(&[0xab; 32]);
+    create_test_user(&app_state, &delivered_user, None).await;
+    create_test_user(&app_state, &unregistered_user, None).await;
+
+    let delivered_token = "ExponentPushToken[delivered]".to_string();
+    let unregistered_token = "ExponentPushToken[unregistered]".to_string();
+    push_token_repo
+        .upsert(&delivered_user.pubkey().to_string(), &delivered_token)
+        .await
+        .unwrap();
+    push_token_repo
+        .upsert(&unregistered_user.pubkey().to_string(), &unregistered_token)
+        .await
+        .unwrap();
+
+    // The delivered token carries a failure streak that a successful ticket should reset
+    let settings = app_state.config.token_prune_settings();
+    push_token_repo
+        .record_not_registered(&delivered_token, &settings)
+        .await
+        .unwrap();
+
+    // One batch, one ticket per message in the same order as the tokens
+    let tickets: Vec<ExpoPushTicket> = serde_json::from_value(json!([
+        { "status": "ok", "id": "ticket-1" },
+        {
+            "status": "error",
+            "message": "\"ExponentPushToken[unregistered]\" is not a registered push notification recipient",
+            "details": { "error": "DeviceNotRegistered" }
+        }
+    ]))
+    .unwrap();
+
+    let summary = handle_expo_tickets(
+        &app_state,
+        &[delivered_token.clone(), unregistered_token.clone()],
+        &tickets,
+    )
+    .await;
+    assert_eq!(
+        summary,
+        PushSendSummary {
+            delivered: 1,
+            failed: 1,
+            not_registered: 1,
+            pruned_tokens: 0,
+        }
+    );
+
+    let not_registered_count = |push_token: String| {
+        let pool = app_state.db_pool.clone();
+        async move {
+            sqlx::query_scalar::<_, i32>(
+                "SELECT not_registered_count FROM push_tokens WHERE push_token = $1",
+            )
+            .bind(push_token)
+            .fetch_one(&pool)
+            .await
+            .unwrap()
+        }
+    };
+    assert_eq!(not_registered_count(delivered_token).await, 0);
+    assert_eq!(not_registered_count(unregistered_token).await, 1);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_oversized_push_payload_is_not_sent() {
+    let (_app, app_state, _guard) = setup_test_app().await;
+    let user = TestUser::new();
+    let pubkey = user.pubkey().to_string();
+    create_test_user(&app_state, &user, None).await;
+    PushTokenRepository::new(&app_state.db_pool)
+        .upsert(&pubkey, "ExponentPushToken[oversized]")
+        .await
+        .unwrap();
+
+    let data = PushNotificationData {
+        title: Some("Payment request".to_string()),
+        body: None,
+        data: "x".repeat(app_state.config.push_max_payload_bytes),
+        priority: Priority::High,
+        content_available: true,
+    };
+
+    let summary = send_push_notification(app_state.clone(), data, Some(pubkey))
+        .await
+        .unwrap();
+    assert_eq!(
+        summary,
+        PushSendSummary {
+            failed: 1,
+            ..Default::default()
+        }
+    );
+    assert!(logs_contain("payload is too large"));
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_unique_k1_send_reports_summary() {
+    // Stand-in UnifiedPush endpoint that accepts every delivery
+    let push_endpoint = Router::new().route("/push", axum::routing::post(|| async {}));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, push_endpoint).await.unwrap() });
+
+    let (_app, app_state, _guard) = setup_test_app().await;
+    let user = TestUser::new();
+    let pubkey = user.pubkey().to_string();
+    create_test_user(&app_state, &user, None).await;
+    PushTokenRepository::new(&app_state.db_pool)
+        .upsert(&pubkey, &format!("http://{}/push", addr))
+        .await
+        .unwrap();
+
+    let sent = send_push_notification_with_unique_k1(
+        app_state.clone(),
+        NotificationRequestData::Maintenance,
+        Some(pubkey.clone()),
+    )
+    .await
+    .unwrap();
+    assert_eq!(sent.receipts.len(), 1);
+    assert_eq!(sent.receipts[0].pubkey, pubkey);
+    assert_eq!(
+        sent.summary,
+        PushSendSummary {
+            delivered: 1,
+            ..Default::default()
+        }
+    );
+}
@@ -1,3 +1,6 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
 use axum::body::Body;
 use axum::http::{self, Request, StatusCode};
 use chrono::{Duration, Utc};
@@ -5,14 +8,23 @@ use http_body_util::BodyExt;
 use serde_json::json;
 use tower::ServiceExt;
 
+use crate::config::FeatureFlags;
 use crate::db::backup_repo::BackupRepository;
+use crate::db::feature_flag_repo::FeatureFlagRepository;
 use crate::db::heartbeat_repo::HeartbeatRepository;
 use crate::db::job_status_repo::JobStatusRepository;
+use crate::db::lightning_address_history_repo::LightningAddressHistoryRepository;
 use crate::db::mailbox_authorization_repo::MailboxAuthorizationRepository;
 use crate::db::push_token_repo::PushTokenRepository;
 use crate::db::user_repo::UserRepository;
-use crate::tests::common::{TestUser, create_test_user, setup_test_app};
-use crate::types::UserInfoResponse;
+use crate::routes::gated_api_v0::BACKUP_USAGE_FLAG;
+use crate::tests::common::{
+    TestUser, create_test_user, setup_test_app, setup_test_app_with_config,
+};
+use crate::types::{
+    ApiErrorResponse, FeatureFlagsResponse, INVALID_LN_USERNAME_MESSAGE, LnurlMetadataResponse,
+    LnurlpDefaultResponse, LnurlpInvoiceResponse, LnurlpSuccessAction, UserInfoResponse,
+};
 
 #[tracing_test::traced_test]
 #[tokio::test]
@@ -58,6 +70,47 @@ async fn test_get_user_info() {
     assert_eq!(res.lightning_address, "existing@localhost");
 }
 
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_lnurl_metadata_decodes_to_lnurlp_endpoint() {
+    let (app, app_state, _guard) = setup_test_app().await;
+
+    let user = TestUser::new();
+    create_test_user(&app_state, &user, None).await;
+    let access_token = user.access_token(&app_state);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/lnurl_metadata")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .header(
+                    http::header::AUTHORIZATION,
+                    format!("Bearer {}", access_token),
+                )
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let res: LnurlMetadataResponse = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(res.lightning_address, "test@localhost");
+    assert!(res.lnurl.starts_with("LNURL1"));
+
+    let (hrp, data) = bitcoin::bech32::decode(&res.lnurl).unwrap();
+    assert_eq!(hrp.to_lowercase(), "lnurl");
+    assert_eq!(
+        String::from_utf8(data).unwrap(),
+        "https://localhost/.well-known/lnurlp/test"
+    );
+}
+
 #[tracing_test::traced_test]
 #[tokio::test]
 async fn test_update_ln_address() {
@@ -114,6 +167,353 @@ async fn test_update_ln_address() {
     );
 }
 
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_ln_address_under_accepted_domain_resolves_and_canonicalizes() {
+    let mut config = TestUser::get_config();
+    config.accepted_lnurl_domains = vec!["noah.io".to_string()];
+    let (app, app_state, _guard) = setup_test_app_with_config(config).await;
+
+    // Registered before the domain migration, so still stored under the old domain
+    let legacy_user = TestUser::new_with_key(&[0x51; 32]);
+    let mut tx = app_state.db_pool.begin().await.unwrap();
+    UserRepository::create(
+        &mut tx,
+        &legacy_user.pubkey().to_string(),
+        "legacy@noah.io",
+        Some("ark1legacy"),
+    )
+    .await
+    .unwrap();
+    tx.commit().await.unwrap();
+
+    let lnurlp = |uri: &'static str| {
+        let app = app.clone();
+        async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .method(http::Method::GET)
+                        .uri(uri)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            (
+                status,
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            )
+        }
+    };
+
+    let (status, body) = lnurlp("/.well-known/lnurlp/legacy").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body["callback"],
+        "https://localhost/.well-known/lnurlp/legacy"
+    );
+
+    let (status, body) = lnurlp("/.well-known/lnurlp/legacy?amount=330000&wallet=noahwallet").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["ark"], "ark1legacy");
+
+    let user = TestUser::new();
+    create_test_user(&app_state, &user, None).await;
+    let access_token = user.access_token(&app_state);
+    let update_ln_address = |ln_address: &'static str| {
+        let app = app.clone();
+        let access_token = access_token.clone();
+        async move {
+            app.oneshot(
+                Request::builder()
+                    .method(http::Method::POST)
+                    .uri("/update_ln_address")
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .header(
+                        http::header::AUTHORIZATION,
+                        format!("Bearer {}", access_token),
+                    )
+                    .body(Body::from(
+                        serde_json::to_vec(&json!({ "ln_address": ln_address })).unwrap(),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+            .status()
+        }
+    };
+
+    // Addresses under the old domain are stored under the primary one
+    assert_eq!(update_ln_address("fresh@noah.io").await, StatusCode::OK);
+    let stored = UserRepository::new(&app_state.db_pool)
+        .find_by_pubkey(&user.pubkey().to_string())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        stored.lightning_address,
+        Some("fresh@localhost".to_string())
+    );
+
+    // The legacy user's username can't be taken under the primary domain
+    assert_eq!(
+        update_ln_address("legacy@localhost").await,
+        StatusCode::BAD_REQUEST
+    );
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_retired_ln_address_resolves_during_grace_period() {
+    let mut config = TestUser::get_config();
+    config.ln_address_history_enabled = true;
+    config.ln_address_retired_grace_days = 30;
+    let (app, app_state, _guard) = setup_test_app_with_config(config).await;
+
+    let user = TestUser::new();
+    let access_token = user.access_token(&app_state);
+    let mut tx = app_state.db_pool.begin().await.unwrap();
+    UserRepository::create(
+        &mut tx,
+        &user.pubkey().to_string(),
+        "old@localhost",
+        Some("ark1retired"),
+    )
+    .await
+    .unwrap();
+    tx.commit().await.unwrap();
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/update_ln_address")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .header(
+                    http::header::AUTHORIZATION,
+                    format!("Bearer {}", access_token),
+                )
+                .body(Body::from(
+                    serde_json::to_vec(&json!({ "ln_address": "new@localhost" })).unwrap(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let lnurlp = |uri: &'static str| {
+        let app = app.clone();
+        async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .method(http::Method::GET)
+                        .uri(uri)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            (
+                status,
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            )
+        }
+    };
+
+    // Active address
+    let (status, _) = lnurlp("/.well-known/lnurlp/new").await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Retired address within the grace period still reaches the user, and tells the payer
+    let (status, body) = lnurlp("/.well-known/lnurlp/old?amount=330000&wallet=noahwallet").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["ark"], "ark1retired");
+    assert_eq!(body["successAction"]["tag"], "message");
+    assert!(
+        body["successAction"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("new@localhost")
+    );
+
+    // Past the grace period the address is reported as retired
+    LightningAddressHistoryRepository::new(&app_state.db_pool)
+        .set_retired_at("old@localhost", Utc::now() - Duration::days(31))
+        .await
+        .unwrap();
+    let (status, body) = lnurlp("/.well-known/lnurlp/old").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["status"], "ERROR");
+    assert_eq!(body["reason"], "Lightning address has been retired");
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_update_default_sendable() {
+    let (app, app_state, _guard) = setup_test_app().await;
+
+    let user = TestUser::new();
+    create_test_user(&app_state, &user, None).await;
+    let access_token = user.access_token(&app_state);
+
+    let set_default_sendable = |amount: serde_json::Value| {
+        Request::builder()
+            .method(http::Method::POST)
+            .uri("/lnurlp/default_sendable")
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .header(
+                http::header::AUTHORIZATION,
+                format!("Bearer {}", access_token),
+            )
+            .body(Body::from(
+                serde_json::to_vec(&json!({ "default_sendable_msat": amount })).unwrap(),
+            ))
+            .unwrap()
+    };
+    let lnurlp_metadata = || async {
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method(http::Method::GET)
+                    .uri("/.well-known/lnurlp/test")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let res: LnurlpDefaultResponse = serde_json::from_slice(&body).unwrap();
+        res.metadata
+    };
+
+    let response = app
+        .clone()
+        .oneshot(set_default_sendable(json!(1000)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = app
+        .clone()
+        .oneshot(set_default_sendable(json!(1_000_000)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(lnurlp_metadata().await.contains("Suggested: 1000 sats"));
+
+    let response = app
+        .clone()
+        .oneshot(set_default_sendable(json!(null)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!lnurlp_metadata().await.contains("Suggested"));
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_update_success_action() {
+    let (app, app_state, _guard) = setup_test_app().await;
+
+    let user = TestUser::new();
+    let pubkey = user.pubkey().to_string();
+    create_test_user(&app_state, &user, None).await;
+    let access_token = user.access_token(&app_state);
+
+    let set_success_action = |success_action: serde_json::Value| {
+        Request::builder()
+            .method(http::Method::POST)
+            .uri("/lnurlp/success_action")
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .header(
+                http::header::AUTHORIZATION,
+                format!("Bearer {}", access_token),
+            )
+            .body(Body::from(
+                serde_json::to_vec(&json!({ "success_action": success_action })).unwrap(),
+            ))
+            .unwrap()
+    };
+    let user_repo = UserRepository::new(&app_state.db_pool);
+
+    for invalid in [
+        json!({ "tag": "url", "description": "Receipt", "url": "http://localhost/receipt" }),
+        json!({ "tag": "url", "description": "Receipt", "url": "https://shop.example/receipt" }),
+        json!({ "tag": "url", "description": "Receipt", "url": "javascript:alert(1)" }),
+        json!({ "tag": "message", "message": "x".repeat(145) }),
+        json!({ "tag": "message", "message": " " }),
+    ] {
+        let response = app
+            .clone()
+            .oneshot(set_success_action(invalid))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+    assert_eq!(
+        user_repo.get_lnurlp_success_action(&pubkey).await.unwrap(),
+        None
+    );
+
+    let response = app
+        .clone()
+        .oneshot(set_success_action(json!({
+            "tag": "url",
+            "description": "Your receipt",
+            "url": "https://localhost/receipt/1"
+        })))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let success_action = user_repo.get_lnurlp_success_action(&pubkey).await.unwrap();
+    assert_eq!(
+        success_action,
+        Some(LnurlpSuccessAction::Url {
+            description: "Your receipt".to_string(),
+            url: "https://localhost/receipt/1".to_string(),
+        })
+    );
+
+    // Payers see it as the LUD-09 successAction of the invoice response
+    let invoice_response = serde_json::to_value(LnurlpInvoiceResponse {
+        pr: "lnbc1test".to_string(),
+        routes: vec![],
+        ark: None,
+        success_action,
+    })
+    .unwrap();
+    assert_eq!(
+        invoice_response["successAction"],
+        json!({
+            "tag": "url",
+            "description": "Your receipt",
+            "url": "https://localhost/receipt/1"
+        })
+    );
+
+    let response = app
+        .clone()
+        .oneshot(set_success_action(json!(null)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        user_repo.get_lnurlp_success_action(&pubkey).await.unwrap(),
+        None
+    );
+}
+
 #[tracing_test::traced_test]
 #[tokio::test]
 async fn test_deregister_user() {
@@ -657,6 +1057,77 @@ async fn test_stale_pending_job_reports_are_marked_timeout_after_one_hour() {
     assert_eq!(fresh_row.1, None);
 }
 
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_job_status_retention_deletes_old_reports() {
+    let (_app, app_state, _guard) = setup_test_app().await;
+    let user = TestUser::new();
+    let pubkey = user.pubkey().to_string();
+    create_test_user(&app_state, &user, None).await;
+
+    use crate::types::{ReportStatus, ReportType};
+
+    for (k1, age_days) in [("old-k1-1", 120), ("old-k1-2", 91), ("recent-k1", 10)] {
+        JobStatusRepository::create_with_k1_and_created_at(
+            &app_state.db_pool,
+            &pubkey,
+            k1,
+            &ReportType::Backup,
+            &ReportStatus::Success,
+            None,
+            Utc::now() - Duration::days(age_days),
+        )
+        .await
+        .unwrap();
+    }
+
+    let deleted = crate::cron::prune_job_status_reports(app_state.clone(), 90, 1)
+        .await
+        .unwrap();
+    assert_eq!(deleted, 2);
+    assert_eq!(
+        JobStatusRepository::count_by_pubkey(&app_state.db_pool, &pubkey)
+            .await
+            .unwrap(),
+        1
+    );
+    assert!(
+        JobStatusRepository::find_status_and_error_by_k1(&app_state.db_pool, &pubkey, "recent-k1")
+            .await
+            .unwrap()
+            .is_some()
+    );
+
+    // Nothing left past the retention age
+    let deleted = crate::cron::prune_job_status_reports(app_state.clone(), 90, 0)
+        .await
+        .unwrap();
+    assert_eq!(deleted, 0);
+
+    // A retention of 0 keeps everything, however old
+    JobStatusRepository::create_with_k1_and_created_at(
+        &app_state.db_pool,
+        &pubkey,
+        "ancient-k1",
+        &ReportType::Backup,
+        &ReportStatus::Success,
+        None,
+        Utc::now() - Duration::days(3650),
+    )
+    .await
+    .unwrap();
+    let deleted = crate::cron::prune_job_status_reports(app_state.clone(), 0, 0)
+        .await
+        .unwrap();
+    assert_eq!(deleted, 0);
+    assert_eq!(
+        JobStatusRepository::count_by_pubkey(&app_state.db_pool, &pubkey)
+            .await
+            .unwrap(),
+        2
+    );
+}
+
 #[tracing_test::traced_test]
 #[tokio::test]
 async fn test_stale_pending_timeout_cleanup_does_not_override_existing_error_message() {
@@ -947,6 +1418,103 @@ async fn test_update_ark_address_taken() {
     assert_eq!(current_user1.ark_address, ark_address1);
 }
 
+/// Registers `user` with `ark_address` and returns the response status.
+async fn register_with_ark_address(
+    app: &axum::Router,
+    app_state: &crate::AppState,
+    user: &TestUser,
+    ark_address: &str,
+) -> StatusCode {
+    app.clone()
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/register")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .header(
+                    http::header::AUTHORIZATION,
+                    format!("Bearer {}", user.access_token(app_state)),
+                )
+                .body(Body::from(
+                    serde_json::to_vec(&json!({ "ark_address": ark_address })).unwrap(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+        .status()
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_shared_ark_address_allowed_when_uniqueness_disabled() {
+    let mut config = TestUser::get_config();
+    config.ark_address_unique = false;
+    let (app, app_state, _guard) = setup_test_app_with_config(config).await;
+    let shared = "tark1sharedcustody";
+
+    let first = TestUser::new_with_key(&[0x71; 32]);
+    let second = TestUser::new_with_key(&[0x72; 32]);
+    assert_eq!(
+        register_with_ark_address(&app, &app_state, &first, shared).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        register_with_ark_address(&app, &app_state, &second, shared).await,
+        StatusCode::OK
+    );
+
+    // Existing users can switch to an address another account holds too
+    let third = TestUser::new_with_key(&[0x73; 32]);
+    assert_eq!(
+        register_with_ark_address(&app, &app_state, &third, "tark1third").await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        register_with_ark_address(&app, &app_state, &third, shared).await,
+        StatusCode::OK
+    );
+
+    let holders: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users WHERE ark_address = $1")
+        .bind(shared)
+        .fetch_one(&app_state.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(holders, 3);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_ark_address_rejected_when_ark_disabled() {
+    let mut config = TestUser::get_config();
+    config.ark_enabled = false;
+    let (app, app_state, _guard) = setup_test_app_with_config(config).await;
+
+    let user = TestUser::new();
+    assert_eq!(
+        register_with_ark_address(&app, &app_state, &user, "tark1disabled").await,
+        StatusCode::BAD_REQUEST
+    );
+
+    // Registering without an ark address still works
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/register")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .header(
+                    http::header::AUTHORIZATION,
+                    format!("Bearer {}", user.access_token(&app_state)),
+                )
+                .body(Body::from(serde_json::to_vec(&json!({})).unwrap()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
 #[tracing_test::traced_test]
 #[tokio::test]
 async fn test_report_last_login() {
@@ -1076,3 +1644,388 @@ async fn test_report_last_login_updates_timestamp() {
 
     assert!(second_login > first_login);
 }
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_purge_inactive_accounts_only_removes_inactive_users_without_backups() {
+    let (_app, app_state, _guard) = setup_test_app().await;
+
+    let inactive_user = TestUser::new_with_key(&[0x11; 32]);
+    let inactive_with_backup = TestUser::new_with_key(&[0x12; 32]);
+    let recently_active = TestUser::new_with_key(&[0x13; 32]);
+
+    for (i, user) in [&inactive_user, &inactive_with_backup, &recently_active]
+        .iter()
+        .enumerate()
+    {
+        let mut tx = app_state.db_pool.begin().await.unwrap();
+        UserRepository::create(
+            &mut tx,
+            &user.pubkey().to_string(),
+            &format!("purge{}@localhost", i),
+            None,
+        )
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+    }
+
+    let stale_login = Utc::now() - Duration::days(400);
+    sqlx::query("UPDATE users SET created_at = $1, last_login_at = $1")
+        .bind(stale_login)
+        .execute(&app_state.db_pool)
+        .await
+        .unwrap();
+
+    BackupRepository::new(&app_state.db_pool)
+        .upsert_metadata(&inactive_with_backup.pubkey().to_string(), "key", 1024, 1)
+        .await
+        .unwrap();
+    UserRepository::new(&app_state.db_pool)
+        .update_last_login(&recently_active.pubkey().to_string())
+        .await
+        .unwrap();
+
+    // Dry run must not delete anything
+    crate::cron::purge_inactive_accounts(app_state.clone(), 365, true)
+        .await
+        .unwrap();
+
+    let user_repo = UserRepository::new(&app_state.db_pool);
+    assert_eq!(user_repo.count_all().await.unwrap(), 3);
+
+    crate::cron::purge_inactive_accounts(app_state.clone(), 365, false)
+        .await
+        .unwrap();
+
+    assert!(
+        user_repo
+            .find_by_pubkey(&inactive_user.pubkey().to_string())
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert!(
+        user_repo
+            .find_by_pubkey(&inactive_with_backup.pubkey().to_string())
+            .await
+            .unwrap()
+            .is_some()
+    );
+    assert!(
+        user_repo
+            .find_by_pubkey(&recently_active.pubkey().to_string())
+            .await
+            .unwrap()
+            .is_some()
+    );
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_update_timezone() {
+    let (app, app_state, _guard) = setup_test_app().await;
+
+    let user = TestUser::new();
+    let pubkey = user.pubkey().to_string();
+    create_test_user(&app_state, &user, None).await;
+    let access_token = user.access_token(&app_state);
+
+    let set_timezone = |timezone: serde_json::Value| {
+        Request::builder()
+            .method(http::Method::POST)
+            .uri("/update_timezone")
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .header(
+                http::header::AUTHORIZATION,
+                format!("Bearer {}", access_token),
+            )
+            .body(Body::from(
+                serde_json::to_vec(&json!({ "timezone": timezone })).unwrap(),
+            ))
+            .unwrap()
+    };
+    let user_repo = UserRepository::new(&app_state.db_pool);
+    let stored_timezone = || async {
+        user_repo
+            .find_by_pubkey(&pubkey)
+            .await
+            .unwrap()
+            .unwrap()
+            .timezone
+    };
+
+    let response = app
+        .clone()
+        .oneshot(set_timezone(json!("Europe/Berlin")))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(stored_timezone().await.as_deref(), Some("Europe/Berlin"));
+
+    let response = app
+        .clone()
+        .oneshot(set_timezone(json!("Mars/Olympus_Mons")))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(stored_timezone().await.as_deref(), Some("Europe/Berlin"));
+
+    let response = app
+        .clone()
+        .oneshot(set_timezone(json!(null)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(stored_timezone().await, None);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_find_many_by_pubkeys_skips_missing() {
+    let (_, app_state, _guard) = setup_test_app().await;
+
+    let existing = [
+        TestUser::new_with_key(&[0x61; 32]),
+        TestUser::new_with_key(&[0x62; 32]),
+    ];
+    for (i, user) in existing.iter().enumerate() {
+        let mut tx = app_state.db_pool.begin().await.unwrap();
+        UserRepository::create(
+            &mut tx,
+            &user.pubkey().to_string(),
+            &format!("many{}@localhost", i),
+            None,
+        )
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+    }
+    let missing = TestUser::new_with_key(&[0x63; 32]);
+
+    let lookup = vec![
+        existing[0].pubkey().to_string(),
+        missing.pubkey().to_string(),
+        existing[1].pubkey().to_string(),
+    ];
+    let users = UserRepository::new(&app_state.db_pool)
+        .find_many_by_pubkeys(&lookup)
+        .await
+        .unwrap();
+
+    let mut found: Vec<String> = users.into_iter().map(|user| user.pubkey).collect();
+    found.sort();
+    let mut expected: Vec<String> = existing
+        .iter()
+        .map(|user| user.pubkey().to_string())
+        .collect();
+    expected.sort();
+    assert_eq!(found, expected);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_feature_flag_overrides_take_precedence() {
+    let (app, app_state, _guard) = setup_test_app().await;
+
+    let rollout_user = TestUser::new_with_key(&[0x61; 32]);
+    let other_user = TestUser::new_with_key(&[0x62; 32]);
+    for (i, user) in [&rollout_user, &other_user].iter().enumerate() {
+        let mut tx = app_state.db_pool.begin().await.unwrap();
+        UserRepository::create(
+            &mut tx,
+            &user.pubkey().to_string(),
+            &format!("flags{}@localhost", i),
+            None,
+        )
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+    }
+
+    let global = FeatureFlags::from_str("new_backups=true, beta_swaps=false").unwrap();
+    let flag_repo = FeatureFlagRepository::new(&app_state.db_pool);
+    let rollout_pubkey = rollout_user.pubkey().to_string();
+    flag_repo
+        .set_override(&rollout_pubkey, "beta_swaps", Some(true))
+        .await
+        .unwrap();
+    flag_repo
+        .set_override(&rollout_pubkey, "new_backups", Some(false))
+        .await
+        .unwrap();
+
+    let resolved = flag_repo.resolve(&global, &rollout_pubkey).await.unwrap();
+    assert_eq!(resolved.get("beta_swaps"), Some(&true));
+    assert_eq!(resolved.get("new_backups"), Some(&false));
+
+    let other_pubkey = other_user.pubkey().to_string();
+    assert!(
+        !flag_repo
+            .is_enabled(&global, &other_pubkey, "beta_swaps")
+            .await
+            .unwrap()
+    );
+    assert!(
+        flag_repo
+            .is_enabled(&global, &other_pubkey, "new_backups")
+            .await
+            .unwrap()
+    );
+    assert!(
+        !flag_repo
+            .is_enabled(&global, &other_pubkey, "unknown_flag")
+            .await
+            .unwrap()
+    );
+
+    // Removing the override falls back to the global value
+    flag_repo
+        .set_override(&rollout_pubkey, "new_backups", None)
+        .await
+        .unwrap();
+    assert!(
+        flag_repo
+            .is_enabled(&global, &rollout_pubkey, "new_backups")
+            .await
+            .unwrap()
+    );
+
+    // The gated endpoint serves the user's resolved flags
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/feature_flags")
+                .header(
+                    http::header::AUTHORIZATION,
+                    format!("Bearer {}", rollout_user.access_token(&app_state)),
+                )
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let res: FeatureFlagsResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        res.feature_flags,
+        BTreeMap::from([("beta_swaps".to_string(), true)])
+    );
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_feature_flag_hides_route_for_users_without_rollout() {
+    let mut config = TestUser::get_config();
+    config.feature_flags = format!("{}=false", BACKUP_USAGE_FLAG);
+    let (app, app_state, _guard) = setup_test_app_with_config(config).await;
+
+    let rollout_user = TestUser::new_with_key(&[0x64; 32]);
+    let other_user = TestUser::new_with_key(&[0x65; 32]);
+    for (i, user) in [&rollout_user, &other_user].iter().enumerate() {
+        let mut tx = app_state.db_pool.begin().await.unwrap();
+        UserRepository::create(
+            &mut tx,
+            &user.pubkey().to_string(),
+            &format!("dark{}@localhost", i),
+            None,
+        )
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+    }
+    FeatureFlagRepository::new(&app_state.db_pool)
+        .set_override(
+            &rollout_user.pubkey().to_string(),
+            BACKUP_USAGE_FLAG,
+            Some(true),
+        )
+        .await
+        .unwrap();
+
+    let backup_usage = |user: &TestUser| {
+        let app = app.clone();
+        let access_token = user.access_token(&app_state);
+        async move {
+            app.oneshot(
+                Request::builder()
+                    .method(http::Method::POST)
+                    .uri("/backup/usage")
+                    .header(
+                        http::header::AUTHORIZATION,
+                        format!("Bearer {}", access_token),
+                    )
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+            .status()
+        }
+    };
+
+    assert_eq!(backup_usage(&rollout_user).await, StatusCode::OK);
+    assert_eq!(backup_usage(&other_user).await, StatusCode::NOT_FOUND);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_update_ln_address_rejects_long_and_illegal_usernames() {
+    let mut config = TestUser::get_config();
+    config.ln_username_max_length = 8;
+    let (app, app_state, _guard) = setup_test_app_with_config(config).await;
+
+    let user = TestUser::new();
+    create_test_user(&app_state, &user, None).await;
+    let access_token = user.access_token(&app_state);
+
+    let update = |ln_address: &'static str| {
+        let app = app.clone();
+        let access_token = access_token.clone();
+        async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .method(http::Method::POST)
+                        .uri("/update_ln_address")
+                        .header(http::header::CONTENT_TYPE, "application/json")
+                        .header(
+                            http::header::AUTHORIZATION,
+                            format!("Bearer {}", access_token),
+                        )
+                        .body(Body::from(
+                            serde_json::to_vec(&json!({ "ln_address": ln_address })).unwrap(),
+                        ))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            (
+                status,
+                serde_json::from_slice::<ApiErrorResponse>(&body).ok(),
+            )
+        }
+    };
+
+    let (status, error) = update("waytoolong@localhost").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        error.unwrap().message,
+        "Lightning address usernames can be at most 8 characters, e.g. satoshi.n"
+    );
+
+    let (status, error) = update("bad\u{7}name@localhost").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let field_errors = error.unwrap().field_errors.unwrap();
+    assert_eq!(
+        field_errors["ln_address"],
+        vec![INVALID_LN_USERNAME_MESSAGE.to_string()]
+    );
+
+    let (status, _) = update("ok.name@localhost").await;
+    assert_eq!(status, StatusCode::OK);
+}
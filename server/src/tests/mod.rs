@@ -1,3 +1,4 @@
+pub mod admin_api_tests;
 pub mod common;
 pub mod coordinator_tests;
 pub mod email_verification_tests;
@@ -6,6 +7,8 @@ pub mod gated_backup_tests;
 pub mod gated_error_tests;
 pub mod gated_heartbeat_tests;
 pub mod gated_invoice_tests;
+pub mod gated_offboarding_tests;
+pub mod gated_push_tests;
 pub mod gated_suggestions_tests;
 pub mod gated_user_tests;
 pub mod public_api_v0;
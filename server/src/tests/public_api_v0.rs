@@ -1,6 +1,26 @@
-use crate::routes::public_api_v0::{GetK1, LnurlpDefaultResponse};
-use crate::tests::common::setup_public_test_app;
-use crate::types::{AppVersionCheckPayload, AppVersionInfo};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, Instant};
+
+use bitcoin::hashes::{Hash, sha256};
+
+use crate::AppState;
+use crate::app_middleware::SERVER_SIGNATURE_HEADER;
+use crate::routes::public_api_v0::{
+    GetK1, HealthState, K1PowChallenge, LNURLP_MAX_SENDABLE, LNURLP_MIN_SENDABLE, health_check,
+    lnurlp_metadata, run_invoice_session, validate_lnurlp_amount,
+};
+use crate::tests::common::{
+    TestDbGuard, TestUser, setup_public_test_app, setup_public_test_app_with_config,
+};
+use crate::types::{
+    ApiErrorResponse, AppVersionCheckPayload, AppVersionInfo, AuthLoginPayload, HealthResponse,
+    InvoiceStatusFrame, LightningAddressAvailabilityResponse, LnurlErrorResponse,
+    LnurlpDefaultResponse, LnurlpInvoiceResponse, LnurlpPreRegisterPayload,
+    LnurlpPreRegisterResponse, LnurlpSuccessAction, ServerInfoResponse, ServerTimeResponse,
+};
+use crate::utils::{make_k1, verify_pow};
 use axum::body::Body;
 use axum::http::{self, Request, StatusCode};
 use http_body_util::BodyExt;
@@ -38,6 +58,629 @@ async fn test_lnurlp_request_default() {
     assert_eq!(res.callback, "https://localhost/.well-known/lnurlp/test");
 }
 
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_lnurlp_metadata_descriptions() {
+    assert_eq!(
+        lnurlp_metadata("alice@noah", None, true),
+        r#"[["text/identifier","alice@noah"],["text/plain","Paying satoshis to alice@noah"]]"#
+    );
+    assert!(
+        lnurlp_metadata("alice@noah", Some(1_234_000), false)
+            .contains("Paying satoshis to alice@noah. Suggested: 1234 sats")
+    );
+    assert!(
+        lnurlp_metadata("alice@noah", Some(1_234_000), true)
+            .contains("Pay 1,234 sats to alice@noah")
+    );
+    assert!(
+        lnurlp_metadata("alice@noah", Some(330_000), true).contains("Pay 330 sats to alice@noah")
+    );
+    assert!(
+        lnurlp_metadata("alice@noah", Some(100_000_000), true)
+            .contains("Pay 100,000 sats to alice@noah")
+    );
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_lnurl_response_shapes() {
+    let default_response = LnurlpDefaultResponse {
+        callback: "https://localhost/.well-known/lnurlp/test".to_string(),
+        max_sendable: LNURLP_MAX_SENDABLE,
+        min_sendable: LNURLP_MIN_SENDABLE,
+        metadata: lnurlp_metadata("test@localhost", None, false),
+        tag: "payRequest".to_string(),
+        comment_allowed: 280,
+    };
+    assert_eq!(
+        serde_json::to_value(&default_response).unwrap(),
+        serde_json::json!({
+            "callback": "https://localhost/.well-known/lnurlp/test",
+            "maxSendable": LNURLP_MAX_SENDABLE,
+            "minSendable": LNURLP_MIN_SENDABLE,
+            "metadata": r#"[["text/identifier","test@localhost"],["text/plain","Paying satoshis to test@localhost"]]"#,
+            "tag": "payRequest",
+            "commentAllowed": 280,
+        })
+    );
+
+    // Optional fields are left out rather than sent as null
+    let invoice_response = LnurlpInvoiceResponse {
+        pr: "lnbc1test".to_string(),
+        routes: vec![],
+        ark: None,
+        success_action: None,
+    };
+    assert_eq!(
+        serde_json::to_value(&invoice_response).unwrap(),
+        serde_json::json!({ "pr": "lnbc1test", "routes": [] })
+    );
+
+    let invoice_response = LnurlpInvoiceResponse {
+        pr: "lnbc1test".to_string(),
+        routes: vec![],
+        ark: Some("tark1test".to_string()),
+        success_action: Some(LnurlpSuccessAction::Message {
+            message: "Thanks!".to_string(),
+        }),
+    };
+    let value = serde_json::to_value(&invoice_response).unwrap();
+    assert_eq!(
+        value,
+        serde_json::json!({
+            "pr": "lnbc1test",
+            "routes": [],
+            "ark": "tark1test",
+            "successAction": { "tag": "message", "message": "Thanks!" },
+        })
+    );
+    assert_eq!(
+        serde_json::from_value::<LnurlpInvoiceResponse>(value).unwrap(),
+        invoice_response
+    );
+
+    let error_response = LnurlErrorResponse {
+        status: "ERROR".to_string(),
+        reason: "User not found".to_string(),
+    };
+    assert_eq!(
+        serde_json::to_value(&error_response).unwrap(),
+        serde_json::json!({ "status": "ERROR", "reason": "User not found" })
+    );
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_lnurlp_errors_use_lnurl_error_shape() {
+    let (app, _app_state, _guard) = setup_public_test_app().await;
+
+    let (status, body) = get_status_and_body(&app, "/.well-known/lnurlp/nobody").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let error: LnurlErrorResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        error,
+        LnurlErrorResponse {
+            status: "ERROR".to_string(),
+            reason: "User not found".to_string(),
+        }
+    );
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_lnurlp_amount_description_keeps_metadata_hash_stable() {
+    let mut config = TestUser::get_config();
+    config.lnurlp_amount_description = true;
+    let (app, app_state, _guard) = setup_public_test_app_with_config(config).await;
+
+    sqlx::query(
+        "INSERT INTO users (pubkey, lightning_address, default_sendable_msat) VALUES ($1, $2, $3)",
+    )
+    .bind("test_pubkey")
+    .bind("test@localhost")
+    .bind(1_234_000_i64)
+    .execute(&app_state.db_pool)
+    .await
+    .unwrap();
+
+    let fetch_metadata = || async {
+        let (status, body) = get_status_and_body(&app, "/.well-known/lnurlp/test").await;
+        assert_eq!(status, StatusCode::OK);
+        serde_json::from_slice::<LnurlpDefaultResponse>(&body)
+            .unwrap()
+            .metadata
+    };
+
+    let first = fetch_metadata().await;
+    let second = fetch_metadata().await;
+    assert!(first.contains("Pay 1,234 sats to test@localhost"));
+
+    // The invoice's description hash commits to the metadata the payer saw, so it must not drift
+    let expected = lnurlp_metadata("test@localhost", Some(1_234_000), true);
+    assert_eq!(
+        sha256::Hash::hash(first.as_bytes()),
+        sha256::Hash::hash(expected.as_bytes())
+    );
+    assert_eq!(
+        sha256::Hash::hash(first.as_bytes()),
+        sha256::Hash::hash(second.as_bytes())
+    );
+}
+
+async fn get_server_info(app: &axum::Router) -> ServerInfoResponse {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(http::Method::GET)
+                .uri("/info")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_server_time_is_close_to_now() {
+    let (app, app_state, _guard) = setup_public_test_app().await;
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::GET)
+                .uri("/time")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let time: ServerTimeResponse = serde_json::from_slice(&body).unwrap();
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    assert!(now.abs_diff(time.server_time) <= 5);
+    assert_eq!(time.k1_ttl_secs, app_state.k1_cache.ttl_seconds());
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_lnurlp_response_signed_with_server_key() {
+    let mut config = TestUser::get_config();
+    config.response_signing_key = Some("11".repeat(32));
+    let (app, app_state, _guard) = setup_public_test_app_with_config(config).await;
+
+    sqlx::query("INSERT INTO users (pubkey, lightning_address, ark_address) VALUES ($1, $2, NULL)")
+        .bind("test_pubkey")
+        .bind("test@localhost")
+        .execute(&app_state.db_pool)
+        .await
+        .unwrap();
+
+    let signing_pubkey = get_server_info(&app)
+        .await
+        .signing_pubkey
+        .expect("signing pubkey should be published");
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::GET)
+                .uri("/.well-known/lnurlp/test")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let signature = response
+        .headers()
+        .get(SERVER_SIGNATURE_HEADER)
+        .expect("response should be signed")
+        .to_str()
+        .unwrap()
+        .to_string();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+
+    let secp = bitcoin::secp256k1::Secp256k1::verification_only();
+    let hash = sha256::Hash::hash(&body);
+    let msg = bitcoin::secp256k1::Message::from_digest(hash.to_byte_array());
+    let signature = bitcoin::secp256k1::ecdsa::Signature::from_str(&signature).unwrap();
+    let pubkey = bitcoin::secp256k1::PublicKey::from_str(&signing_pubkey).unwrap();
+    assert!(secp.verify_ecdsa(&msg, &signature, &pubkey).is_ok());
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_responses_unsigned_without_server_key() {
+    let (app, app_state, _guard) = setup_public_test_app().await;
+
+    sqlx::query("INSERT INTO users (pubkey, lightning_address, ark_address) VALUES ($1, $2, NULL)")
+        .bind("test_pubkey")
+        .bind("test@localhost")
+        .execute(&app_state.db_pool)
+        .await
+        .unwrap();
+
+    assert!(get_server_info(&app).await.signing_pubkey.is_none());
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::GET)
+                .uri("/.well-known/lnurlp/test")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get(SERVER_SIGNATURE_HEADER).is_none());
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_invoice_session_delivers_invoice() {
+    let (_app, app_state, _guard) = setup_public_test_app().await;
+
+    let (frames_tx, mut frames_rx) = tokio::sync::mpsc::channel(4);
+    tokio::spawn(run_invoice_session(
+        app_state.clone(),
+        "test_pubkey".to_string(),
+        330000,
+        std::time::Duration::from_secs(10),
+        frames_tx,
+    ));
+
+    let Some(InvoiceStatusFrame::Pending { transaction_id }) = frames_rx.recv().await else {
+        panic!("expected a pending frame first");
+    };
+    app_state
+        .invoice_store
+        .store(&transaction_id, "lnbc1test")
+        .await
+        .unwrap();
+
+    assert_eq!(
+        frames_rx.recv().await,
+        Some(InvoiceStatusFrame::Invoiced {
+            pr: "lnbc1test".to_string()
+        })
+    );
+    assert_eq!(frames_rx.recv().await, None);
+    assert!(
+        app_state
+            .invoice_store
+            .get(&transaction_id)
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_invoice_session_times_out() {
+    let (_app, app_state, _guard) = setup_public_test_app().await;
+
+    let (frames_tx, mut frames_rx) = tokio::sync::mpsc::channel(4);
+    tokio::spawn(run_invoice_session(
+        app_state,
+        "test_pubkey".to_string(),
+        330000,
+        std::time::Duration::from_millis(100),
+        frames_tx,
+    ));
+
+    assert!(matches!(
+        frames_rx.recv().await,
+        Some(InvoiceStatusFrame::Pending { .. })
+    ));
+    assert_eq!(frames_rx.recv().await, Some(InvoiceStatusFrame::Timeout));
+}
+
+/// Registers `test_pubkey` as `test@localhost` with a push token pointing at a stand-in
+/// UnifiedPush endpoint that accepts every notification.
+async fn create_reachable_recipient(app_state: &AppState) {
+    let push_endpoint = axum::Router::new().route("/push", axum::routing::post(|| async {}));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, push_endpoint).await.unwrap() });
+
+    sqlx::query("INSERT INTO users (pubkey, lightning_address) VALUES ($1, $2)")
+        .bind("test_pubkey")
+        .bind("test@localhost")
+        .execute(&app_state.db_pool)
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO push_tokens (pubkey, push_token) VALUES ($1, $2)")
+        .bind("test_pubkey")
+        .bind(format!("http://{}/push", addr))
+        .execute(&app_state.db_pool)
+        .await
+        .unwrap();
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_invoice_session_reports_push_sent() {
+    let (_app, app_state, _guard) = setup_public_test_app().await;
+    create_reachable_recipient(&app_state).await;
+
+    let (frames_tx, mut frames_rx) = tokio::sync::mpsc::channel(4);
+    tokio::spawn(run_invoice_session(
+        app_state.clone(),
+        "test_pubkey".to_string(),
+        330000,
+        std::time::Duration::from_secs(10),
+        frames_tx,
+    ));
+
+    let Some(InvoiceStatusFrame::Pending { transaction_id }) = frames_rx.recv().await else {
+        panic!("expected a pending frame first");
+    };
+    assert_eq!(
+        frames_rx.recv().await,
+        Some(InvoiceStatusFrame::PushSent {
+            transaction_id: transaction_id.clone()
+        })
+    );
+
+    app_state
+        .invoice_store
+        .store(&transaction_id, "lnbc1pushed")
+        .await
+        .unwrap();
+    assert_eq!(
+        frames_rx.recv().await,
+        Some(InvoiceStatusFrame::Invoiced {
+            pr: "lnbc1pushed".to_string()
+        })
+    );
+    assert_eq!(frames_rx.recv().await, None);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_lnurlp_poll_reports_push_sent() {
+    let (app, app_state, _guard) = setup_public_test_app().await;
+    create_reachable_recipient(&app_state).await;
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/lnurlp/test/request")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .body(Body::from(
+                    serde_json::to_vec(&LnurlpPreRegisterPayload { amount: 330000 }).unwrap(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let transaction_id = serde_json::from_slice::<LnurlpPreRegisterResponse>(&body)
+        .unwrap()
+        .transaction_id;
+    let poll_uri = format!("/lnurlp/poll/{}", transaction_id);
+
+    // The push goes out in the background, so poll until it's recorded
+    let mut frame = None;
+    for _ in 0..50 {
+        let (status, body) = get_status_and_body(&app, &poll_uri).await;
+        assert_eq!(status, StatusCode::OK);
+        let polled = serde_json::from_slice::<InvoiceStatusFrame>(&body).unwrap();
+        if !matches!(polled, InvoiceStatusFrame::Pending { .. }) {
+            frame = Some(polled);
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    assert_eq!(
+        frame,
+        Some(InvoiceStatusFrame::PushSent {
+            transaction_id: transaction_id.clone()
+        })
+    );
+
+    app_state
+        .invoice_store
+        .store(&transaction_id, "lnbc1polled")
+        .await
+        .unwrap();
+    let (status, body) = get_status_and_body(&app, &poll_uri).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        serde_json::from_slice::<InvoiceStatusFrame>(&body).unwrap(),
+        InvoiceStatusFrame::Invoiced {
+            pr: "lnbc1polled".to_string()
+        }
+    );
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_lnurlp_request_daily_cap() {
+    let mut config = TestUser::get_config();
+    config.lnurlp_daily_request_cap = 2;
+    let (app, app_state, _guard) = setup_public_test_app_with_config(config).await;
+
+    sqlx::query("INSERT INTO users (pubkey, lightning_address) VALUES ($1, $2)")
+        .bind("test_pubkey")
+        .bind("test@localhost")
+        .execute(&app_state.db_pool)
+        .await
+        .unwrap();
+
+    // Use up today's budget without waiting on invoices that will never arrive
+    for _ in 0..2 {
+        app_state
+            .invoice_store
+            .record_daily_request("test_pubkey", chrono::Utc::now().date_naive())
+            .await
+            .unwrap();
+    }
+
+    let (status, body) = get_status_and_body(&app, "/.well-known/lnurlp/test?amount=330000").await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(error["status"], "ERROR");
+    assert!(
+        error["reason"]
+            .as_str()
+            .unwrap()
+            .contains("Too many requests")
+    );
+
+    // The first LNURL-pay step doesn't wake the device and stays available
+    let (status, _) = get_status_and_body(&app, "/.well-known/lnurlp/test").await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_lnurlp_request_reports_invoice_timeout() {
+    let mut config = TestUser::get_config();
+    config.request_timeout_secs = 1;
+    config.lnurlp_invoice_timeout_secs = 1;
+    let (app, app_state, _guard) = setup_public_test_app_with_config(config).await;
+
+    sqlx::query("INSERT INTO users (pubkey, lightning_address) VALUES ($1, $2)")
+        .bind("test_pubkey")
+        .bind("test@localhost")
+        .execute(&app_state.db_pool)
+        .await
+        .unwrap();
+
+    // No device answers, so the handler's own timeout must fire before the route timeout
+    let (status, body) = get_status_and_body(&app, "/.well-known/lnurlp/test?amount=330000").await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(error["status"], "ERROR");
+    assert_eq!(error["reason"], "Request timed out");
+}
+
+fn amount_error(amount_msat: u64, require_whole_sats: bool) -> String {
+    match validate_lnurlp_amount(amount_msat, require_whole_sats) {
+        Err(crate::errors::ApiError::InvalidArgument(reason)) => reason,
+        other => panic!("expected InvalidArgument for {amount_msat}, got {other:?}"),
+    }
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_validate_lnurlp_amount_boundaries() {
+    assert!(validate_lnurlp_amount(LNURLP_MIN_SENDABLE, true).is_ok());
+    assert!(validate_lnurlp_amount(LNURLP_MAX_SENDABLE, true).is_ok());
+
+    assert!(amount_error(LNURLP_MIN_SENDABLE - 1000, true).starts_with("Minimum"));
+    assert!(amount_error(LNURLP_MAX_SENDABLE + 1000, true).starts_with("Maximum"));
+    assert_eq!(amount_error(0, true), "Amount must be greater than zero");
+    assert_eq!(amount_error(0, false), "Amount must be greater than zero");
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_validate_lnurlp_amount_sub_sat_and_misunit() {
+    // 330,001 mSats is in range but not a whole number of sats
+    let sub_sat = LNURLP_MIN_SENDABLE + 1;
+    assert_eq!(
+        amount_error(sub_sat, true),
+        "Amount must be a whole number of sats (a multiple of 1000 mSats)"
+    );
+    assert!(validate_lnurlp_amount(sub_sat, false).is_ok());
+
+    // 330 looks like sats rather than mSats, so the error points at the unit
+    assert_eq!(
+        amount_error(330, true),
+        "Minimum invoice request is 330000 mSats (amounts are in mSats, not sats)"
+    );
+    // Too small in either unit, so no hint
+    assert_eq!(
+        amount_error(1, false),
+        "Minimum invoice request is 330000 mSats"
+    );
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_lnurlp_pre_register_and_poll() {
+    let (app, app_state, _guard) = setup_public_test_app().await;
+
+    sqlx::query("INSERT INTO users (pubkey, lightning_address) VALUES ($1, $2)")
+        .bind("test_pubkey")
+        .bind("test@localhost")
+        .execute(&app_state.db_pool)
+        .await
+        .unwrap();
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/lnurlp/test/request")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .body(Body::from(
+                    serde_json::to_vec(&LnurlpPreRegisterPayload { amount: 330000 }).unwrap(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let transaction_id = serde_json::from_slice::<LnurlpPreRegisterResponse>(&body)
+        .unwrap()
+        .transaction_id;
+    let poll_uri = format!("/lnurlp/poll/{}", transaction_id);
+
+    let (status, body) = get_status_and_body(&app, &poll_uri).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        serde_json::from_slice::<InvoiceStatusFrame>(&body).unwrap(),
+        InvoiceStatusFrame::Pending {
+            transaction_id: transaction_id.clone()
+        }
+    );
+
+    // Stand in for the recipient's device submitting the invoice
+    app_state
+        .invoice_store
+        .store(&transaction_id, "lnbc1polled")
+        .await
+        .unwrap();
+
+    let (status, body) = get_status_and_body(&app, &poll_uri).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        serde_json::from_slice::<InvoiceStatusFrame>(&body).unwrap(),
+        InvoiceStatusFrame::Invoiced {
+            pr: "lnbc1polled".to_string()
+        }
+    );
+
+    // The invoice is handed out once
+    let (status, _) = get_status_and_body(&app, &poll_uri).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = get_status_and_body(&app, "/lnurlp/poll/unknown").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
 #[tracing_test::traced_test]
 #[tokio::test]
 async fn test_get_k1() {
@@ -69,6 +712,284 @@ async fn test_get_k1() {
     );
 }
 
+const TEST_POW_DIFFICULTY: u8 = 8;
+
+async fn get_status_and_body(app: &axum::Router, uri: &str) -> (StatusCode, Vec<u8>) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(http::Method::GET)
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, body.to_vec())
+}
+
+async fn setup_pow_test_app() -> (axum::Router, AppState, TestDbGuard) {
+    let mut config = TestUser::get_config();
+    config.k1_pow_difficulty = Some(TEST_POW_DIFFICULTY);
+    setup_public_test_app_with_config(config).await
+}
+
+async fn fetch_pow_challenge(app: &axum::Router) -> K1PowChallenge {
+    let (status, body) = get_status_and_body(app, "/getk1/challenge").await;
+    assert_eq!(status, StatusCode::OK);
+    serde_json::from_slice(&body).unwrap()
+}
+
+fn solve_pow(challenge: &K1PowChallenge) -> String {
+    (0u64..)
+        .map(|nonce| nonce.to_string())
+        .find(|nonce| verify_pow(&challenge.challenge, nonce, challenge.difficulty))
+        .unwrap()
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_get_k1_challenge_disabled_by_default() {
+    let (app, _app_state, _guard) = setup_public_test_app().await;
+
+    let (status, _) = get_status_and_body(&app, "/getk1/challenge").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_get_k1_with_valid_pow() {
+    let (app, app_state, _guard) = setup_pow_test_app().await;
+
+    let challenge = fetch_pow_challenge(&app).await;
+    assert_eq!(challenge.difficulty, TEST_POW_DIFFICULTY);
+    let nonce = solve_pow(&challenge);
+
+    let uri = format!(
+        "/getk1?pow_challenge={}&pow_nonce={}",
+        challenge.challenge, nonce
+    );
+    let (status, body) = get_status_and_body(&app, &uri).await;
+    assert_eq!(status, StatusCode::OK);
+    let res: GetK1 = serde_json::from_slice(&body).unwrap();
+    assert!(app_state.k1_cache.contains(&res.k1).await.unwrap());
+
+    // A solved challenge can only be redeemed once
+    let (status, _) = get_status_and_body(&app, &uri).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_get_k1_rejects_invalid_pow() {
+    let (app, _app_state, _guard) = setup_pow_test_app().await;
+
+    let (status, _) = get_status_and_body(&app, "/getk1").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let challenge = fetch_pow_challenge(&app).await;
+    let bad_nonce = (0u64..)
+        .map(|nonce| nonce.to_string())
+        .find(|nonce| !verify_pow(&challenge.challenge, nonce, challenge.difficulty))
+        .unwrap();
+    let (status, body) = get_status_and_body(
+        &app,
+        &format!(
+            "/getk1?pow_challenge={}&pow_nonce={}",
+            challenge.challenge, bad_nonce
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(error["code"], "INVALID_PROOF_OF_WORK");
+
+    // A valid-looking solution for a challenge the server never issued is rejected
+    let unknown = K1PowChallenge {
+        challenge: "00".repeat(32),
+        difficulty: TEST_POW_DIFFICULTY,
+    };
+    let nonce = solve_pow(&unknown);
+    let (status, _) = get_status_and_body(
+        &app,
+        &format!(
+            "/getk1?pow_challenge={}&pow_nonce={}",
+            unknown.challenge, nonce
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+async fn setup_abuse_test_app() -> (axum::Router, AppState, TestDbGuard) {
+    let mut config = TestUser::get_config();
+    config.abuse_score_threshold = 20;
+    config.abuse_score_window_secs = 60;
+    config.abuse_ban_secs = 1;
+    setup_public_test_app_with_config(config).await
+}
+
+async fn get_k1_from(app: &axum::Router, ip: &str) -> StatusCode {
+    app.clone()
+        .oneshot(
+            Request::builder()
+                .method(http::Method::GET)
+                .uri("/getk1")
+                .header("x-forwarded-for", ip)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+        .status()
+}
+
+async fn login_from(
+    app: &axum::Router,
+    payload: &AuthLoginPayload,
+    ip: &str,
+) -> (StatusCode, Vec<u8>) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(http::Method::POST)
+                .uri("/auth/login")
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .header("x-forwarded-for", ip)
+                .body(Body::from(serde_json::to_vec(payload).unwrap()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, body.to_vec())
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_excessive_k1_requests_block_ip_until_expiry() {
+    let (app, _app_state, _guard) = setup_abuse_test_app().await;
+
+    for _ in 0..19 {
+        assert_eq!(get_k1_from(&app, "203.0.113.7").await, StatusCode::OK);
+    }
+    assert_eq!(
+        get_k1_from(&app, "203.0.113.7").await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        get_k1_from(&app, "203.0.113.7").await,
+        StatusCode::FORBIDDEN
+    );
+
+    // Other callers are unaffected
+    assert_eq!(get_k1_from(&app, "203.0.113.8").await, StatusCode::OK);
+
+    tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+    assert_eq!(get_k1_from(&app, "203.0.113.7").await, StatusCode::OK);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_failed_signatures_block_ip_not_pubkey() {
+    let (app, app_state, _guard) = setup_abuse_test_app().await;
+
+    let user = TestUser::new();
+    let impostor = TestUser::new_with_key(&[0x42; 32]);
+
+    for expected in [StatusCode::UNAUTHORIZED, StatusCode::FORBIDDEN] {
+        let k1 = make_k1(&app_state.k1_cache).await.unwrap().to_string();
+        let mut payload = user.auth_payload(&k1);
+        payload.sig = impostor.auth_payload(&k1).sig;
+        let (status, _) = login_from(&app, &payload, "198.51.100.1").await;
+        assert_eq!(status, expected);
+    }
+
+    // The forging IP is blocked, even for a correctly signed login
+    let k1 = make_k1(&app_state.k1_cache).await.unwrap().to_string();
+    let (status, body) = login_from(&app, &user.auth_payload(&k1), "198.51.100.1").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let error: ApiErrorResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(error.code, "TEMPORARILY_BLOCKED");
+
+    // The victim's pubkey was never scored, so they can still log in from elsewhere
+    let (status, _) = login_from(&app, &user.auth_payload(&k1), "198.51.100.2").await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_excessive_logins_block_pubkey_until_expiry() {
+    let (app, app_state, _guard) = setup_abuse_test_app().await;
+
+    let user = TestUser::new();
+
+    // Each verified login scores the pubkey, until the threshold blocks it
+    for _ in 0..19 {
+        let k1 = make_k1(&app_state.k1_cache).await.unwrap().to_string();
+        let (status, _) = login_from(&app, &user.auth_payload(&k1), "198.51.100.3").await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let k1 = make_k1(&app_state.k1_cache).await.unwrap().to_string();
+    let (status, _) = login_from(&app, &user.auth_payload(&k1), "198.51.100.3").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // The block follows the pubkey to another IP
+    let k1 = make_k1(&app_state.k1_cache).await.unwrap().to_string();
+    let (status, body) = login_from(&app, &user.auth_payload(&k1), "198.51.100.4").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let error: ApiErrorResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(error.code, "TEMPORARILY_BLOCKED");
+
+    tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+    let k1 = make_k1(&app_state.k1_cache).await.unwrap().to_string();
+    let (status, _) = login_from(&app, &user.auth_payload(&k1), "198.51.100.4").await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_ln_address_available() {
+    let (app, app_state, _guard) = setup_public_test_app().await;
+
+    sqlx::query("INSERT INTO users (pubkey, lightning_address) VALUES ($1, $2)")
+        .bind("taken_pubkey")
+        .bind("taken@localhost")
+        .execute(&app_state.db_pool)
+        .await
+        .unwrap();
+
+    let (status, body) = get_status_and_body(&app, "/ln_address_available?username=free").await;
+    assert_eq!(status, StatusCode::OK);
+    let res: LightningAddressAvailabilityResponse = serde_json::from_slice(&body).unwrap();
+    assert!(res.available);
+    assert!(res.suggestions.is_empty());
+
+    let (status, body) = get_status_and_body(&app, "/ln_address_available?username=taken").await;
+    assert_eq!(status, StatusCode::OK);
+    let res: LightningAddressAvailabilityResponse = serde_json::from_slice(&body).unwrap();
+    assert!(!res.available);
+    assert!(!res.suggestions.is_empty());
+    for suggestion in &res.suggestions {
+        assert!(suggestion.ends_with("@localhost"));
+        assert_ne!(suggestion, "taken@localhost");
+    }
+
+    let (status, body) = get_status_and_body(&app, "/ln_address_available?username=admin").await;
+    assert_eq!(status, StatusCode::OK);
+    let res: LightningAddressAvailabilityResponse = serde_json::from_slice(&body).unwrap();
+    assert!(!res.available);
+
+    let (status, _) = get_status_and_body(&app, "/ln_address_available?username=Not%20Valid").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
 #[tracing_test::traced_test]
 #[tokio::test]
 async fn test_app_version_check_update_required() {
@@ -182,3 +1103,54 @@ async fn test_app_version_check_invalid_version() {
 
     assert_eq!(response.status(), StatusCode::BAD_REQUEST);
 }
+
+fn health_app(s3_healthy: bool) -> axum::Router {
+    axum::Router::new().route(
+        "/health",
+        axum::routing::get(health_check).with_state(HealthState {
+            started_at: Instant::now() - Duration::from_secs(90),
+            network: "regtest".to_string(),
+            s3_healthy: Arc::new(AtomicBool::new(s3_healthy)),
+        }),
+    )
+}
+
+async fn get_health(app: axum::Router) -> (StatusCode, HealthResponse) {
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method(http::Method::GET)
+                .uri("/health")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_health_reports_build_and_uptime() {
+    let (status, health) = get_health(health_app(true)).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(health.status, "ok");
+    assert_eq!(health.version, env!("CARGO_PKG_VERSION"));
+    assert!(!health.commit.is_empty());
+    assert!(health.uptime_secs >= 90);
+    assert_eq!(health.network, "regtest");
+    assert!(health.s3_healthy);
+}
+
+#[tracing_test::traced_test]
+#[tokio::test]
+async fn test_health_stays_live_when_s3_unhealthy() {
+    let (status, health) = get_health(health_app(false)).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(health.status, "ok");
+    assert!(!health.s3_healthy);
+}
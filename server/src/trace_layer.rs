@@ -2,6 +2,7 @@ use axum::{
     body::Body, extract::Request, http::Response, middleware::Next, response::IntoResponse,
 };
 use http_body_util::BodyExt;
+use tracing::Instrument;
 
 use crate::wide_event::{WideEvent, WideEventHandle};
 
@@ -19,6 +20,22 @@ pub async fn trace_middleware(mut req: Request, next: Next) -> impl IntoResponse
 
     req.extensions_mut().insert(event_handle.clone());
 
+    // Every log emitted while handling the request carries its request id
+    let span = event_handle.with(|e| {
+        tracing::info_span!(
+            "request",
+            request_id = e.request_id.as_deref().unwrap_or_default(),
+            method = e.method.as_deref().unwrap_or_default(),
+            path = e.path.as_deref().unwrap_or_default(),
+        )
+    });
+
+    handle_request(req, next, event_handle)
+        .instrument(span)
+        .await
+}
+
+async fn handle_request(req: Request, next: Next, event_handle: WideEventHandle) -> Response<Body> {
     let response = next.run(req).await;
 
     let status = response.status();
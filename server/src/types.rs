@@ -1,5 +1,7 @@
+use bitcoin::hashes::{Hash, sha256};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::sync::OnceLock;
 use ts_rs::TS;
 use validator::{Validate, ValidationError};
@@ -13,6 +15,38 @@ fn is_valid_ln_username(username: &str) -> bool {
     ln_username_regex().is_match(username)
 }
 
+/// Usernames that could be mistaken for official accounts and can't be registered.
+const RESERVED_LN_USERNAMES: [&str; 16] = [
+    "abuse",
+    "admin",
+    "administrator",
+    "billing",
+    "help",
+    "hostmaster",
+    "info",
+    "noah",
+    "noahwallet",
+    "official",
+    "postmaster",
+    "root",
+    "security",
+    "support",
+    "system",
+    "webmaster",
+];
+
+/// Returns true if the username part of a lightning address is reserved.
+pub fn is_reserved_ln_username(username: &str) -> bool {
+    RESERVED_LN_USERNAMES.contains(&username.to_lowercase().as_str())
+}
+
+/// Returns true if the lightning address uses a reserved username.
+pub fn is_reserved_lightning_address(value: &str) -> bool {
+    value
+        .split_once('@')
+        .is_some_and(|(username, _)| is_reserved_ln_username(username))
+}
+
 pub(crate) fn is_valid_lightning_address(value: &str) -> bool {
     let (username, domain) = match value.split_once('@') {
         Some(parts) => parts,
@@ -30,11 +64,15 @@ pub(crate) fn is_valid_lightning_address(value: &str) -> bool {
     is_valid_ln_username(username)
 }
 
+/// Shown when a lightning address username uses characters outside `[a-z0-9._-]`.
+pub(crate) const INVALID_LN_USERNAME_MESSAGE: &str = "Lightning address usernames may only contain lowercase letters, digits, '.', '_' and '-', e.g. satoshi.n";
+
 fn validate_lightning_address(value: &str) -> Result<(), ValidationError> {
     if is_valid_lightning_address(value) {
         Ok(())
     } else {
-        Err(ValidationError::new("lightning_address"))
+        Err(ValidationError::new("lightning_address")
+            .with_message(INVALID_LN_USERNAME_MESSAGE.into()))
     }
 }
 
@@ -49,6 +87,8 @@ pub struct AuthLoginPayload {
 #[derive(Debug, Clone)]
 pub struct AuthenticatedUser {
     pub key: String,
+    /// The only action the access token may be used for, when its k1 was bound to one.
+    pub action: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, TS)]
@@ -73,6 +113,16 @@ pub struct ApiErrorResponse {
     pub message: String,
     /// Safe error reason for compatibility.
     pub reason: String,
+    /// Server unix time in seconds, sent with `K1_EXPIRED` and `CLOCK_SKEW` so clients can
+    /// detect clock skew.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(optional, type = "number")]
+    pub server_time: Option<u64>,
+    /// Validation messages keyed by field, sent with `INVALID_ARGUMENT` when request payload
+    /// validation fails.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub field_errors: Option<BTreeMap<String, Vec<String>>>,
 }
 
 /// Represents events that can occur during LNURL-auth.
@@ -94,8 +144,12 @@ pub struct RegisterResponse {
     pub event: Option<AuthEvent>,
     /// An optional reason for an error, if one occurred.
     pub reason: Option<String>,
-    /// The user's lightning address.
+    /// The user's effective lightning address, including one assigned by the server.
     pub lightning_address: Option<String>,
+    /// The user's registered Ark address.
+    pub ark_address: Option<String>,
+    /// Whether automatic backups are enabled for the user.
+    pub backup_enabled: bool,
     /// Whether the user's email is verified.
     pub is_email_verified: bool,
 }
@@ -132,6 +186,32 @@ pub struct RegisterPushToken {
     pub push_token: String,
 }
 
+/// A push token registered for the authenticated user, with the token itself masked.
+#[derive(Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
+pub struct PushTokenInfo {
+    #[ts(type = "number")]
+    pub id: i64,
+    /// Only the last characters of the token are shown.
+    pub masked_token: String,
+    pub created_at: String,
+    pub updated_at: String,
+    /// Device the token was registered from, `null` when no device info was reported.
+    pub device_model: Option<String>,
+    pub os_name: Option<String>,
+    pub os_version: Option<String>,
+    /// Last time the device reported its info to the server.
+    pub last_seen_at: Option<String>,
+}
+
+/// Defines the payload for revoking one of the user's push tokens.
+#[derive(Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
+pub struct RevokePushTokenPayload {
+    #[ts(type = "number")]
+    pub id: i64,
+}
+
 /// Defines the payload for granting mailbox authorization to the server.
 #[derive(Serialize, Deserialize, TS, Validate)]
 #[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
@@ -154,6 +234,16 @@ pub struct UserInfoResponse {
     pub lightning_address: String,
 }
 
+/// Everything the client needs to render the user's receive QR codes.
+#[derive(Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
+pub struct LnurlMetadataResponse {
+    /// The bech32-encoded LNURL of the user's LNURL-pay endpoint.
+    pub lnurl: String,
+    /// The user's lightning address.
+    pub lightning_address: String,
+}
+
 /// Defines the payload for submitting a BOLT11 invoice.
 #[derive(Serialize, Deserialize, TS)]
 #[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
@@ -173,6 +263,156 @@ pub struct UpdateLnAddressPayload {
     pub ln_address: String,
 }
 
+/// Defines the payload for setting the amount suggested to LNURL-pay payers.
+#[derive(Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
+pub struct UpdateDefaultSendablePayload {
+    /// The suggested amount in millisatoshis, or `null` to stop suggesting one.
+    #[ts(type = "number | null")]
+    pub default_sendable_msat: Option<u64>,
+}
+
+/// Represents the first response in the LNURL-pay protocol.
+///
+/// This response provides the necessary details for a wallet to make a payment,
+/// such as the callback URL, sendable amounts, and metadata.
+#[derive(Serialize, Deserialize, TS, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+#[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
+pub struct LnurlpDefaultResponse {
+    /// The URL where the wallet should send the second request.
+    pub callback: String,
+    /// The maximum amount that can be sent in a single payment, in millisatoshis.
+    #[ts(type = "number")]
+    pub max_sendable: u64,
+    /// The minimum amount that can be sent in a single payment, in millisatoshis.
+    #[ts(type = "number")]
+    pub min_sendable: u64,
+    /// A JSON string containing metadata about the payment.
+    pub metadata: String,
+    /// The LNURL-pay tag, which is always "payRequest".
+    pub tag: String,
+    /// The maximum length of a comment that can be included with the payment.
+    pub comment_allowed: u16,
+}
+
+/// Represents the second response in the LNURL-pay protocol.
+///
+/// This response contains the BOLT11 invoice that the wallet will use to pay.
+#[derive(Serialize, Deserialize, TS, Debug, PartialEq)]
+#[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
+pub struct LnurlpInvoiceResponse {
+    /// The BOLT11 payment request (invoice).
+    pub pr: String,
+    /// A list of routes for the payment, typically empty.
+    pub routes: Vec<String>,
+    /// The recipient's Ark address, only sent to Noah wallets.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub ark: Option<String>,
+    /// Shown by the payer's wallet after paying, configured by the recipient.
+    #[serde(
+        rename = "successAction",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[ts(optional)]
+    pub success_action: Option<LnurlpSuccessAction>,
+}
+
+/// The LUD-06 error response LNURL wallets understand.
+///
+/// Every `ApiErrorResponse` carries these two fields, so errors from the LNURL endpoints can be
+/// read by any wallet as well as by the client.
+// Only built by clients and tests, the server sends these fields inside `ApiErrorResponse`
+#[allow(dead_code)]
+#[derive(Serialize, Deserialize, TS, Debug, PartialEq)]
+#[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
+pub struct LnurlErrorResponse {
+    /// Always "ERROR".
+    pub status: String,
+    /// Why the request failed, safe to show to the payer.
+    pub reason: String,
+}
+
+/// LUD-09 success action the payer's wallet shows once the payment went through.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(tag = "tag", rename_all = "lowercase")]
+#[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
+pub enum LnurlpSuccessAction {
+    /// A short note such as a thank-you message.
+    Message { message: String },
+    /// An `https` link on the LNURL domain, such as a receipt, shown with its description.
+    Url { description: String, url: String },
+}
+
+/// Defines the payload for setting the success action shown to LNURL-pay payers.
+#[derive(Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
+pub struct UpdateSuccessActionPayload {
+    /// The action to show, or `null` to stop showing one.
+    pub success_action: Option<LnurlpSuccessAction>,
+}
+
+/// Defines the payload for setting the user's timezone.
+#[derive(Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
+pub struct UpdateTimezonePayload {
+    /// IANA timezone name such as `Europe/Berlin`, or `null` to fall back to UTC.
+    pub timezone: Option<String>,
+}
+
+/// Represents a status update for an invoice request streamed over WebSocket.
+#[derive(Serialize, Deserialize, TS, Debug, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+#[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
+pub enum InvoiceStatusFrame {
+    /// The recipient's device has been asked to create an invoice.
+    Pending { transaction_id: String },
+    /// The push notification reached the push service, waiting for the recipient's device.
+    PushSent { transaction_id: String },
+    /// The recipient's device returned an invoice.
+    Invoiced { pr: String },
+    /// The recipient's device didn't respond in time.
+    Timeout,
+    /// The invoice request failed on the server.
+    Error { reason: String },
+}
+
+/// Defines the payload for pre-registering an invoice request to poll for later.
+#[derive(Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
+pub struct LnurlpPreRegisterPayload {
+    /// The amount of the payment in millisatoshis.
+    #[ts(type = "number")]
+    pub amount: u64,
+}
+
+/// Identifies a pre-registered invoice request, to be polled at `/lnurlp/poll/{transaction_id}`.
+#[derive(Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
+pub struct LnurlpPreRegisterResponse {
+    pub transaction_id: String,
+}
+
+/// Defines the query for checking whether a lightning address username is available.
+#[derive(Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
+pub struct LightningAddressAvailabilityQuery {
+    /// The username part of the lightning address, without the domain.
+    pub username: String,
+}
+
+/// Represents whether a lightning address username can be registered.
+#[derive(Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
+pub struct LightningAddressAvailabilityResponse {
+    /// Whether the username is free to register.
+    pub available: bool,
+    /// Available alternative lightning addresses, only set when the username is taken or reserved.
+    pub suggestions: Vec<String>,
+}
+
 /// Defines the payload for querying lightning address suggestions.
 #[derive(Serialize, Deserialize, TS)]
 #[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
@@ -189,17 +429,57 @@ pub struct LightningAddressSuggestionsResponse {
     pub suggestions: Vec<String>,
 }
 
+/// Defines the payload for checking an offboarding address signature.
+#[derive(Serialize, Deserialize, TS, Validate)]
+#[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
+pub struct VerifyOffboardingSignaturePayload {
+    /// The on-chain address the user wants to offboard to.
+    #[validate(length(min = 1))]
+    pub address: String,
+    /// Signature over the address made with the user's key.
+    #[validate(length(min = 1))]
+    pub address_signature: String,
+}
+
+/// Defines the payload for reporting unwanted LNURL-pay requests.
+#[derive(Serialize, Deserialize, Validate, TS)]
+#[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
+pub struct ReportAbusePayload {
+    /// Free-form description for the operator reviewing the report.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    #[validate(length(max = 500))]
+    pub reason: Option<String>,
+}
+
+/// Represents the result of an offboarding signature check.
 #[derive(Serialize, Deserialize, TS)]
 #[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
-pub struct GetUploadUrlPayload {
-    pub backup_version: i32, // 1 or 2 (rolling)
+pub struct VerifyOffboardingSignatureResponse {
+    /// Whether the signature is valid for the address and the user's key.
+    pub valid: bool,
 }
 
 #[derive(Serialize, Deserialize, TS)]
 #[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
-pub struct UploadUrlResponse {
-    pub upload_url: String, // Pre-signed S3 URL
-    pub s3_key: String,     // S3 object key
+pub struct GetUploadUrlPayload {
+    pub backup_version: i32, // 1..=MAX_BACKUP_VERSION (rolling)
+    /// Hex SHA-256 of the backup about to be uploaded. When it matches the checksum stored for
+    /// this version, no upload URL is issued.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub checksum: Option<String>,
+}
+
+/// Represents where to upload a backup, or that the upload can be skipped.
+#[derive(Serialize, Deserialize, TS, Debug, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+#[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
+pub enum UploadUrlResponse {
+    /// Upload the backup to the pre-signed S3 URL.
+    Upload { upload_url: String, s3_key: String },
+    /// The stored backup already has the requested checksum, so the upload can be skipped.
+    Unchanged,
 }
 
 #[derive(Serialize, Deserialize, TS)]
@@ -209,6 +489,37 @@ pub struct CompleteUploadPayload {
     pub backup_version: i32,
     #[ts(type = "number")]
     pub backup_size: u64,
+    /// Hex SHA-256 of the uploaded backup, compared by later `upload_url` calls.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub checksum: Option<String>,
+}
+
+/// Tells the client whether a backup exists and which version to restore.
+#[derive(Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
+pub struct BackupExistsResponse {
+    pub exists: bool,
+    /// The version last completed by the client, which `download_url` serves by default.
+    pub latest_version: Option<i32>,
+}
+
+/// Storage used by the user's backups, for the settings screen.
+#[derive(Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
+pub struct BackupUsageResponse {
+    #[ts(type = "number")]
+    pub total_bytes: u64,
+    #[ts(type = "number")]
+    pub backup_count: u64,
+    pub oldest_backup_at: Option<String>,
+    pub newest_backup_at: Option<String>,
+    /// Bytes left under `MAX_TOTAL_BACKUP_BYTES_PER_USER`, null when no byte quota is set.
+    #[ts(type = "number | null")]
+    pub remaining_bytes: Option<u64>,
+    /// Backups left under `MAX_BACKUPS_PER_USER`, null when no count quota is set.
+    #[ts(type = "number | null")]
+    pub remaining_backups: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, TS)]
@@ -294,6 +605,84 @@ impl std::str::FromStr for HeartbeatStatus {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BroadcastJobStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+impl std::fmt::Display for BroadcastJobStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BroadcastJobStatus::Running => write!(f, "running"),
+            BroadcastJobStatus::Completed => write!(f, "completed"),
+            BroadcastJobStatus::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+impl std::str::FromStr for BroadcastJobStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "running" => Ok(BroadcastJobStatus::Running),
+            "completed" => Ok(BroadcastJobStatus::Completed),
+            "failed" => Ok(BroadcastJobStatus::Failed),
+            _ => Err(anyhow::anyhow!("Invalid broadcast job status: {}", s)),
+        }
+    }
+}
+
+/// Priority a broadcast job was started with, stored so a resumed job keeps it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BroadcastJobPriority {
+    Normal,
+    High,
+}
+
+impl std::fmt::Display for BroadcastJobPriority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BroadcastJobPriority::Normal => write!(f, "normal"),
+            BroadcastJobPriority::High => write!(f, "high"),
+        }
+    }
+}
+
+impl std::str::FromStr for BroadcastJobPriority {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "normal" => Ok(BroadcastJobPriority::Normal),
+            "high" => Ok(BroadcastJobPriority::High),
+            _ => Err(anyhow::anyhow!("Invalid broadcast job priority: {}", s)),
+        }
+    }
+}
+
+impl From<&expo_push_notification_client::Priority> for BroadcastJobPriority {
+    /// Broadcasts treat anything below `High` as a normal notification.
+    fn from(priority: &expo_push_notification_client::Priority) -> Self {
+        if *priority == expo_push_notification_client::Priority::High {
+            BroadcastJobPriority::High
+        } else {
+            BroadcastJobPriority::Normal
+        }
+    }
+}
+
+impl From<BroadcastJobPriority> for expo_push_notification_client::Priority {
+    fn from(priority: BroadcastJobPriority) -> Self {
+        match priority {
+            BroadcastJobPriority::Normal => expo_push_notification_client::Priority::Normal,
+            BroadcastJobPriority::High => expo_push_notification_client::Priority::High,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, TS, Clone)]
 #[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
 pub struct MaintenanceNotification {
@@ -304,8 +693,13 @@ pub struct MaintenanceNotification {
 #[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
 pub struct LightningInvoiceRequestNotification {
     pub transaction_id: String,
+    /// Requested amount in millisatoshis, as received from the LNURL-pay payer.
     #[ts(type = "number")]
     pub amount: u64,
+    /// Hex SHA256 of the LNURL metadata the payer saw, to use as the invoice's description hash.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub description_hash: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, TS, Clone)]
@@ -336,6 +730,27 @@ impl NotificationRequestData {
         }
     }
 
+    /// Hash identifying the notification's content, so identical notifications can be told
+    /// apart from new ones of the same type.
+    pub fn content_hash(&self) -> String {
+        let payload = match self {
+            NotificationRequestData::Heartbeat(heartbeat) => heartbeat.notification_id.as_str(),
+            NotificationRequestData::Maintenance | NotificationRequestData::BackupTrigger => "",
+        };
+        sha256::Hash::hash(format!("{}:{}", self.notification_type(), payload).as_bytes())
+            .to_string()
+    }
+
+    /// Rebuilds the request for a broadcast notification type, used to resume interrupted
+    /// broadcasts. Only types without a per-user payload can be rebuilt.
+    pub fn from_broadcast_type(notification_type: &str) -> Option<Self> {
+        match notification_type {
+            "maintenance" => Some(NotificationRequestData::Maintenance),
+            "backup_trigger" => Some(NotificationRequestData::BackupTrigger),
+            _ => None,
+        }
+    }
+
     pub fn needs_unique_k1(&self) -> bool {
         matches!(
             self,
@@ -343,6 +758,11 @@ impl NotificationRequestData {
         )
     }
 
+    /// Whether broadcasts of this type should skip users who turned off backups.
+    pub fn requires_backup_enabled(&self) -> bool {
+        matches!(self, NotificationRequestData::BackupTrigger)
+    }
+
     pub fn report_type(&self) -> Option<ReportType> {
         match self {
             NotificationRequestData::Maintenance => Some(ReportType::Maintenance),
@@ -458,6 +878,58 @@ pub struct AppVersionCheckPayload {
     pub client_version: String,
 }
 
+/// Describes the server to clients.
+#[derive(Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
+pub struct ServerInfoResponse {
+    /// Bitcoin network the server operates on.
+    pub network: String,
+    /// Hex public key that signs critical responses, `null` when signing is disabled.
+    ///
+    /// Signed responses carry an `x-server-sig` header holding the hex DER ECDSA signature
+    /// over the SHA-256 of the response body.
+    pub signing_pubkey: Option<String>,
+    /// Feature flags enabled or disabled globally on this deployment.
+    pub feature_flags: BTreeMap<String, bool>,
+}
+
+/// Server clock reference, so clients can spot clock skew before a login fails with
+/// `K1_EXPIRED`.
+#[derive(Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
+pub struct ServerTimeResponse {
+    /// Unix time in seconds, from the same clock that timestamps k1s.
+    #[ts(type = "number")]
+    pub server_time: u64,
+    /// How long an issued k1 stays valid, in seconds.
+    #[ts(type = "number")]
+    pub k1_ttl_secs: u64,
+    /// Clock drift tolerated on either side of the k1 lifetime, in seconds.
+    #[ts(type = "number")]
+    pub k1_clock_skew_tolerance_secs: u64,
+}
+
+/// Body of the `/health` probe, used to confirm which build is serving traffic.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HealthResponse {
+    pub status: String,
+    pub version: String,
+    /// Git commit the binary was built from, `unknown` when not provided at build time.
+    pub commit: String,
+    pub uptime_secs: u64,
+    pub network: String,
+    /// `false` when the last S3 self-test failed, `true` when it passed or hasn't run.
+    pub s3_healthy: bool,
+}
+
+/// Represents the feature flags resolved for the authenticated user.
+#[derive(Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
+pub struct FeatureFlagsResponse {
+    /// Global flags with the user's rollout overrides applied.
+    pub feature_flags: BTreeMap<String, bool>,
+}
+
 #[derive(Serialize, Deserialize, TS)]
 #[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
 pub struct AppVersionInfo {
@@ -480,6 +952,25 @@ pub struct VerifyEmailPayload {
     pub code: String,
 }
 
+/// Defines the payload for checking whether an email is already in use.
+#[derive(Serialize, Deserialize, TS, Validate)]
+#[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
+pub struct EmailAvailabilityPayload {
+    #[validate(email)]
+    pub email: String,
+}
+
+/// Represents the response for an email availability check.
+///
+/// Only says whether another account has verified the address, never which one.
+#[derive(Serialize, Deserialize, TS)]
+#[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
+pub struct EmailAvailabilityResponse {
+    /// `false` when another account has verified the address. An account verifying its first
+    /// email may still share it, but a verified account cannot change to it.
+    pub available: bool,
+}
+
 /// Represents the response for email verification requests.
 #[derive(Serialize, Deserialize, TS)]
 #[ts(export, export_to = "../../client/src/types/serverTypes.ts")]
@@ -1,6 +1,9 @@
 use std::str::FromStr;
 
-use crate::cache::k1_store::K1Store;
+use bitcoin::hashes::{Hash, sha256};
+use lightning_invoice::{Bolt11Invoice, Bolt11InvoiceDescriptionRef};
+
+use crate::cache::k1_store::{K1, K1Store};
 use crate::db::user_repo::UserRepository;
 use crate::errors::ApiError;
 use sqlx::PgPool;
@@ -29,10 +32,85 @@ pub async fn verify_auth(
     Ok(is_valid)
 }
 
-pub async fn make_k1(k1_store: &K1Store) -> anyhow::Result<String> {
+/// Verifies that `address_signature` is the user's signature over an offboarding `address`.
+///
+/// Returns an error if the address is not valid for `network`, and `false` if the
+/// signature is malformed or doesn't match.
+pub async fn verify_address_signature(
+    network: bitcoin::Network,
+    pubkey: &str,
+    address: &str,
+    address_signature: &str,
+) -> Result<bool, ApiError> {
+    bitcoin::Address::from_str(address)
+        .ok()
+        .and_then(|address| address.require_network(network).ok())
+        .ok_or_else(|| ApiError::InvalidArgument("Invalid address".to_string()))?;
+
+    let public_key = bitcoin::secp256k1::PublicKey::from_str(pubkey)?;
+    let Ok(signature) = bitcoin::secp256k1::ecdsa::Signature::from_str(address_signature) else {
+        return Ok(false);
+    };
+
+    verify_message(address, signature, &public_key).await
+}
+
+/// Signs a response body with the server key, see `ServerInfoResponse::signing_pubkey`.
+///
+/// Returns the hex DER encoded ECDSA signature over the SHA-256 of `body`.
+pub fn sign_response_body(secret_key: &bitcoin::secp256k1::SecretKey, body: &[u8]) -> String {
+    let hash = sha256::Hash::hash(body);
+    let secp = bitcoin::secp256k1::Secp256k1::signing_only();
+    let msg = bitcoin::secp256k1::Message::from_digest(hash.to_byte_array());
+    secp.sign_ecdsa(&msg, secret_key).to_string()
+}
+
+/// Encodes a URL as a LUD-01 LNURL: bech32 with the `lnurl` prefix, uppercased so QR codes
+/// can use the compact alphanumeric mode.
+pub fn encode_lnurl(url: &str) -> anyhow::Result<String> {
+    let hrp = bitcoin::bech32::Hrp::parse("lnurl")?;
+    Ok(bitcoin::bech32::encode_upper::<bitcoin::bech32::Bech32>(
+        hrp,
+        url.as_bytes(),
+    )?)
+}
+
+/// Reads the description hash (`h` field) from a BOLT11 invoice.
+///
+/// Returns `None` when the invoice commits to a plain description instead.
+pub fn bolt11_description_hash(invoice: &str) -> anyhow::Result<Option<sha256::Hash>> {
+    let invoice = Bolt11Invoice::from_str(invoice.trim())
+        .map_err(|e| anyhow::anyhow!("Invoice is not a valid BOLT11 invoice: {}", e))?;
+    Ok(match invoice.description() {
+        Bolt11InvoiceDescriptionRef::Hash(hash) => Some(hash.0),
+        Bolt11InvoiceDescriptionRef::Direct(_) => None,
+    })
+}
+
+pub async fn make_k1(k1_store: &K1Store) -> anyhow::Result<K1> {
     k1_store.issue_k1().await
 }
 
+/// Checks a hashcash-style proof of work for a `get_k1` challenge.
+///
+/// The solution is valid when `sha256("{challenge}:{nonce}")` starts with at least
+/// `difficulty` zero bits.
+pub fn verify_pow(challenge: &str, nonce: &str, difficulty: u8) -> bool {
+    let hash = sha256::Hash::hash(format!("{}:{}", challenge, nonce).as_bytes());
+    leading_zero_bits(hash.as_byte_array()) >= u32::from(difficulty)
+}
+
+fn leading_zero_bits(bytes: &[u8]) -> u32 {
+    let mut count = 0;
+    for byte in bytes {
+        count += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    count
+}
+
 pub async fn verify_user_exists(pool: &PgPool, pubkey: &str) -> Result<bool, ApiError> {
     let user_repo = UserRepository::new(pool);
     user_repo.exists_by_pubkey(pubkey).await.map_err(|e| {